@@ -0,0 +1,154 @@
+//! The Accessibility permission that typing into other apps, following their windows and
+//! reading their window titles need. macOS doesn't announce grants, so a watcher polls
+//! and emits `accessibility-permission-changed` when the user flips the switch in System
+//! Settings, which lets the frontend walk them through it.
+
+use crate::events::EventSink;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Also the `accessibility-permission-changed` payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct AccessibilityStatus {
+    pub granted: bool,
+    // False where the platform has no such permission (granted is then always true)
+    pub required: bool,
+}
+
+pub fn accessibility_status() -> AccessibilityStatus {
+    AccessibilityStatus { granted: crate::keyboard::accessibility_trusted(), required: cfg!(target_os = "macos") }
+}
+
+/// Asks for the permission: macOS shows its prompt (only the first time it's asked) and,
+/// with `open_settings`, the Accessibility list in System Settings opens too. The grant
+/// itself arrives later, through the watcher.
+pub fn request_accessibility(open_settings: bool) -> Result<AccessibilityStatus, String> {
+    #[cfg(target_os = "macos")]
+    {
+        if !mac::prompt() && open_settings {
+            std::process::Command::new("open")
+                .arg("x-apple.systempreferences:com.apple.preference.security?Privacy_Accessibility")
+                .status()
+                .map_err(|e| format!("Failed to open System Settings: {}", e))?;
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = open_settings;
+    Ok(accessibility_status())
+}
+
+#[cfg(target_os = "macos")]
+mod mac {
+    use std::ffi::c_void;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFBooleanTrue: *const c_void;
+        static kCFTypeDictionaryKeyCallBacks: c_void;
+        static kCFTypeDictionaryValueCallBacks: c_void;
+        fn CFDictionaryCreate(
+            allocator: *const c_void,
+            keys: *const *const c_void,
+            values: *const *const c_void,
+            count: isize,
+            key_callbacks: *const c_void,
+            value_callbacks: *const c_void,
+        ) -> *const c_void;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        static kAXTrustedCheckOptionPrompt: *const c_void;
+        fn AXIsProcessTrustedWithOptions(options: *const c_void) -> u8;
+    }
+
+    // Whether the app is trusted already; shows the system prompt if not
+    pub fn prompt() -> bool {
+        unsafe {
+            let keys = [kAXTrustedCheckOptionPrompt];
+            let values = [kCFBooleanTrue];
+            let options = CFDictionaryCreate(
+                std::ptr::null(),
+                keys.as_ptr(),
+                values.as_ptr(),
+                1,
+                &kCFTypeDictionaryKeyCallBacks as *const c_void,
+                &kCFTypeDictionaryValueCallBacks as *const c_void,
+            );
+            let trusted = AXIsProcessTrustedWithOptions(options) != 0;
+            if !options.is_null() {
+                CFRelease(options);
+            }
+            trusted
+        }
+    }
+}
+
+/// Tracks the grant and emits `accessibility-permission-changed` when it changes.
+pub struct AccessibilityWatcher {
+    events: Arc<dyn EventSink>,
+    granted: Mutex<Option<bool>>,
+}
+
+impl AccessibilityWatcher {
+    pub fn new(events: Arc<dyn EventSink>) -> Self {
+        AccessibilityWatcher { events, granted: Mutex::new(None) }
+    }
+
+    /// Records the current status; true if it changed since the last one (the first
+    /// one isn't a change).
+    pub fn update(&self, status: AccessibilityStatus) -> Result<bool, String> {
+        let mut granted = self.granted.lock().map_err(|_| "Lock poisoned")?;
+        let changed = granted.is_some_and(|g| g != status.granted);
+        *granted = Some(status.granted);
+        drop(granted);
+        if changed {
+            self.events.emit("accessibility-permission-changed", status);
+        }
+        Ok(changed)
+    }
+
+    /// Polls the grant for as long as the watcher lives. Nothing to watch where the
+    /// permission doesn't exist.
+    pub fn start(self: &Arc<Self>) {
+        let _ = self.update(accessibility_status());
+        if !accessibility_status().required {
+            return;
+        }
+        let watcher = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+            let Some(watcher) = watcher.upgrade() else { return };
+            let _ = watcher.update(accessibility_status());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+
+    #[test]
+    fn emits_only_when_the_grant_changes() {
+        let sink = Arc::new(RecordingSink::default());
+        let watcher = AccessibilityWatcher::new(sink.clone());
+        let status = |granted| AccessibilityStatus { granted, required: true };
+
+        assert!(!watcher.update(status(false)).unwrap());
+        assert!(!watcher.update(status(false)).unwrap());
+        assert!(watcher.update(status(true)).unwrap());
+        assert!(!watcher.update(status(true)).unwrap());
+        assert!(watcher.update(status(false)).unwrap());
+
+        let events = sink.named("accessibility-permission-changed");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["granted"], true);
+        assert_eq!(events[1]["granted"], false);
+    }
+}
@@ -0,0 +1,112 @@
+//! Per-session interaction and output recency, for MRU tab switching.
+
+use crate::pty::SessionManager;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Typing and output are reported at most this often per session
+const EVENT_INTERVAL_MS: u64 = 1000;
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Unix times in milliseconds; 0 if it never happened.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionActivity {
+    pub session_id: String,
+    // Focused or typed into
+    pub last_interaction: u64,
+    pub last_output: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct ActivityTracker {
+    last_interaction: AtomicU64,
+    last_output: AtomicU64,
+    last_output_event: AtomicU64,
+}
+
+impl ActivityTracker {
+    // Both return true when the change should be reported
+    pub(crate) fn interact(&self) -> bool {
+        let previous = self.last_interaction.swap(now_ms(), Ordering::SeqCst);
+        now_ms().saturating_sub(previous) >= EVENT_INTERVAL_MS
+    }
+
+    pub(crate) fn output(&self) -> bool {
+        let now = now_ms();
+        self.last_output.store(now, Ordering::SeqCst);
+        let last_event = self.last_output_event.load(Ordering::SeqCst);
+        now.saturating_sub(last_event) >= EVENT_INTERVAL_MS
+            && self.last_output_event
+                .compare_exchange(last_event, now, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+    }
+
+    pub(crate) fn snapshot(&self, session_id: &str) -> SessionActivity {
+        SessionActivity {
+            session_id: session_id.to_string(),
+            last_interaction: self.last_interaction.load(Ordering::SeqCst),
+            last_output: self.last_output.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl SessionManager {
+    /// Records that the user switched to the session.
+    pub fn focus_session(&self, session_id: &str) -> Result<(), String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        session.activity.interact();
+        // Always reported: switching tabs changes the MRU order
+        self.events.emit("session-activity-changed", session.activity.snapshot(session_id));
+        Ok(())
+    }
+
+    /// Most recently interacted-with first; ties broken by most recent output.
+    pub fn sessions_by_activity(&self) -> Result<Vec<SessionActivity>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let mut activity: Vec<SessionActivity> = sessions.iter()
+            .map(|(id, s)| s.activity.snapshot(id))
+            .collect();
+        activity.sort_by_key(|a| std::cmp::Reverse((a.last_interaction, a.last_output)));
+        Ok(activity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_output_events() {
+        let tracker = ActivityTracker::default();
+        assert!(tracker.output());
+        assert!(!tracker.output());
+        assert!(tracker.snapshot("s").last_output > 0);
+        assert_eq!(tracker.snapshot("s").last_interaction, 0);
+    }
+
+    #[test]
+    fn orders_by_interaction_then_output() {
+        let sink = std::sync::Arc::new(crate::events::testing::RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), crate::history::HistoryStore::load(None));
+        let a = manager.spawn_session(portable_pty::CommandBuilder::new("sh"), "sh").unwrap();
+        let b = manager.spawn_session(portable_pty::CommandBuilder::new("sh"), "sh").unwrap();
+        manager.focus_session(&b).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        manager.focus_session(&a).unwrap();
+
+        let order: Vec<String> = manager.sessions_by_activity().unwrap().into_iter().map(|s| s.session_id).collect();
+        assert_eq!(order, vec![a.clone(), b.clone()]);
+        let events = sink.named("session-activity-changed");
+        assert!(events.iter().any(|e| e["session_id"] == a.as_str() && e["last_interaction"].as_u64() > Some(0)));
+        manager.close(&a).unwrap();
+        manager.close(&b).unwrap();
+    }
+}
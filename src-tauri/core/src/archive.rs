@@ -0,0 +1,205 @@
+//! Session archives: a session's scrollback, the commands run in it (with their times and
+//! exit statuses), its marks and metadata in one gzipped JSON file, so an important
+//! session can be kept after it's closed. Opening an archive gives a read-only view of
+//! it, its buffer drawn like `get_session_buffer`'s.
+
+use crate::buffer::SessionBuffer;
+use crate::history::HistoryEntry;
+use crate::process;
+use crate::pty::{OutputMark, SessionManager};
+use crate::unix_now;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveMetadata {
+    pub session_id: String,
+    pub shell: String,
+    pub profile: Option<String>,
+    pub title: Option<String>,
+    pub cwd: Option<String>,
+    pub created_at: u64,
+    pub archived_at: u64,
+    // Whether the shell had already exited
+    pub exited: bool,
+    // Stream offset of the first archived byte; above 0 if older output had been dropped
+    pub start_offset: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionArchive {
+    pub version: u32,
+    pub metadata: ArchiveMetadata,
+    pub commands: Vec<HistoryEntry>,
+    pub marks: Vec<OutputMark>,
+    // Raw output, base64-encoded
+    scrollback: String,
+}
+
+/// An opened archive: everything in it, read-only.
+#[derive(Clone, Serialize)]
+pub struct OpenedArchive {
+    pub metadata: ArchiveMetadata,
+    pub commands: Vec<HistoryEntry>,
+    pub marks: Vec<OutputMark>,
+    pub buffer: SessionBuffer,
+}
+
+impl SessionArchive {
+    pub fn scrollback(&self) -> Result<Vec<u8>, String> {
+        BASE64.decode(&self.scrollback).map_err(|e| format!("Corrupt archive scrollback: {}", e))
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let json = serde_json::to_vec(self).map_err(|e| format!("Failed to serialize archive: {}", e))?;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&json).and_then(|_| encoder.finish()).map_err(|e| format!("Failed to compress archive: {}", e))
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<SessionArchive, String> {
+        let mut json = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut json).map_err(|_| "Not a session archive".to_string())?;
+        let archive: SessionArchive = serde_json::from_slice(&json).map_err(|e| format!("Invalid session archive: {}", e))?;
+        if archive.version > ARCHIVE_VERSION {
+            return Err(format!("Archive version {} is newer than this app supports", archive.version));
+        }
+        Ok(archive)
+    }
+}
+
+impl SessionManager {
+    /// Writes the session's retained scrollback, commands, marks and metadata to `path`,
+    /// redacted like other exports. Works on sessions whose shell has exited, until
+    /// they're closed.
+    pub fn archive_session(&self, session_id: &str, path: &Path) -> Result<ArchiveMetadata, String> {
+        let (pid, mut metadata, scrollback, marks) = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            let log = session.output_log.lock().map_err(|_| "Lock poisoned")?;
+            let metadata = ArchiveMetadata {
+                session_id: session_id.to_string(),
+                shell: session.shell.clone(),
+                profile: session.profile.clone(),
+                title: session.last_title.clone(),
+                cwd: session.title.cwd.clone(),
+                created_at: session.created_at,
+                archived_at: unix_now(),
+                exited: session.exited.load(Ordering::SeqCst),
+                start_offset: log.start(),
+            };
+            let marks: Vec<OutputMark> = session.marks.iter().filter(|m| m.offset >= log.start()).cloned().collect();
+            (session.pid, metadata, log.contents(), marks)
+        };
+        if !metadata.exited {
+            metadata.cwd = pid.and_then(process::process_cwd).or(metadata.cwd);
+        }
+        let redactor = self.redactor.lock().map_err(|_| "Lock poisoned")?;
+        let commands = self.history.lock().map_err(|_| "Lock poisoned")?.entries().iter()
+            .filter(|entry| entry.session_id == session_id)
+            .map(|entry| HistoryEntry { command: redactor.redact(&entry.command).into_owned(), ..entry.clone() })
+            .collect();
+        let scrollback = redactor.redact(&String::from_utf8_lossy(&scrollback)).into_owned();
+        drop(redactor);
+
+        let archive = SessionArchive {
+            version: ARCHIVE_VERSION,
+            metadata: metadata.clone(),
+            commands,
+            marks,
+            scrollback: BASE64.encode(scrollback),
+        };
+        std::fs::write(path, archive.encode()?).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(metadata)
+    }
+
+    /// Reads an archive written by `archive_session`.
+    pub fn open_archive(&self, path: &Path) -> Result<OpenedArchive, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let archive = SessionArchive::decode(&bytes)?;
+        let scrollback = archive.scrollback()?;
+        let start_offset = archive.metadata.start_offset;
+        let buffer = SessionBuffer {
+            end_offset: start_offset + scrollback.len() as u64,
+            data: self.output_protocol.encode(scrollback),
+            start_offset,
+        };
+        Ok(OpenedArchive { metadata: archive.metadata, commands: archive.commands, marks: archive.marks, buffer })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_other_files_and_newer_versions() {
+        assert_eq!(SessionArchive::decode(b"{}").err().unwrap(), "Not a session archive");
+        let metadata = ArchiveMetadata {
+            session_id: "s1".into(),
+            shell: "zsh".into(),
+            profile: None,
+            title: None,
+            cwd: None,
+            created_at: 1,
+            archived_at: 2,
+            exited: true,
+            start_offset: 0,
+        };
+        let mut archive = SessionArchive {
+            version: ARCHIVE_VERSION,
+            metadata,
+            commands: Vec::new(),
+            marks: Vec::new(),
+            scrollback: BASE64.encode("hi"),
+        };
+        let decoded = SessionArchive::decode(&archive.encode().unwrap()).unwrap();
+        assert_eq!(decoded.scrollback().unwrap(), b"hi");
+        archive.version = ARCHIVE_VERSION + 1;
+        assert!(SessionArchive::decode(&archive.encode().unwrap()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn archives_scrollback_and_commands() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use crate::ipc::OutputData;
+        use portable_pty::CommandBuilder;
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.write(&id, "echo archived-$((40+2))\n").unwrap();
+        let contents = || String::from_utf8_lossy(&manager.sessions.lock().unwrap()[&id].output_log.lock().unwrap().contents()).into_owned();
+        for _ in 0..150 {
+            if contents().contains("archived-42") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        let path = std::env::temp_dir().join(format!("shelll-archive-{}.gz", uuid::Uuid::new_v4()));
+        let metadata = manager.archive_session(&id, &path).unwrap();
+        assert_eq!(metadata.shell, "sh");
+        manager.close(&id).unwrap();
+
+        let opened = manager.open_archive(&path).unwrap();
+        assert_eq!(opened.metadata, metadata);
+        assert_eq!(opened.commands.iter().map(|c| c.command.as_str()).collect::<Vec<_>>(), ["echo archived-$((40+2))"]);
+        let OutputData::Bytes(data) = opened.buffer.data else { panic!("expected bytes") };
+        assert!(String::from_utf8_lossy(&data).contains("archived-42"));
+        assert_eq!(opened.buffer.end_offset, data.len() as u64);
+        let _ = std::fs::remove_file(&path);
+    }
+}
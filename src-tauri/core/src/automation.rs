@@ -0,0 +1,267 @@
+//! Named expect-and-type flows: when a session prints a pattern, focus the target app
+//! and type into it (or into a session). Automations can also be run by hand, or dry-run
+//! to see what they would do.
+
+use crate::config::{load_json, save_json};
+use crate::feedback::{self, Feedback};
+use crate::focus;
+use crate::pty::{PtySession, SessionManager};
+use crate::scratchpad;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Step {
+    FocusApp { app: String },
+    // Keystrokes into the frontmost app
+    TypeText { text: String },
+    // Written to `session_id` (an id or "scratchpad"), or to the session that fired the trigger
+    SendToSession { session_id: Option<String>, text: String },
+    Wait { ms: u64 },
+    PlayFeedback { feedback: Feedback },
+}
+
+impl Step {
+    fn describe(&self, session_id: Option<&str>) -> String {
+        match self {
+            Step::FocusApp { app } => format!("Focus {}", app),
+            Step::TypeText { text } => format!("Type {:?} into the frontmost app", text),
+            Step::SendToSession { session_id: target, text } => {
+                let target = target.as_deref().or(session_id).unwrap_or("(no session)");
+                format!("Send {:?} to session {}", text, target)
+            }
+            Step::Wait { ms } => format!("Wait {}ms", ms),
+            Step::PlayFeedback { feedback } => format!("Play {:?}", feedback),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutputTrigger {
+    // Plain substring of the printed text (escape sequences removed)
+    pub pattern: String,
+    // Any session when None
+    pub session_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Automation {
+    pub name: String,
+    pub trigger: Option<OutputTrigger>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct AutomationTriggeredPayload {
+    pub name: String,
+    pub session_id: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct AutomationFile {
+    automations: Vec<Automation>,
+}
+
+// Automations persisted as JSON in the config dir
+pub struct AutomationStore {
+    path: Option<PathBuf>,
+    automations: Vec<Automation>,
+}
+
+impl AutomationStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file: AutomationFile = load_json(path.as_deref());
+        AutomationStore { path, automations: file.automations }
+    }
+
+    pub fn list(&self) -> Vec<Automation> {
+        self.automations.clone()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Automation, String> {
+        self.automations.iter()
+            .find(|a| a.name == name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown automation '{}'", name))
+    }
+
+    pub fn save_automation(&mut self, automation: Automation) -> Result<(), String> {
+        if automation.name.trim().is_empty() {
+            return Err("Automation name cannot be empty".into());
+        }
+        if automation.trigger.as_ref().is_some_and(|t| t.pattern.is_empty()) {
+            return Err("Trigger pattern cannot be empty".into());
+        }
+        match self.automations.iter_mut().find(|a| a.name == automation.name) {
+            Some(existing) => *existing = automation,
+            None => self.automations.push(automation),
+        }
+        self.save()
+    }
+
+    pub fn delete_automation(&mut self, name: &str) -> Result<(), String> {
+        self.automations.retain(|a| a.name != name);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        match &self.path {
+            Some(path) => save_json(path, &AutomationFile { automations: self.automations.clone() }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Triggered automations, matched against each session's output by its reader thread.
+#[derive(Default)]
+pub(crate) struct TriggerSet {
+    automations: Vec<Automation>,
+    // End of each session's previous chunk, so patterns split across reads still match
+    tails: HashMap<String, String>,
+}
+
+impl TriggerSet {
+    /// Automations whose pattern appeared in `text`.
+    pub(crate) fn scan(&mut self, session_id: &str, text: &str) -> Vec<Automation> {
+        if self.automations.is_empty() || text.is_empty() {
+            return Vec::new();
+        }
+        let tail = self.tails.entry(session_id.to_string()).or_default();
+        let window = format!("{}{}", tail, text);
+        let tail_len = tail.len();
+
+        let fired = self.automations.iter()
+            .filter(|a| {
+                let Some(trigger) = &a.trigger else { return false };
+                if trigger.session_id.as_deref().is_some_and(|id| id != session_id) {
+                    return false;
+                }
+                // Only matches that end in the new text, so a match isn't reported twice
+                window.match_indices(&trigger.pattern).any(|(i, m)| i + m.len() > tail_len)
+            })
+            .cloned()
+            .collect();
+
+        let keep = self.automations.iter()
+            .filter_map(|a| a.trigger.as_ref())
+            .map(|t| t.pattern.len().saturating_sub(1))
+            .max()
+            .unwrap_or(0);
+        let mut start = window.len().saturating_sub(keep);
+        while !window.is_char_boundary(start) {
+            start += 1;
+        }
+        *tail = window[start..].to_string();
+        fired
+    }
+
+    pub(crate) fn forget(&mut self, session_id: &str) {
+        self.tails.remove(session_id);
+    }
+}
+
+pub(crate) fn execute(steps: &[Step], session_id: Option<&str>, sessions: &Mutex<HashMap<String, PtySession>>) -> Result<(), String> {
+    for step in steps {
+        match step {
+            Step::FocusApp { app } => focus::activate_app(app)?,
+            Step::TypeText { text } => focus::type_text(text)?,
+            Step::SendToSession { session_id: target, text } => {
+                let target = target.as_deref().or(session_id).ok_or("No session to send to")?;
+                let sessions = sessions.lock().map_err(|_| "Lock poisoned")?;
+                let session = sessions.get(&scratchpad::resolve_id(&sessions, target)).ok_or("Session not found")?;
+                if session.input_locked {
+                    return Err("Session input is locked".into());
+                }
+                let mut writer = session.writer.lock().map_err(|_| "Lock poisoned")?;
+                writer.write_all(text.as_bytes()).map_err(|e| format!("Failed to write: {}", e))?;
+            }
+            Step::Wait { ms } => thread::sleep(Duration::from_millis(*ms)),
+            Step::PlayFeedback { feedback } => feedback::play_feedback(feedback)?,
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn spawn_execute(automation: Automation, session_id: String, sessions: Arc<Mutex<HashMap<String, PtySession>>>) {
+    thread::spawn(move || {
+        if let Err(e) = execute(&automation.steps, Some(&session_id), &sessions) {
+            eprintln!("Automation '{}' failed: {}", automation.name, e);
+        }
+    });
+}
+
+impl SessionManager {
+    /// Replaces the set of automations watched for output triggers.
+    pub fn set_automations(&self, automations: Vec<Automation>) -> Result<(), String> {
+        let mut triggers = self.triggers.lock().map_err(|_| "Lock poisoned")?;
+        triggers.automations = automations.into_iter().filter(|a| a.trigger.is_some()).collect();
+        triggers.tails.clear();
+        Ok(())
+    }
+
+    /// Runs `automation` now. Returns a description of each step; with `dry_run` nothing
+    /// is executed.
+    pub fn run_automation(&self, automation: &Automation, session_id: Option<&str>, dry_run: bool) -> Result<Vec<String>, String> {
+        let plan = automation.steps.iter().map(|s| s.describe(session_id)).collect();
+        if !dry_run {
+            execute(&automation.steps, session_id, &self.sessions)?;
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watching(pattern: &str, session_id: Option<&str>) -> TriggerSet {
+        TriggerSet {
+            automations: vec![Automation {
+                name: "done".into(),
+                trigger: Some(OutputTrigger { pattern: pattern.into(), session_id: session_id.map(String::from) }),
+                steps: vec![],
+            }],
+            tails: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn matches_patterns_split_across_chunks() {
+        let mut triggers = watching("DONE", None);
+        assert!(triggers.scan("s1", "build DO").is_empty());
+        assert_eq!(triggers.scan("s1", "NE\n").len(), 1);
+        // The tail that completed the match doesn't fire it again
+        assert!(triggers.scan("s1", "next").is_empty());
+        assert!(triggers.scan("s2", "NE").is_empty());
+    }
+
+    #[test]
+    fn respects_trigger_session() {
+        let mut triggers = watching("DONE", Some("s1"));
+        assert!(triggers.scan("s2", "DONE").is_empty());
+        assert_eq!(triggers.scan("s1", "DONE").len(), 1);
+    }
+
+    #[test]
+    fn dry_run_describes_steps() {
+        let sink = Arc::new(crate::events::testing::RecordingSink::default());
+        let manager = SessionManager::new(sink, crate::history::HistoryStore::load(None));
+        let automation = Automation {
+            name: "deploy".into(),
+            trigger: None,
+            steps: vec![
+                Step::FocusApp { app: "Safari".into() },
+                Step::Wait { ms: 100 },
+                Step::SendToSession { session_id: None, text: "ok\r".into() },
+            ],
+        };
+        let plan = manager.run_automation(&automation, Some("s1"), true).unwrap();
+        assert_eq!(plan, vec!["Focus Safari", "Wait 100ms", "Send \"ok\\r\" to session s1"]);
+    }
+}
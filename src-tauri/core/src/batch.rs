@@ -0,0 +1,199 @@
+//! Coalescing of PTY output into fewer, larger `pty-output` events. The reader thread
+//! gets at most 4KB per read, and emitting each one separately floods the IPC bridge
+//! when a program prints a lot (`cat` of a big file), making the UI stutter. Output is
+//! held for a few milliseconds after the first byte, or until a batch is full. Chunks
+//! from different streams (see `stream`) are never merged. With flow control on (see
+//! `flow`), `push` blocks while the frontend is too far behind.
+
+use crate::events::EventSink;
+use crate::flow::{FlowControl, HIGH_WATER, LOW_WATER};
+use crate::ipc::OutputProtocol;
+use crate::pty::PtyOutputPayload;
+use crate::stream::OutputStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Short enough that typing still echoes within a frame
+const FLUSH_INTERVAL: Duration = Duration::from_millis(8);
+const MAX_BATCH: usize = 64 * 1024;
+
+#[derive(Default)]
+struct Pending {
+    // Runs of output from one stream, in order
+    chunks: Vec<(OutputStream, Vec<u8>)>,
+    len: usize,
+    // When the oldest unsent byte arrived
+    since: Option<Instant>,
+    closed: bool,
+    // Emitted but not yet acked by the frontend
+    unacked: usize,
+    // Waiting for acks to bring what's outstanding (unacked and pending) to the low mark
+    throttled: bool,
+}
+
+pub(crate) struct OutputBatcher {
+    pending: Mutex<Pending>,
+    changed: Condvar,
+    acked: Condvar,
+    flow: FlowControl,
+}
+
+impl OutputBatcher {
+    fn new(flow: FlowControl) -> Self {
+        OutputBatcher {
+            pending: Mutex::default(),
+            changed: Condvar::new(),
+            acked: Condvar::new(),
+            flow,
+        }
+    }
+
+    /// Queues output for the next batch. Blocks while the session is throttled.
+    pub(crate) fn push(&self, stream: OutputStream, data: &[u8]) {
+        let Ok(mut pending) = self.pending.lock() else { return };
+        while pending.throttled && !pending.closed && self.flow.enabled() {
+            pending = match self.acked.wait(pending) {
+                Ok(pending) => pending,
+                Err(_) => return,
+            };
+        }
+        if pending.closed {
+            return;
+        }
+        match pending.chunks.last_mut() {
+            Some((last, chunk)) if *last == stream => chunk.extend_from_slice(data),
+            _ => pending.chunks.push((stream, data.to_vec())),
+        }
+        pending.len += data.len();
+        pending.since.get_or_insert_with(Instant::now);
+        if self.flow.enabled() && pending.unacked + pending.len >= HIGH_WATER {
+            pending.throttled = true;
+        }
+        self.changed.notify_one();
+    }
+
+    /// Flushes what's pending and stops the flusher.
+    pub(crate) fn close(&self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.closed = true;
+        }
+        self.changed.notify_one();
+        self.acked.notify_all();
+    }
+
+    pub(crate) fn ack(&self, bytes: usize) {
+        let Ok(mut pending) = self.pending.lock() else { return };
+        pending.unacked = pending.unacked.saturating_sub(bytes);
+        if pending.throttled && pending.unacked + pending.len <= LOW_WATER {
+            pending.throttled = false;
+            self.acked.notify_all();
+        }
+    }
+
+    // Forgets outstanding output, when flow control is switched on or off
+    pub(crate) fn reset_credit(&self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.unacked = 0;
+            pending.throttled = false;
+        }
+        self.acked.notify_all();
+    }
+
+    // Blocks until a batch is due; None once closed and drained
+    fn next_batch(&self) -> Option<Vec<(OutputStream, Vec<u8>)>> {
+        let mut pending = self.pending.lock().ok()?;
+        loop {
+            if !pending.chunks.is_empty() {
+                let due = pending.since.map_or(Duration::ZERO, |t| FLUSH_INTERVAL.saturating_sub(t.elapsed()));
+                if pending.closed || pending.len >= MAX_BATCH || due.is_zero() {
+                    if self.flow.enabled() {
+                        pending.unacked += pending.len;
+                    }
+                    pending.since = None;
+                    pending.len = 0;
+                    return Some(std::mem::take(&mut pending.chunks));
+                }
+                pending = self.changed.wait_timeout(pending, due).ok()?.0;
+            } else if pending.closed {
+                return None;
+            } else {
+                pending = self.changed.wait(pending).ok()?;
+            }
+        }
+    }
+}
+
+/// Starts the thread that emits `session_id`'s batched output, encoded for `protocol`.
+/// Join it after `close` to know everything has been emitted.
+pub(crate) fn spawn_flusher(
+    session_id: String,
+    events: Arc<dyn EventSink>,
+    protocol: OutputProtocol,
+    flow: FlowControl,
+) -> (Arc<OutputBatcher>, JoinHandle<()>) {
+    let batcher = Arc::new(OutputBatcher::new(flow));
+    let flusher = batcher.clone();
+    let handle = thread::spawn(move || {
+        while let Some(chunks) = flusher.next_batch() {
+            for (stream, data) in chunks {
+                events.emit("pty-output", PtyOutputPayload {
+                    session_id: session_id.clone(),
+                    data: protocol.encode(data),
+                    stream,
+                });
+            }
+        }
+    });
+    (batcher, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+
+    #[test]
+    fn coalesces_reads_and_flushes_on_close() {
+        let sink = Arc::new(RecordingSink::default());
+        let (batcher, flusher) = spawn_flusher("s1".into(), sink.clone(), OutputProtocol::default(), FlowControl::default());
+        for _ in 0..100 {
+            batcher.push(OutputStream::Stdout, &[b'x'; 4096]);
+        }
+        batcher.push(OutputStream::Stdout, b"tail");
+        batcher.close();
+        flusher.join().unwrap();
+
+        let batches: Vec<Vec<u8>> = sink.named("pty-output").iter()
+            .map(|p| serde_json::from_value(p["data"].clone()).unwrap())
+            .collect();
+        assert!(batches.len() < 20, "{} events", batches.len());
+        assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 100 * 4096 + 4);
+        assert!(batches.last().unwrap().ends_with(b"tail"));
+    }
+
+    #[test]
+    fn keeps_streams_apart() {
+        let sink = Arc::new(RecordingSink::default());
+        let (batcher, flusher) = spawn_flusher("s1".into(), sink.clone(), OutputProtocol::default(), FlowControl::default());
+        batcher.push(OutputStream::Stdout, b"compiling ");
+        batcher.push(OutputStream::Stdout, b"shelll\n");
+        batcher.push(OutputStream::Stderr, b"warning: unused\n");
+        batcher.push(OutputStream::Stdout, b"done\n");
+        batcher.close();
+        flusher.join().unwrap();
+        let events = sink.named("pty-output");
+        let streams: Vec<&str> = events.iter().map(|p| p["stream"].as_str().unwrap()).collect();
+        assert_eq!(streams, ["stdout", "stderr", "stdout"]);
+    }
+
+    #[test]
+    fn small_output_goes_out_promptly() {
+        let sink = Arc::new(RecordingSink::default());
+        let (batcher, _flusher) = spawn_flusher("s1".into(), sink.clone(), OutputProtocol::default(), FlowControl::default());
+        batcher.push(OutputStream::Stdout, b"$ ");
+        thread::sleep(FLUSH_INTERVAL * 5);
+        assert_eq!(sink.named("pty-output").len(), 1);
+        batcher.close();
+    }
+}
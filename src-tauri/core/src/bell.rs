@@ -0,0 +1,229 @@
+//! What a BEL in a session's output does besides the configured sound (see `feedback`):
+//! a `session-bell` event for the frontend, a bounce of the Dock icon, and a count of
+//! bells rung while Shelll was in the background, shown as the Dock icon's badge until
+//! it comes back to the front.
+
+use crate::config::{load_json, save_json};
+use crate::events::EventSink;
+use crate::progress::{ProgressState, SessionProgress};
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// A program printing many BELs in a row counts as one bell
+const MIN_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BellSettings {
+    // Emit `session-bell`
+    pub event: bool,
+    // Bounce the Dock icon once while Shelll is in the background
+    pub bounce: bool,
+    // Count background bells on the Dock icon's badge
+    pub badge: bool,
+}
+
+impl Default for BellSettings {
+    fn default() -> Self {
+        BellSettings { event: true, bounce: false, badge: false }
+    }
+}
+
+// Bell settings persisted as JSON in the config dir
+pub struct BellStore {
+    path: Option<PathBuf>,
+    pub settings: BellSettings,
+}
+
+impl BellStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        BellStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+/// How the backend reaches the app: whether Shelll is in front, and how to bounce the
+/// Dock icon.
+#[derive(Clone)]
+pub struct BellHooks {
+    pub app_active: Arc<dyn Fn() -> bool + Send + Sync>,
+    pub bounce: Arc<dyn Fn() + Send + Sync>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionBellPayload {
+    pub session_id: String,
+}
+
+#[derive(Default)]
+pub(crate) struct Bells {
+    settings: BellSettings,
+    hooks: Option<BellHooks>,
+    last: HashMap<String, Instant>,
+    // Bells since Shelll was last in front
+    unseen: u32,
+}
+
+impl Bells {
+    // Whether a bell in `session_id` counts, i.e. isn't part of a burst
+    fn ring(&mut self, session_id: &str, now: Instant) -> bool {
+        if self.last.get(session_id).is_some_and(|at| now.duration_since(*at) < MIN_INTERVAL) {
+            return false;
+        }
+        self.last.insert(session_id.to_string(), now);
+        true
+    }
+
+    pub(crate) fn forget(&mut self, session_id: &str) {
+        self.last.remove(session_id);
+    }
+}
+
+// From the reader thread: does whatever the settings ask for a bell in `session_id`
+pub(crate) fn ring(bells: &Mutex<Bells>, session_id: &str, events: &Arc<dyn EventSink>) {
+    let Ok(mut state) = bells.lock() else { return };
+    if !state.ring(session_id, Instant::now()) {
+        return;
+    }
+    let settings = state.settings.clone();
+    let in_background = state.hooks.as_ref().is_some_and(|hooks| !(hooks.app_active)());
+    if settings.badge && in_background {
+        state.unseen += 1;
+    }
+    let hooks = state.hooks.clone();
+    drop(state);
+
+    if settings.event {
+        events.emit("session-bell", SessionBellPayload { session_id: session_id.to_string() });
+    }
+    if let (true, true, Some(hooks)) = (settings.bounce, in_background, hooks) {
+        (hooks.bounce)();
+    }
+}
+
+/// The Dock badge for `unseen` bells and the sessions' combined progress: the bell count,
+/// else "!" for failed progress, else none.
+pub fn dock_badge(unseen: u32, progress: SessionProgress) -> Option<String> {
+    if unseen > 0 {
+        Some(unseen.to_string())
+    } else if progress.state == ProgressState::Error {
+        Some("!".into())
+    } else {
+        None
+    }
+}
+
+/// Sets or clears the Dock icon's badge. Must be called from the main thread.
+#[cfg(target_os = "macos")]
+pub fn set_dock_badge(label: Option<&str>) -> Result<(), String> {
+    use objc::runtime::Object;
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::CString;
+
+    let label = label.map(|l| CString::new(l.replace('\0', ""))).transpose().map_err(|e| e.to_string())?;
+    unsafe {
+        let app: *mut Object = msg_send![class!(NSApplication), sharedApplication];
+        let tile: *mut Object = msg_send![app, dockTile];
+        if tile.is_null() {
+            return Err("The app has no Dock tile".into());
+        }
+        let label: *mut Object = match &label {
+            Some(label) => msg_send![class!(NSString), stringWithUTF8String: label.as_ptr()],
+            None => std::ptr::null_mut(),
+        };
+        let _: () = msg_send![tile, setBadgeLabel: label];
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn set_dock_badge(_label: Option<&str>) -> Result<(), String> {
+    Err("The Dock badge is only available on macOS".into())
+}
+
+impl SessionManager {
+    pub fn set_bell_settings(&self, settings: BellSettings) -> Result<(), String> {
+        let mut bells = self.bells.lock().map_err(|_| "Lock poisoned")?;
+        if !settings.badge {
+            bells.unseen = 0;
+        }
+        bells.settings = settings;
+        Ok(())
+    }
+
+    pub fn set_bell_hooks(&self, hooks: BellHooks) -> Result<(), String> {
+        self.bells.lock().map_err(|_| "Lock poisoned")?.hooks = Some(hooks);
+        Ok(())
+    }
+
+    /// Bells rung while Shelll was in the background, for the Dock badge.
+    pub fn unseen_bells(&self) -> Result<u32, String> {
+        Ok(self.bells.lock().map_err(|_| "Lock poisoned")?.unseen)
+    }
+
+    /// Call when Shelll comes to the front: the bells have been seen.
+    pub fn clear_unseen_bells(&self) -> Result<(), String> {
+        self.bells.lock().map_err(|_| "Lock poisoned")?.unseen = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_dock_badge() {
+        let failed = SessionProgress { state: ProgressState::Error, percent: 40 };
+        assert_eq!(dock_badge(0, SessionProgress::default()), None);
+        assert_eq!(dock_badge(0, failed).as_deref(), Some("!"));
+        assert_eq!(dock_badge(3, failed).as_deref(), Some("3"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn counts_background_bells_and_emits_events() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+        use std::thread;
+
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        let active = Arc::new(AtomicBool::new(false));
+        let bounces = Arc::new(AtomicUsize::new(0));
+        let (is_active, bounced) = (active.clone(), bounces.clone());
+        manager.set_bell_hooks(BellHooks {
+            app_active: Arc::new(move || is_active.load(Ordering::SeqCst)),
+            bounce: Arc::new(move || {
+                bounced.fetch_add(1, Ordering::SeqCst);
+            }),
+        }).unwrap();
+        manager.set_bell_settings(BellSettings { event: true, bounce: true, badge: true }).unwrap();
+
+        manager.write(&id, "printf '\\007'\n").unwrap();
+        for _ in 0..150 {
+            if !sink.named("session-bell").is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(sink.named("session-bell")[0]["session_id"], id.as_str());
+        assert_eq!(bounces.load(Ordering::SeqCst), 1);
+        assert_eq!(manager.unseen_bells().unwrap(), 1);
+        manager.clear_unseen_bells().unwrap();
+        assert_eq!(manager.unseen_bells().unwrap(), 0);
+        manager.close(&id).unwrap();
+    }
+}
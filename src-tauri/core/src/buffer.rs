@@ -0,0 +1,199 @@
+//! Retained raw output per session, addressed by absolute stream offsets (the same
+//! offsets marks use). Backs pull-based reads for automation clients and reattaching
+//! frontends that consume output at their own pace, and the whole buffer for a webview
+//! that reloaded (or a window that was re-created) and needs to redraw its sessions.
+
+use crate::ipc::OutputData;
+use crate::pty::SessionManager;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+pub(crate) const RETAINED_BYTES: usize = 2 * 1024 * 1024;
+// Upper bound for one read, whatever the caller asks for
+pub const MAX_READ_BYTES: usize = 256 * 1024;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OutputChunk {
+    // Offset of the first byte in `data`
+    pub offset: u64,
+    // Pass this as `offset` to continue reading
+    pub next_offset: u64,
+    pub data: Vec<u8>,
+    // The requested offset had already been dropped; reading resumed at the oldest byte
+    pub truncated: bool,
+}
+
+/// Everything still retained for a session.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionBuffer {
+    // Encoded like `pty-output`
+    pub data: OutputData,
+    // Offset of the first byte; above 0 once older output has been dropped
+    pub start_offset: u64,
+    // Output after this arrives as `pty-output` events
+    pub end_offset: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct OutputLog {
+    // Offset of data[0]
+    start: u64,
+    data: VecDeque<u8>,
+    // Lower cap than RETAINED_BYTES while memory is low
+    limit: Option<usize>,
+}
+
+impl OutputLog {
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        self.data.extend(bytes);
+        self.trim_to(self.limit.unwrap_or(RETAINED_BYTES));
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+        self.push(&[]);
+    }
+
+    // Drops everything but the last `max` bytes and hands the freed memory back
+    pub(crate) fn trim_to(&mut self, max: usize) {
+        if self.data.len() > max {
+            let excess = self.data.len() - max;
+            self.data.drain(..excess);
+            self.data.shrink_to_fit();
+            self.start += excess as u64;
+        }
+    }
+
+    // Output from before the session existed (a forked snapshot's scrollback); shifts
+    // everything after it
+    pub(crate) fn prepend(&mut self, bytes: &[u8]) {
+        let mut data: VecDeque<u8> = bytes.iter().copied().collect();
+        data.append(&mut self.data);
+        self.data = data;
+        self.push(&[]);
+    }
+
+    pub(crate) fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub(crate) fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+
+    pub(crate) fn contents(&self) -> Vec<u8> {
+        self.data.iter().copied().collect()
+    }
+
+    // What's still retained between two offsets
+    pub(crate) fn range(&self, start: u64, end: u64) -> Vec<u8> {
+        let start = start.clamp(self.start, self.end());
+        let end = end.clamp(start, self.end());
+        self.data.range((start - self.start) as usize..(end - self.start) as usize).copied().collect()
+    }
+
+    pub(crate) fn read(&self, offset: u64, max_bytes: usize) -> OutputChunk {
+        let truncated = offset < self.start;
+        let offset = offset.clamp(self.start, self.end());
+        let skip = (offset - self.start) as usize;
+        let data: Vec<u8> = self.data.iter()
+            .skip(skip)
+            .take(max_bytes.min(MAX_READ_BYTES))
+            .copied()
+            .collect();
+        OutputChunk {
+            offset,
+            next_offset: offset + data.len() as u64,
+            data,
+            truncated,
+        }
+    }
+}
+
+impl SessionManager {
+    /// Output from `offset` onwards, at most `max_bytes` (capped at `MAX_READ_BYTES`).
+    /// An empty chunk means the caller is caught up.
+    pub fn read_output_since(&self, session_id: &str, offset: u64, max_bytes: usize) -> Result<OutputChunk, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let log = session.output_log.lock().map_err(|_| "Lock poisoned")?;
+        Ok(log.read(offset, max_bytes))
+    }
+
+    /// The retained output of a session (up to 2MB, less in low-memory mode), for redrawing it from scratch.
+    pub fn session_buffer(&self, session_id: &str) -> Result<SessionBuffer, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let log = session.output_log.lock().map_err(|_| "Lock poisoned")?;
+        Ok(SessionBuffer {
+            data: self.output_protocol.encode(log.contents()),
+            start_offset: log.start,
+            end_offset: log.end(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_from_offsets() {
+        let mut log = OutputLog::default();
+        log.push(b"hello ");
+        log.push(b"world");
+        let chunk = log.read(0, 4);
+        assert_eq!((chunk.data.as_slice(), chunk.next_offset), (&b"hell"[..], 4));
+        let chunk = log.read(chunk.next_offset, 100);
+        assert_eq!(chunk.data, b"o world");
+        assert!(log.read(chunk.next_offset, 100).data.is_empty());
+    }
+
+    #[test]
+    fn reports_dropped_output() {
+        let mut log = OutputLog::default();
+        log.push(&vec![b'x'; RETAINED_BYTES]);
+        log.push(b"tail");
+        let chunk = log.read(0, 10);
+        assert!(chunk.truncated);
+        assert_eq!(chunk.offset, 4);
+        assert_eq!(log.end(), RETAINED_BYTES as u64 + 4);
+    }
+
+    #[test]
+    fn lowering_the_limit_trims_from_the_front() {
+        let mut log = OutputLog::default();
+        log.push(b"0123456789");
+        log.set_limit(Some(4));
+        assert_eq!((log.contents().as_slice(), log.start), (&b"6789"[..], 6));
+        log.push(b"ab");
+        assert_eq!(log.contents(), b"89ab");
+        log.set_limit(None);
+        log.push(b"cd");
+        assert_eq!((log.contents().as_slice(), log.end()), (&b"89abcd"[..], 14));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn returns_the_whole_buffer() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+        use std::sync::Arc;
+
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.write(&id, "echo restored-$((6*7))\n").unwrap();
+        for _ in 0..100 {
+            let buffer = manager.session_buffer(&id).unwrap();
+            let OutputData::Bytes(data) = buffer.data else { panic!("expected bytes") };
+            if String::from_utf8_lossy(&data).contains("restored-42") {
+                assert_eq!((buffer.start_offset, buffer.end_offset), (0, data.len() as u64));
+                manager.close(&id).unwrap();
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        panic!("output never reached the buffer");
+    }
+}
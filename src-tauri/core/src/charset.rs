@@ -0,0 +1,236 @@
+//! Character-set translation for PTY output. Programs can designate DEC special graphics
+//! (line drawing) or a national replacement set into G0/G1 and shift between them; the
+//! translator rewrites the affected characters to UTF-8 and drops the designations, so
+//! every consumer downstream (renderer, screen model, logs) only ever sees UTF-8.
+
+// 0x5f..=0x7e in the DEC special graphics set
+const DEC_SPECIAL_GRAPHICS: [char; 32] = [
+    '\u{a0}', '◆', '▒', '␉', '␌', '␍', '␊', '°', '±', '␤', '␋', '┘', '┐', '┌', '└', '┼',
+    '⎺', '⎻', '─', '⎼', '⎽', '├', '┤', '┴', '┬', '│', '≤', '≥', 'π', '≠', '£', '·',
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Charset {
+    #[default]
+    Ascii,
+    DecSpecialGraphics,
+    British,
+    German,
+    French,
+    Swedish,
+    Norwegian,
+    Spanish,
+}
+
+impl Charset {
+    fn from_final(byte: u8) -> Charset {
+        match byte {
+            b'0' => Charset::DecSpecialGraphics,
+            b'A' => Charset::British,
+            b'K' => Charset::German,
+            b'R' | b'f' => Charset::French,
+            b'H' | b'7' => Charset::Swedish,
+            b'E' | b'6' | b'`' => Charset::Norwegian,
+            b'Z' => Charset::Spanish,
+            _ => Charset::Ascii,
+        }
+    }
+
+    fn map(self, byte: u8) -> Option<char> {
+        let c = match (self, byte) {
+            (Charset::DecSpecialGraphics, 0x5f..=0x7e) => DEC_SPECIAL_GRAPHICS[(byte - 0x5f) as usize],
+            (Charset::British, b'#') => '£',
+            (Charset::German, _) => match byte {
+                b'@' => '§',
+                b'[' => 'Ä',
+                b'\\' => 'Ö',
+                b']' => 'Ü',
+                b'{' => 'ä',
+                b'|' => 'ö',
+                b'}' => 'ü',
+                b'~' => 'ß',
+                _ => return None,
+            },
+            (Charset::French, _) => match byte {
+                b'#' => '£',
+                b'@' => 'à',
+                b'[' => '°',
+                b'\\' => 'ç',
+                b']' => '§',
+                b'{' => 'é',
+                b'|' => 'ù',
+                b'}' => 'è',
+                b'~' => '¨',
+                _ => return None,
+            },
+            (Charset::Swedish, _) => match byte {
+                b'@' => 'É',
+                b'[' => 'Ä',
+                b'\\' => 'Ö',
+                b']' => 'Å',
+                b'^' => 'Ü',
+                b'`' => 'é',
+                b'{' => 'ä',
+                b'|' => 'ö',
+                b'}' => 'å',
+                b'~' => 'ü',
+                _ => return None,
+            },
+            (Charset::Norwegian, _) => match byte {
+                b'@' => 'Ä',
+                b'[' => 'Æ',
+                b'\\' => 'Ø',
+                b']' => 'Å',
+                b'^' => 'Ü',
+                b'`' => 'ä',
+                b'{' => 'æ',
+                b'|' => 'ø',
+                b'}' => 'å',
+                b'~' => 'ü',
+                _ => return None,
+            },
+            (Charset::Spanish, _) => match byte {
+                b'#' => '£',
+                b'@' => '§',
+                b'[' => '¡',
+                b'\\' => 'Ñ',
+                b']' => '¿',
+                b'{' => '°',
+                b'|' => 'ñ',
+                b'}' => 'ç',
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(c)
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    // ESC ( / ESC ) ...: waiting for the final byte; `Some(g)` for G0/G1, None for G2/G3
+    Designate(Option<usize>),
+    Csi,
+    String,
+    StringEsc,
+}
+
+#[derive(Default)]
+pub(crate) struct CharsetTranslator {
+    state: State,
+    // G0 and G1
+    sets: [Charset; 2],
+    // SO selects G1, SI selects G0
+    shifted: bool,
+}
+
+impl CharsetTranslator {
+    fn active(&self) -> Charset {
+        self.sets[self.shifted as usize]
+    }
+
+    /// Returns `data` with designations and shifts removed and translated characters
+    /// re-encoded as UTF-8. Output that never switches charsets passes through unchanged.
+    pub(crate) fn translate(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for &byte in data {
+            self.advance(byte, &mut out);
+        }
+        out
+    }
+
+    fn advance(&mut self, byte: u8, out: &mut Vec<u8>) {
+        match self.state {
+            State::Ground => match byte {
+                0x1b => self.state = State::Escape,
+                0x0e => self.shifted = true,
+                0x0f => self.shifted = false,
+                0x20..=0x7e => match self.active().map(byte) {
+                    Some(c) => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    None => out.push(byte),
+                },
+                _ => out.push(byte),
+            },
+            State::Escape => match byte {
+                b'(' | b')' => self.state = State::Designate(Some((byte == b')') as usize)),
+                b'*' | b'+' | b'-' | b'.' | b'/' => self.state = State::Designate(None),
+                // ESC ESC: the first one wasn't a designation
+                0x1b => out.push(0x1b),
+                _ => {
+                    out.extend_from_slice(&[0x1b, byte]);
+                    self.state = match byte {
+                        b'[' => State::Csi,
+                        b']' | b'P' | b'_' | b'^' | b'X' => State::String,
+                        _ => State::Ground,
+                    };
+                    // RIS resets the designations too
+                    if byte == b'c' {
+                        *self = CharsetTranslator::default();
+                    }
+                }
+            },
+            State::Designate(g) => match byte {
+                // Intermediates of multi-byte set names (e.g. ESC ( % 5)
+                0x20..=0x2f => {}
+                _ => {
+                    if let Some(g) = g {
+                        self.sets[g] = Charset::from_final(byte);
+                    }
+                    self.state = State::Ground;
+                }
+            },
+            State::Csi => {
+                out.push(byte);
+                if (0x40..=0x7e).contains(&byte) {
+                    self.state = State::Ground;
+                }
+            }
+            State::String => {
+                out.push(byte);
+                match byte {
+                    0x07 => self.state = State::Ground,
+                    0x1b => self.state = State::StringEsc,
+                    _ => {}
+                }
+            }
+            State::StringEsc => {
+                out.push(byte);
+                self.state = if byte == b'\\' { State::Ground } else { State::String };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate(input: &[u8]) -> String {
+        String::from_utf8(CharsetTranslator::default().translate(input)).unwrap()
+    }
+
+    #[test]
+    fn translates_line_drawing() {
+        assert_eq!(translate(b"\x1b(0lqqk\x1b(B ok"), "┌──┐ ok");
+        // Via G1 and shift-out/shift-in
+        assert_eq!(translate(b"\x1b)0a\x0ex\x0fx"), "a│x");
+    }
+
+    #[test]
+    fn leaves_escape_sequences_alone() {
+        assert_eq!(translate(b"\x1b(0\x1b[2q\x1b]0;q\x07q"), "\x1b[2q\x1b]0;q\x07─");
+        assert_eq!(translate(b"plain \x1b[31mred\x1b[0m"), "plain \x1b[31mred\x1b[0m");
+    }
+
+    #[test]
+    fn handles_national_sets_and_split_reads() {
+        let mut translator = CharsetTranslator::default();
+        let mut out = translator.translate(b"\x1b(");
+        out.extend(translator.translate(b"K{Gr\x7e}e"));
+        assert_eq!(String::from_utf8(out).unwrap(), "äGrßüe");
+        assert_eq!(translate(b"\x1b(A#5"), "£5");
+    }
+}
@@ -0,0 +1,128 @@
+//! Window chrome for a custom titlebar: the frontend declares which parts of its tab bar
+//! drag the window, and the native titlebar and traffic lights are toggled on the NSWindow.
+
+use serde::{Deserialize, Serialize};
+use std::ffi::c_void;
+
+/// A rectangle in CSS pixels relative to the top-left of the webview.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DragRegion {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl DragRegion {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+}
+
+// Both off by default, matching the undecorated window in tauri.conf.json
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TitlebarOptions {
+    // The system titlebar with the window title; off lets content extend under it
+    pub native_titlebar: bool,
+    pub traffic_lights: bool,
+}
+
+#[derive(Default)]
+pub struct WindowChrome {
+    drag_regions: Vec<DragRegion>,
+    // Holes in the drag regions, e.g. tabs and buttons inside the bar
+    no_drag_regions: Vec<DragRegion>,
+    titlebar: TitlebarOptions,
+}
+
+impl WindowChrome {
+    pub fn set_drag_regions(&mut self, drag: Vec<DragRegion>, no_drag: Vec<DragRegion>) {
+        self.drag_regions = drag;
+        self.no_drag_regions = no_drag;
+    }
+
+    pub fn is_drag_point(&self, x: f64, y: f64) -> bool {
+        self.drag_regions.iter().any(|r| r.contains(x, y))
+            && !self.no_drag_regions.iter().any(|r| r.contains(x, y))
+    }
+
+    pub fn titlebar(&self) -> TitlebarOptions {
+        self.titlebar
+    }
+
+    pub fn set_titlebar(&mut self, options: TitlebarOptions) {
+        self.titlebar = options;
+    }
+}
+
+/// Applies `options` to an NSWindow. Must be called on the main thread.
+#[cfg(target_os = "macos")]
+pub fn apply_titlebar(ns_window: *mut c_void, options: TitlebarOptions) -> Result<(), String> {
+    use objc::runtime::{Object, NO, YES};
+    use objc::{msg_send, sel, sel_impl};
+
+    const TITLED: u64 = 1;
+    const CLOSABLE: u64 = 1 << 1;
+    const MINIATURIZABLE: u64 = 1 << 2;
+    const RESIZABLE: u64 = 1 << 3;
+    const FULL_SIZE_CONTENT_VIEW: u64 = 1 << 15;
+    // NSWindowCloseButton, NSWindowMiniaturizeButton, NSWindowZoomButton
+    const STANDARD_BUTTONS: [u64; 3] = [0, 1, 2];
+
+    let window = ns_window as *mut Object;
+    if window.is_null() {
+        return Err("Window not available".into());
+    }
+    unsafe {
+        let mut mask: u64 = msg_send![window, styleMask];
+        // Traffic lights only exist on titled windows; a hidden titlebar keeps the title
+        // bar area but draws the content underneath it
+        if options.native_titlebar || options.traffic_lights {
+            mask |= TITLED | CLOSABLE | MINIATURIZABLE | RESIZABLE;
+        } else {
+            mask &= !TITLED;
+        }
+        if options.native_titlebar {
+            mask &= !FULL_SIZE_CONTENT_VIEW;
+        } else {
+            mask |= FULL_SIZE_CONTENT_VIEW;
+        }
+        let _: () = msg_send![window, setStyleMask: mask];
+
+        let hidden_titlebar = if options.native_titlebar { NO } else { YES };
+        let _: () = msg_send![window, setTitlebarAppearsTransparent: hidden_titlebar];
+        // NSWindowTitleVisible = 0, NSWindowTitleHidden = 1
+        let _: () = msg_send![window, setTitleVisibility: !options.native_titlebar as i64];
+
+        for kind in STANDARD_BUTTONS {
+            let button: *mut Object = msg_send![window, standardWindowButton: kind];
+            if !button.is_null() {
+                let hidden = if options.traffic_lights { NO } else { YES };
+                let _: () = msg_send![button, setHidden: hidden];
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn apply_titlebar(_ns_window: *mut c_void, _options: TitlebarOptions) -> Result<(), String> {
+    Err("Titlebar customization is only supported on macOS".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_drag_regions_punch_holes() {
+        let mut chrome = WindowChrome::default();
+        chrome.set_drag_regions(
+            vec![DragRegion { x: 0.0, y: 0.0, width: 800.0, height: 40.0 }],
+            vec![DragRegion { x: 100.0, y: 0.0, width: 120.0, height: 40.0 }],
+        );
+        assert!(chrome.is_drag_point(50.0, 20.0));
+        assert!(!chrome.is_drag_point(150.0, 20.0));
+        assert!(!chrome.is_drag_point(50.0, 40.0));
+    }
+}
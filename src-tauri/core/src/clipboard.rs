@@ -0,0 +1,236 @@
+//! OSC 52 clipboard access for programs in a session (tmux, neovim, anything over ssh):
+//! `ESC ] 52 ; <selection> ; <base64>` sets the system clipboard and `... ; ?` asks for
+//! it. Both are off until the user allows them, and writes are capped in size. Blocked
+//! requests are reported with `clipboard-request-blocked` so the frontend can say why.
+
+use crate::config::{load_json, save_json};
+use crate::events::EventSink;
+use crate::pty::{PtySession, SessionManager};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardSettings {
+    // Programs may set the clipboard
+    pub allow_write: bool,
+    // Programs may read it back; anything in the session could then see what was copied
+    pub allow_read: bool,
+    // Largest text a program may set, in bytes
+    pub max_bytes: usize,
+}
+
+impl Default for ClipboardSettings {
+    fn default() -> Self {
+        ClipboardSettings { allow_write: false, allow_read: false, max_bytes: DEFAULT_MAX_BYTES }
+    }
+}
+
+// Clipboard settings persisted as JSON in the config dir
+pub struct ClipboardStore {
+    path: Option<PathBuf>,
+    pub settings: ClipboardSettings,
+}
+
+impl ClipboardStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        ClipboardStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ClipboardRequest {
+    // Still base64-encoded, so oversized ones can be turned away before decoding
+    Set(Vec<u8>),
+    Query,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ClipboardSetPayload {
+    pub session_id: String,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ClipboardBlockedPayload {
+    pub session_id: String,
+    // "write" or "read"
+    pub access: String,
+    pub reason: String,
+}
+
+/// The request in an OSC body (without `ESC ]` and the terminator), if it's OSC 52. The
+/// selection (clipboard, primary, ...) is ignored: there's only the one clipboard.
+pub(crate) fn parse_osc52(osc: &[u8]) -> Option<ClipboardRequest> {
+    let rest = osc.strip_prefix(b"52;")?;
+    let split = rest.iter().position(|&b| b == b';')?;
+    let data = &rest[split + 1..];
+    Some(if data == b"?" { ClipboardRequest::Query } else { ClipboardRequest::Set(data.to_vec()) })
+}
+
+/// Whether `request` is allowed under `settings`, and the text to set if it's a write.
+pub(crate) fn check(settings: &ClipboardSettings, request: &ClipboardRequest) -> Result<Option<String>, String> {
+    match request {
+        ClipboardRequest::Query if !settings.allow_read => Err("Reading the clipboard is not allowed".into()),
+        ClipboardRequest::Query => Ok(None),
+        ClipboardRequest::Set(_) if !settings.allow_write => Err("Setting the clipboard is not allowed".into()),
+        ClipboardRequest::Set(encoded) => {
+            let encoded: Vec<u8> = encoded.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+            if encoded.len() / 4 * 3 > settings.max_bytes {
+                return Err(format!("Clipboard text is over the {} byte limit", settings.max_bytes));
+            }
+            let text = BASE64.decode(&encoded).map_err(|_| "Clipboard text is not valid base64".to_string())?;
+            if text.len() > settings.max_bytes {
+                return Err(format!("Clipboard text is over the {} byte limit", settings.max_bytes));
+            }
+            Ok(Some(String::from_utf8_lossy(&text).into_owned()))
+        }
+    }
+}
+
+// From the reader thread: applies the request if allowed, answering queries on the session
+pub(crate) fn handle(
+    settings: &Mutex<ClipboardSettings>,
+    session_id: &str,
+    request: ClipboardRequest,
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    events: &Arc<dyn EventSink>,
+) {
+    let Ok(settings) = settings.lock().map(|s| s.clone()) else { return };
+    let access = if request == ClipboardRequest::Query { "read" } else { "write" };
+    let result = check(&settings, &request).and_then(|text| match text {
+        Some(text) => write_clipboard(&text).map(|_| {
+            events.emit("clipboard-set", ClipboardSetPayload { session_id: session_id.to_string(), bytes: text.len() });
+        }),
+        None => {
+            let text = read_clipboard()?.unwrap_or_default();
+            let reply = format!("\x1b]52;c;{}\x07", BASE64.encode(text));
+            let sessions = sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            let mut writer = session.writer.lock().map_err(|_| "Lock poisoned")?;
+            writer.write_all(reply.as_bytes()).map_err(|e| format!("Failed to answer the clipboard query: {}", e))
+        }
+    });
+    if let Err(reason) = result {
+        events.emit("clipboard-request-blocked", ClipboardBlockedPayload {
+            session_id: session_id.to_string(),
+            access: access.to_string(),
+            reason,
+        });
+    }
+}
+
+pub fn write_clipboard(text: &str) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    return mac::write(text);
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = text;
+        Err("Setting the clipboard is only supported on macOS".into())
+    }
+}
+
+pub fn read_clipboard() -> Result<Option<String>, String> {
+    #[cfg(target_os = "macos")]
+    return Ok(mac::read());
+    #[cfg(not(target_os = "macos"))]
+    Err("Reading the clipboard is only supported on macOS".into())
+}
+
+#[cfg(target_os = "macos")]
+mod mac {
+    use objc::runtime::{Object, BOOL, NO};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::{c_char, CStr, CString};
+
+    const STRING_TYPE: &CStr = c"public.utf8-plain-text";
+
+    unsafe fn ns_string(s: &CStr) -> *mut Object {
+        msg_send![class!(NSString), stringWithUTF8String: s.as_ptr()]
+    }
+
+    // Called off the main thread, so autoreleased objects need a pool of their own
+    fn with_pool<T>(f: impl FnOnce() -> T) -> T {
+        unsafe {
+            let pool: *mut Object = msg_send![class!(NSAutoreleasePool), new];
+            let result = f();
+            let _: () = msg_send![pool, drain];
+            result
+        }
+    }
+
+    pub fn write(text: &str) -> Result<(), String> {
+        let text = CString::new(text.replace('\0', "")).map_err(|e| e.to_string())?;
+        with_pool(|| unsafe {
+            let pasteboard: *mut Object = msg_send![class!(NSPasteboard), generalPasteboard];
+            let _: isize = msg_send![pasteboard, clearContents];
+            let ok: BOOL = msg_send![pasteboard, setString: ns_string(&text) forType: ns_string(STRING_TYPE)];
+            if ok != NO { Ok(()) } else { Err("Failed to set the clipboard".into()) }
+        })
+    }
+
+    pub fn read() -> Option<String> {
+        with_pool(|| unsafe {
+            let pasteboard: *mut Object = msg_send![class!(NSPasteboard), generalPasteboard];
+            let string: *mut Object = msg_send![pasteboard, stringForType: ns_string(STRING_TYPE)];
+            if string.is_null() {
+                return None;
+            }
+            let utf8: *const c_char = msg_send![string, UTF8String];
+            (!utf8.is_null()).then(|| CStr::from_ptr(utf8).to_string_lossy().into_owned())
+        })
+    }
+}
+
+impl SessionManager {
+    pub fn set_clipboard_settings(&self, settings: ClipboardSettings) -> Result<(), String> {
+        *self.clipboard.lock().map_err(|_| "Lock poisoned")? = settings;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_osc52() {
+        assert_eq!(parse_osc52(b"52;c;aGk="), Some(ClipboardRequest::Set(b"aGk=".to_vec())));
+        assert_eq!(parse_osc52(b"52;;aGk="), Some(ClipboardRequest::Set(b"aGk=".to_vec())));
+        assert_eq!(parse_osc52(b"52;c;?"), Some(ClipboardRequest::Query));
+        assert_eq!(parse_osc52(b"52;c"), None);
+        assert_eq!(parse_osc52(b"2;title"), None);
+    }
+
+    #[test]
+    fn applies_the_policy() {
+        let set = |data: &str| ClipboardRequest::Set(data.as_bytes().to_vec());
+        let mut settings = ClipboardSettings::default();
+        assert!(check(&settings, &set("aGk=")).is_err());
+        assert!(check(&settings, &ClipboardRequest::Query).is_err());
+
+        settings.allow_write = true;
+        assert_eq!(check(&settings, &set("aGk=")).unwrap().as_deref(), Some("hi"));
+        assert_eq!(check(&settings, &set("aG\nk=")).unwrap().as_deref(), Some("hi"));
+        assert!(check(&settings, &set("not base64!")).is_err());
+        assert!(check(&settings, &ClipboardRequest::Query).is_err());
+
+        settings.max_bytes = 4;
+        assert!(check(&settings, &set(&BASE64.encode("too long"))).unwrap_err().contains("limit"));
+        settings.allow_read = true;
+        assert_eq!(check(&settings, &ClipboardRequest::Query), Ok(None));
+    }
+}
@@ -0,0 +1,579 @@
+//! Shell command-line parsing for completion, dangerous-command warnings and syntax
+//! highlighting. A lexer rather than a full grammar: it understands the quoting, operators,
+//! redirects and substitutions of zsh, bash and fish well enough to split a line into
+//! simple commands and tell what the word under the cursor is, and it never fails (an
+//! unterminated quote just marks the line incomplete).
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShellDialect {
+    #[default]
+    Zsh,
+    Bash,
+    Fish,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    Command,
+    Argument,
+    // NAME=value before the command
+    Assignment,
+    // | |& || && ; & ( ) and newlines
+    Operator,
+    Redirect,
+    // The file (or fd) a redirect points at
+    RedirectTarget,
+    Comment,
+}
+
+/// Offsets are byte offsets into the line; `value` has quotes and escapes removed
+/// (substitutions are left as written).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub value: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Redirect {
+    pub fd: Option<u32>,
+    pub op: String,
+    pub target: Option<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SimpleCommand {
+    // The program that actually runs, after sudo/env/time and friends
+    pub name: Option<String>,
+    pub args: Vec<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WordRole {
+    Command,
+    Argument,
+    Assignment,
+    RedirectTarget,
+    Variable,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CursorWord {
+    // Empty when the cursor is between words
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub role: WordRole,
+    // The simple command the word belongs to, if it has a name yet
+    pub command: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ParsedCommandLine {
+    pub tokens: Vec<Token>,
+    pub redirects: Vec<Redirect>,
+    pub commands: Vec<SimpleCommand>,
+    pub word_under_cursor: Option<CursorWord>,
+    // Ends inside a quote or substitution, or after an operator expecting more
+    pub incomplete: bool,
+    // Human-readable reasons the line looks destructive
+    pub warnings: Vec<String>,
+}
+
+// Run their argument as the real command
+const PRECOMMANDS: &[&str] = &["sudo", "doas", "env", "time", "nohup", "exec", "command", "builtin", "nice", "caffeinate"];
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+struct Lexer<'a> {
+    line: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    dialect: ShellDialect,
+    incomplete: bool,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.bytes.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.line[self.pos..].starts_with(s)
+    }
+
+    // Consumes a balanced (...) starting at `pos` (just past the opening paren)
+    fn skip_parens(&mut self) {
+        let mut depth = 1;
+        while let Some(b) = self.peek(0) {
+            match b {
+                b'\\' => self.pos += 1,
+                b'\'' => {
+                    self.pos += 1;
+                    while self.peek(0).is_some_and(|b| b != b'\'') {
+                        self.pos += 1;
+                    }
+                }
+                b'"' => {
+                    self.pos += 1;
+                    let mut value = String::new();
+                    self.double_quoted(&mut value);
+                    continue;
+                }
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        self.incomplete = true;
+    }
+
+    // After the opening quote; leaves `pos` past the closing one
+    fn double_quoted(&mut self, value: &mut String) {
+        while let Some(b) = self.peek(0) {
+            match b {
+                b'"' => {
+                    self.pos += 1;
+                    return;
+                }
+                b'\\' if matches!(self.peek(1), Some(b'"' | b'\\' | b'$' | b'`' | b'\n')) => {
+                    value.push(self.peek(1).unwrap_or(b'\\') as char);
+                    self.pos += 2;
+                    continue;
+                }
+                b'$' if self.peek(1) == Some(b'(') && self.dialect != ShellDialect::Fish => {
+                    let from = self.pos;
+                    self.pos += 2;
+                    self.skip_parens();
+                    value.push_str(&self.line[from..self.pos]);
+                    continue;
+                }
+                _ => {}
+            }
+            let c = self.line[self.pos..].chars().next().unwrap_or(' ');
+            value.push(c);
+            self.pos += c.len_utf8();
+        }
+        self.incomplete = true;
+    }
+
+    // A word, from `pos`; returns its unquoted value
+    fn word(&mut self) -> String {
+        let mut value = String::new();
+        while let Some(b) = self.peek(0) {
+            match b {
+                b' ' | b'\t' | b'\n' | b';' | b'&' | b'|' | b'<' | b'>' => break,
+                b'(' | b')' if self.dialect != ShellDialect::Fish => break,
+                b'\\' => {
+                    if let Some(c) = self.line[self.pos + 1..].chars().next() {
+                        value.push(c);
+                        self.pos += 1 + c.len_utf8();
+                    } else {
+                        self.pos += 1;
+                        self.incomplete = true;
+                    }
+                }
+                b'\'' => {
+                    self.pos += 1;
+                    self.single_quoted(&mut value, false);
+                }
+                b'$' if self.peek(1) == Some(b'\'') && self.dialect != ShellDialect::Fish => {
+                    self.pos += 2;
+                    self.single_quoted(&mut value, true);
+                }
+                b'"' => {
+                    self.pos += 1;
+                    self.double_quoted(&mut value);
+                }
+                b'$' if self.peek(1) == Some(b'(') && self.dialect != ShellDialect::Fish => {
+                    let from = self.pos;
+                    self.pos += 2;
+                    self.skip_parens();
+                    value.push_str(&self.line[from..self.pos]);
+                }
+                // fish command substitution
+                b'(' => {
+                    let from = self.pos;
+                    self.pos += 1;
+                    self.skip_parens();
+                    value.push_str(&self.line[from..self.pos]);
+                }
+                b')' => break,
+                b'`' => {
+                    let from = self.pos;
+                    self.pos += 1;
+                    while self.peek(0).is_some_and(|b| b != b'`') {
+                        self.pos += 1;
+                    }
+                    if self.peek(0).is_none() {
+                        self.incomplete = true;
+                    } else {
+                        self.pos += 1;
+                    }
+                    value.push_str(&self.line[from..self.pos]);
+                }
+                _ => {
+                    let c = self.line[self.pos..].chars().next().unwrap_or(' ');
+                    value.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+        value
+    }
+
+    // After the opening quote. fish and $'...' allow \' and \\ inside
+    fn single_quoted(&mut self, value: &mut String, ansi_c: bool) {
+        let escapes = ansi_c || self.dialect == ShellDialect::Fish;
+        while let Some(b) = self.peek(0) {
+            match b {
+                b'\'' => {
+                    self.pos += 1;
+                    return;
+                }
+                b'\\' if escapes && self.peek(1).is_some() => {
+                    let escaped = self.peek(1).unwrap_or(b'\\');
+                    value.push(match (ansi_c, escaped) {
+                        (true, b'n') => '\n',
+                        (true, b't') => '\t',
+                        (true, b'e') => '\x1b',
+                        (_, other) => other as char,
+                    });
+                    self.pos += 2;
+                }
+                _ => {
+                    let c = self.line[self.pos..].chars().next().unwrap_or(' ');
+                    value.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+        self.incomplete = true;
+    }
+
+    // Redirect operator at `pos` (after any fd digits), longest match first
+    fn redirect_op(&self) -> Option<&'static str> {
+        const OPS: &[&str] = &["&>>", "<<<", "<<-", ">>", ">|", ">&", "<&", "<<", "<>", "&>", ">", "<"];
+        OPS.iter().copied().find(|op| self.starts_with(op))
+    }
+
+    fn operator(&self) -> Option<&'static str> {
+        const OPS: &[&str] = &["||", "&&", "|&", ";;", "|", ";", "&", "\n", "(", ")"];
+        OPS.iter().copied().find(|op| self.starts_with(op))
+    }
+}
+
+fn danger_warnings(command: &SimpleCommand, piped_from_download: bool) -> Vec<String> {
+    let Some(name) = command.name.as_deref() else {
+        return Vec::new();
+    };
+    let name = name.rsplit('/').next().unwrap_or(name);
+    let args: Vec<&str> = command.args.iter().map(String::as_str).collect();
+    let flags: String = args.iter()
+        .filter(|a| a.starts_with('-') && !a.starts_with("--"))
+        .flat_map(|a| a.chars().skip(1))
+        .collect();
+    let long = |flag: &str| args.contains(&flag);
+    let system_path = |a: &&str| matches!(*a, "/" | "/*" | "~" | "~/" | "$HOME" | "." | ".." | "*") || a.starts_with("/dev/");
+    let mut warnings = Vec::new();
+    match name {
+        "rm" if (flags.contains('r') || flags.contains('R') || long("--recursive")) && args.iter().any(system_path) => {
+            warnings.push("Recursively deletes a top-level or home directory".to_string());
+        }
+        "rm" if flags.contains('f') && (flags.contains('r') || flags.contains('R')) => {
+            warnings.push("Force-deletes recursively without confirmation".to_string());
+        }
+        "dd" if args.iter().any(|a| a.starts_with("of=/dev/")) => {
+            warnings.push("Writes directly to a device".to_string());
+        }
+        "chmod" | "chown" if (flags.contains('R') || long("--recursive")) && args.iter().any(system_path) => {
+            warnings.push("Recursively changes ownership or permissions of a system directory".to_string());
+        }
+        "git" if args.first() == Some(&"push") && (flags.contains('f') || long("--force")) => {
+            warnings.push("Force-pushes, possibly overwriting remote history".to_string());
+        }
+        "git" if args.first() == Some(&"reset") && long("--hard") => {
+            warnings.push("Discards uncommitted changes".to_string());
+        }
+        "git" if args.first() == Some(&"clean") && flags.contains('f') => {
+            warnings.push("Deletes untracked files".to_string());
+        }
+        "sh" | "bash" | "zsh" | "fish" | "python" | "python3" | "perl" | "ruby" if piped_from_download => {
+            warnings.push("Runs a script downloaded from the network".to_string());
+        }
+        _ if name.starts_with("mkfs") => warnings.push("Formats a filesystem".to_string()),
+        _ => {}
+    }
+    warnings
+}
+
+pub fn parse_command_line(line: &str, cursor: Option<usize>, dialect: ShellDialect) -> ParsedCommandLine {
+    let mut lexer = Lexer { line, bytes: line.as_bytes(), pos: 0, dialect, incomplete: false };
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut redirects: Vec<Redirect> = Vec::new();
+    let mut commands: Vec<SimpleCommand> = Vec::new();
+    let mut current: Option<SimpleCommand> = None;
+    let mut warnings = Vec::new();
+    // The previous command ended in `|` and downloaded something
+    let mut piped_from_download = false;
+    let mut pending_redirect: Option<usize> = None;
+    let mut precommand = false;
+
+    let finish = |current: &mut Option<SimpleCommand>, commands: &mut Vec<SimpleCommand>| {
+        if let Some(command) = current.take() {
+            commands.push(command);
+        }
+    };
+
+    while lexer.pos < line.len() {
+        let b = lexer.bytes[lexer.pos];
+        if b == b' ' || b == b'\t' {
+            lexer.pos += 1;
+            continue;
+        }
+        let start = lexer.pos;
+
+        // Comments start a word; zsh only allows them with INTERACTIVE_COMMENTS
+        if b == b'#' && dialect != ShellDialect::Zsh {
+            let end = line[start..].find('\n').map(|i| start + i).unwrap_or(line.len());
+            tokens.push(Token { kind: TokenKind::Comment, text: line[start..end].to_string(), value: String::new(), start, end });
+            lexer.pos = end;
+            continue;
+        }
+
+        // [n]>file and friends
+        let digits = line[start..].bytes().take_while(u8::is_ascii_digit).count();
+        lexer.pos = start + digits;
+        if let Some(op) = lexer.redirect_op() {
+            lexer.pos += op.len();
+            let fd = line[start..start + digits].parse().ok();
+            // >&2, <&-: the target is part of the operator
+            let mut target = None;
+            if op.ends_with('&') && op != "&>" {
+                let t = line[lexer.pos..].bytes().take_while(|b| b.is_ascii_digit() || *b == b'-').count();
+                if t > 0 {
+                    target = Some(line[lexer.pos..lexer.pos + t].to_string());
+                    lexer.pos += t;
+                }
+            }
+            tokens.push(Token { kind: TokenKind::Redirect, text: line[start..lexer.pos].to_string(), value: op.to_string(), start, end: lexer.pos });
+            let has_target = target.is_some();
+            redirects.push(Redirect { fd, op: op.to_string(), target, start, end: lexer.pos });
+            if !has_target {
+                pending_redirect = Some(redirects.len() - 1);
+            }
+            continue;
+        }
+        lexer.pos = start;
+
+        if let Some(op) = lexer.operator() {
+            // In fish, parens are command substitution inside a word
+            if !(dialect == ShellDialect::Fish && (op == "(" || op == ")")) {
+                lexer.pos += op.len();
+                tokens.push(Token { kind: TokenKind::Operator, text: op.to_string(), value: op.to_string(), start, end: lexer.pos });
+                let downloaded = current.as_ref()
+                    .and_then(|c| c.name.as_deref())
+                    .is_some_and(|n| matches!(n, "curl" | "wget"));
+                if let Some(command) = &current {
+                    warnings.extend(danger_warnings(command, piped_from_download));
+                }
+                piped_from_download = matches!(op, "|" | "|&") && downloaded;
+                finish(&mut current, &mut commands);
+                precommand = false;
+                continue;
+            }
+        }
+
+        let value = lexer.word();
+        if lexer.pos == start {
+            // Nothing consumed (e.g. a stray ')' in fish); skip it
+            lexer.pos += 1;
+            continue;
+        }
+        let text = line[start..lexer.pos].to_string();
+        let kind = if let Some(index) = pending_redirect.take() {
+            redirects[index].target = Some(value.clone());
+            redirects[index].end = lexer.pos;
+            TokenKind::RedirectTarget
+        } else {
+            let command = current.get_or_insert_with(|| SimpleCommand { name: None, args: Vec::new(), start, end: start });
+            command.end = lexer.pos;
+            if command.name.is_none() && !precommand && is_assignment(&text) {
+                TokenKind::Assignment
+            } else if command.name.is_none() {
+                precommand = PRECOMMANDS.contains(&value.as_str());
+                command.name = Some(value.clone());
+                TokenKind::Command
+            } else if precommand && !value.starts_with('-') && !is_assignment(&value) {
+                // sudo -u root rm ...: the real command replaces the precommand
+                precommand = PRECOMMANDS.contains(&value.as_str());
+                command.name = Some(value.clone());
+                command.args.clear();
+                TokenKind::Command
+            } else {
+                command.args.push(value.clone());
+                TokenKind::Argument
+            }
+        };
+        tokens.push(Token { kind, text, value, start, end: lexer.pos });
+    }
+    if let Some(command) = &current {
+        warnings.extend(danger_warnings(command, piped_from_download));
+    }
+    finish(&mut current, &mut commands);
+
+    let trailing_operator = tokens.last().is_some_and(|t| {
+        (t.kind == TokenKind::Operator && matches!(t.text.as_str(), "|" | "||" | "&&" | "|&"))
+            || t.kind == TokenKind::Redirect && pending_redirect.is_some()
+    });
+    let word_under_cursor = cursor.map(|c| word_at(line, &tokens, &commands, c.min(line.len())));
+
+    ParsedCommandLine {
+        tokens,
+        redirects,
+        commands,
+        word_under_cursor,
+        incomplete: lexer.incomplete || trailing_operator,
+        warnings,
+    }
+}
+
+fn word_at(line: &str, tokens: &[Token], commands: &[SimpleCommand], cursor: usize) -> CursorWord {
+    let command_at = |pos: usize| {
+        commands.iter()
+            .find(|c| c.start <= pos && pos <= c.end)
+            .and_then(|c| c.name.clone())
+    };
+    if let Some(token) = tokens.iter().find(|t| t.start <= cursor && cursor <= t.end && t.kind != TokenKind::Operator) {
+        let role = match token.kind {
+            TokenKind::Command => WordRole::Command,
+            TokenKind::Assignment => WordRole::Assignment,
+            TokenKind::RedirectTarget => WordRole::RedirectTarget,
+            _ if line[token.start..cursor].rfind('$').is_some_and(|i| {
+                line[token.start + i + 1..cursor].chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '{')
+            }) => WordRole::Variable,
+            _ => WordRole::Argument,
+        };
+        return CursorWord {
+            text: token.text.clone(),
+            start: token.start,
+            end: token.end,
+            role,
+            command: if role == WordRole::Command { None } else { command_at(token.start) },
+        };
+    }
+    // Between words: what would be typed here
+    let previous = tokens.iter().rev().find(|t| t.end <= cursor);
+    let role = match previous.map(|t| t.kind) {
+        None | Some(TokenKind::Operator | TokenKind::Assignment) => WordRole::Command,
+        Some(TokenKind::Redirect) if !previous.is_some_and(|t| t.value.ends_with('&') && t.text.len() > t.value.len()) => {
+            WordRole::RedirectTarget
+        }
+        _ => WordRole::Argument,
+    };
+    CursorWord {
+        text: String::new(),
+        start: cursor,
+        end: cursor,
+        role,
+        command: if role == WordRole::Command { None } else { previous.and_then(|t| command_at(t.start)) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(parsed: &ParsedCommandLine) -> Vec<(TokenKind, &str)> {
+        parsed.tokens.iter().map(|t| (t.kind, t.value.as_str())).collect()
+    }
+
+    #[test]
+    fn splits_commands_quotes_and_redirects() {
+        let parsed = parse_command_line(r#"FOO=1 grep -n "a b" 'c' src\ dir 2>&1 | tee out.log >> all.log"#, None, ShellDialect::Zsh);
+        assert_eq!(kinds(&parsed), vec![
+            (TokenKind::Assignment, "FOO=1"),
+            (TokenKind::Command, "grep"),
+            (TokenKind::Argument, "-n"),
+            (TokenKind::Argument, "a b"),
+            (TokenKind::Argument, "c"),
+            (TokenKind::Argument, "src dir"),
+            (TokenKind::Redirect, ">&"),
+            (TokenKind::Operator, "|"),
+            (TokenKind::Command, "tee"),
+            (TokenKind::Argument, "out.log"),
+            (TokenKind::Redirect, ">>"),
+            (TokenKind::RedirectTarget, "all.log"),
+        ]);
+        assert_eq!(parsed.redirects[0], Redirect { fd: Some(2), op: ">&".into(), target: Some("1".into()), start: 33, end: 37 });
+        assert_eq!(parsed.redirects[1].target.as_deref(), Some("all.log"));
+        assert_eq!(parsed.commands.len(), 2);
+        assert!(!parsed.incomplete);
+    }
+
+    #[test]
+    fn keeps_substitutions_in_one_word() {
+        let parsed = parse_command_line("echo $(date +%s; echo \")\") done", None, ShellDialect::Bash);
+        assert_eq!(parsed.commands[0].args, vec!["$(date +%s; echo \")\")", "done"]);
+        let fish = parse_command_line("echo (date) # note", None, ShellDialect::Fish);
+        assert_eq!(kinds(&fish), vec![
+            (TokenKind::Command, "echo"),
+            (TokenKind::Argument, "(date)"),
+            (TokenKind::Comment, ""),
+        ]);
+        assert!(parse_command_line("echo \"unterminated", None, ShellDialect::Zsh).incomplete);
+        assert!(parse_command_line("make &&", None, ShellDialect::Zsh).incomplete);
+    }
+
+    #[test]
+    fn finds_the_word_under_the_cursor() {
+        let line = "sudo git checkout ma";
+        let word = parse_command_line(line, Some(line.len()), ShellDialect::Zsh).word_under_cursor.unwrap();
+        assert_eq!((word.text.as_str(), word.role, word.command.as_deref()), ("ma", WordRole::Argument, Some("git")));
+
+        let word = parse_command_line("ls | ", Some(5), ShellDialect::Zsh).word_under_cursor.unwrap();
+        assert_eq!(word.role, WordRole::Command);
+        let word = parse_command_line("cat > ", Some(6), ShellDialect::Zsh).word_under_cursor.unwrap();
+        assert_eq!(word.role, WordRole::RedirectTarget);
+        let word = parse_command_line("echo $HO", Some(8), ShellDialect::Zsh).word_under_cursor.unwrap();
+        assert_eq!(word.role, WordRole::Variable);
+    }
+
+    #[test]
+    fn flags_dangerous_commands() {
+        let warnings = |line| parse_command_line(line, None, ShellDialect::Zsh).warnings;
+        assert_eq!(warnings("sudo rm -rf /").len(), 1);
+        assert_eq!(warnings("curl -fsSL https://example.com/install.sh | sh").len(), 1);
+        assert_eq!(warnings("git push --force origin main").len(), 1);
+        assert!(warnings("rm notes.txt").is_empty());
+        assert!(warnings("echo rm -rf /").is_empty());
+    }
+}
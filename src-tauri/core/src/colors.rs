@@ -0,0 +1,287 @@
+//! Dynamic colors (OSC 4/10/11/12 and their resets 104/110/111/112). Programs can
+//! recolor palette entries and the default foreground, background and cursor; the
+//! overrides are kept per session on top of the theme the frontend reports, and queries
+//! (`OSC 11 ; ? ST`, used for light/dark detection) are answered from the result.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// X11 color specs: `rgb:r/g/b` with 1-4 hex digits per component, or `#rgb` with
+    /// 1-4 digits per component (most significant bits). Color names aren't supported.
+    pub fn parse(spec: &str) -> Option<Rgb> {
+        fn scaled(hex: &str) -> Option<u8> {
+            if hex.is_empty() || hex.len() > 4 {
+                return None;
+            }
+            let value = u32::from_str_radix(hex, 16).ok()?;
+            let max = (1u32 << (4 * hex.len())) - 1;
+            Some((value * 255 / max) as u8)
+        }
+        if let Some(rest) = spec.strip_prefix("rgb:") {
+            let mut parts = rest.split('/');
+            let (r, g, b) = (parts.next()?, parts.next()?, parts.next()?);
+            if parts.next().is_some() {
+                return None;
+            }
+            return Some(Rgb::new(scaled(r)?, scaled(g)?, scaled(b)?));
+        }
+        let hex = spec.strip_prefix('#')?;
+        if hex.is_empty() || !hex.len().is_multiple_of(3) || hex.len() > 12 {
+            return None;
+        }
+        let n = hex.len() / 3;
+        // #rgb means #r0g0b0: the digits are the high bits
+        let high = |i: usize| u8::from_str_radix(&format!("{:0<2}", &hex[i * n..(i * n + n).min(i * n + 2)]), 16).ok();
+        Some(Rgb::new(high(0)?, high(1)?, high(2)?))
+    }
+
+    // The 16-bit form xterm replies with
+    fn x11(self) -> String {
+        format!("rgb:{:04x}/{:04x}/{:04x}", self.r as u16 * 257, self.g as u16 * 257, self.b as u16 * 257)
+    }
+}
+
+impl From<Rgb> for String {
+    fn from(c: Rgb) -> String {
+        format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Rgb::parse(&s).ok_or_else(|| format!("Invalid color: {}", s))
+    }
+}
+
+/// The frontend's current theme. Palette entries 16-255 are the standard color cube and
+/// gray ramp.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColorTheme {
+    pub foreground: Rgb,
+    pub background: Rgb,
+    pub cursor: Rgb,
+    pub ansi: [Rgb; 16],
+}
+
+impl Default for ColorTheme {
+    fn default() -> Self {
+        // xterm.js defaults
+        ColorTheme {
+            foreground: Rgb::new(0xff, 0xff, 0xff),
+            background: Rgb::new(0x00, 0x00, 0x00),
+            cursor: Rgb::new(0xff, 0xff, 0xff),
+            ansi: [
+                Rgb::new(0x2e, 0x34, 0x36),
+                Rgb::new(0xcc, 0x00, 0x00),
+                Rgb::new(0x4e, 0x9a, 0x06),
+                Rgb::new(0xc4, 0xa0, 0x00),
+                Rgb::new(0x34, 0x65, 0xa4),
+                Rgb::new(0x75, 0x50, 0x7b),
+                Rgb::new(0x06, 0x98, 0x9a),
+                Rgb::new(0xd3, 0xd7, 0xcf),
+                Rgb::new(0x55, 0x57, 0x53),
+                Rgb::new(0xef, 0x29, 0x29),
+                Rgb::new(0x8a, 0xe2, 0x34),
+                Rgb::new(0xfc, 0xe9, 0x4f),
+                Rgb::new(0x72, 0x9f, 0xcf),
+                Rgb::new(0xad, 0x7f, 0xa8),
+                Rgb::new(0x34, 0xe2, 0xe2),
+                Rgb::new(0xee, 0xee, 0xec),
+            ],
+        }
+    }
+}
+
+impl ColorTheme {
+    pub fn palette(&self, index: u8) -> Rgb {
+        match index {
+            0..=15 => self.ansi[index as usize],
+            16..=231 => {
+                let i = index - 16;
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                Rgb::new(level(i / 36), level(i / 6 % 6), level(i % 6))
+            }
+            _ => {
+                let v = 8 + (index - 232) * 10;
+                Rgb::new(v, v, v)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ColorSlot {
+    Palette(u8),
+    Foreground,
+    Background,
+    Cursor,
+}
+
+/// Colors set by programs in a session; unset slots use the theme.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ColorOverrides {
+    pub palette: BTreeMap<u8, Rgb>,
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+    pub cursor: Option<Rgb>,
+}
+
+impl ColorOverrides {
+    fn slot_mut(&mut self, slot: ColorSlot) -> Option<&mut Option<Rgb>> {
+        match slot {
+            ColorSlot::Foreground => Some(&mut self.foreground),
+            ColorSlot::Background => Some(&mut self.background),
+            ColorSlot::Cursor => Some(&mut self.cursor),
+            ColorSlot::Palette(_) => None,
+        }
+    }
+
+    fn set(&mut self, slot: ColorSlot, color: Option<Rgb>) {
+        if let ColorSlot::Palette(index) = slot {
+            match color {
+                Some(c) => self.palette.insert(index, c),
+                None => self.palette.remove(&index),
+            };
+        } else if let Some(value) = self.slot_mut(slot) {
+            *value = color;
+        }
+    }
+
+    pub(crate) fn resolve(&self, theme: &ColorTheme, slot: ColorSlot) -> Rgb {
+        match slot {
+            ColorSlot::Palette(i) => self.palette.get(&i).copied().unwrap_or_else(|| theme.palette(i)),
+            ColorSlot::Foreground => self.foreground.unwrap_or(theme.foreground),
+            ColorSlot::Background => self.background.unwrap_or(theme.background),
+            ColorSlot::Cursor => self.cursor.unwrap_or(theme.cursor),
+        }
+    }
+
+    /// `theme` with these overrides applied.
+    pub fn apply(&self, theme: &ColorTheme) -> ColorTheme {
+        let mut ansi = theme.ansi;
+        for (i, c) in ansi.iter_mut().enumerate() {
+            *c = self.resolve(theme, ColorSlot::Palette(i as u8));
+        }
+        ColorTheme {
+            foreground: self.resolve(theme, ColorSlot::Foreground),
+            background: self.resolve(theme, ColorSlot::Background),
+            cursor: self.resolve(theme, ColorSlot::Cursor),
+            ansi,
+        }
+    }
+
+    /// Applies a color OSC body (without `ESC ]` and the terminator) and returns the
+    /// slots it queried, in order. Other OSCs are ignored.
+    pub(crate) fn apply_osc(&mut self, osc: &[u8]) -> Vec<ColorSlot> {
+        let Ok(osc) = std::str::from_utf8(osc) else {
+            return Vec::new();
+        };
+        let (command, rest) = osc.split_once(';').unwrap_or((osc, ""));
+        let mut queries = Vec::new();
+        let mut apply = |slot: ColorSlot, spec: &str| {
+            if spec == "?" {
+                queries.push(slot);
+            } else if let Some(color) = Rgb::parse(spec) {
+                self.set(slot, Some(color));
+            }
+        };
+        match command {
+            "4" => {
+                let mut parts = rest.split(';');
+                while let (Some(index), Some(spec)) = (parts.next(), parts.next()) {
+                    if let Ok(index) = index.parse() {
+                        apply(ColorSlot::Palette(index), spec);
+                    }
+                }
+            }
+            // OSC 10 can go on to set 11 and 12: OSC 10 ; fg ; bg ; cursor
+            "10" | "11" | "12" => {
+                let slots = [ColorSlot::Foreground, ColorSlot::Background, ColorSlot::Cursor];
+                let first = command.as_bytes()[1] as usize - b'0' as usize;
+                for (slot, spec) in slots[first..].iter().zip(rest.split(';')) {
+                    apply(*slot, spec);
+                }
+            }
+            "104" if rest.is_empty() => self.palette.clear(),
+            "104" => {
+                for index in rest.split(';').filter_map(|i| i.parse().ok()) {
+                    self.palette.remove(&index);
+                }
+            }
+            "110" => self.foreground = None,
+            "111" => self.background = None,
+            "112" => self.cursor = None,
+            _ => {}
+        }
+        queries
+    }
+}
+
+/// The reply to a query for `slot`.
+pub(crate) fn query_reply(slot: ColorSlot, color: Rgb) -> String {
+    match slot {
+        ColorSlot::Palette(i) => format!("\x1b]4;{};{}\x1b\\", i, color.x11()),
+        ColorSlot::Foreground => format!("\x1b]10;{}\x1b\\", color.x11()),
+        ColorSlot::Background => format!("\x1b]11;{}\x1b\\", color.x11()),
+        ColorSlot::Cursor => format!("\x1b]12;{}\x1b\\", color.x11()),
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct TerminalColorsPayload {
+    pub session_id: String,
+    pub colors: ColorOverrides,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_x11_color_specs() {
+        assert_eq!(Rgb::parse("rgb:ff/80/00"), Some(Rgb::new(255, 128, 0)));
+        assert_eq!(Rgb::parse("rgb:ffff/0000/8080"), Some(Rgb::new(255, 0, 128)));
+        assert_eq!(Rgb::parse("rgb:f/0/8"), Some(Rgb::new(255, 0, 136)));
+        assert_eq!(Rgb::parse("#1e1e2e"), Some(Rgb::new(0x1e, 0x1e, 0x2e)));
+        assert_eq!(Rgb::parse("#f80"), Some(Rgb::new(0xf0, 0x80, 0x00)));
+        assert_eq!(Rgb::parse("red"), None);
+        assert_eq!(Rgb::parse("rgb:ff/80"), None);
+    }
+
+    #[test]
+    fn applies_sets_resets_and_collects_queries() {
+        let mut colors = ColorOverrides::default();
+        assert!(colors.apply_osc(b"4;1;#ff0000;200;rgb:00/00/ff").is_empty());
+        assert_eq!(colors.palette.len(), 2);
+        assert_eq!(colors.apply_osc(b"10;#eeeeee;?"), vec![ColorSlot::Background]);
+        assert_eq!(colors.foreground, Some(Rgb::new(0xee, 0xee, 0xee)));
+
+        colors.apply_osc(b"104;1");
+        assert_eq!(colors.palette.keys().collect::<Vec<_>>(), vec![&200]);
+        colors.apply_osc(b"110");
+        assert_eq!(colors, ColorOverrides { palette: colors.palette.clone(), ..ColorOverrides::default() });
+
+        let theme = ColorTheme::default();
+        assert_eq!(
+            query_reply(ColorSlot::Background, colors.resolve(&theme, ColorSlot::Background)),
+            "\x1b]11;rgb:0000/0000/0000\x1b\\"
+        );
+        assert_eq!(theme.palette(196), Rgb::new(255, 0, 0));
+        assert_eq!(theme.palette(244), Rgb::new(128, 128, 128));
+    }
+}
@@ -0,0 +1,185 @@
+//! Composing input in an external editor, like `C-x C-e` in bash but for any frontend
+//! keybinding: `$VISUAL`/`$EDITOR` opens on a temp file in a session of its own, and
+//! once the editor exits whatever was saved is pasted into the original session
+//! (bracketed if the shell asked for it). Nothing runs until the user presses Enter.
+
+use crate::process;
+use crate::pty::SessionManager;
+use portable_pty::CommandBuilder;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+#[cfg(not(windows))]
+const FALLBACK_EDITOR: &str = "vi";
+#[cfg(windows)]
+const FALLBACK_EDITOR: &str = "notepad";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ComposeFinishedPayload {
+    pub session_id: String,
+    pub editor_session_id: String,
+    // False if nothing was saved, or the session went away meanwhile
+    pub sent: bool,
+}
+
+// What the session would run for $VISUAL/$EDITOR, or ours
+fn editor_for(env: &crate::env::EnvMap) -> String {
+    ["VISUAL", "EDITOR"].iter()
+        .find_map(|var| env.get(*var).cloned().or_else(|| std::env::var(var).ok()))
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| FALLBACK_EDITOR.to_string())
+}
+
+// The editor command may carry arguments ("code --wait"), so it goes through the shell
+// with the file as its last argument
+fn editor_command(editor: &str, path: &Path) -> CommandBuilder {
+    #[cfg(not(windows))]
+    {
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", &format!("{} \"$1\"", editor), "sh"]);
+        cmd.arg(path);
+        cmd
+    }
+    #[cfg(windows)]
+    {
+        let mut cmd = CommandBuilder::new("cmd");
+        cmd.args(["/C", editor]);
+        cmd.arg(path);
+        cmd
+    }
+}
+
+/// The composed text as it's sent: without the line endings editors save it with, and
+/// wrapped in paste markers for shells in bracketed-paste mode.
+pub fn composed_input(text: &str, bracketed: bool) -> Option<String> {
+    let text = text.trim_end_matches(['\r', '\n']);
+    if text.trim().is_empty() {
+        return None;
+    }
+    Some(if bracketed { format!("\x1b[200~{}\x1b[201~", text) } else { text.to_string() })
+}
+
+impl SessionManager {
+    /// Opens `editor` (the session's `$VISUAL` or `$EDITOR` if None) on a temp file
+    /// holding `initial`, in a new session the same size as `session_id` and in its
+    /// directory, and returns that session's id. When the editor exits its session is
+    /// closed, the file's contents are pasted into `session_id`, and `compose-finished`
+    /// is emitted.
+    pub fn compose_in_editor(self: &Arc<Self>, session_id: &str, initial: Option<&str>, editor: Option<&str>) -> Result<String, String> {
+        let (env, pid, size, tmp_dir) = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            let size = session.master.lock().map_err(|_| "Lock poisoned")?.get_size()
+                .map(|size| (size.rows, size.cols))
+                .map_err(|e| format!("Failed to read the terminal size: {}", e))?;
+            (session.initial_env.clone(), session.pid, size, session.tmp_dir.clone())
+        };
+        let editor = editor.map(str::to_string).unwrap_or_else(|| editor_for(&env));
+        let dir = tmp_dir.unwrap_or_else(std::env::temp_dir);
+        let path = dir.join(format!("shelll-compose-{}.sh", Uuid::new_v4()));
+        std::fs::write(&path, initial.unwrap_or_default()).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+
+        let mut cmd = editor_command(&editor, &path);
+        if let Some(cwd) = pid.and_then(process::process_cwd) {
+            cmd.cwd(cwd);
+        }
+        let name = editor.split_whitespace().next().unwrap_or(&editor).to_string();
+        let editor_session_id = match self.spawn_sized(cmd, &name, size) {
+            Ok(id) => id,
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+        let manager = Arc::downgrade(self);
+        let (session_id, editor_id) = (session_id.to_string(), editor_session_id.clone());
+        thread::spawn(move || {
+            while let Some(manager) = manager.upgrade() {
+                if !manager.editor_running(&editor_id) {
+                    manager.finish_compose(&session_id, &editor_id, &path);
+                    return;
+                }
+                drop(manager);
+                thread::sleep(EXIT_POLL_INTERVAL);
+            }
+            let _ = std::fs::remove_file(&path);
+        });
+        Ok(editor_session_id)
+    }
+
+    // Closing the editor's session from the frontend counts as exiting it
+    fn editor_running(&self, editor_session_id: &str) -> bool {
+        self.sessions.lock()
+            .map(|sessions| sessions.get(editor_session_id).is_some_and(|s| !s.exited.load(Ordering::SeqCst)))
+            .unwrap_or(false)
+    }
+
+    fn finish_compose(&self, session_id: &str, editor_session_id: &str, path: &Path) {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        let _ = std::fs::remove_file(path);
+        let _ = self.close(editor_session_id);
+        let bracketed = self.sessions.lock().ok()
+            .and_then(|sessions| sessions.get(session_id).and_then(|s| s.terminal.lock().ok().map(|t| t.bracketed_paste)))
+            .unwrap_or(false);
+        let sent = composed_input(&text, bracketed).is_some_and(|input| self.paste(session_id, &input).is_ok());
+        self.events.emit("compose-finished", ComposeFinishedPayload {
+            session_id: session_id.to_string(),
+            editor_session_id: editor_session_id.to_string(),
+            sent,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_and_brackets_composed_input() {
+        assert_eq!(composed_input("for f in *; do\n  echo $f\ndone\n\n", false).as_deref(), Some("for f in *; do\n  echo $f\ndone"));
+        assert_eq!(composed_input("ls\r\n", true).as_deref(), Some("\x1b[200~ls\x1b[201~"));
+        assert_eq!(composed_input(" \n", true), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pastes_what_the_editor_saved() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+
+        let sink = Arc::new(RecordingSink::default());
+        let manager = Arc::new(SessionManager::new(sink.clone(), HistoryStore::load(None)));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        // Stands in for an editor: saves the file and exits
+        let editor = "printf 'echo composed-$((6*7))\\n' >";
+        let editor_id = manager.compose_in_editor(&id, None, Some(editor)).unwrap();
+        assert_ne!(editor_id, id);
+
+        for _ in 0..150 {
+            if !sink.named("compose-finished").is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let finished = &sink.named("compose-finished")[0];
+        assert_eq!(finished["sent"], true);
+        assert!(!manager.sessions.lock().unwrap().contains_key(&editor_id));
+
+        manager.write(&id, "\n").unwrap();
+        let contents = || String::from_utf8_lossy(&manager.sessions.lock().unwrap()[&id].output_log.lock().unwrap().contents()).into_owned();
+        for _ in 0..150 {
+            if contents().contains("composed-42") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(contents().contains("composed-42"));
+        manager.close(&id).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reads a JSON file, falling back to the default value if it is missing or unreadable.
+pub fn load_json<T: DeserializeOwned + Default>(path: Option<&Path>) -> T {
+    path.and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create config dir: {}", e))?;
+    }
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {}: {}", path.display(), e))?;
+    fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnboardingStep {
+    Welcome,
+    Permissions,
+    ShellIntegration,
+    DefaultProfile,
+    Complete,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OnboardingState {
+    pub step: OnboardingStep,
+    pub permissions_granted: bool,
+    pub shell_integration_installed: bool,
+    pub default_profile: Option<String>,
+}
+
+impl Default for OnboardingState {
+    fn default() -> Self {
+        OnboardingState {
+            step: OnboardingStep::Welcome,
+            permissions_granted: false,
+            shell_integration_installed: false,
+            default_profile: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OnboardingEvent {
+    Start,
+    PermissionsGranted,
+    ShellIntegrationInstalled,
+    ProfileChosen { profile: String },
+    // Move past the current step without completing it (e.g. user declined a permission prompt)
+    Skip,
+}
+
+impl OnboardingState {
+    pub fn apply(&mut self, event: OnboardingEvent) -> Result<(), String> {
+        match (self.step, event) {
+            (OnboardingStep::Welcome, OnboardingEvent::Start) => {}
+            (OnboardingStep::Permissions, OnboardingEvent::PermissionsGranted) => {
+                self.permissions_granted = true;
+            }
+            (OnboardingStep::ShellIntegration, OnboardingEvent::ShellIntegrationInstalled) => {
+                self.shell_integration_installed = true;
+            }
+            (OnboardingStep::DefaultProfile, OnboardingEvent::ProfileChosen { profile }) => {
+                self.default_profile = Some(profile);
+            }
+            (OnboardingStep::Complete, _) => return Err("Onboarding already complete".into()),
+            (step, OnboardingEvent::Skip) if step != OnboardingStep::Welcome => {}
+            (step, _) => return Err(format!("Event not valid in onboarding step {:?}", step)),
+        }
+
+        self.step = match self.step {
+            OnboardingStep::Welcome => OnboardingStep::Permissions,
+            OnboardingStep::Permissions => OnboardingStep::ShellIntegration,
+            OnboardingStep::ShellIntegration => OnboardingStep::DefaultProfile,
+            OnboardingStep::DefaultProfile | OnboardingStep::Complete => OnboardingStep::Complete,
+        };
+        Ok(())
+    }
+}
+
+// Onboarding progress persisted as JSON so the setup flow resumes after restarts
+// (e.g. macOS relaunching the app after a permission grant).
+pub struct OnboardingStore {
+    path: Option<PathBuf>,
+    pub state: OnboardingState,
+}
+
+impl OnboardingStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let state = load_json(path.as_deref());
+        OnboardingStore { path, state }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn onboarding_walks_through_every_step() {
+        let mut state = OnboardingState::default();
+        state.apply(OnboardingEvent::Start).unwrap();
+        state.apply(OnboardingEvent::PermissionsGranted).unwrap();
+        state.apply(OnboardingEvent::Skip).unwrap();
+        state.apply(OnboardingEvent::ProfileChosen { profile: "work".into() }).unwrap();
+
+        assert_eq!(state.step, OnboardingStep::Complete);
+        assert!(state.permissions_granted);
+        assert!(!state.shell_integration_installed);
+        assert_eq!(state.default_profile.as_deref(), Some("work"));
+        assert!(state.apply(OnboardingEvent::Start).is_err());
+    }
+
+    #[test]
+    fn onboarding_rejects_out_of_order_events() {
+        let mut state = OnboardingState::default();
+        assert!(state.apply(OnboardingEvent::Skip).is_err());
+        assert!(state.apply(OnboardingEvent::PermissionsGranted).is_err());
+        assert_eq!(state.step, OnboardingStep::Welcome);
+    }
+
+    #[test]
+    fn onboarding_store_round_trips() {
+        let path = std::env::temp_dir().join(format!("shelll-onboarding-{}.json", uuid::Uuid::new_v4()));
+        let mut store = OnboardingStore::load(Some(path.clone()));
+        store.state.apply(OnboardingEvent::Start).unwrap();
+        store.save().unwrap();
+
+        let reloaded = OnboardingStore::load(Some(path.clone()));
+        assert_eq!(reloaded.state.step, OnboardingStep::Permissions);
+        let _ = fs::remove_file(path);
+    }
+}
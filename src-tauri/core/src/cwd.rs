@@ -0,0 +1,153 @@
+//! Working directories reported by the shell through OSC 7 (`ESC ] 7 ; file://host/path`),
+//! which most shells emit from their prompt hooks. Each report becomes the session's cwd
+//! (for tab titles, history and new tabs in the same directory) and `session-cwd-changed`.
+
+use crate::events::EventSink;
+use crate::process;
+use crate::pty::{emit_tab_title, PtySession, SessionManager};
+use crate::template::TemplateSettings;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportedCwd {
+    // Empty for the local machine
+    pub host: String,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionCwdPayload {
+    pub session_id: String,
+    pub cwd: String,
+    // The host the shell reported, when it's not this machine (e.g. over ssh)
+    pub host: Option<String>,
+}
+
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// The directory in an OSC 7 body (without `ESC ]` and the terminator), if it is one.
+pub(crate) fn parse_osc7(osc: &[u8]) -> Option<ReportedCwd> {
+    let url = std::str::from_utf8(osc.strip_prefix(b"7;")?).ok()?;
+    // kitty sends kitty-shell-cwd:// for the same thing
+    let rest = url.strip_prefix("file://").or_else(|| url.strip_prefix("kitty-shell-cwd://"))?;
+    let (host, path) = rest.split_at(rest.find('/')?);
+    let path = percent_decode(path)?;
+    let local = host.is_empty() || host == "localhost" || local_hostname().is_some_and(|name| name.eq_ignore_ascii_case(host));
+    Some(ReportedCwd { host: if local { String::new() } else { host.to_string() }, path })
+}
+
+fn local_hostname() -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        // SAFETY: the buffer outlives the call and its length is passed along
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+            return None;
+        }
+        let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        std::str::from_utf8(&buf[..end]).ok().map(str::to_string)
+    }
+    #[cfg(not(unix))]
+    std::env::var("COMPUTERNAME").ok()
+}
+
+// From the reader thread: records the reported directory and reports it if it changed
+pub(crate) fn apply(
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    templates: &Mutex<TemplateSettings>,
+    session_id: &str,
+    reported: ReportedCwd,
+    events: &Arc<dyn EventSink>,
+) {
+    let Ok(mut sessions) = sessions.lock() else { return };
+    let Some(session) = sessions.get_mut(session_id) else { return };
+    if session.title.cwd.as_deref() == Some(reported.path.as_str()) && session.cwd_host == reported.host {
+        return;
+    }
+    session.title.cwd = Some(reported.path.clone());
+    session.cwd_host = reported.host.clone();
+    if let Ok(templates) = templates.lock() {
+        emit_tab_title(events, session_id, session, &templates.tab_title);
+    }
+    events.emit("session-cwd-changed", SessionCwdPayload {
+        session_id: session_id.to_string(),
+        cwd: reported.path,
+        host: Some(reported.host).filter(|host| !host.is_empty()),
+    });
+}
+
+impl SessionManager {
+    /// The session's working directory: the last one its shell reported, else the shell
+    /// process's own. Remote directories (see `SessionCwdPayload::host`) aren't returned.
+    pub fn session_cwd(&self, session_id: &str) -> Result<Option<String>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        if !session.cwd_host.is_empty() {
+            return Ok(None);
+        }
+        Ok(session.title.cwd.clone().or_else(|| session.pid.and_then(process::process_cwd)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_osc7_urls() {
+        let local = |path: &str| Some(ReportedCwd { host: String::new(), path: path.into() });
+        assert_eq!(parse_osc7(b"7;file:///Users/me/My%20Project"), local("/Users/me/My Project"));
+        assert_eq!(parse_osc7(b"7;file://localhost/tmp"), local("/tmp"));
+        assert_eq!(parse_osc7(b"7;kitty-shell-cwd:///srv"), local("/srv"));
+        assert_eq!(
+            parse_osc7(b"7;file://devbox.invalid/home/me"),
+            Some(ReportedCwd { host: "devbox.invalid".into(), path: "/home/me".into() }),
+        );
+        assert_eq!(parse_osc7(b"7;file:///bad%zz"), None);
+        assert_eq!(parse_osc7(b"7;https://example.com/"), None);
+        assert_eq!(parse_osc7(b"0;title"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tracks_the_reported_directory() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+        use std::thread;
+        use std::time::Duration;
+
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.write(&id, "printf '\\033]7;file:///srv/%s\\007' reported\n").unwrap();
+        for _ in 0..150 {
+            if !sink.named("session-cwd-changed").is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let changed = &sink.named("session-cwd-changed")[0];
+        assert_eq!(changed["cwd"], "/srv/reported");
+        assert!(changed["host"].is_null());
+        assert_eq!(manager.session_cwd(&id).unwrap().as_deref(), Some("/srv/reported"));
+        manager.close(&id).unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+//! Startup health check: everything a session depends on (a PTY, a runnable shell, a
+//! terminfo entry for TERM, readable config) plus the macOS permissions the automation
+//! features need. Each check reports what it found and, when something is wrong, what to
+//! do about it, for the troubleshooting page.
+
+use crate::profiles::Profile;
+use crate::{shell, terminfo, unix_now};
+use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem};
+use serde::Serialize;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long the shell gets to start and exit
+const SHELL_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    // Works, but something will misbehave
+    Warn,
+    Fail,
+    // Doesn't apply on this platform
+    Skipped,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DiagnosticCheck {
+    pub id: String,
+    pub label: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+impl DiagnosticCheck {
+    fn new(id: &str, label: &str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        DiagnosticCheck { id: id.into(), label: label.into(), status, detail: detail.into(), fix: None }
+    }
+
+    fn fix(mut self, fix: impl Into<String>) -> Self {
+        self.fix = Some(fix.into());
+        self
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DiagnosticsReport {
+    pub checks: Vec<DiagnosticCheck>,
+    // No check failed (warnings allowed)
+    pub healthy: bool,
+    pub generated_at: u64,
+}
+
+/// Runs every check for sessions started with `profile`. `config_dir` is where the
+/// persisted settings live, if the app has one.
+pub fn run_diagnostics(profile: &Profile, config_dir: Option<&Path>) -> DiagnosticsReport {
+    let mut checks = vec![check_pty(), check_shell(), check_terminfo(&profile.term)];
+    checks.extend(check_permissions());
+    checks.push(check_config(config_dir));
+    DiagnosticsReport {
+        healthy: checks.iter().all(|c| c.status != CheckStatus::Fail),
+        checks,
+        generated_at: unix_now(),
+    }
+}
+
+fn check_pty() -> DiagnosticCheck {
+    match NativePtySystem::default().openpty(PtySize::default()) {
+        Ok(_) => DiagnosticCheck::new("pty", "Pseudo-terminal", CheckStatus::Pass, "Opened a pseudo-terminal"),
+        Err(e) => DiagnosticCheck::new("pty", "Pseudo-terminal", CheckStatus::Fail, format!("Failed to create PTY: {}", e))
+            .fix(if cfg!(windows) {
+                "ConPTY needs Windows 10 version 1809 or later"
+            } else {
+                "Too many terminals may be open; close some sessions or raise the system's PTY limit"
+            }),
+    }
+}
+
+// Arguments that make `shell` exit straight away without reading the user's startup files
+fn exit_args(shell: &Path) -> Vec<&'static str> {
+    let name = shell.file_stem().map(|n| n.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    match name.as_str() {
+        "pwsh" | "powershell" => vec!["-NoProfile", "-Command", "exit 0"],
+        "cmd" => vec!["/c", "exit 0"],
+        _ => vec!["-c", "exit 0"],
+    }
+}
+
+// Starts the login shell in a PTY and waits for it to exit
+fn run_shell(shell: &Path) -> Result<(), String> {
+    let pair = NativePtySystem::default().openpty(PtySize::default())
+        .map_err(|e| format!("Failed to create PTY: {}", e))?;
+    let mut cmd = CommandBuilder::new(shell);
+    cmd.args(exit_args(shell));
+    let mut child = pair.slave.spawn_command(cmd).map_err(|e| format!("Failed to start: {}", e))?;
+    // Some platforms (ConPTY) stall the child until its output is read
+    if let Ok(mut reader) = pair.master.try_clone_reader() {
+        thread::spawn(move || std::io::copy(&mut reader, &mut std::io::sink()));
+    }
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("Exited with {}", status)),
+            Ok(None) if started.elapsed() < SHELL_TIMEOUT => thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                return Err(format!("Did not exit within {}s", SHELL_TIMEOUT.as_secs()));
+            }
+            Err(e) => return Err(format!("Failed to wait: {}", e)),
+        }
+    }
+}
+
+fn check_shell() -> DiagnosticCheck {
+    let shell = shell::login_shell();
+    let label = "Login shell";
+    if !shell::is_executable(&shell) {
+        return DiagnosticCheck::new("shell", label, CheckStatus::Fail, format!("{} is not an executable file", shell.display()))
+            .fix("Set SHELL to an installed shell, or pick one in the session options");
+    }
+    match run_shell(&shell) {
+        Ok(()) => DiagnosticCheck::new("shell", label, CheckStatus::Pass, format!("{} starts and exits cleanly", shell.display())),
+        Err(e) => DiagnosticCheck::new("shell", label, CheckStatus::Fail, format!("{}: {}", shell.display(), e))
+            .fix("Run the shell from another terminal to see why it fails"),
+    }
+}
+
+fn check_terminfo(term: &str) -> DiagnosticCheck {
+    let label = format!("Terminfo for {}", term);
+    if cfg!(windows) {
+        return DiagnosticCheck::new("terminfo", &label, CheckStatus::Skipped, "Windows has no terminfo database");
+    }
+    let diagnosis = terminfo::diagnose_terminfo(term);
+    let status = match (diagnosis.found, diagnosis.warnings.is_empty()) {
+        (false, _) => CheckStatus::Fail,
+        (true, false) => CheckStatus::Warn,
+        (true, true) => CheckStatus::Pass,
+    };
+    let detail = match &diagnosis.path {
+        Some(path) => format!("Resolved to {}", path),
+        None if diagnosis.found => "Found in the terminfo database".to_string(),
+        None => format!("No entry for '{}'", term),
+    };
+    let check = DiagnosticCheck::new("terminfo", &label, status, detail);
+    if diagnosis.warnings.is_empty() {
+        check
+    } else {
+        check.fix(diagnosis.warnings.join("; "))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn check_permissions() -> Vec<DiagnosticCheck> {
+    let settings = "System Settings > Privacy & Security";
+    let accessibility = if crate::keyboard::accessibility_trusted() {
+        DiagnosticCheck::new("accessibility", "Accessibility", CheckStatus::Pass, "Granted")
+    } else {
+        DiagnosticCheck::new("accessibility", "Accessibility", CheckStatus::Warn, "Not granted; typing into other apps won't work")
+            .fix(format!("Enable shelll in {} > Accessibility", settings))
+    };
+    let screen_recording = if mac::screen_capture_allowed() {
+        DiagnosticCheck::new("screen_recording", "Screen Recording", CheckStatus::Pass, "Granted")
+    } else {
+        DiagnosticCheck::new("screen_recording", "Screen Recording", CheckStatus::Warn, "Not granted; other apps' window titles are hidden")
+            .fix(format!("Enable shelll in {} > Screen Recording", settings))
+    };
+    vec![accessibility, screen_recording]
+}
+
+#[cfg(not(target_os = "macos"))]
+fn check_permissions() -> Vec<DiagnosticCheck> {
+    [("accessibility", "Accessibility"), ("screen_recording", "Screen Recording")].iter()
+        .map(|(id, label)| DiagnosticCheck::new(id, label, CheckStatus::Skipped, "Only required on macOS"))
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+mod mac {
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGPreflightScreenCaptureAccess() -> bool;
+    }
+
+    pub fn screen_capture_allowed() -> bool {
+        unsafe { CGPreflightScreenCaptureAccess() }
+    }
+}
+
+// Settings files that fail to parse are silently replaced by defaults, so say which
+fn check_config(config_dir: Option<&Path>) -> DiagnosticCheck {
+    let label = "Configuration";
+    let Some(dir) = config_dir else {
+        return DiagnosticCheck::new("config", label, CheckStatus::Warn, "No config directory; settings won't be saved");
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return DiagnosticCheck::new("config", label, CheckStatus::Pass, "No settings saved yet");
+    };
+    let mut files: Vec<_> = entries.flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .collect();
+    files.sort();
+    let invalid: Vec<String> = files.iter()
+        .filter_map(|path| {
+            let error = match std::fs::read_to_string(path) {
+                Ok(text) => serde_json::from_str::<serde_json::Value>(&text).err()?.to_string(),
+                Err(e) => e.to_string(),
+            };
+            Some(format!("{}: {}", path.file_name()?.to_string_lossy(), error))
+        })
+        .collect();
+    if invalid.is_empty() {
+        DiagnosticCheck::new("config", label, CheckStatus::Pass, format!("{} settings files in {}", files.len(), dir.display()))
+    } else {
+        DiagnosticCheck::new("config", label, CheckStatus::Fail, invalid.join("\n"))
+            .fix("Fix or delete these files; their settings are being ignored and will be overwritten")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn flags_unparseable_settings_files() {
+        let dir = std::env::temp_dir().join(format!("shelll-diagnostics-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("profiles.json"), r#"{"profiles": []}"#).unwrap();
+        assert_eq!(check_config(Some(&dir)).status, CheckStatus::Pass);
+        fs::write(dir.join("redaction.json"), "{ not json").unwrap();
+        let check = check_config(Some(&dir));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.detail.starts_with("redaction.json: "));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sessions_can_start_here() {
+        let report = run_diagnostics(&Profile::default(), None);
+        for id in ["pty", "shell"] {
+            let check = report.checks.iter().find(|c| c.id == id).unwrap();
+            assert_eq!(check.status, CheckStatus::Pass, "{}: {}", id, check.detail);
+        }
+        assert!(exit_args(Path::new("pwsh.exe")).contains(&"-NoProfile"));
+    }
+}
@@ -0,0 +1,125 @@
+//! Cell metrics for the PTY's pixel size. The frontend reports cell sizes in CSS pixels;
+//! the window's scale factor turns them into device pixels, so TIOCGWINSZ stays correct
+//! for image protocols and `tput` when the window moves between displays.
+
+use crate::pty::SessionManager;
+use portable_pty::PtySize;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct CellMetrics {
+    // CSS pixels; 0 until the frontend has measured the font
+    pub cell_width: f64,
+    pub cell_height: f64,
+    pub scale_factor: f64,
+}
+
+impl Default for CellMetrics {
+    fn default() -> Self {
+        CellMetrics { cell_width: 0.0, cell_height: 0.0, scale_factor: 1.0 }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct CellMetricsPayload {
+    pub scale_factor: f64,
+    // Device pixels
+    pub cell_width_px: u32,
+    pub cell_height_px: u32,
+}
+
+impl CellMetrics {
+    pub fn device_cell_size(&self) -> (u32, u32) {
+        (
+            (self.cell_width * self.scale_factor).round() as u32,
+            (self.cell_height * self.scale_factor).round() as u32,
+        )
+    }
+
+    pub(crate) fn pty_size(&self, rows: u16, cols: u16) -> PtySize {
+        let (cell_width, cell_height) = self.device_cell_size();
+        PtySize {
+            rows,
+            cols,
+            pixel_width: (cell_width * cols as u32).min(u16::MAX as u32) as u16,
+            pixel_height: (cell_height * rows as u32).min(u16::MAX as u32) as u16,
+        }
+    }
+
+    fn payload(&self) -> CellMetricsPayload {
+        let (cell_width_px, cell_height_px) = self.device_cell_size();
+        CellMetricsPayload { scale_factor: self.scale_factor, cell_width_px, cell_height_px }
+    }
+}
+
+impl SessionManager {
+    pub fn cell_metrics(&self) -> Result<CellMetrics, String> {
+        Ok(*self.metrics.lock().map_err(|_| "Lock poisoned")?)
+    }
+
+    /// Records the font's cell size in CSS pixels (sent along with resizes).
+    pub fn set_cell_size(&self, cell_width: f64, cell_height: f64) -> Result<(), String> {
+        if !(cell_width > 0.0 && cell_height > 0.0) {
+            return Err("Cell size must be positive".into());
+        }
+        self.update_metrics(|m| {
+            m.cell_width = cell_width;
+            m.cell_height = cell_height;
+        })
+    }
+
+    /// Called when the window moves to a display with a different scale factor.
+    pub fn set_scale_factor(&self, scale_factor: f64) -> Result<(), String> {
+        if scale_factor <= 0.0 {
+            return Err("Scale factor must be positive".into());
+        }
+        self.update_metrics(|m| m.scale_factor = scale_factor)
+    }
+
+    // Re-issues every session's current size with the new pixel dimensions
+    fn update_metrics(&self, change: impl FnOnce(&mut CellMetrics)) -> Result<(), String> {
+        let metrics = {
+            let mut metrics = self.metrics.lock().map_err(|_| "Lock poisoned")?;
+            let before = *metrics;
+            change(&mut metrics);
+            if *metrics == before {
+                return Ok(());
+            }
+            *metrics
+        };
+
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        for session in sessions.values() {
+            if let Ok(master) = session.master.lock() {
+                if let Ok(size) = master.get_size() {
+                    let _ = master.resize(metrics.pty_size(size.rows, size.cols));
+                }
+            }
+        }
+        self.events.emit("cell-metrics-changed", metrics.payload());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_size_scales_with_display() {
+        let mut metrics = CellMetrics { cell_width: 7.5, cell_height: 16.0, scale_factor: 1.0 };
+        let size = metrics.pty_size(24, 80);
+        assert_eq!((size.pixel_width, size.pixel_height), (640, 384));
+
+        metrics.scale_factor = 2.0;
+        assert_eq!(metrics.device_cell_size(), (15, 32));
+        let size = metrics.pty_size(24, 80);
+        assert_eq!((size.rows, size.cols, size.pixel_width, size.pixel_height), (24, 80, 1200, 768));
+    }
+
+    #[test]
+    fn unknown_cell_size_reports_no_pixels() {
+        let size = CellMetrics::default().pty_size(24, 80);
+        assert_eq!((size.pixel_width, size.pixel_height), (0, 0));
+    }
+}
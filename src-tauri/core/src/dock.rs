@@ -0,0 +1,403 @@
+//! Edge-docked windows: the window spans one edge of its screen and, with autohide, slides
+//! off-screen when it loses focus and back in on a hotkey or when the pointer touches that
+//! edge. Frames are in AppKit screen coordinates (points, origin at the bottom left of the
+//! primary screen), and the slide itself is AppKit's own frame animation.
+
+use serde::{Deserialize, Serialize};
+use std::ffi::c_void;
+
+// The pointer counts as touching the edge within this many points of it
+const EDGE_TRIGGER: f64 = 2.0;
+// A docked window is never thinner than this
+const MIN_SIZE: f64 = 80.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DockEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// A window's frame and its screen's, captured on the main thread.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WindowFrames {
+    pub window: Frame,
+    pub screen: Frame,
+    // The screen minus the menu bar and Dock
+    pub visible: Frame,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DockOptions {
+    pub edge: DockEdge,
+    // Height for top/bottom docks, width for left/right, in points
+    pub size: f64,
+    #[serde(default)]
+    pub autohide: bool,
+    // Global shortcut that toggles the window, e.g. "CmdOrCtrl+`"
+    #[serde(default)]
+    pub hotkey: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DockChangedPayload {
+    pub docked: Option<DockOptions>,
+    pub shown: bool,
+}
+
+/// Where a docked window goes: along the edge of the visible area when shown, just past
+/// the screen's edge when hidden.
+pub fn docked_frame(frames: &WindowFrames, options: &DockOptions, shown: bool) -> Frame {
+    let (screen, visible) = (frames.screen, frames.visible);
+    match options.edge {
+        DockEdge::Top | DockEdge::Bottom => {
+            let height = options.size.clamp(MIN_SIZE, visible.height.max(MIN_SIZE));
+            let y = match (options.edge, shown) {
+                (DockEdge::Top, true) => visible.y + visible.height - height,
+                (DockEdge::Top, false) => screen.y + screen.height,
+                (_, true) => visible.y,
+                (_, false) => screen.y - height,
+            };
+            Frame { x: visible.x, y, width: visible.width, height }
+        }
+        DockEdge::Left | DockEdge::Right => {
+            let width = options.size.clamp(MIN_SIZE, visible.width.max(MIN_SIZE));
+            let x = match (options.edge, shown) {
+                (DockEdge::Left, true) => visible.x,
+                (DockEdge::Left, false) => screen.x - width,
+                (_, true) => visible.x + visible.width - width,
+                (_, false) => screen.x + screen.width,
+            };
+            Frame { x, y: visible.y, width, height: visible.height }
+        }
+    }
+}
+
+/// Whether a pointer at (`x`, `y`) is touching `edge` of `screen`.
+pub fn touches_edge(screen: &Frame, edge: DockEdge, x: f64, y: f64) -> bool {
+    let within_x = x >= screen.x && x <= screen.x + screen.width;
+    let within_y = y >= screen.y && y <= screen.y + screen.height;
+    match edge {
+        DockEdge::Top => within_x && y >= screen.y + screen.height - EDGE_TRIGGER,
+        DockEdge::Bottom => within_x && y <= screen.y + EDGE_TRIGGER,
+        DockEdge::Left => within_y && x <= screen.x + EDGE_TRIGGER,
+        DockEdge::Right => within_y && x >= screen.x + screen.width - EDGE_TRIGGER,
+    }
+}
+
+/// One window's dock state.
+#[derive(Debug, Default)]
+pub struct WindowDock {
+    options: Option<DockOptions>,
+    shown: bool,
+    frames: WindowFrames,
+    // Restored on undock
+    undocked_frame: Option<Frame>,
+}
+
+impl WindowDock {
+    /// Docks (or re-docks with new options) and returns the frame to show the window at.
+    pub fn dock(&mut self, options: DockOptions, frames: WindowFrames) -> Result<Frame, String> {
+        if !options.size.is_finite() || options.size <= 0.0 {
+            return Err("Dock size must be positive".into());
+        }
+        if self.undocked_frame.is_none() {
+            self.undocked_frame = Some(frames.window);
+        }
+        let frame = docked_frame(&frames, &options, true);
+        self.options = Some(options);
+        self.frames = frames;
+        self.shown = true;
+        Ok(frame)
+    }
+
+    /// Returns the frame the window had before it was docked.
+    pub fn undock(&mut self) -> Option<Frame> {
+        self.options = None;
+        self.shown = false;
+        self.undocked_frame.take()
+    }
+
+    pub fn options(&self) -> Option<&DockOptions> {
+        self.options.as_ref()
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.shown
+    }
+
+    /// The frame to slide to, or None if not docked or already there.
+    pub fn set_shown(&mut self, shown: bool) -> Option<Frame> {
+        let options = self.options.as_ref()?;
+        if self.shown == shown {
+            return None;
+        }
+        self.shown = shown;
+        Some(docked_frame(&self.frames, options, shown))
+    }
+
+    /// Whether losing focus should slide the window away.
+    pub fn hides_on_blur(&self) -> bool {
+        self.shown && self.options.as_ref().is_some_and(|o| o.autohide)
+    }
+
+    /// Whether a pointer at (`x`, `y`) should reveal the hidden window.
+    pub fn reveals(&self, x: f64, y: f64) -> bool {
+        match &self.options {
+            Some(options) if options.autohide && !self.shown => touches_edge(&self.frames.screen, options.edge, x, y),
+            _ => false,
+        }
+    }
+
+    pub fn payload(&self) -> DockChangedPayload {
+        DockChangedPayload { docked: self.options.clone(), shown: self.shown }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod mac {
+    use super::{Frame, WindowFrames};
+    use objc::runtime::{Object, BOOL, YES};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::c_void;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct NSPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct NSSize {
+        width: f64,
+        height: f64,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct NSRect {
+        origin: NSPoint,
+        size: NSSize,
+    }
+
+    impl From<NSRect> for Frame {
+        fn from(r: NSRect) -> Frame {
+            Frame { x: r.origin.x, y: r.origin.y, width: r.size.width, height: r.size.height }
+        }
+    }
+
+    impl From<Frame> for NSRect {
+        fn from(f: Frame) -> NSRect {
+            NSRect { origin: NSPoint { x: f.x, y: f.y }, size: NSSize { width: f.width, height: f.height } }
+        }
+    }
+
+    fn window(ns_window: *mut c_void) -> Result<*mut Object, String> {
+        let window = ns_window as *mut Object;
+        if window.is_null() {
+            return Err("Window not available".into());
+        }
+        Ok(window)
+    }
+
+    pub fn window_frames(ns_window: *mut c_void) -> Result<WindowFrames, String> {
+        let window = window(ns_window)?;
+        unsafe {
+            let mut screen: *mut Object = msg_send![window, screen];
+            if screen.is_null() {
+                screen = msg_send![class!(NSScreen), mainScreen];
+            }
+            if screen.is_null() {
+                return Err("No screen available".into());
+            }
+            let frame: NSRect = msg_send![window, frame];
+            let screen_frame: NSRect = msg_send![screen, frame];
+            let visible: NSRect = msg_send![screen, visibleFrame];
+            Ok(WindowFrames { window: frame.into(), screen: screen_frame.into(), visible: visible.into() })
+        }
+    }
+
+    pub fn set_frame(ns_window: *mut c_void, frame: Frame, animate: bool) -> Result<(), String> {
+        let window = window(ns_window)?;
+        let rect: NSRect = frame.into();
+        let animate = animate as BOOL;
+        unsafe {
+            let _: () = msg_send![window, setFrame: rect display: YES animate: animate];
+        }
+        Ok(())
+    }
+
+    pub fn pointer_location() -> (f64, f64) {
+        let p: NSPoint = unsafe { msg_send![class!(NSEvent), mouseLocation] };
+        (p.x, p.y)
+    }
+
+    // The screen containing (x, y), or the main screen: its frame and visible frame
+    pub fn screen_at(x: f64, y: f64) -> Option<(Frame, Frame)> {
+        unsafe {
+            let screens: *mut Object = msg_send![class!(NSScreen), screens];
+            let count: usize = msg_send![screens, count];
+            let mut found: *mut Object = std::ptr::null_mut();
+            for i in 0..count {
+                let screen: *mut Object = msg_send![screens, objectAtIndex: i];
+                let r: NSRect = msg_send![screen, frame];
+                let f = Frame::from(r);
+                if x >= f.x && x <= f.x + f.width && y >= f.y && y <= f.y + f.height {
+                    found = screen;
+                    break;
+                }
+            }
+            if found.is_null() {
+                found = msg_send![class!(NSScreen), mainScreen];
+            }
+            if found.is_null() {
+                return None;
+            }
+            let screen_frame: NSRect = msg_send![found, frame];
+            let visible: NSRect = msg_send![found, visibleFrame];
+            Some((screen_frame.into(), visible.into()))
+        }
+    }
+
+    pub fn pointer_screen_frames(ns_window: *mut c_void) -> Result<WindowFrames, String> {
+        let window = window(ns_window)?;
+        let (x, y) = pointer_location();
+        let (screen, visible) = screen_at(x, y).ok_or("No screen available")?;
+        let frame: NSRect = unsafe { msg_send![window, frame] };
+        Ok(WindowFrames { window: frame.into(), screen, visible })
+    }
+
+    pub fn set_floating(ns_window: *mut c_void, floating: bool) -> Result<(), String> {
+        // NSFloatingWindowLevel / NSNormalWindowLevel
+        let level: isize = if floating { 3 } else { 0 };
+        let window = window(ns_window)?;
+        unsafe {
+            let _: () = msg_send![window, setLevel: level];
+        }
+        Ok(())
+    }
+}
+
+/// The window's frame and screen. Must be called on the main thread.
+#[cfg(target_os = "macos")]
+pub fn window_frames(ns_window: *mut c_void) -> Result<WindowFrames, String> {
+    mac::window_frames(ns_window)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn window_frames(_ns_window: *mut c_void) -> Result<WindowFrames, String> {
+    Err("Docking is only supported on macOS".into())
+}
+
+/// Moves the window, sliding it there if `animate`. Must be called on the main thread.
+#[cfg(target_os = "macos")]
+pub fn set_frame(ns_window: *mut c_void, frame: Frame, animate: bool) -> Result<(), String> {
+    mac::set_frame(ns_window, frame, animate)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn set_frame(_ns_window: *mut c_void, _frame: Frame, _animate: bool) -> Result<(), String> {
+    Err("Docking is only supported on macOS".into())
+}
+
+/// The pointer's position in screen coordinates, if it can be read.
+#[cfg(target_os = "macos")]
+pub fn pointer_location() -> Option<(f64, f64)> {
+    Some(mac::pointer_location())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn pointer_location() -> Option<(f64, f64)> {
+    None
+}
+
+/// The frame and visible frame of the screen containing (`x`, `y`), or of the main
+/// screen. Must be called on the main thread.
+#[cfg(target_os = "macos")]
+pub fn screen_at(x: f64, y: f64) -> Option<(Frame, Frame)> {
+    mac::screen_at(x, y)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn screen_at(_x: f64, _y: f64) -> Option<(Frame, Frame)> {
+    None
+}
+
+/// The window's frame, and the screen the pointer is on (the main screen if it can't be
+/// told). Must be called on the main thread.
+#[cfg(target_os = "macos")]
+pub fn pointer_screen_frames(ns_window: *mut c_void) -> Result<WindowFrames, String> {
+    mac::pointer_screen_frames(ns_window)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn pointer_screen_frames(_ns_window: *mut c_void) -> Result<WindowFrames, String> {
+    Err("Docking is only supported on macOS".into())
+}
+
+/// Keeps the window above other apps' windows, or puts it back among them. Must be called
+/// on the main thread.
+#[cfg(target_os = "macos")]
+pub fn set_floating(ns_window: *mut c_void, floating: bool) -> Result<(), String> {
+    mac::set_floating(ns_window, floating)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn set_floating(_ns_window: *mut c_void, _floating: bool) -> Result<(), String> {
+    Err("Docking is only supported on macOS".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames() -> WindowFrames {
+        WindowFrames {
+            window: Frame { x: 200.0, y: 200.0, width: 800.0, height: 500.0 },
+            screen: Frame { x: 0.0, y: 0.0, width: 1440.0, height: 900.0 },
+            // Menu bar at the top, Dock at the bottom
+            visible: Frame { x: 0.0, y: 70.0, width: 1440.0, height: 805.0 },
+        }
+    }
+
+    fn options(edge: DockEdge) -> DockOptions {
+        DockOptions { edge, size: 300.0, autohide: true, hotkey: None }
+    }
+
+    #[test]
+    fn docked_frames_hug_the_edge_and_hide_past_it() {
+        let f = frames();
+        assert_eq!(docked_frame(&f, &options(DockEdge::Top), true), Frame { x: 0.0, y: 575.0, width: 1440.0, height: 300.0 });
+        assert_eq!(docked_frame(&f, &options(DockEdge::Top), false).y, 900.0);
+        assert_eq!(docked_frame(&f, &options(DockEdge::Bottom), false).y, -300.0);
+        assert_eq!(docked_frame(&f, &options(DockEdge::Right), true), Frame { x: 1140.0, y: 70.0, width: 300.0, height: 805.0 });
+        assert_eq!(docked_frame(&f, &options(DockEdge::Left), false).x, -300.0);
+    }
+
+    #[test]
+    fn autohide_and_reveal() {
+        let mut dock = WindowDock::default();
+        assert!(dock.set_shown(false).is_none());
+        dock.dock(options(DockEdge::Top), frames()).unwrap();
+        assert!(!dock.reveals(700.0, 900.0));
+        assert!(dock.hides_on_blur());
+        assert_eq!(dock.set_shown(false).map(|f| f.y), Some(900.0));
+        assert!(dock.reveals(700.0, 899.0));
+        assert!(!dock.reveals(700.0, 800.0));
+        assert!(dock.set_shown(true).is_some());
+        assert_eq!(dock.undock(), Some(frames().window));
+        assert!(dock.options().is_none());
+    }
+}
@@ -0,0 +1,158 @@
+//! The Dock icon's menu (macOS): "New Session" and one item per recent directory (see
+//! `recent_dirs`), built fresh each time it's opened. Picking one calls back with the
+//! directory to start the session in, None for the default.
+
+use crate::recent_dirs::RecentDir;
+use serde::Serialize;
+
+/// The `dock-menu-new-session` payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DockMenuNewSessionPayload {
+    pub cwd: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DockMenuItem {
+    pub title: String,
+    // Where the new session starts; None for "New Session"
+    pub cwd: Option<String>,
+}
+
+// The path with the home directory as ~
+fn display_path(path: &str) -> String {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let home = home.trim_end_matches('/');
+    match path.strip_prefix(home) {
+        Some(rest) if !home.is_empty() && rest.starts_with('/') => format!("~{}", rest),
+        _ => path.to_string(),
+    }
+}
+
+pub fn dock_menu_items(dirs: &[RecentDir]) -> Vec<DockMenuItem> {
+    let mut items = vec![DockMenuItem { title: "New Session".into(), cwd: None }];
+    items.extend(dirs.iter().map(|dir| DockMenuItem {
+        title: format!("New Session in {}", display_path(&dir.path)),
+        cwd: Some(dir.path.clone()),
+    }));
+    items
+}
+
+/// Gives the app a Dock menu of `items()` that calls `on_select` with the picked item's
+/// directory. Must be called from the main thread once the app delegate is set, i.e.
+/// from Tauri's `setup`.
+#[cfg(target_os = "macos")]
+pub fn install_dock_menu(
+    items: impl Fn() -> Vec<DockMenuItem> + Send + Sync + 'static,
+    on_select: impl Fn(Option<String>) + Send + Sync + 'static,
+) -> Result<(), String> {
+    use objc::declare::ClassDecl;
+    use objc::runtime::{class_addMethod, object_getClass, Class, Imp, Object, Sel, NO};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::CString;
+    use std::sync::OnceLock;
+
+    type Items = Box<dyn Fn() -> Vec<DockMenuItem> + Send + Sync>;
+    type OnSelect = Box<dyn Fn(Option<String>) + Send + Sync>;
+    static ITEMS: OnceLock<Items> = OnceLock::new();
+    static ON_SELECT: OnceLock<OnSelect> = OnceLock::new();
+    // The menu items' target, kept for the life of the app
+    static TARGET: OnceLock<usize> = OnceLock::new();
+    if ITEMS.set(Box::new(items)).is_err() || ON_SELECT.set(Box::new(on_select)).is_err() {
+        return Err("The Dock menu is already installed".into());
+    }
+
+    unsafe fn ns_string(s: &str) -> *mut Object {
+        let s = CString::new(s.replace('\0', "")).unwrap_or_default();
+        msg_send![class!(NSString), stringWithUTF8String: s.as_ptr()]
+    }
+
+    extern "C" fn new_session(_: &Object, _: Sel, sender: *mut Object) {
+        let cwd = unsafe {
+            let path: *mut Object = msg_send![sender, representedObject];
+            if path.is_null() {
+                None
+            } else {
+                let utf8: *const std::ffi::c_char = msg_send![path, UTF8String];
+                (!utf8.is_null()).then(|| std::ffi::CStr::from_ptr(utf8).to_string_lossy().into_owned())
+            }
+        };
+        if let Some(on_select) = ON_SELECT.get() {
+            on_select(cwd);
+        }
+    }
+
+    extern "C" fn dock_menu(_: &Object, _: Sel, _: *mut Object) -> *mut Object {
+        let (Some(items), Some(&target)) = (ITEMS.get(), TARGET.get()) else { return std::ptr::null_mut() };
+        unsafe {
+            let menu: *mut Object = msg_send![class!(NSMenu), alloc];
+            let menu: *mut Object = msg_send![menu, initWithTitle: ns_string("")];
+            let _: *mut Object = msg_send![menu, autorelease];
+            for (i, item) in items().into_iter().enumerate() {
+                if i == 1 {
+                    let separator: *mut Object = msg_send![class!(NSMenuItem), separatorItem];
+                    let _: () = msg_send![menu, addItem: separator];
+                }
+                let menu_item: *mut Object = msg_send![class!(NSMenuItem), alloc];
+                let menu_item: *mut Object = msg_send![menu_item,
+                    initWithTitle: ns_string(&item.title)
+                    action: sel!(newSession:)
+                    keyEquivalent: ns_string("")];
+                let _: () = msg_send![menu_item, setTarget: target as *mut Object];
+                if let Some(cwd) = &item.cwd {
+                    let _: () = msg_send![menu_item, setRepresentedObject: ns_string(cwd)];
+                }
+                let _: () = msg_send![menu, addItem: menu_item];
+                let _: () = msg_send![menu_item, release];
+            }
+            menu
+        }
+    }
+
+    unsafe {
+        let mut decl = ClassDecl::new("ShelllDockMenuTarget", class!(NSObject)).ok_or("The Dock menu is already installed")?;
+        decl.add_method(sel!(newSession:), new_session as extern "C" fn(&Object, Sel, *mut Object));
+        let target_class = decl.register();
+        let target: *mut Object = msg_send![target_class, new];
+        let _ = TARGET.set(target as usize);
+
+        // AppKit asks the app delegate for the menu, so the method goes on its class
+        let app: *mut Object = msg_send![class!(NSApplication), sharedApplication];
+        let delegate: *mut Object = msg_send![app, delegate];
+        if delegate.is_null() {
+            return Err("The app has no delegate yet".into());
+        }
+        let imp: Imp = std::mem::transmute(dock_menu as extern "C" fn(&Object, Sel, *mut Object) -> *mut Object);
+        let added = class_addMethod(object_getClass(delegate) as *mut Class, sel!(applicationDockMenu:), imp, c"@@:@".as_ptr());
+        if added == NO {
+            return Err("The app delegate already provides a Dock menu".into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn install_dock_menu(
+    _items: impl Fn() -> Vec<DockMenuItem> + Send + Sync + 'static,
+    _on_select: impl Fn(Option<String>) + Send + Sync + 'static,
+) -> Result<(), String> {
+    Err("The Dock menu is only available on macOS".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_recent_directories_after_new_session() {
+        let dirs = vec![RecentDir { path: "/srv/app".into(), last_used: 2 }, RecentDir { path: "/opt".into(), last_used: 1 }];
+        let items = dock_menu_items(&dirs);
+        assert_eq!(items[0], DockMenuItem { title: "New Session".into(), cwd: None });
+        assert_eq!(items[1], DockMenuItem { title: "New Session in /srv/app".into(), cwd: Some("/srv/app".into()) });
+        assert_eq!(items.len(), 3);
+
+        if let Some(home) = std::env::var("HOME").ok().filter(|h| h.len() > 1) {
+            assert_eq!(display_path(&format!("{}/src/shelll", home)), "~/src/shelll");
+            assert_eq!(display_path(&format!("{}-other", home)), format!("{}-other", home));
+        }
+    }
+}
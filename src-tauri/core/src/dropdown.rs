@@ -0,0 +1,149 @@
+//! Dropdown ("Quake") mode for the main window: the global hotkey slides it down from the
+//! top of the display the pointer is on, it floats above other windows (full-screen apps
+//! included), and it slides back up when it loses focus. Frames and the slide are the
+//! same as for docked windows (see `dock`).
+
+use crate::dock::{Frame, WindowFrames};
+use serde::{Deserialize, Serialize};
+
+// Fractions are clamped to these, so the window never vanishes or spills off the display
+const MIN_FRACTION: f64 = 0.1;
+const MAX_FRACTION: f64 = 1.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DropdownOptions {
+    // Fractions of the display's visible area; the window is centered horizontally
+    pub height: f64,
+    pub width: f64,
+}
+
+impl Default for DropdownOptions {
+    fn default() -> Self {
+        DropdownOptions { height: 0.4, width: 1.0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DropdownChangedPayload {
+    pub dropdown: Option<DropdownOptions>,
+    pub shown: bool,
+}
+
+/// Where the dropdown goes: hanging from the top of the visible area when shown, just
+/// above the screen's top edge when hidden.
+pub fn dropdown_frame(frames: &WindowFrames, options: &DropdownOptions, shown: bool) -> Frame {
+    let (screen, visible) = (frames.screen, frames.visible);
+    let width = visible.width * options.width.clamp(MIN_FRACTION, MAX_FRACTION);
+    let height = visible.height * options.height.clamp(MIN_FRACTION, MAX_FRACTION);
+    let y = if shown { visible.y + visible.height - height } else { screen.y + screen.height };
+    Frame { x: visible.x + (visible.width - width) / 2.0, y, width, height }
+}
+
+/// The main window's dropdown state.
+#[derive(Debug, Default)]
+pub struct WindowDropdown {
+    options: Option<DropdownOptions>,
+    shown: bool,
+    // The display it last dropped down on
+    frames: WindowFrames,
+    // Restored when dropdown mode is turned off
+    normal_frame: Option<Frame>,
+}
+
+impl WindowDropdown {
+    /// Turns dropdown mode on (or changes its options). `window` is the frame to go back
+    /// to when it's turned off.
+    pub fn enable(&mut self, options: DropdownOptions, window: Frame) -> Result<(), String> {
+        if !options.height.is_finite() || !options.width.is_finite() || options.height <= 0.0 || options.width <= 0.0 {
+            return Err("Dropdown size must be positive".into());
+        }
+        if self.normal_frame.is_none() {
+            self.normal_frame = Some(window);
+        }
+        self.options = Some(options);
+        Ok(())
+    }
+
+    /// Returns the frame the window had before dropdown mode.
+    pub fn disable(&mut self) -> Option<Frame> {
+        self.options = None;
+        self.shown = false;
+        self.normal_frame.take()
+    }
+
+    pub fn options(&self) -> Option<&DropdownOptions> {
+        self.options.as_ref()
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.shown
+    }
+
+    /// Drops down on the display in `frames`: the frame to place the window at first
+    /// (out of sight above that display) and the one to slide it to. None if dropdown
+    /// mode is off or the window is already down.
+    pub fn show(&mut self, frames: WindowFrames) -> Option<(Frame, Frame)> {
+        let options = self.options.as_ref()?;
+        if self.shown {
+            return None;
+        }
+        self.shown = true;
+        self.frames = frames;
+        Some((dropdown_frame(&frames, options, false), dropdown_frame(&frames, options, true)))
+    }
+
+    /// The frame to slide up to, or None if dropdown mode is off or the window is up.
+    pub fn hide(&mut self) -> Option<Frame> {
+        let options = self.options.as_ref()?;
+        if !self.shown {
+            return None;
+        }
+        self.shown = false;
+        Some(dropdown_frame(&self.frames, options, false))
+    }
+
+    pub fn payload(&self) -> DropdownChangedPayload {
+        DropdownChangedPayload { dropdown: self.options.clone(), shown: self.shown }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A second display to the right of the primary one
+    fn frames() -> WindowFrames {
+        WindowFrames {
+            window: Frame { x: 200.0, y: 200.0, width: 800.0, height: 500.0 },
+            screen: Frame { x: 1440.0, y: 0.0, width: 1920.0, height: 1080.0 },
+            visible: Frame { x: 1440.0, y: 0.0, width: 1920.0, height: 1000.0 },
+        }
+    }
+
+    #[test]
+    fn drops_down_from_the_top_of_the_display() {
+        let options = DropdownOptions { height: 0.4, width: 0.5 };
+        assert_eq!(dropdown_frame(&frames(), &options, true), Frame { x: 1920.0, y: 600.0, width: 960.0, height: 400.0 });
+        assert_eq!(dropdown_frame(&frames(), &options, false).y, 1080.0);
+        let tiny = DropdownOptions { height: 0.0, width: 3.0 };
+        assert_eq!(dropdown_frame(&frames(), &tiny, true).width, 1920.0);
+        assert_eq!(dropdown_frame(&frames(), &tiny, true).height, 100.0);
+    }
+
+    #[test]
+    fn shows_and_hides_once_enabled() {
+        let mut dropdown = WindowDropdown::default();
+        assert!(dropdown.show(frames()).is_none());
+        assert!(dropdown.enable(DropdownOptions { height: -1.0, width: 1.0 }, frames().window).is_err());
+        dropdown.enable(DropdownOptions::default(), frames().window).unwrap();
+
+        let (start, end) = dropdown.show(frames()).unwrap();
+        assert_eq!((start.y, end.y), (1080.0, 600.0));
+        assert!(dropdown.show(frames()).is_none());
+        assert_eq!(dropdown.hide(), Some(start));
+        assert!(dropdown.hide().is_none());
+        assert_eq!(dropdown.disable(), Some(frames().window));
+        assert!(dropdown.options().is_none());
+    }
+}
@@ -0,0 +1,241 @@
+//! Environment snapshots for debugging "works in my other terminal" problems. The initial
+//! environment is recorded at spawn; the current one is captured by running `env` at the
+//! shell prompt with the session's output held back, so nothing shows up on screen.
+
+use crate::pty::SessionManager;
+use crate::process;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(3);
+// Lets the shell finish redrawing its prompt before output is released again
+const PROMPT_SETTLE: Duration = Duration::from_millis(150);
+// Set by the shell per command; always different
+const IGNORED_VARS: &[&str] = &["_"];
+
+pub type EnvMap = BTreeMap<String, String>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EnvChange {
+    pub name: String,
+    pub initial: String,
+    pub current: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct EnvDiff {
+    pub added: EnvMap,
+    pub removed: EnvMap,
+    pub changed: Vec<EnvChange>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProcessEnv {
+    pub pid: u32,
+    pub session_id: String,
+    pub name: Option<String>,
+    pub env: EnvMap,
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// `env` output; a line that doesn't start a variable continues a multi-line value
+fn parse_env_output(output: &str) -> EnvMap {
+    let mut vars = EnvMap::new();
+    let mut last: Option<String> = None;
+    for line in output.lines() {
+        match line.split_once('=') {
+            Some((name, value)) if is_var_name(name) => {
+                vars.insert(name.to_string(), value.to_string());
+                last = Some(name.to_string());
+            }
+            _ => {
+                if let Some(value) = last.as_ref().and_then(|name| vars.get_mut(name)) {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+    vars
+}
+
+pub fn diff_env(initial: &EnvMap, current: &EnvMap) -> EnvDiff {
+    let mut diff = EnvDiff::default();
+    for (name, value) in current {
+        if IGNORED_VARS.contains(&name.as_str()) {
+            continue;
+        }
+        match initial.get(name) {
+            None => {
+                diff.added.insert(name.clone(), value.clone());
+            }
+            Some(before) if before != value => diff.changed.push(EnvChange {
+                name: name.clone(),
+                initial: before.clone(),
+                current: value.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (name, value) in initial {
+        if !current.contains_key(name) && !IGNORED_VARS.contains(&name.as_str()) {
+            diff.removed.insert(name.clone(), value.clone());
+        }
+    }
+    diff
+}
+
+impl SessionManager {
+    /// The environment the session's shell was started with.
+    pub fn initial_session_env(&self, session_id: &str) -> Result<EnvMap, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        Ok(session.initial_env.clone())
+    }
+
+    /// Runs `env` in the shell without showing it. Only works while the shell is at its
+    /// prompt (no foreground job), since the command is typed into the session.
+    pub fn capture_session_env(&self, session_id: &str) -> Result<EnvMap, String> {
+        let (writer, gate) = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            if session.hibernation.is_some() {
+                return Err("Session is hibernated".into());
+            }
+            if session.input_locked {
+                return Err("Session input is locked".into());
+            }
+            if process::foreground_pid(session) != session.pid {
+                return Err("A command is running in the session".into());
+            }
+            (session.writer.clone(), session.gate.clone())
+        };
+
+        let id = Uuid::new_v4();
+        let partial = std::env::temp_dir().join(format!("shelll-env-{}.tmp", id));
+        let done = std::env::temp_dir().join(format!("shelll-env-{}", id));
+        // Leading space keeps it out of history in shells that honor it; mv makes completion atomic
+        let command = format!(
+            " /usr/bin/env > '{}' && mv '{}' '{}'\r",
+            partial.display(),
+            partial.display(),
+            done.display()
+        );
+
+        gate.pause();
+        let written = writer.lock()
+            .map_err(|_| "Lock poisoned".to_string())
+            .and_then(|mut w| w.write_all(command.as_bytes()).map_err(|e| format!("Failed to write: {}", e)));
+        let deadline = Instant::now() + CAPTURE_TIMEOUT;
+        while written.is_ok() && !done.exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        thread::sleep(PROMPT_SETTLE);
+        // The typed command and the prompt redraw are discarded
+        gate.resume();
+        written?;
+
+        let output = fs::read_to_string(&done);
+        let _ = fs::remove_file(&done);
+        let _ = fs::remove_file(&partial);
+        let output = output.map_err(|_| "Timed out waiting for the shell".to_string())?;
+        Ok(parse_env_output(&output))
+    }
+
+    /// The environment of a process running in one of the sessions. Other processes are
+    /// refused, so this can't be used to read arbitrary processes' secrets.
+    pub fn process_env(&self, pid: u32) -> Result<ProcessEnv, String> {
+        let session_id = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let roots: Vec<(String, u32)> = sessions.iter()
+                .filter_map(|(id, s)| s.pid.map(|pid| (id.clone(), pid)))
+                .collect();
+            drop(sessions);
+            // Processes that left the tree (daemons, nohup) still carry their session's tag
+            roots.iter()
+                .find(|(_, root)| process::is_descendant(pid, *root))
+                .map(|(id, _)| id.clone())
+                .or_else(|| process::session_tag(pid).filter(|tag| roots.iter().any(|(id, _)| id == tag)))
+                .ok_or("Process is not running in a session")?
+        };
+        let env = process::process_env(pid).ok_or("Failed to read the process environment")?;
+        Ok(ProcessEnv { pid, session_id, name: process::process_name(pid), env })
+    }
+
+    pub fn diff_session_env(&self, session_id: &str) -> Result<EnvDiff, String> {
+        let initial = self.initial_session_env(session_id)?;
+        let current = self.capture_session_env(session_id)?;
+        Ok(diff_env(&initial, &current))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use portable_pty::CommandBuilder;
+    use std::sync::Arc;
+
+    #[test]
+    fn parses_multiline_values() {
+        let vars = parse_env_output("A=1\nMULTI=first\nsecond\nEMPTY=\n");
+        assert_eq!(vars["A"], "1");
+        assert_eq!(vars["MULTI"], "first\nsecond");
+        assert_eq!(vars["EMPTY"], "");
+    }
+
+    #[test]
+    fn diffs_environments() {
+        let initial = parse_env_output("PATH=/bin\nOLD=1\n_=/bin/zsh\nSAME=x\n");
+        let current = parse_env_output("PATH=/opt/bin:/bin\nNEW=2\n_=/usr/bin/env\nSAME=x\n");
+        let diff = diff_env(&initial, &current);
+        assert_eq!(diff.added, parse_env_output("NEW=2"));
+        assert_eq!(diff.removed, parse_env_output("OLD=1"));
+        assert_eq!(diff.changed, vec![EnvChange {
+            name: "PATH".into(),
+            initial: "/bin".into(),
+            current: "/opt/bin:/bin".into(),
+        }]);
+    }
+
+    #[test]
+    fn captures_exports_without_showing_them() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.write(&id, "export SHELLL_ENV_TEST=yes\r").unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let shown = sink.named("pty-output").len();
+        let diff = manager.diff_session_env(&id).unwrap();
+        assert_eq!(diff.added.get("SHELLL_ENV_TEST").map(String::as_str), Some("yes"));
+        assert_eq!(sink.named("pty-output").len(), shown);
+        manager.close(&id).unwrap();
+    }
+
+    #[test]
+    fn reads_environments_of_session_processes_only() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.env("SHELLL_PROCESS_ENV_TEST", "1");
+        let id = manager.spawn_session(cmd, "sh").unwrap();
+        let pid = manager.sessions.lock().unwrap()[&id].pid.unwrap();
+        // Until the shell has exec'd, the child still has the test's environment
+        thread::sleep(Duration::from_millis(200));
+
+        let env = manager.process_env(pid).unwrap();
+        assert_eq!(env.session_id, id);
+        assert_eq!(env.env.get("SHELLL_PROCESS_ENV_TEST").map(String::as_str), Some("1"));
+        assert!(manager.process_env(std::process::id()).is_err());
+        manager.close(&id).unwrap();
+    }
+}
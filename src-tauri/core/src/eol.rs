@@ -0,0 +1,117 @@
+//! Line-ending translation for input. Shells want CR for Enter, but serial consoles,
+//! network devices and some remote systems expect LF or CRLF, and pasted text arrives with
+//! whatever endings its source used. Each session can rewrite line endings in its input
+//! and drop the trailing newline of a paste so it isn't run straight away.
+
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+
+const PASTE_END: &str = "\x1b[201~";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Newline {
+    Cr,
+    Lf,
+    CrLf,
+}
+
+impl Newline {
+    fn as_str(self) -> &'static str {
+        match self {
+            Newline::Cr => "\r",
+            Newline::Lf => "\n",
+            Newline::CrLf => "\r\n",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputTranslation {
+    // Every line ending in the input (CR, LF or CRLF) is sent as this; None sends input as is
+    #[serde(default)]
+    pub newline: Option<Newline>,
+    #[serde(default)]
+    pub strip_paste_newline: bool,
+}
+
+impl InputTranslation {
+    pub fn translate(&self, data: &str) -> String {
+        let Some(newline) = self.newline else {
+            return data.to_string();
+        };
+        let mut out = String::with_capacity(data.len());
+        let mut chars = data.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' => {
+                    chars.next_if_eq(&'\n');
+                    out.push_str(newline.as_str());
+                }
+                '\n' => out.push_str(newline.as_str()),
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    /// Drops the line endings a paste ends with (inside bracketed-paste markers, if any).
+    pub fn strip_paste<'a>(&self, data: &'a str) -> std::borrow::Cow<'a, str> {
+        if !self.strip_paste_newline {
+            return data.into();
+        }
+        let (body, end) = match data.strip_suffix(PASTE_END) {
+            Some(body) => (body, PASTE_END),
+            None => (data, ""),
+        };
+        let trimmed = body.trim_end_matches(['\r', '\n']);
+        if trimmed.len() == body.len() {
+            data.into()
+        } else {
+            format!("{}{}", trimmed, end).into()
+        }
+    }
+}
+
+impl SessionManager {
+    pub fn input_translation(&self, session_id: &str) -> Result<InputTranslation, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        Ok(session.input_translation)
+    }
+
+    pub fn set_input_translation(&self, session_id: &str, translation: InputTranslation) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        session.input_translation = translation;
+        Ok(())
+    }
+
+    /// Like `write`, for pasted text.
+    pub fn paste(&self, session_id: &str, data: &str) -> Result<(), String> {
+        let translation = self.input_translation(session_id)?;
+        self.write(session_id, &translation.strip_paste(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_every_kind_of_line_ending() {
+        let crlf = InputTranslation { newline: Some(Newline::CrLf), ..Default::default() };
+        assert_eq!(crlf.translate("a\rb\nc\r\nd"), "a\r\nb\r\nc\r\nd");
+        let cr = InputTranslation { newline: Some(Newline::Cr), ..Default::default() };
+        assert_eq!(cr.translate("ls\n\r\n"), "ls\r\r");
+        assert_eq!(InputTranslation::default().translate("ls\n"), "ls\n");
+    }
+
+    #[test]
+    fn strips_trailing_newlines_from_pastes() {
+        let t = InputTranslation { strip_paste_newline: true, ..Default::default() };
+        assert_eq!(t.strip_paste("make\r\n\n"), "make");
+        assert_eq!(t.strip_paste("\x1b[200~a\nb\n\x1b[201~"), "\x1b[200~a\nb\x1b[201~");
+        assert_eq!(InputTranslation::default().strip_paste("make\n"), "make\n");
+    }
+}
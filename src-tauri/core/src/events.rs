@@ -0,0 +1,125 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+
+/// Destination for events raised by background work (PTY readers, monitors).
+/// The Tauri binary forwards these to the webviews; tests can record them.
+pub trait EventSink: Send + Sync {
+    fn emit_value(&self, event: &str, payload: Value);
+}
+
+impl dyn EventSink {
+    pub fn emit<T: Serialize>(&self, event: &str, payload: T) {
+        if let Ok(value) = serde_json::to_value(payload) {
+            self.emit_value(event, value);
+        }
+    }
+}
+
+/// Which windows receive which events, so output and focus updates only cross the IPC
+/// bridge to the webviews that display them. Events about a session (a `session_id` in
+/// the payload) go to the windows that claimed it; other events go to the windows that
+/// subscribed to them. Anything unclaimed or unsubscribed is broadcast.
+#[derive(Default)]
+pub struct EventRoutes {
+    session_windows: HashMap<String, BTreeSet<String>>,
+    subscriptions: HashMap<String, BTreeSet<String>>,
+}
+
+impl EventRoutes {
+    pub fn claim_session(&mut self, session_id: &str, window: &str) {
+        self.session_windows.entry(session_id.to_string()).or_default().insert(window.to_string());
+    }
+
+    pub fn release_session(&mut self, session_id: &str, window: &str) {
+        if let Some(windows) = self.session_windows.get_mut(session_id) {
+            windows.remove(window);
+            if windows.is_empty() {
+                self.session_windows.remove(session_id);
+            }
+        }
+    }
+
+    pub fn forget_session(&mut self, session_id: &str) {
+        self.session_windows.remove(session_id);
+    }
+
+    pub fn subscribe(&mut self, event: &str, window: &str) {
+        self.subscriptions.entry(event.to_string()).or_default().insert(window.to_string());
+    }
+
+    pub fn unsubscribe(&mut self, event: &str, window: &str) {
+        if let Some(windows) = self.subscriptions.get_mut(event) {
+            windows.remove(window);
+            if windows.is_empty() {
+                self.subscriptions.remove(event);
+            }
+        }
+    }
+
+    /// Drops everything a closed window claimed or subscribed to.
+    pub fn forget_window(&mut self, window: &str) {
+        for windows in self.session_windows.values_mut().chain(self.subscriptions.values_mut()) {
+            windows.remove(window);
+        }
+        self.session_windows.retain(|_, w| !w.is_empty());
+        self.subscriptions.retain(|_, w| !w.is_empty());
+    }
+
+    /// The windows to deliver to, or None to broadcast.
+    pub fn targets(&self, event: &str, payload: &Value) -> Option<Vec<String>> {
+        let windows = match payload.get("session_id").and_then(Value::as_str) {
+            Some(session_id) => self.session_windows.get(session_id),
+            None => self.subscriptions.get(event),
+        };
+        windows.map(|w| w.iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn routes_session_events_to_owning_windows() {
+        let mut routes = EventRoutes::default();
+        routes.claim_session("s1", "main");
+        routes.claim_session("s1", "detached");
+        routes.subscribe("app-focus-changed", "main");
+
+        assert_eq!(routes.targets("pty-output", &json!({"session_id": "s1"})), Some(vec!["detached".into(), "main".into()]));
+        assert_eq!(routes.targets("pty-output", &json!({"session_id": "s2"})), None);
+        assert_eq!(routes.targets("app-focus-changed", &json!({"focused_app": "Xcode"})), Some(vec!["main".into()]));
+
+        routes.forget_window("main");
+        assert_eq!(routes.targets("pty-output", &json!({"session_id": "s1"})), Some(vec!["detached".into()]));
+        assert_eq!(routes.targets("app-focus-changed", &json!({})), None);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    pub struct RecordingSink {
+        pub events: Mutex<Vec<(String, Value)>>,
+    }
+
+    impl RecordingSink {
+        pub fn named(&self, event: &str) -> Vec<Value> {
+            self.events.lock().unwrap().iter()
+                .filter(|(name, _)| name == event)
+                .map(|(_, payload)| payload.clone())
+                .collect()
+        }
+    }
+
+    impl EventSink for RecordingSink {
+        fn emit_value(&self, event: &str, payload: Value) {
+            self.events.lock().unwrap().push((event.to_string(), payload));
+        }
+    }
+}
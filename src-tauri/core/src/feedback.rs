@@ -0,0 +1,196 @@
+//! Audible and tactile cues: system sounds and trackpad haptics, played on request or
+//! when a configured event (bell, command result) happens in a session.
+
+use crate::config::{load_json, save_json};
+use crate::pty::SessionManager;
+use crate::rules::{self, RuleEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// A program printing many BELs in a row should not play many sounds
+const MIN_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HapticPattern {
+    #[default]
+    Generic,
+    Alignment,
+    LevelChange,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Feedback {
+    // The user's alert sound
+    Beep,
+    // A system sound by name, e.g. "Glass" or "Basso"
+    Sound { name: String },
+    // Force Touch trackpads only; silently does nothing elsewhere
+    Haptic {
+        #[serde(default)]
+        pattern: HapticPattern,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackEvent {
+    Bell,
+    CommandFailed,
+    CommandSucceeded,
+}
+
+/// Feedback per event; None plays nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedbackSettings {
+    #[serde(default)]
+    pub bell: Option<Feedback>,
+    #[serde(default)]
+    pub command_failed: Option<Feedback>,
+    #[serde(default)]
+    pub command_succeeded: Option<Feedback>,
+}
+
+impl FeedbackSettings {
+    pub fn for_event(&self, event: FeedbackEvent) -> Option<&Feedback> {
+        match event {
+            FeedbackEvent::Bell => self.bell.as_ref(),
+            FeedbackEvent::CommandFailed => self.command_failed.as_ref(),
+            FeedbackEvent::CommandSucceeded => self.command_succeeded.as_ref(),
+        }
+    }
+}
+
+// Feedback settings persisted as JSON in the config dir
+pub struct FeedbackStore {
+    path: Option<PathBuf>,
+    pub settings: FeedbackSettings,
+}
+
+impl FeedbackStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        FeedbackStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FeedbackTriggers {
+    settings: FeedbackSettings,
+    last_played: HashMap<FeedbackEvent, Instant>,
+}
+
+impl FeedbackTriggers {
+    // The feedback to play for `event`, unless it played very recently
+    fn fire(&mut self, event: FeedbackEvent) -> Option<Feedback> {
+        let feedback = self.settings.for_event(event)?.clone();
+        let now = Instant::now();
+        if self.last_played.get(&event).is_some_and(|last| now.duration_since(*last) < MIN_INTERVAL) {
+            return None;
+        }
+        self.last_played.insert(event, now);
+        Some(feedback)
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn play_feedback(feedback: &Feedback) -> Result<(), String> {
+    use objc::runtime::Object;
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::CString;
+
+    #[link(name = "AppKit", kind = "framework")]
+    extern "C" {
+        fn NSBeep();
+    }
+
+    unsafe {
+        match feedback {
+            Feedback::Beep => NSBeep(),
+            Feedback::Sound { name } => {
+                let name = CString::new(name.as_str()).map_err(|_| "Invalid sound name")?;
+                let name: *mut Object = msg_send![class!(NSString), stringWithUTF8String: name.as_ptr()];
+                let sound: *mut Object = msg_send![class!(NSSound), soundNamed: name];
+                if sound.is_null() {
+                    return Err("Unknown sound".into());
+                }
+                let _: bool = msg_send![sound, play];
+            }
+            Feedback::Haptic { pattern } => {
+                let performer: *mut Object = msg_send![class!(NSHapticFeedbackManager), defaultPerformer];
+                // NSHapticFeedbackPerformanceTimeNow
+                let _: () = msg_send![performer, performFeedbackPattern: *pattern as i64 performanceTime: 1u64];
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn play_feedback(_feedback: &Feedback) -> Result<(), String> {
+    Err("Feedback is only supported on macOS".into())
+}
+
+impl SessionManager {
+    pub fn set_feedback_settings(&self, settings: FeedbackSettings) -> Result<(), String> {
+        self.feedback.lock().map_err(|_| "Lock poisoned")?.settings = settings;
+        Ok(())
+    }
+
+    /// Reports a finished command so the configured success/failure feedback plays,
+    /// `command_finished` rules run and its resource usage is stored.
+    pub fn command_finished(&self, session_id: &str, exit_code: i32) -> Result<(), String> {
+        if !self.sessions.lock().map_err(|_| "Lock poisoned")?.contains_key(session_id) {
+            return Err("Session not found".into());
+        }
+        let event = if exit_code == 0 { FeedbackEvent::CommandSucceeded } else { FeedbackEvent::CommandFailed };
+        notify(&self.feedback, event);
+        self.finish_command(session_id)?;
+        let (command, duration) = self.last_command(session_id);
+        let event = RuleEvent::CommandFinished { session_id: session_id.to_string(), command, exit_code, duration };
+        rules::fire(&self.rules, &event, &self.sessions, &self.events);
+        Ok(())
+    }
+}
+
+pub(crate) fn notify(triggers: &std::sync::Mutex<FeedbackTriggers>, event: FeedbackEvent) {
+    let feedback = triggers.lock().ok().and_then(|mut t| t.fire(event));
+    if let Some(feedback) = feedback {
+        if let Err(e) = play_feedback(&feedback) {
+            eprintln!("Failed to play feedback: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_repeated_events() {
+        let mut triggers = FeedbackTriggers::default();
+        assert_eq!(triggers.fire(FeedbackEvent::Bell), None);
+        triggers.settings.bell = Some(Feedback::Beep);
+        triggers.settings.command_failed = Some(Feedback::Sound { name: "Basso".into() });
+        assert_eq!(triggers.fire(FeedbackEvent::Bell), Some(Feedback::Beep));
+        assert_eq!(triggers.fire(FeedbackEvent::Bell), None);
+        assert!(triggers.fire(FeedbackEvent::CommandFailed).is_some());
+    }
+
+    #[test]
+    fn parses_settings() {
+        let settings: FeedbackSettings = serde_json::from_str(
+            r#"{"bell": {"type": "haptic"}, "command_failed": {"type": "sound", "name": "Basso"}}"#,
+        ).unwrap();
+        assert_eq!(settings.bell, Some(Feedback::Haptic { pattern: HapticPattern::Generic }));
+        assert_eq!(settings.command_succeeded, None);
+    }
+}
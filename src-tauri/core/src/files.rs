@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const QUARANTINE_XATTR: &str = "com.apple.quarantine";
+
+// Parsed form of the com.apple.quarantine xattr: "flags;hex-timestamp;agent;event-uuid"
+#[derive(Clone, Serialize)]
+pub struct QuarantineInfo {
+    pub path: String,
+    pub flags: String,
+    pub timestamp: Option<u64>,
+    pub agent: Option<String>,
+    pub event_id: Option<String>,
+    pub raw: String,
+}
+
+fn parse_quarantine(path: &Path, raw: &str) -> QuarantineInfo {
+    let mut fields = raw.trim().split(';');
+    let non_empty = |s: Option<&str>| s.filter(|v| !v.is_empty()).map(|v| v.to_string());
+    QuarantineInfo {
+        path: path.to_string_lossy().into_owned(),
+        flags: fields.next().unwrap_or_default().to_string(),
+        timestamp: fields.next().and_then(|t| u64::from_str_radix(t, 16).ok()),
+        agent: non_empty(fields.next()),
+        event_id: non_empty(fields.next()),
+        raw: raw.trim().to_string(),
+    }
+}
+
+/// Expands a leading `~/` and canonicalizes, failing if the path doesn't exist.
+pub fn resolve_user_path(path: &str) -> Result<PathBuf, String> {
+    let expanded = match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(env::var("HOME").map_err(|_| "HOME is not set")?).join(rest),
+        None => PathBuf::from(path),
+    };
+    expanded.canonicalize().map_err(|e| format!("Cannot access {}: {}", path, e))
+}
+
+pub fn read_quarantine(path: &Path) -> Result<Option<QuarantineInfo>, String> {
+    let output = Command::new("xattr")
+        .args(["-p", QUARANTINE_XATTR])
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to run xattr: {}", e))?;
+    // xattr exits non-zero when the attribute is absent
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(parse_quarantine(path, &String::from_utf8_lossy(&output.stdout))))
+}
+
+pub fn clear_quarantine(path: &Path) -> Result<(), String> {
+    let output = Command::new("xattr")
+        .args(["-d", QUARANTINE_XATTR])
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to run xattr: {}", e))?;
+    if !output.status.success() && read_quarantine(path)?.is_some() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct SafeOpenOptions {
+    pub make_executable: bool,
+    pub clear_quarantine: bool,
+}
+
+/// The confirmation text shown before `safe_open` touches a file.
+pub fn describe_safe_open(path: &Path, quarantine: Option<&QuarantineInfo>, options: &SafeOpenOptions) -> String {
+    let mut message = format!("Open {}?", path.display());
+    if let Some(info) = quarantine {
+        message.push_str(&format!(
+            "\n\nThis file was downloaded by {}.",
+            info.agent.as_deref().unwrap_or("an unknown application")
+        ));
+    }
+    if options.make_executable {
+        message.push_str("\n\nIt will be marked as executable.");
+    }
+    if options.clear_quarantine && quarantine.is_some() {
+        message.push_str("\n\nThe quarantine flag will be removed, skipping Gatekeeper's download warning.");
+    }
+    message
+}
+
+pub(crate) fn system_opener() -> Command {
+    if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        Command::new("explorer")
+    } else {
+        Command::new("xdg-open")
+    }
+}
+
+/// Applies the chosen options and hands the file to the system opener.
+pub fn open_confirmed(path: &Path, quarantine: Option<&QuarantineInfo>, options: &SafeOpenOptions) -> Result<(), String> {
+    // Windows has no executable bit
+    #[cfg(unix)]
+    if options.make_executable {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = fs::metadata(path)
+            .map_err(|e| format!("Failed to read permissions: {}", e))?
+            .permissions();
+        perms.set_mode(perms.mode() | 0o111);
+        fs::set_permissions(path, perms).map_err(|e| format!("Failed to chmod: {}", e))?;
+    }
+    if options.clear_quarantine && quarantine.is_some() {
+        clear_quarantine(path)?;
+    }
+
+    system_opener()
+        .arg(path)
+        .spawn()
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Opens a directory in the system file manager.
+pub fn open_directory(path: &Path) -> Result<(), String> {
+    if !path.is_dir() {
+        return Err(format!("Not a directory: {}", path.display()));
+    }
+    system_opener()
+        .arg(path)
+        .spawn()
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quarantine_fields() {
+        let info = parse_quarantine(Path::new("/tmp/tool"), "0083;65a1b2c3;Safari;0D2E0C4A-1111\n");
+        assert_eq!(info.flags, "0083");
+        assert_eq!(info.timestamp, Some(0x65a1b2c3));
+        assert_eq!(info.agent.as_deref(), Some("Safari"));
+        assert_eq!(info.event_id.as_deref(), Some("0D2E0C4A-1111"));
+    }
+
+    #[test]
+    fn tolerates_truncated_quarantine_value() {
+        let info = parse_quarantine(Path::new("/tmp/tool"), "0081;;");
+        assert_eq!(info.timestamp, None);
+        assert_eq!(info.agent, None);
+        assert_eq!(info.event_id, None);
+    }
+
+    #[test]
+    fn describes_what_safe_open_will_change() {
+        let info = parse_quarantine(Path::new("/tmp/tool"), "0083;65a1b2c3;curl;");
+        let options = SafeOpenOptions { make_executable: true, clear_quarantine: true };
+        let message = describe_safe_open(Path::new("/tmp/tool"), Some(&info), &options);
+        assert!(message.contains("downloaded by curl"));
+        assert!(message.contains("executable"));
+        assert!(message.contains("quarantine flag"));
+    }
+}
@@ -0,0 +1,202 @@
+//! Live find-bar data. Each session keeps a plain-text log of its recent output lines;
+//! an active find reports match ranges as output arrives, adding matches in new lines and
+//! removing ones whose line was rewritten (progress bars) or dropped from the log.
+
+use crate::pty::SessionManager;
+use serde::Serialize;
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+const MAX_LINES: usize = 10_000;
+const MAX_LINE_CHARS: usize = 4096;
+
+/// `line` counts output lines since the session started; columns are in characters.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MatchRange {
+    pub line: u64,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FindResult {
+    pub find_id: String,
+    pub matches: Vec<MatchRange>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FindUpdatePayload {
+    pub session_id: String,
+    pub find_id: String,
+    pub added: Vec<MatchRange>,
+    pub removed: Vec<MatchRange>,
+}
+
+struct ActiveFind {
+    id: String,
+    // Lowercased; matching is case-insensitive
+    query: Vec<char>,
+    matches: Vec<MatchRange>,
+}
+
+#[derive(Default)]
+pub(crate) struct SessionFind {
+    // Number of the first retained line
+    first_line: u64,
+    // The last line is the one still being written
+    lines: VecDeque<String>,
+    // Saw CR: the next character starts rewriting the current line
+    overwrite: bool,
+    active: Option<ActiveFind>,
+}
+
+fn find_in_line(line_no: u64, line: &str, query: &[char]) -> Vec<MatchRange> {
+    let chars: Vec<char> = line.chars().flat_map(char::to_lowercase).collect();
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while !query.is_empty() && i + query.len() <= chars.len() {
+        if chars[i..i + query.len()] == *query {
+            matches.push(MatchRange { line: line_no, start: i, end: i + query.len() });
+            i += query.len();
+        } else {
+            i += 1;
+        }
+    }
+    matches
+}
+
+impl SessionFind {
+    fn current_line(&self) -> u64 {
+        self.first_line + self.lines.len().saturating_sub(1) as u64
+    }
+
+    fn matches_from(&self, from_line: u64, query: &[char]) -> Vec<MatchRange> {
+        let skip = from_line.saturating_sub(self.first_line) as usize;
+        self.lines.iter()
+            .enumerate()
+            .skip(skip)
+            .flat_map(|(i, line)| find_in_line(self.first_line + i as u64, line, query))
+            .collect()
+    }
+
+    fn append(&mut self, text: &str) {
+        if self.lines.is_empty() {
+            self.lines.push_back(String::new());
+        }
+        for c in text.chars() {
+            match c {
+                '\n' => {
+                    self.lines.push_back(String::new());
+                    self.overwrite = false;
+                }
+                '\r' => self.overwrite = true,
+                c => {
+                    let Some(line) = self.lines.back_mut() else { continue };
+                    if std::mem::take(&mut self.overwrite) {
+                        line.clear();
+                    }
+                    if line.len() < MAX_LINE_CHARS {
+                        line.push(c);
+                    }
+                }
+            }
+        }
+        while self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+            self.first_line += 1;
+        }
+    }
+
+    /// Appends output text; with a find active, returns the match changes it caused.
+    pub(crate) fn feed(&mut self, text: &str) -> Option<(String, Vec<MatchRange>, Vec<MatchRange>)> {
+        if text.is_empty() {
+            return None;
+        }
+        // Lines from the one being written onwards are (re)scanned
+        let dirty = self.current_line();
+        self.append(text);
+
+        let mut active = self.active.take()?;
+        let fresh = self.matches_from(dirty, &active.query);
+        let first_line = self.first_line;
+        let (kept, stale): (Vec<_>, Vec<_>) = active.matches.drain(..)
+            .partition(|m| m.line >= first_line && m.line < dirty);
+        let removed: Vec<MatchRange> = stale.iter().filter(|m| !fresh.contains(m)).cloned().collect();
+        let added: Vec<MatchRange> = fresh.iter().filter(|m| !stale.contains(m)).cloned().collect();
+        active.matches = kept;
+        active.matches.extend(fresh);
+
+        let id = active.id.clone();
+        self.active = Some(active);
+        if added.is_empty() && removed.is_empty() {
+            return None;
+        }
+        Some((id, added, removed))
+    }
+
+    fn start(&mut self, query: &str) -> FindResult {
+        let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+        let matches = self.matches_from(self.first_line, &query);
+        let id = Uuid::new_v4().to_string();
+        self.active = Some(ActiveFind { id: id.clone(), query, matches: matches.clone() });
+        FindResult { find_id: id, matches }
+    }
+}
+
+impl SessionManager {
+    /// Starts (or replaces) the session's live find. Returns the current matches; later
+    /// changes arrive as `find-matches-changed` events carrying the same `find_id`.
+    pub fn find_incremental(&self, session_id: &str, query: &str) -> Result<FindResult, String> {
+        if query.is_empty() {
+            return Err("Query cannot be empty".into());
+        }
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let mut find = session.find.lock().map_err(|_| "Lock poisoned")?;
+        Ok(find.start(query))
+    }
+
+    pub fn stop_find(&self, session_id: &str) -> Result<(), String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        session.find.lock().map_err(|_| "Lock poisoned")?.active = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(line: u64, start: usize, end: usize) -> MatchRange {
+        MatchRange { line, start, end }
+    }
+
+    #[test]
+    fn reports_existing_and_new_matches() {
+        let mut find = SessionFind::default();
+        find.feed("error: one\nok\n");
+        let result = find.start("ERROR");
+        assert_eq!(result.matches, vec![range(0, 0, 5)]);
+
+        let (id, added, removed) = find.feed("two error error\n").unwrap();
+        assert_eq!(id, result.find_id);
+        assert_eq!(added, vec![range(2, 4, 9), range(2, 10, 15)]);
+        assert!(removed.is_empty());
+        assert!(find.feed("fine\n").is_none());
+    }
+
+    #[test]
+    fn rewritten_lines_drop_their_matches() {
+        let mut find = SessionFind::default();
+        find.start("fail");
+        let (_, added, _) = find.feed("50% fail").unwrap();
+        assert_eq!(added, vec![range(0, 4, 8)]);
+        let (_, added, removed) = find.feed("\r60% ok").unwrap();
+        assert!(added.is_empty());
+        assert_eq!(removed, vec![range(0, 4, 8)]);
+        // A CRLF doesn't erase the line it ends
+        find.feed("\r\n");
+        assert_eq!(find.lines[0], "60% ok");
+    }
+}
@@ -0,0 +1,97 @@
+//! Credit-based flow control for `pty-output`. Without it a program that prints without
+//! pause (`yes`) is read as fast as the PTY allows and the events pile up in the webview
+//! faster than the terminal can render them. With flow control on, the frontend acks the
+//! bytes it has written to the terminal; once too many are outstanding a session stops
+//! reading its PTY until acks catch up, so the kernel's buffer fills and the program
+//! blocks on its writes, like in any terminal. Off by default: a frontend that never acks
+//! would stall every session.
+
+use crate::pty::SessionManager;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Reading stops with this many bytes unacked...
+pub(crate) const HIGH_WATER: usize = 512 * 1024;
+// ...and resumes once acks bring it down to this
+pub(crate) const LOW_WATER: usize = 128 * 1024;
+
+// Whether the frontend acks output, shared with every session's batcher
+#[derive(Clone, Default)]
+pub(crate) struct FlowControl(Arc<AtomicBool>);
+
+impl FlowControl {
+    pub(crate) fn enabled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl SessionManager {
+    /// Turns acking on or off for every session. Turning it off resumes paused sessions.
+    pub fn set_flow_control(&self, enabled: bool) -> Result<(), String> {
+        self.flow_control.0.store(enabled, Ordering::SeqCst);
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        for session in sessions.values() {
+            session.batcher.reset_credit();
+        }
+        Ok(())
+    }
+
+    pub fn flow_control(&self) -> bool {
+        self.flow_control.enabled()
+    }
+
+    /// The frontend has written `bytes` more of the session's output to its terminal.
+    pub fn ack_output(&self, session_id: &str, bytes: usize) -> Result<(), String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        session.batcher.ack(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::spawn_flusher;
+    use crate::events::testing::RecordingSink;
+    use crate::ipc::OutputProtocol;
+    use crate::stream::OutputStream;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn stops_reading_until_output_is_acked() {
+        let sink = Arc::new(RecordingSink::default());
+        let flow = FlowControl::default();
+        flow.0.store(true, Ordering::SeqCst);
+        let (batcher, flusher) = spawn_flusher("s1".into(), sink.clone(), OutputProtocol::default(), flow);
+
+        // A reader pushing 4KB at a time, as fast as it can
+        let pushed = Arc::new(AtomicUsize::new(0));
+        let reader = {
+            let (batcher, pushed) = (batcher.clone(), pushed.clone());
+            thread::spawn(move || {
+                for _ in 0..256 {
+                    batcher.push(OutputStream::Stdout, &[b'y'; 4096]);
+                    pushed.fetch_add(4096, Ordering::SeqCst);
+                }
+            })
+        };
+        thread::sleep(Duration::from_millis(200));
+        let stalled_at = pushed.load(Ordering::SeqCst);
+        assert!(stalled_at <= HIGH_WATER + 128 * 1024, "pushed {}", stalled_at);
+
+        // Acking a little isn't enough to resume; acking down to the low mark is
+        batcher.ack(4096);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(pushed.load(Ordering::SeqCst), stalled_at);
+        while !reader.is_finished() {
+            batcher.ack(64 * 1024);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pushed.load(Ordering::SeqCst), 256 * 4096);
+        batcher.close();
+        flusher.join().unwrap();
+    }
+}
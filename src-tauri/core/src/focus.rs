@@ -0,0 +1,1068 @@
+use crate::events::EventSink;
+use crate::keyboard::{self, InjectionMethod};
+use crate::unix_now;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "macos")]
+use objc::{msg_send, sel, sel_impl, class};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunningApp {
+    pub name: String,
+    pub bundle_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FocusChangedPayload {
+    pub subscription_id: String,
+    // Localized name
+    pub focused_app: String,
+    pub focused_bundle_id: String,
+    // Which of the subscription's targets is focused, if any
+    pub focused_target: Option<String>,
+    pub is_target_focused: bool,
+    pub is_self_focused: bool,
+}
+
+// A target app started (`app-launched`) or quit (`app-terminated`)
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AppLifecyclePayload {
+    pub subscription_id: String,
+    pub app: RunningApp,
+    // The subscription's target it matched
+    pub target: String,
+}
+
+// An app becoming frontmost, seen by the focus monitor
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FocusEntry {
+    pub app: String,
+    pub timestamp: u64,
+}
+
+const FOCUS_HISTORY_MAX: usize = 10_000;
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+// Without launch notifications, running apps are listed every this many polls
+const LIFECYCLE_POLLS: u32 = 10;
+// While a handoff waits for its target to come to the front
+const FAST_POLL_INTERVAL: Duration = Duration::from_millis(10);
+// How long a handoff waits for the target before giving up without typing
+const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Focus changes seen while any monitor subscription ran, oldest first
+static FOCUS_HISTORY: Mutex<VecDeque<FocusEntry>> = Mutex::new(VecDeque::new());
+
+#[cfg(target_os = "macos")]
+pub fn get_frontmost_app_name() -> Option<String> {
+    unsafe {
+        let workspace: *mut objc::runtime::Object = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let frontmost_app: *mut objc::runtime::Object = msg_send![workspace, frontmostApplication];
+        if frontmost_app.is_null() {
+            return None;
+        }
+        let name: *mut objc::runtime::Object = msg_send![frontmost_app, localizedName];
+        if name.is_null() {
+            return None;
+        }
+        let utf8: *const i8 = msg_send![name, UTF8String];
+        if utf8.is_null() {
+            return None;
+        }
+        Some(std::ffi::CStr::from_ptr(utf8).to_string_lossy().into_owned())
+    }
+}
+
+// The foreground window's executable name, e.g. "Code" for Code.exe
+#[cfg(windows)]
+pub fn get_frontmost_app_name() -> Option<String> {
+    use std::ffi::{c_void, OsString};
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::PathBuf;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetForegroundWindow() -> *mut c_void;
+        fn GetWindowThreadProcessId(hwnd: *mut c_void, pid: *mut u32) -> u32;
+    }
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn QueryFullProcessImageNameW(process: *mut c_void, flags: u32, name: *mut u16, size: *mut u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_null() {
+            return None;
+        }
+        let mut pid = 0u32;
+        GetWindowThreadProcessId(hwnd, &mut pid);
+        if pid == 0 {
+            return None;
+        }
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return None;
+        }
+        let mut buf = [0u16; 1024];
+        let mut len = buf.len() as u32;
+        let ok = QueryFullProcessImageNameW(process, 0, buf.as_mut_ptr(), &mut len);
+        CloseHandle(process);
+        if ok == 0 {
+            return None;
+        }
+        let path = PathBuf::from(OsString::from_wide(&buf[..len as usize]));
+        path.file_stem().map(|stem| stem.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_frontmost_app_name() -> Option<String> {
+    linux::frontmost_app()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+pub fn get_frontmost_app_name() -> Option<String> {
+    None
+}
+
+/// The frontmost app with its bundle id. Elsewhere the bundle id is the app's name.
+#[cfg(target_os = "macos")]
+pub fn get_frontmost_app() -> Option<RunningApp> {
+    unsafe {
+        let workspace: *mut objc::runtime::Object = msg_send![class!(NSWorkspace), sharedWorkspace];
+        workspace::app_info(msg_send![workspace, frontmostApplication])
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_frontmost_app() -> Option<RunningApp> {
+    linux::frontmost_running_app()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn get_frontmost_app() -> Option<RunningApp> {
+    get_frontmost_app_name().map(|name| RunningApp { bundle_id: name.clone(), name })
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_running_applications() -> Vec<RunningApp> {
+    unsafe {
+        let workspace: *mut objc::runtime::Object = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let apps: *mut objc::runtime::Object = msg_send![workspace, runningApplications];
+        let count: usize = msg_send![apps, count];
+
+        let mut result = Vec::new();
+
+        for i in 0..count {
+            let app: *mut objc::runtime::Object = msg_send![apps, objectAtIndex: i];
+
+            // Check if it's a regular app (not background)
+            let activation_policy: i64 = msg_send![app, activationPolicy];
+            if activation_policy != 0 {
+                continue; // Skip non-regular apps
+            }
+
+            let name: *mut objc::runtime::Object = msg_send![app, localizedName];
+            let bundle_id: *mut objc::runtime::Object = msg_send![app, bundleIdentifier];
+
+            if name.is_null() {
+                continue;
+            }
+
+            let name_utf8: *const i8 = msg_send![name, UTF8String];
+            let name_str = if !name_utf8.is_null() {
+                std::ffi::CStr::from_ptr(name_utf8).to_string_lossy().into_owned()
+            } else {
+                continue;
+            };
+
+            let bundle_str = if !bundle_id.is_null() {
+                let bundle_utf8: *const i8 = msg_send![bundle_id, UTF8String];
+                if !bundle_utf8.is_null() {
+                    std::ffi::CStr::from_ptr(bundle_utf8).to_string_lossy().into_owned()
+                } else {
+                    String::new()
+                }
+            } else {
+                String::new()
+            };
+
+            result.push(RunningApp {
+                name: name_str,
+                bundle_id: bundle_str,
+            });
+        }
+
+        // Sort by name
+        result.sort_by_key(|a| a.name.to_lowercase());
+        result
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_running_applications() -> Vec<RunningApp> {
+    let mut apps = linux::running_apps();
+    apps.sort_by_key(|a| a.name.to_lowercase());
+    apps.dedup_by(|a, b| a.name == b.name);
+    apps
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn get_running_applications() -> Vec<RunningApp> {
+    Vec::new()
+}
+
+// Apps are identified by window class (X11 WM_CLASS, Wayland app_id), which is what the
+// desktop uses to group windows. There is no portal for the focused window, so Wayland
+// needs the compositor's own IPC (sway, Hyprland); elsewhere only XWayland windows are seen.
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::RunningApp;
+    use serde_json::Value;
+    use std::process::Command;
+
+    fn run(program: &str, args: &[&str]) -> Option<String> {
+        let output = Command::new(program).args(args).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn wayland() -> bool {
+        std::env::var_os("WAYLAND_DISPLAY").is_some()
+    }
+
+    pub fn frontmost_app() -> Option<String> {
+        frontmost_running_app().map(|app| app.name)
+    }
+
+    // Identified like `running_apps` does
+    pub fn frontmost_running_app() -> Option<RunningApp> {
+        if wayland() {
+            if let Some(app) = sway_focused().or_else(hyprland_focused) {
+                return Some(RunningApp { name: app.clone(), bundle_id: app });
+            }
+        }
+        let root = run("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+        let window = parse_window_ids(&root).into_iter().next()?;
+        x11_class(&window).map(|(instance, class)| RunningApp { name: class, bundle_id: instance })
+    }
+
+    pub fn running_apps() -> Vec<RunningApp> {
+        if wayland() {
+            let apps = sway_apps().or_else(hyprland_apps).unwrap_or_default();
+            if !apps.is_empty() {
+                return apps;
+            }
+        }
+        let Some(list) = run("xprop", &["-root", "_NET_CLIENT_LIST"]) else {
+            return Vec::new();
+        };
+        parse_window_ids(&list).iter()
+            .filter_map(|window| x11_class(window))
+            .map(|(instance, class)| RunningApp { name: class, bundle_id: instance })
+            .collect()
+    }
+
+    // Focuses a window of the app identified as `running_apps` does (X11 needs wmctrl)
+    pub fn activate(app: &str) -> Result<(), String> {
+        let quoted = app.replace('"', "\\\"");
+        if wayland() {
+            let sway = |key: &str| format!("[{}=\"{}\"] focus", key, quoted);
+            let hyprland = format!("class:^{}$", regex::escape(app));
+            // swaymsg fails when nothing matches; hyprctl always exits 0
+            if run("swaymsg", &[&sway("app_id")]).or_else(|| run("swaymsg", &[&sway("class")])).is_some()
+                || run("hyprctl", &["dispatch", "focuswindow", &hyprland]).is_some_and(|out| out.trim() == "ok")
+            {
+                return Ok(());
+            }
+        }
+        run("wmctrl", &["-x", "-a", app])
+            .map(|_| ())
+            .ok_or_else(|| format!("No window of {} could be activated", app))
+    }
+
+    fn x11_class(window: &str) -> Option<(String, String)> {
+        parse_wm_class(&run("xprop", &["-id", window, "WM_CLASS"])?)
+    }
+
+    // "_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007" (or a comma-separated list)
+    pub(super) fn parse_window_ids(xprop: &str) -> Vec<String> {
+        let Some((_, ids)) = xprop.split_once('#') else {
+            return Vec::new();
+        };
+        ids.split(',')
+            .map(str::trim)
+            .filter(|id| id.starts_with("0x") && u64::from_str_radix(&id[2..], 16).is_ok_and(|n| n != 0))
+            .map(String::from)
+            .collect()
+    }
+
+    // `WM_CLASS(STRING) = "navigator", "firefox"`: instance, then class
+    pub(super) fn parse_wm_class(xprop: &str) -> Option<(String, String)> {
+        let (_, values) = xprop.split_once('=')?;
+        let mut parts = values.split(',').map(|v| v.trim().trim_matches('"').to_string());
+        let instance = parts.next()?;
+        let class = parts.next().unwrap_or_else(|| instance.clone());
+        (!class.is_empty()).then_some((instance, class))
+    }
+
+    fn sway_tree() -> Option<Value> {
+        serde_json::from_str(&run("swaymsg", &["-t", "get_tree", "-r"])?).ok()
+    }
+
+    // Windows in a sway tree: (app name, focused)
+    pub(super) fn sway_windows(node: &Value, out: &mut Vec<(String, bool)>) {
+        let app = node["app_id"].as_str().or_else(|| node["window_properties"]["class"].as_str());
+        if let Some(app) = app.filter(|a| !a.is_empty()) {
+            out.push((app.to_string(), node["focused"].as_bool().unwrap_or(false)));
+        }
+        for key in ["nodes", "floating_nodes"] {
+            for child in node[key].as_array().into_iter().flatten() {
+                sway_windows(child, out);
+            }
+        }
+    }
+
+    fn sway_focused() -> Option<String> {
+        let mut windows = Vec::new();
+        sway_windows(&sway_tree()?, &mut windows);
+        windows.into_iter().find(|(_, focused)| *focused).map(|(app, _)| app)
+    }
+
+    fn sway_apps() -> Option<Vec<RunningApp>> {
+        let mut windows = Vec::new();
+        sway_windows(&sway_tree()?, &mut windows);
+        Some(windows.into_iter().map(|(app, _)| RunningApp { name: app.clone(), bundle_id: app }).collect())
+    }
+
+    fn hyprland_focused() -> Option<String> {
+        let window: Value = serde_json::from_str(&run("hyprctl", &["activewindow", "-j"])?).ok()?;
+        window["class"].as_str().filter(|c| !c.is_empty()).map(String::from)
+    }
+
+    fn hyprland_apps() -> Option<Vec<RunningApp>> {
+        let clients: Value = serde_json::from_str(&run("hyprctl", &["clients", "-j"])?).ok()?;
+        Some(clients.as_array()?.iter()
+            .filter_map(|c| c["class"].as_str().filter(|c| !c.is_empty()))
+            .map(|class| RunningApp { name: class.to_string(), bundle_id: class.to_string() })
+            .collect())
+    }
+}
+
+// Activations, launches and quits as NSWorkspace reports them, instead of polling for them.
+// Notifications are posted on the main thread, and arrive as long as the app's run loop runs.
+#[cfg(target_os = "macos")]
+mod workspace {
+    use super::{FocusMonitor, RunningApp};
+    use objc::declare::ClassDecl;
+    use objc::runtime::{Object, Sel};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::CStr;
+    use std::sync::OnceLock;
+
+    #[link(name = "AppKit", kind = "framework")]
+    extern "C" {
+        static NSWorkspaceDidActivateApplicationNotification: *mut Object;
+        static NSWorkspaceDidLaunchApplicationNotification: *mut Object;
+        static NSWorkspaceDidTerminateApplicationNotification: *mut Object;
+        static NSWorkspaceApplicationKey: *mut Object;
+    }
+
+    // The monitor notifications go to; any other monitor polls
+    static OBSERVER: OnceLock<FocusMonitor> = OnceLock::new();
+
+    unsafe fn string(s: *mut Object) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        let utf8: *const i8 = msg_send![s, UTF8String];
+        (!utf8.is_null()).then(|| CStr::from_ptr(utf8).to_string_lossy().into_owned())
+    }
+
+    // An NSRunningApplication
+    pub(super) unsafe fn app_info(app: *mut Object) -> Option<RunningApp> {
+        if app.is_null() {
+            return None;
+        }
+        let name = string(msg_send![app, localizedName])?;
+        let bundle_id = string(msg_send![app, bundleIdentifier]).unwrap_or_default();
+        Some(RunningApp { name, bundle_id })
+    }
+
+    // The app a workspace notification is about
+    unsafe fn notified_app(notification: *mut Object) -> Option<RunningApp> {
+        let info: *mut Object = msg_send![notification, userInfo];
+        if info.is_null() {
+            return None;
+        }
+        app_info(msg_send![info, objectForKey: NSWorkspaceApplicationKey])
+    }
+
+    extern "C" fn app_activated(_this: &Object, _cmd: Sel, notification: *mut Object) {
+        let Some(monitor) = OBSERVER.get() else { return };
+        let app = unsafe { notified_app(notification) };
+        if app.is_some() {
+            monitor.observe(app);
+        }
+    }
+
+    extern "C" fn app_launched(_this: &Object, _cmd: Sel, notification: *mut Object) {
+        let Some(monitor) = OBSERVER.get() else { return };
+        if let Some(app) = unsafe { notified_app(notification) } {
+            monitor.app_lifecycle(&app, true);
+        }
+    }
+
+    extern "C" fn app_terminated(_this: &Object, _cmd: Sel, notification: *mut Object) {
+        let Some(monitor) = OBSERVER.get() else { return };
+        if let Some(app) = unsafe { notified_app(notification) } {
+            monitor.app_lifecycle(&app, false);
+        }
+    }
+
+    /// Sends workspace notifications to `monitor` from now on. False if they already go to
+    /// another one.
+    pub(super) fn observe_workspace(monitor: &FocusMonitor) -> bool {
+        if OBSERVER.set(monitor.clone()).is_err() {
+            return false;
+        }
+        let Some(mut decl) = ClassDecl::new("ShelllFocusObserver", class!(NSObject)) else {
+            return false;
+        };
+        unsafe {
+            type Handler = extern "C" fn(&Object, Sel, *mut Object);
+            decl.add_method(sel!(appActivated:), app_activated as Handler);
+            decl.add_method(sel!(appLaunched:), app_launched as Handler);
+            decl.add_method(sel!(appTerminated:), app_terminated as Handler);
+            let observer: *mut Object = msg_send![decl.register(), new];
+            let workspace: *mut Object = msg_send![class!(NSWorkspace), sharedWorkspace];
+            let center: *mut Object = msg_send![workspace, notificationCenter];
+            for (selector, name) in [
+                (sel!(appActivated:), NSWorkspaceDidActivateApplicationNotification),
+                (sel!(appLaunched:), NSWorkspaceDidLaunchApplicationNotification),
+                (sel!(appTerminated:), NSWorkspaceDidTerminateApplicationNotification),
+            ] {
+                let _: () = msg_send![center, addObserver: observer
+                    selector: selector
+                    name: name
+                    object: std::ptr::null_mut::<Object>()];
+            }
+        }
+        true
+    }
+}
+
+const SELF_BUNDLE_ID: &str = "com.shelll.app";
+
+fn is_self_app(name: &str) -> bool {
+    name == "Shelll" || name == "shelll"
+}
+
+// Targets are bundle ids, which stay the same across languages and renames; names are
+// still accepted from older callers
+fn is_target(target: &str, app: &RunningApp) -> bool {
+    (!app.bundle_id.is_empty() && target.eq_ignore_ascii_case(&app.bundle_id)) || target == app.name
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FocusSubscription {
+    pub id: String,
+    // Whoever started it (a window label); its subscriptions end together when it goes away
+    pub owner: String,
+    pub targets: Vec<String>,
+}
+
+struct Subscriber {
+    subscription: FocusSubscription,
+    // The frontmost app as last reported to this subscriber
+    last_app: Option<RunningApp>,
+}
+
+/// Told about every app that comes to the front, e.g. to run rules.
+pub type FocusListener = Arc<dyn Fn(&RunningApp) + Send + Sync>;
+
+#[derive(Default)]
+struct MonitorState {
+    subscribers: Vec<Subscriber>,
+    polling: bool,
+    // Activations, launches and quits are reported by the system, so nothing needs to poll
+    notified: bool,
+    // Running apps as of the last listing, when polling for launches and quits
+    running: Option<Vec<RunningApp>>,
+    // Handoffs waiting for an app to come to the front
+    waiters: usize,
+    frontmost: Option<RunningApp>,
+    // The app last added to the focus history
+    recorded: Option<String>,
+    listener: Option<FocusListener>,
+}
+
+impl MonitorState {
+    // Subscriptions with `app` as a target, and the target it matched
+    fn matching_targets(&self, app: &RunningApp) -> Vec<(String, String)> {
+        self.subscribers.iter()
+            .filter_map(|s| {
+                let target = s.subscription.targets.iter().find(|t| is_target(t, app))?;
+                Some((s.subscription.id.clone(), target.clone()))
+            })
+            .collect()
+    }
+
+    // Events for subscribers that haven't seen `app` yet (new ones get the current app)
+    fn focus_changed(&mut self, app: &RunningApp) -> Vec<FocusChangedPayload> {
+        self.subscribers.iter_mut()
+            .filter(|s| s.last_app.as_ref() != Some(app))
+            .map(|s| {
+                s.last_app = Some(app.clone());
+                let focused_target = s.subscription.targets.iter().find(|t| is_target(t, app)).cloned();
+                FocusChangedPayload {
+                    subscription_id: s.subscription.id.clone(),
+                    focused_app: app.name.clone(),
+                    focused_bundle_id: app.bundle_id.clone(),
+                    is_target_focused: focused_target.is_some(),
+                    focused_target,
+                    is_self_focused: app.bundle_id == SELF_BUNDLE_ID || is_self_app(&app.name),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Focus monitoring for any number of independent subscribers (the attach overlay,
+/// analytics, ...). Each has its own targets and gets its own `app-focus-changed` events.
+/// On macOS activations are reported as they happen; elsewhere, or if that can't be set
+/// up, one polling thread runs while there are subscribers.
+#[derive(Clone)]
+pub struct FocusMonitor {
+    state: Arc<Mutex<MonitorState>>,
+    // Signaled when the frontmost app changes
+    changed: Arc<Condvar>,
+    events: Arc<dyn EventSink>,
+}
+
+impl FocusMonitor {
+    pub fn new(events: Arc<dyn EventSink>) -> Self {
+        let monitor = FocusMonitor { state: Arc::default(), changed: Arc::default(), events };
+        #[cfg(target_os = "macos")]
+        if workspace::observe_workspace(&monitor) {
+            if let Ok(mut state) = monitor.state.lock() {
+                state.notified = true;
+            }
+        }
+        monitor
+    }
+
+    /// Starts a subscription and returns its id.
+    pub fn subscribe(&self, owner: &str, targets: Vec<String>) -> Result<String, String> {
+        let mut state = self.state.lock().map_err(|_| "Lock poisoned")?;
+        let id = uuid::Uuid::new_v4().to_string();
+        state.subscribers.push(Subscriber {
+            subscription: FocusSubscription { id: id.clone(), owner: owner.to_string(), targets },
+            last_app: None,
+        });
+        if state.notified {
+            // Nothing is reported until the next activation; tell the new subscriber now
+            drop(state);
+            self.observe(get_frontmost_app());
+        } else {
+            self.start_polling(&mut state);
+        }
+        Ok(id)
+    }
+
+    fn start_polling(&self, state: &mut MonitorState) {
+        if !state.polling && !state.notified {
+            state.polling = true;
+            let monitor = self.clone();
+            thread::spawn(move || monitor.poll());
+        }
+    }
+
+    pub fn set_targets(&self, id: &str, targets: Vec<String>) -> Result<(), String> {
+        self.retarget(id, |current| *current = targets)
+    }
+
+    pub fn add_target(&self, id: &str, target: String) -> Result<(), String> {
+        self.retarget(id, |targets| {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        })
+    }
+
+    pub fn remove_target(&self, id: &str, target: &str) -> Result<(), String> {
+        self.retarget(id, |targets| targets.retain(|t| t != target))
+    }
+
+    // Changes a subscription's targets and reports the frontmost app to it again, judged
+    // against the new ones
+    fn retarget(&self, id: &str, change: impl FnOnce(&mut Vec<String>)) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|_| "Lock poisoned")?;
+        let subscriber = state.subscribers.iter_mut()
+            .find(|s| s.subscription.id == id)
+            .ok_or("Subscription not found")?;
+        change(&mut subscriber.subscription.targets);
+        subscriber.last_app = None;
+        if state.notified {
+            drop(state);
+            self.observe(get_frontmost_app());
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, id: &str) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|_| "Lock poisoned")?;
+        let before = state.subscribers.len();
+        state.subscribers.retain(|s| s.subscription.id != id);
+        if state.subscribers.len() == before {
+            return Err("Subscription not found".into());
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe_owner(&self, owner: &str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.subscribers.retain(|s| s.subscription.owner != owner);
+        }
+    }
+
+    /// Whether the frontmost app is a target of any subscription.
+    pub fn target_focused(&self) -> bool {
+        let Ok(state) = self.state.lock() else { return false };
+        let Some(app) = &state.frontmost else { return false };
+        state.subscribers.iter().any(|s| s.subscription.targets.iter().any(|t| is_target(t, app)))
+    }
+
+    /// Sets (or with None, removes) the listener. Keeps the monitor running without
+    /// subscribers.
+    pub fn set_listener(&self, listener: Option<FocusListener>) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|_| "Lock poisoned")?;
+        state.listener = listener;
+        if state.listener.is_some() {
+            self.start_polling(&mut state);
+        }
+        Ok(())
+    }
+
+    pub fn subscriptions(&self) -> Vec<FocusSubscription> {
+        self.state.lock()
+            .map(|state| state.subscribers.iter().map(|s| s.subscription.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    // The frontmost app, polled or reported: wakes handoffs and tells subscribers
+    fn observe(&self, current: Option<RunningApp>) {
+        let (changes, listener) = {
+            let Ok(mut state) = self.state.lock() else { return };
+            let moved = state.frontmost != current;
+            if moved {
+                state.frontmost = current.clone();
+                self.changed.notify_all();
+            }
+            let Some(app) = &current else { return };
+            if !state.subscribers.is_empty() && state.recorded.as_ref() != Some(&app.name) {
+                record_focus(&app.name);
+                state.recorded = Some(app.name.clone());
+            }
+            (state.focus_changed(app), state.listener.clone().filter(|_| moved))
+        };
+        for payload in changes {
+            self.events.emit("app-focus-changed", payload);
+        }
+        if let (Some(listener), Some(app)) = (listener, current) {
+            listener(&app);
+        }
+    }
+
+    // Tells subscriptions targeting `app` that it started or quit
+    fn app_lifecycle(&self, app: &RunningApp, launched: bool) {
+        let matches = match self.state.lock() {
+            Ok(state) => state.matching_targets(app),
+            Err(_) => return,
+        };
+        let event = if launched { "app-launched" } else { "app-terminated" };
+        for (subscription_id, target) in matches {
+            self.events.emit(event, AppLifecyclePayload { subscription_id, app: app.clone(), target });
+        }
+    }
+
+    // Launches and quits by comparing listings of running apps, for lack of notifications
+    fn poll_lifecycle(&self) {
+        let wanted = self.state.lock().is_ok_and(|s| s.subscribers.iter().any(|s| !s.subscription.targets.is_empty()));
+        if !wanted {
+            if let Ok(mut state) = self.state.lock() {
+                state.running = None;
+            }
+            return;
+        }
+        let running = get_running_applications();
+        let previous = match self.state.lock() {
+            Ok(mut state) => state.running.replace(running.clone()),
+            Err(_) => return,
+        };
+        let Some(previous) = previous else { return };
+        let key = |app: &RunningApp| (app.bundle_id.clone(), app.name.clone());
+        for app in running.iter().filter(|app| !previous.iter().any(|p| key(p) == key(app))) {
+            self.app_lifecycle(app, true);
+        }
+        for app in previous.iter().filter(|app| !running.iter().any(|r| key(r) == key(app))) {
+            self.app_lifecycle(app, false);
+        }
+    }
+
+    // Runs until the last subscriber, waiter and listener are gone
+    fn poll(&self) {
+        let mut polls = 0u32;
+        loop {
+            if polls.is_multiple_of(LIFECYCLE_POLLS) {
+                self.poll_lifecycle();
+            }
+            polls = polls.wrapping_add(1);
+            let current = get_frontmost_app();
+            let interval = {
+                let Ok(mut state) = self.state.lock() else { return };
+                if state.subscribers.is_empty() && state.waiters == 0 && state.listener.is_none() {
+                    state.polling = false;
+                    return;
+                }
+                if state.waiters > 0 { FAST_POLL_INTERVAL } else { POLL_INTERVAL }
+            };
+            self.observe(current);
+            thread::sleep(interval);
+        }
+    }
+
+    /// Waits until the monitor sees a frontmost app matching `target`, and returns it.
+    /// None on timeout.
+    pub fn wait_for_frontmost(&self, target: impl Fn(&RunningApp) -> bool, timeout: Duration) -> Option<RunningApp> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().ok()?;
+        state.waiters += 1;
+        // What the monitor saw last may be stale by up to a slow poll
+        state.frontmost = get_frontmost_app();
+        self.start_polling(&mut state);
+        let found = loop {
+            if let Some(app) = state.frontmost.as_ref().filter(|app| target(app)) {
+                break Some(app.clone());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break None;
+            }
+            state = match self.changed.wait_timeout(state, remaining) {
+                Ok((state, _)) => state,
+                Err(_) => return None,
+            };
+        };
+        state.waiters -= 1;
+        found
+    }
+
+    /// Brings the app with `bundle_id` to the front, waits until the monitor has seen it
+    /// there, then types `text` after the settle time `delay` picks. Nothing is typed if
+    /// the app doesn't come to the front, so keys can't land in the wrong app.
+    pub fn focus_target_and_send(
+        &self,
+        bundle_id: &str,
+        text: &str,
+        delay: DelayStrategy,
+        method: InjectionMethod,
+    ) -> Result<HandoffReport, String> {
+        let started = Instant::now();
+        activate_app_by_bundle_id(bundle_id)?;
+        self.wait_for_frontmost(|app| app.bundle_id.eq_ignore_ascii_case(bundle_id), ACTIVATION_TIMEOUT)
+            .ok_or_else(|| format!("{} did not come to the front within {}s", bundle_id, ACTIVATION_TIMEOUT.as_secs()))?;
+        let activation = started.elapsed();
+        let settle = delay.settle_time(activation);
+        thread::sleep(settle);
+        keyboard::inject_text(text, method)?;
+        Ok(HandoffReport {
+            activation_ms: activation.as_millis() as u64,
+            settle_ms: settle.as_millis() as u64,
+        })
+    }
+}
+
+/// How long to wait between the target coming to the front and typing into it: apps
+/// report being frontmost slightly before their window takes keyboard focus.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DelayStrategy {
+    // Type right away
+    Immediate,
+    Fixed { ms: u64 },
+    // Scaled to how long activation took: an app that was slow to come forward is
+    // usually slow to focus its window too
+    #[default]
+    Adaptive,
+}
+
+impl DelayStrategy {
+    pub fn settle_time(self, activation: Duration) -> Duration {
+        match self {
+            DelayStrategy::Immediate => Duration::ZERO,
+            DelayStrategy::Fixed { ms } => Duration::from_millis(ms),
+            DelayStrategy::Adaptive => (activation / 2).clamp(Duration::from_millis(20), Duration::from_millis(150)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HandoffReport {
+    // From asking for activation to seeing the app frontmost
+    pub activation_ms: u64,
+    pub settle_ms: u64,
+}
+
+fn record_focus(app: &str) {
+    if let Ok(mut history) = FOCUS_HISTORY.lock() {
+        history.push_back(FocusEntry { app: app.to_string(), timestamp: unix_now() });
+        if history.len() > FOCUS_HISTORY_MAX {
+            history.pop_front();
+        }
+    }
+}
+
+pub fn focus_history() -> Vec<FocusEntry> {
+    FOCUS_HISTORY.lock().map(|h| h.iter().cloned().collect()).unwrap_or_default()
+}
+
+// AppleScript string literal
+pub(crate) fn applescript_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn run_applescript(script: &str) -> Result<(), String> {
+    let output = std::process::Command::new("osascript")
+        .arg("-e")
+        .arg(script)
+        .output()
+        .map_err(|e| format!("Failed to run osascript: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn run_applescript(_script: &str) -> Result<(), String> {
+    Err("App automation is only supported on macOS".into())
+}
+
+/// Brings `app_name` to the front, launching it if needed.
+pub fn activate_app(app_name: &str) -> Result<(), String> {
+    run_applescript(&format!("tell application {} to activate", applescript_quote(app_name)))
+}
+
+pub fn activate_app_by_bundle_id(bundle_id: &str) -> Result<(), String> {
+    run_applescript(&format!("tell application id {} to activate", applescript_quote(bundle_id)))
+}
+
+/// Brings the running app with `bundle_id` to the front, without launching it if it
+/// isn't running. Needs no Automation permission, unlike `activate_app_by_bundle_id`.
+#[cfg(target_os = "macos")]
+pub fn activate_running_app(bundle_id: &str) -> Result<RunningApp, String> {
+    use objc::runtime::{Object, NO};
+    // NSApplicationActivateIgnoringOtherApps
+    const IGNORING_OTHER_APPS: usize = 1 << 1;
+
+    let id = std::ffi::CString::new(bundle_id).map_err(|_| "Invalid bundle id")?;
+    unsafe {
+        let id: *mut Object = msg_send![class!(NSString), stringWithUTF8String: id.as_ptr()];
+        let apps: *mut Object = msg_send![class!(NSRunningApplication), runningApplicationsWithBundleIdentifier: id];
+        let count: usize = msg_send![apps, count];
+        if count == 0 {
+            return Err(format!("{} is not running", bundle_id));
+        }
+        let app: *mut Object = msg_send![apps, objectAtIndex: 0usize];
+        let activated: objc::runtime::BOOL = msg_send![app, activateWithOptions: IGNORING_OTHER_APPS];
+        if activated == NO {
+            return Err(format!("{} could not be activated", bundle_id));
+        }
+        workspace::app_info(app).ok_or_else(|| format!("{} is not running", bundle_id))
+    }
+}
+
+/// The process id of the running app with `bundle_id`.
+#[cfg(target_os = "macos")]
+pub fn running_app_pid(bundle_id: &str) -> Option<i32> {
+    use objc::runtime::Object;
+
+    let id = std::ffi::CString::new(bundle_id).ok()?;
+    unsafe {
+        let id: *mut Object = msg_send![class!(NSString), stringWithUTF8String: id.as_ptr()];
+        let apps: *mut Object = msg_send![class!(NSRunningApplication), runningApplicationsWithBundleIdentifier: id];
+        let count: usize = msg_send![apps, count];
+        if count == 0 {
+            return None;
+        }
+        let app: *mut Object = msg_send![apps, objectAtIndex: 0usize];
+        let pid: i32 = msg_send![app, processIdentifier];
+        Some(pid)
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn running_app_pid(_bundle_id: &str) -> Option<i32> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+pub fn activate_running_app(bundle_id: &str) -> Result<RunningApp, String> {
+    linux::activate(bundle_id)?;
+    Ok(RunningApp { name: bundle_id.to_string(), bundle_id: bundle_id.to_string() })
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn activate_running_app(_bundle_id: &str) -> Result<RunningApp, String> {
+    Err("Activating apps is not supported on this platform".into())
+}
+
+/// Types `text` into the frontmost app with the current keyboard layout. Requires the
+/// Accessibility permission.
+pub fn type_text(text: &str) -> Result<(), String> {
+    keyboard::inject_text(text, InjectionMethod::Auto)
+}
+
+/// Brings `app_name` to the front and types `text` into it.
+pub fn send_text_to_app(app_name: &str, text: &str, method: InjectionMethod) -> Result<(), String> {
+    activate_app(app_name)?;
+    // Activation is asynchronous; keys sent right away can land in the previous app
+    thread::sleep(Duration::from_millis(150));
+    keyboard::inject_text(text, method)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_applescript_strings() {
+        assert_eq!(applescript_quote(r#"say "hi" \ bye"#), r#""say \"hi\" \\ bye""#);
+    }
+
+    #[test]
+    fn recognizes_own_app_name() {
+        assert!(is_self_app("shelll"));
+        assert!(is_self_app("Shelll"));
+        assert!(!is_self_app("Xcode"));
+    }
+
+    #[test]
+    fn subscribers_are_judged_against_their_own_targets() {
+        let monitor = FocusMonitor::new(Arc::new(crate::events::testing::RecordingSink::default()));
+        let overlay = monitor.subscribe("main", vec!["Xcode".into()]).unwrap();
+        let analytics = monitor.subscribe("stats", Vec::new()).unwrap();
+
+        let app = |name: &str, bundle_id: &str| RunningApp { name: name.into(), bundle_id: bundle_id.into() };
+        let mut state = monitor.state.lock().unwrap();
+        let changes = state.focus_changed(&app("Xcode", "com.apple.dt.Xcode"));
+        assert_eq!(changes.iter().map(|c| (c.subscription_id.as_str(), c.is_target_focused)).collect::<Vec<_>>(),
+            vec![(overlay.as_str(), true), (analytics.as_str(), false)]);
+        assert_eq!(changes[0].focused_bundle_id, "com.apple.dt.Xcode");
+        assert!(state.focus_changed(&app("Xcode", "com.apple.dt.Xcode")).is_empty());
+        drop(state);
+
+        // Bundle ids match whatever the app is called in the user's language
+        monitor.set_targets(&analytics, vec!["com.apple.Safari".into()]).unwrap();
+        monitor.unsubscribe_owner("main");
+        assert!(monitor.unsubscribe(&overlay).is_err());
+        let changes = monitor.state.lock().unwrap().focus_changed(&app("Safari-Browser", "com.apple.safari"));
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].is_target_focused);
+
+        // Several targets at once; the event says which one is in front
+        monitor.add_target(&analytics, "com.apple.iphonesimulator".into()).unwrap();
+        let simulator = app("Simulator", "com.apple.iphonesimulator");
+        let changes = monitor.state.lock().unwrap().focus_changed(&simulator);
+        assert_eq!(changes[0].focused_target.as_deref(), Some("com.apple.iphonesimulator"));
+        monitor.remove_target(&analytics, "com.apple.iphonesimulator").unwrap();
+        // Reported again once the targets change
+        let changes = monitor.state.lock().unwrap().focus_changed(&simulator);
+        assert_eq!(changes[0].focused_target, None);
+        monitor.unsubscribe(&analytics).unwrap();
+        assert!(monitor.subscriptions().is_empty());
+    }
+
+    #[test]
+    fn reports_launches_and_quits_of_targets() {
+        let sink = Arc::new(crate::events::testing::RecordingSink::default());
+        let monitor = FocusMonitor::new(sink.clone());
+        monitor.state.lock().unwrap().notified = true;
+        let id = monitor.subscribe("main", vec!["com.apple.dt.Xcode".into(), "com.apple.iphonesimulator".into()]).unwrap();
+
+        let simulator = RunningApp { name: "Simulator".into(), bundle_id: "com.apple.iphonesimulator".into() };
+        monitor.app_lifecycle(&simulator, true);
+        monitor.app_lifecycle(&RunningApp { name: "Notes".into(), bundle_id: "com.apple.Notes".into() }, true);
+        monitor.app_lifecycle(&simulator, false);
+        let launched = sink.named("app-launched");
+        assert_eq!(launched.len(), 1);
+        assert_eq!(launched[0]["subscription_id"], id.as_str());
+        assert_eq!(launched[0]["target"], "com.apple.iphonesimulator");
+        assert_eq!(sink.named("app-terminated")[0]["app"]["name"], "Simulator");
+    }
+
+    #[test]
+    fn handoff_waits_are_bounded() {
+        let monitor = FocusMonitor::new(Arc::new(crate::events::testing::RecordingSink::default()));
+        let started = Instant::now();
+        assert_eq!(monitor.wait_for_frontmost(|app| app.bundle_id == "com.example.Missing", Duration::from_millis(100)), None);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(monitor.state.lock().unwrap().waiters, 0);
+
+        let adaptive = DelayStrategy::Adaptive;
+        assert_eq!(adaptive.settle_time(Duration::from_millis(10)), Duration::from_millis(20));
+        assert_eq!(adaptive.settle_time(Duration::from_millis(180)), Duration::from_millis(90));
+        assert_eq!(adaptive.settle_time(Duration::from_secs(1)), Duration::from_millis(150));
+        assert_eq!(DelayStrategy::Fixed { ms: 40 }.settle_time(Duration::ZERO), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn reported_activations_need_no_polling() {
+        let sink = Arc::new(crate::events::testing::RecordingSink::default());
+        let monitor = FocusMonitor::new(sink.clone());
+        monitor.state.lock().unwrap().notified = true;
+        monitor.subscribe("main", vec!["Xcode".into()]).unwrap();
+
+        let reporter = {
+            let monitor = monitor.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                monitor.observe(Some(RunningApp { name: "Xcode".into(), bundle_id: "com.apple.dt.Xcode".into() }));
+            })
+        };
+        let app = monitor.wait_for_frontmost(|app| app.bundle_id == "com.apple.dt.Xcode", Duration::from_secs(2));
+        reporter.join().unwrap();
+        assert_eq!(app.map(|app| app.name).as_deref(), Some("Xcode"));
+        assert!(!monitor.state.lock().unwrap().polling);
+        assert_eq!(sink.named("app-focus-changed").last().unwrap()["is_target_focused"], true);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_x11_and_sway_window_info() {
+        assert_eq!(linux::parse_window_ids("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007"), vec!["0x3a00007"]);
+        assert!(linux::parse_window_ids("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0").is_empty());
+        assert_eq!(linux::parse_window_ids("_NET_CLIENT_LIST(WINDOW): window id # 0x1e00003, 0x2400006").len(), 2);
+        assert_eq!(
+            linux::parse_wm_class("WM_CLASS(STRING) = \"navigator\", \"firefox\""),
+            Some(("navigator".into(), "firefox".into()))
+        );
+        assert_eq!(linux::parse_wm_class("WM_CLASS:  not found."), None);
+
+        let tree = serde_json::json!({"nodes": [
+            {"app_id": "foot", "focused": false, "nodes": []},
+            {"app_id": null, "window_properties": {"class": "Code"}, "focused": true, "nodes": []}
+        ]});
+        let mut windows = Vec::new();
+        linux::sway_windows(&tree, &mut windows);
+        assert_eq!(windows, vec![("foot".into(), false), ("Code".into(), true)]);
+    }
+}
@@ -0,0 +1,302 @@
+//! Following the attached app's window: an Accessibility observer watches the app's
+//! focused window and reports its frame whenever it moves or resizes (or another of the
+//! app's windows takes focus), and the window is kept docked beside it. Frames are in
+//! AppKit screen coordinates like `dock`'s; Accessibility's top-left coordinates are
+//! converted on the way in.
+
+use crate::dock::Frame;
+use serde::{Deserialize, Serialize};
+
+// Never narrower than this, however little room there is beside the target
+const MIN_WIDTH: f64 = 200.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FollowSide {
+    Left,
+    #[default]
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FollowOptions {
+    #[serde(default)]
+    pub side: FollowSide,
+    // In points; the height always matches the target's window
+    pub width: f64,
+}
+
+/// Where the window goes beside `target` within `visible` (the visible area of the
+/// target's screen): on the preferred side, or the other one if it only fits there, or
+/// else over the target's edge on the preferred side.
+pub fn follow_frame(target: Frame, visible: Frame, options: &FollowOptions) -> Frame {
+    let width = options.width.max(MIN_WIDTH).min(visible.width);
+    let left = target.x - width;
+    let right = target.x + target.width;
+    let fits_left = left >= visible.x;
+    let fits_right = right + width <= visible.x + visible.width;
+    let x = match (options.side, fits_left, fits_right) {
+        (FollowSide::Left, true, _) | (FollowSide::Right, true, false) => left,
+        (FollowSide::Right, _, true) | (FollowSide::Left, false, true) => right,
+        (FollowSide::Left, false, false) => visible.x,
+        (FollowSide::Right, false, false) => visible.x + visible.width - width,
+    };
+    // Same height and top edge as the target, kept on screen
+    let height = target.height.min(visible.height);
+    let y = target.y.clamp(visible.y, visible.y + visible.height - height);
+    Frame { x, y, width, height }
+}
+
+/// Converts a frame from Accessibility coordinates (origin at the top left of the
+/// primary screen, y growing down) to AppKit's.
+pub fn ax_to_appkit(frame: Frame, primary_height: f64) -> Frame {
+    Frame { y: primary_height - frame.y - frame.height, ..frame }
+}
+
+#[cfg(target_os = "macos")]
+mod mac {
+    use super::{ax_to_appkit, Frame};
+    use std::cell::Cell;
+    use std::ffi::{c_char, c_void, CStr};
+    use std::ptr;
+
+    type CFStringRef = *const c_void;
+    type ObserverCallback = extern "C" fn(*const c_void, *const c_void, CFStringRef, *mut c_void);
+
+    const UTF8: u32 = 0x0800_0100;
+    const AX_VALUE_CG_POINT: u32 = 1;
+    const AX_VALUE_CG_SIZE: u32 = 2;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct CGPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct CGSize {
+        width: f64,
+        height: f64,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct CGRect {
+        origin: CGPoint,
+        size: CGSize,
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFRunLoopDefaultMode: CFStringRef;
+        fn CFRelease(cf: *const c_void);
+        fn CFStringCreateWithCString(allocator: *const c_void, s: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFEqual(a: *const c_void, b: *const c_void) -> u8;
+        fn CFRunLoopGetMain() -> *const c_void;
+        fn CFRunLoopAddSource(run_loop: *const c_void, source: *const c_void, mode: CFStringRef);
+        fn CFRunLoopRemoveSource(run_loop: *const c_void, source: *const c_void, mode: CFStringRef);
+    }
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXUIElementCreateApplication(pid: i32) -> *const c_void;
+        fn AXUIElementCopyAttributeValue(element: *const c_void, attribute: CFStringRef, value: *mut *const c_void) -> i32;
+        fn AXValueGetValue(value: *const c_void, kind: u32, out: *mut c_void) -> u8;
+        fn AXObserverCreate(pid: i32, callback: ObserverCallback, observer: *mut *const c_void) -> i32;
+        fn AXObserverAddNotification(observer: *const c_void, element: *const c_void, notification: CFStringRef, refcon: *mut c_void) -> i32;
+        fn AXObserverRemoveNotification(observer: *const c_void, element: *const c_void, notification: CFStringRef) -> i32;
+        fn AXObserverGetRunLoopSource(observer: *const c_void) -> *const c_void;
+        fn CGMainDisplayID() -> u32;
+        fn CGDisplayBounds(display: u32) -> CGRect;
+    }
+
+    fn cf_str(s: &CStr) -> CFStringRef {
+        unsafe { CFStringCreateWithCString(ptr::null(), s.as_ptr(), UTF8) }
+    }
+
+    unsafe fn attribute<T: Default>(element: *const c_void, name: &CStr, kind: u32) -> Option<T> {
+        let attr = cf_str(name);
+        let mut value = ptr::null();
+        let status = AXUIElementCopyAttributeValue(element, attr, &mut value);
+        CFRelease(attr);
+        if status != 0 || value.is_null() {
+            return None;
+        }
+        let mut out = T::default();
+        let ok = AXValueGetValue(value, kind, &mut out as *mut T as *mut c_void) != 0;
+        CFRelease(value);
+        ok.then_some(out)
+    }
+
+    // Notifications on the target's focused window; the app gets AXFocusedWindowChanged
+    const WINDOW_NOTIFICATIONS: [&CStr; 3] = [c"AXMoved", c"AXResized", c"AXUIElementDestroyed"];
+
+    struct State {
+        observer: *const c_void,
+        app: *const c_void,
+        window: Cell<*const c_void>,
+        window_notifications: [CFStringRef; 3],
+        focus_changed: CFStringRef,
+        on_frame: Box<dyn Fn(Option<Frame>)>,
+    }
+
+    impl State {
+        fn refcon(&self) -> *mut c_void {
+            self as *const State as *mut c_void
+        }
+
+        // Moves the window notifications to whichever window of the app has focus now
+        unsafe fn watch_focused_window(&self) {
+            let previous = self.window.replace(ptr::null());
+            if !previous.is_null() {
+                for name in self.window_notifications {
+                    AXObserverRemoveNotification(self.observer, previous, name);
+                }
+                CFRelease(previous);
+            }
+            let attr = cf_str(c"AXFocusedWindow");
+            let mut window = ptr::null();
+            let found = AXUIElementCopyAttributeValue(self.app, attr, &mut window) == 0 && !window.is_null();
+            CFRelease(attr);
+            if found {
+                for name in self.window_notifications {
+                    AXObserverAddNotification(self.observer, window, name, self.refcon());
+                }
+                self.window.set(window);
+            }
+        }
+
+        unsafe fn frame(&self) -> Option<Frame> {
+            let window = self.window.get();
+            if window.is_null() {
+                return None;
+            }
+            let position: CGPoint = attribute(window, c"AXPosition", AX_VALUE_CG_POINT)?;
+            let size: CGSize = attribute(window, c"AXSize", AX_VALUE_CG_SIZE)?;
+            let primary = CGDisplayBounds(CGMainDisplayID());
+            let frame = Frame { x: position.x, y: position.y, width: size.width, height: size.height };
+            Some(ax_to_appkit(frame, primary.size.height))
+        }
+    }
+
+    extern "C" fn on_notification(_observer: *const c_void, _element: *const c_void, notification: CFStringRef, refcon: *mut c_void) {
+        let state = unsafe { &*(refcon as *const State) };
+        unsafe {
+            let destroyed = CFEqual(notification, state.window_notifications[2]) != 0;
+            if destroyed || CFEqual(notification, state.focus_changed) != 0 {
+                state.watch_focused_window();
+            }
+            (state.on_frame)(state.frame());
+        }
+    }
+
+    pub struct Follower(Box<State>);
+
+    impl Follower {
+        pub fn start(pid: i32, on_frame: Box<dyn Fn(Option<Frame>)>) -> Result<Follower, String> {
+            unsafe {
+                let app = AXUIElementCreateApplication(pid);
+                if app.is_null() {
+                    return Err("The app can't be reached through Accessibility".into());
+                }
+                let mut observer = ptr::null();
+                if AXObserverCreate(pid, on_notification, &mut observer) != 0 || observer.is_null() {
+                    CFRelease(app);
+                    return Err("Failed to observe the app's windows".into());
+                }
+                let state = Box::new(State {
+                    observer,
+                    app,
+                    window: Cell::new(ptr::null()),
+                    window_notifications: WINDOW_NOTIFICATIONS.map(cf_str),
+                    focus_changed: cf_str(c"AXFocusedWindowChanged"),
+                    on_frame,
+                });
+                AXObserverAddNotification(observer, app, state.focus_changed, state.refcon());
+                CFRunLoopAddSource(CFRunLoopGetMain(), AXObserverGetRunLoopSource(observer), kCFRunLoopDefaultMode);
+                state.watch_focused_window();
+                (state.on_frame)(state.frame());
+                Ok(Follower(state))
+            }
+        }
+    }
+
+    impl Drop for Follower {
+        fn drop(&mut self) {
+            let state = &self.0;
+            unsafe {
+                CFRunLoopRemoveSource(CFRunLoopGetMain(), AXObserverGetRunLoopSource(state.observer), kCFRunLoopDefaultMode);
+                let window = state.window.replace(ptr::null());
+                if !window.is_null() {
+                    CFRelease(window);
+                }
+                CFRelease(state.observer);
+                CFRelease(state.app);
+                for name in state.window_notifications {
+                    CFRelease(name);
+                }
+                CFRelease(state.focus_changed);
+            }
+        }
+    }
+}
+
+/// Observes one app's focused window until dropped. Created and dropped on the main
+/// thread, where its callback runs too.
+pub struct WindowFollower {
+    #[cfg(target_os = "macos")]
+    _follower: mac::Follower,
+}
+
+// Only touched on the main thread (see above); kept in app state behind a mutex
+unsafe impl Send for WindowFollower {}
+
+impl WindowFollower {
+    /// Starts following the focused window of process `pid`. `on_frame` gets its frame
+    /// right away and whenever it changes, or None while the app has no focused window.
+    /// Requires the Accessibility permission.
+    #[cfg(target_os = "macos")]
+    pub fn start(pid: i32, on_frame: impl Fn(Option<Frame>) + 'static) -> Result<WindowFollower, String> {
+        if !crate::keyboard::accessibility_trusted() {
+            return Err("Following a window needs the Accessibility permission".into());
+        }
+        Ok(WindowFollower { _follower: mac::Follower::start(pid, Box::new(on_frame))? })
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn start(_pid: i32, _on_frame: impl Fn(Option<Frame>) + 'static) -> Result<WindowFollower, String> {
+        Err("Following windows is only supported on macOS".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visible() -> Frame {
+        Frame { x: 0.0, y: 70.0, width: 1440.0, height: 805.0 }
+    }
+
+    #[test]
+    fn sits_beside_the_target_where_there_is_room() {
+        let right = FollowOptions { side: FollowSide::Right, width: 400.0 };
+        let left = FollowOptions { side: FollowSide::Left, ..right.clone() };
+        let target = Frame { x: 500.0, y: 300.0, width: 500.0, height: 400.0 };
+        assert_eq!(follow_frame(target, visible(), &right), Frame { x: 1000.0, y: 300.0, width: 400.0, height: 400.0 });
+        assert_eq!(follow_frame(target, visible(), &left).x, 100.0);
+
+        // No room on the right: flips to the left, and with no room anywhere overlaps
+        let wide = Frame { x: 600.0, ..target };
+        assert_eq!(follow_frame(wide, visible(), &right).x, 200.0);
+        let full = Frame { x: 0.0, y: 0.0, width: 1440.0, height: 900.0 };
+        assert_eq!(follow_frame(full, visible(), &right), Frame { x: 1040.0, y: 70.0, width: 400.0, height: 805.0 });
+    }
+
+    #[test]
+    fn converts_accessibility_coordinates() {
+        let frame = Frame { x: 10.0, y: 25.0, width: 300.0, height: 200.0 };
+        assert_eq!(ax_to_appkit(frame, 900.0), Frame { x: 10.0, y: 675.0, width: 300.0, height: 200.0 });
+    }
+}
@@ -0,0 +1,314 @@
+//! Port forwards for SSH sessions. The destination is read from the session's running
+//! `ssh` command; forwards go through its control master when one is running
+//! (`ssh -O forward`), otherwise through a separate background `ssh -N` tunnel.
+
+use crate::process;
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+// ssh options that take a value (from ssh(1))
+const SSH_VALUE_FLAGS: &str = "BbcDEeFIiJLlmOoPpQRSWw";
+// Options that select how to reach the host; forwarded to our own ssh invocations
+const SSH_CONNECTION_FLAGS: &str = "FiJlopP";
+const TUNNEL_STARTUP: Duration = Duration::from_millis(1500);
+const TUNNEL_POLL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardKind {
+    // -L: listen locally, connect from the remote side
+    #[default]
+    Local,
+    // -R: listen on the remote side, connect locally
+    Remote,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForwardVia {
+    ControlMaster,
+    Tunnel,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ForwardStatus {
+    Active,
+    Failed { error: String },
+    Closed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PortForward {
+    pub id: String,
+    pub session_id: String,
+    pub destination: String,
+    pub kind: ForwardKind,
+    // [bind_address:]port on this machine
+    pub local: String,
+    // host:port (or [bind_address:]port for remote forwards) on the remote side
+    pub remote: String,
+    pub via: ForwardVia,
+    pub status: ForwardStatus,
+}
+
+impl PortForward {
+    fn flag(&self) -> &'static str {
+        match self.kind {
+            ForwardKind::Local => "-L",
+            ForwardKind::Remote => "-R",
+        }
+    }
+
+    // The -L/-R argument: listener first, then the target
+    fn spec(&self) -> String {
+        match self.kind {
+            ForwardKind::Local => format!("{}:{}", self.local, self.remote),
+            ForwardKind::Remote => format!("{}:{}", self.remote, self.local),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct SshTarget {
+    pub(crate) destination: String,
+    // Connection options from the session's command line, e.g. ["-p", "2222"]
+    options: Vec<String>,
+}
+
+pub(crate) fn parse_ssh_command(args: &[String]) -> Option<SshTarget> {
+    let program = args.first()?;
+    if program.rsplit('/').next() != Some("ssh") {
+        return None;
+    }
+    let mut options = Vec::new();
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        let Some(flags) = arg.strip_prefix('-') else {
+            return Some(SshTarget { destination: arg.clone(), options });
+        };
+        // Flags can be combined (-vp 22); everything after a value flag is its value
+        let Some(i) = flags.find(|c| SSH_VALUE_FLAGS.contains(c)) else { continue };
+        let flag = &flags[i..i + 1];
+        let inline = &flags[i + 1..];
+        let value = if inline.is_empty() { iter.next()?.clone() } else { inline.to_string() };
+        if SSH_CONNECTION_FLAGS.contains(flag) {
+            options.push(format!("-{}", flag));
+            options.push(value);
+        }
+    }
+    None
+}
+
+fn ssh(target: &SshTarget) -> Command {
+    let mut cmd = Command::new("ssh");
+    cmd.args(&target.options);
+    cmd
+}
+
+fn control_master_running(target: &SshTarget) -> bool {
+    ssh(target)
+        .args(["-O", "check", &target.destination])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+struct ForwardEntry {
+    forward: PortForward,
+    target: SshTarget,
+    tunnel: Option<Child>,
+}
+
+#[derive(Default)]
+pub(crate) struct ForwardRegistry {
+    entries: Vec<ForwardEntry>,
+}
+
+impl SessionManager {
+    fn ssh_target(&self, session_id: &str) -> Result<SshTarget, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        process::foreground_pid(session)
+            .and_then(process::process_args)
+            .and_then(|args| parse_ssh_command(&args))
+            .ok_or_else(|| "Session is not running ssh".to_string())
+    }
+
+    fn emit_forward(&self, forward: &PortForward) {
+        self.events.emit("port-forward-changed", forward.clone());
+    }
+
+    pub fn add_port_forward(
+        self: &Arc<Self>,
+        session_id: &str,
+        local: &str,
+        remote: &str,
+        kind: ForwardKind,
+    ) -> Result<PortForward, String> {
+        let target = self.ssh_target(session_id)?;
+        let mut forward = PortForward {
+            id: Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            destination: target.destination.clone(),
+            kind,
+            local: local.to_string(),
+            remote: remote.to_string(),
+            via: ForwardVia::ControlMaster,
+            status: ForwardStatus::Active,
+        };
+
+        let mut tunnel = None;
+        if control_master_running(&target) {
+            let output = ssh(&target)
+                .args(["-O", "forward", forward.flag(), &forward.spec(), &target.destination])
+                .output()
+                .map_err(|e| format!("Failed to run ssh: {}", e))?;
+            if !output.status.success() {
+                return Err(format!("ssh -O forward failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+            }
+        } else {
+            forward.via = ForwardVia::Tunnel;
+            // BatchMode: there's no terminal to prompt for a password on
+            let mut child = ssh(&target)
+                .args(["-N", "-o", "BatchMode=yes", "-o", "ExitOnForwardFailure=yes"])
+                .args([forward.flag(), &forward.spec(), &target.destination])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Failed to run ssh: {}", e))?;
+            thread::sleep(TUNNEL_STARTUP);
+            if let Ok(Some(_)) = child.try_wait() {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = child.stderr.take() {
+                    let _ = std::io::Read::read_to_string(&mut pipe, &mut stderr);
+                }
+                return Err(format!("ssh tunnel failed: {}", stderr.trim()));
+            }
+            tunnel = Some(child);
+        }
+
+        self.forwards.lock().map_err(|_| "Lock poisoned")?.entries.push(ForwardEntry {
+            forward: forward.clone(),
+            target,
+            tunnel,
+        });
+        self.emit_forward(&forward);
+        if forward.via == ForwardVia::Tunnel {
+            self.watch_tunnel(forward.id.clone());
+        }
+        Ok(forward)
+    }
+
+    // Marks a tunnel failed when its ssh process exits on its own
+    fn watch_tunnel(self: &Arc<Self>, forward_id: String) {
+        let manager = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(TUNNEL_POLL);
+            let Some(manager) = manager.upgrade() else { break };
+            let Ok(mut forwards) = manager.forwards.lock() else { break };
+            let Some(entry) = forwards.entries.iter_mut().find(|e| e.forward.id == forward_id) else { break };
+            let Some(status) = entry.tunnel.as_mut().and_then(|c| c.try_wait().ok()).flatten() else { continue };
+            entry.tunnel = None;
+            entry.forward.status = ForwardStatus::Failed { error: format!("ssh exited ({})", status) };
+            manager.emit_forward(&entry.forward);
+            break;
+        });
+    }
+
+    pub fn list_port_forwards(&self) -> Result<Vec<PortForward>, String> {
+        let forwards = self.forwards.lock().map_err(|_| "Lock poisoned")?;
+        Ok(forwards.entries.iter().map(|e| e.forward.clone()).collect())
+    }
+
+    pub fn remove_port_forward(&self, forward_id: &str) -> Result<(), String> {
+        let mut forwards = self.forwards.lock().map_err(|_| "Lock poisoned")?;
+        let index = forwards.entries.iter()
+            .position(|e| e.forward.id == forward_id)
+            .ok_or("Port forward not found")?;
+        let mut entry = forwards.entries.remove(index);
+        drop(forwards);
+
+        match entry.tunnel.take() {
+            Some(mut child) => {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            None if entry.forward.status == ForwardStatus::Active => {
+                let _ = ssh(&entry.target)
+                    .args(["-O", "cancel", entry.forward.flag(), &entry.forward.spec(), &entry.target.destination])
+                    .output();
+            }
+            None => {}
+        }
+        entry.forward.status = ForwardStatus::Closed;
+        self.emit_forward(&entry.forward);
+        Ok(())
+    }
+
+    // Forwards end with their session
+    pub(crate) fn remove_session_forwards(&self, session_id: &str) {
+        let ids: Vec<String> = match self.forwards.lock() {
+            Ok(forwards) => forwards.entries.iter()
+                .filter(|e| e.forward.session_id == session_id)
+                .map(|e| e.forward.id.clone())
+                .collect(),
+            Err(_) => return,
+        };
+        for id in ids {
+            let _ = self.remove_port_forward(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parses_destination_and_connection_options() {
+        let target = parse_ssh_command(&args("/usr/bin/ssh -v -p 2222 -oServerAliveInterval=30 -A deploy@prod ls")).unwrap();
+        assert_eq!(target.destination, "deploy@prod");
+        assert_eq!(target.options, args("-p 2222 -o ServerAliveInterval=30"));
+
+        let target = parse_ssh_command(&args("ssh -L 8080:localhost:80 -vJ bastion host")).unwrap();
+        assert_eq!(target.destination, "host");
+        assert_eq!(target.options, args("-J bastion"));
+    }
+
+    #[test]
+    fn ignores_other_programs() {
+        assert_eq!(parse_ssh_command(&args("zsh")), None);
+        assert_eq!(parse_ssh_command(&args("ssh -p")), None);
+    }
+
+    #[test]
+    fn builds_forward_specs() {
+        let mut forward = PortForward {
+            id: "f".into(),
+            session_id: "s".into(),
+            destination: "host".into(),
+            kind: ForwardKind::Local,
+            local: "8080".into(),
+            remote: "localhost:80".into(),
+            via: ForwardVia::Tunnel,
+            status: ForwardStatus::Active,
+        };
+        assert_eq!((forward.flag(), forward.spec()), ("-L", "8080:localhost:80".to_string()));
+        forward.kind = ForwardKind::Remote;
+        forward.local = "localhost:3000".into();
+        forward.remote = "9000".into();
+        assert_eq!((forward.flag(), forward.spec()), ("-R", "9000:localhost:3000".to_string()));
+    }
+}
@@ -0,0 +1,129 @@
+//! Guest sessions for demos, screen shares and shared machines. The shell runs with a
+//! throwaway HOME (so none of the user's dotfiles, history or credentials are visible),
+//! history is neither saved by the shell nor recorded by the app, and the home is deleted
+//! when the session closes. Homes left behind by a crash are removed on the next start.
+
+use crate::profiles::Profile;
+use crate::pty::{session_command, SessionManager, SessionOptions};
+use portable_pty::CommandBuilder;
+use std::fs;
+use std::path::PathBuf;
+
+fn guest_root() -> PathBuf {
+    std::env::temp_dir().join("shelll-guest")
+}
+
+// Named after the owning app process so stale homes can be told apart from live ones
+fn create_home() -> Result<PathBuf, String> {
+    let home = guest_root().join(format!("{}-{}", std::process::id(), uuid::Uuid::new_v4()));
+    fs::create_dir_all(home.join("tmp")).map_err(|e| format!("Failed to create {}: {}", home.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&home, fs::Permissions::from_mode(0o700));
+    }
+    Ok(home)
+}
+
+fn guest_command(mut cmd: CommandBuilder, home: &std::path::Path) -> CommandBuilder {
+    cmd.env("HOME", home);
+    cmd.env("ZDOTDIR", home);
+    cmd.env("TMPDIR", home.join("tmp"));
+    // Shells and common REPLs keep no history
+    cmd.env("HISTFILE", "/dev/null");
+    cmd.env("SAVEHIST", "0");
+    cmd.env("LESSHISTFILE", "-");
+    cmd.env("NODE_REPL_HISTORY", "");
+    cmd.env("PYTHON_HISTORY", "/dev/null");
+    // macOS /etc/zshrc_Apple_Terminal session restore
+    cmd.env("SHELL_SESSIONS_DISABLE", "1");
+    for name in ["SSH_AUTH_SOCK", "SSH_AGENT_PID", "GPG_AGENT_INFO"] {
+        cmd.env_remove(name);
+    }
+    cmd.cwd(home);
+    cmd
+}
+
+/// Removes guest homes whose app process is gone (it crashed before closing them).
+pub fn remove_stale_homes() {
+    remove_stale_dirs(&guest_root());
+}
+
+// Entries of `root` are named "<app pid>-..."
+pub(crate) fn remove_stale_dirs(root: &std::path::Path) {
+    let Ok(entries) = fs::read_dir(root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let owner = name.split('-').next().and_then(|pid| pid.parse::<u32>().ok());
+        if owner.is_none_or(|pid| !process_alive(pid)) {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    pid == std::process::id() || unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+fn process_alive(pid: u32) -> bool {
+    pid == std::process::id()
+}
+
+impl SessionManager {
+    /// Like `create_session`, with a temporary HOME and no history. The session always
+    /// starts in its HOME.
+    pub fn create_guest_session(&self, profile: &Profile, options: &SessionOptions) -> Result<String, String> {
+        let (cmd, process_name) = session_command(profile, options)?;
+        let home = create_home()?;
+        let session_id = match self.spawn_sized(guest_command(cmd, &home), &process_name, options.size()) {
+            Ok(id) => id,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&home);
+                return Err(e);
+            }
+        };
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        if let Some(session) = sessions.get_mut(&session_id) {
+            session.keep_alive = profile.keep_alive;
+            session.guest_home = Some(home);
+        }
+        Ok(session_id)
+    }
+
+    pub fn is_guest_session(&self, session_id: &str) -> Result<bool, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        Ok(session.guest_home.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn guest_sessions_leave_nothing_behind() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let home = create_home().unwrap();
+        let id = manager.spawn_session(guest_command(CommandBuilder::new("sh"), &home), "sh").unwrap();
+        manager.sessions.lock().unwrap().get_mut(&id).unwrap().guest_home = Some(home.clone());
+
+        manager.write(&id, "echo secret > \"$HOME/notes\"\r").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert!(home.join("notes").exists());
+        assert!(manager.is_guest_session(&id).unwrap());
+        assert!(manager.history.lock().unwrap().entries().is_empty());
+
+        manager.close(&id).unwrap();
+        assert!(!home.exists());
+    }
+}
@@ -0,0 +1,242 @@
+//! Heads-ups for sessions that need attention while the user works in a target app (one
+//! the focus monitor watches): a bell or a fired automation shows a notification or
+//! bounces the Dock icon, and the next time Shelll comes to the front it jumps to that
+//! session. All of it happens in the backend, so it works while the window is hidden.
+
+use crate::config::{load_json, save_json};
+use crate::notification;
+use crate::pty::{PtySession, SessionManager};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A session ringing over and over gets one heads-up in this time
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
+// Coming to the front later than this after a heads-up doesn't jump anymore
+const JUMP_WINDOW: Duration = Duration::from_secs(120);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeadsUpStyle {
+    #[default]
+    Notification,
+    // Bounces the Dock icon (flashes the taskbar button elsewhere) once
+    Flash,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeadsUpSettings {
+    pub enabled: bool,
+    pub bell: bool,
+    pub triggers: bool,
+    pub style: HeadsUpStyle,
+}
+
+impl Default for HeadsUpSettings {
+    fn default() -> Self {
+        HeadsUpSettings { enabled: true, bell: true, triggers: true, style: HeadsUpStyle::Notification }
+    }
+}
+
+// Heads-up settings persisted as JSON in the config dir
+pub struct HeadsUpStore {
+    path: Option<PathBuf>,
+    pub settings: HeadsUpSettings,
+}
+
+impl HeadsUpStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        HeadsUpStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeadsUpReason {
+    Bell,
+    // An automation's output trigger, by name
+    Trigger(String),
+}
+
+/// How the backend reaches the app: whether the user is in a target app right now, and
+/// how to draw attention without a notification.
+#[derive(Clone)]
+pub struct HeadsUpHooks {
+    pub in_target: Arc<dyn Fn() -> bool + Send + Sync>,
+    pub flash: Arc<dyn Fn() + Send + Sync>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FocusSessionPayload {
+    pub session_id: String,
+}
+
+#[derive(Default)]
+pub(crate) struct HeadsUp {
+    settings: HeadsUpSettings,
+    hooks: Option<HeadsUpHooks>,
+    last: HashMap<String, Instant>,
+    // The session the last heads-up was about
+    pending: Option<(String, Instant)>,
+}
+
+impl HeadsUp {
+    // Whether `reason` in `session_id` gets a heads-up now; if so, it becomes the one
+    // to jump to
+    fn fire(&mut self, session_id: &str, reason: &HeadsUpReason, now: Instant) -> bool {
+        let settings = &self.settings;
+        let wanted = match reason {
+            HeadsUpReason::Bell => settings.bell,
+            HeadsUpReason::Trigger(_) => settings.triggers,
+        };
+        if !settings.enabled || !wanted {
+            return false;
+        }
+        if self.last.get(session_id).is_some_and(|at| now.duration_since(*at) < MIN_INTERVAL) {
+            return false;
+        }
+        self.last.insert(session_id.to_string(), now);
+        self.pending = Some((session_id.to_string(), now));
+        true
+    }
+
+    pub(crate) fn forget(&mut self, session_id: &str) {
+        self.last.remove(session_id);
+        if self.pending.as_ref().is_some_and(|(id, _)| id == session_id) {
+            self.pending = None;
+        }
+    }
+}
+
+// What the heads-up calls the session
+fn session_label(session: &PtySession) -> String {
+    session.name.clone().or_else(|| session.last_title.clone()).unwrap_or_else(|| session.shell.clone())
+}
+
+/// Raises a heads-up for `session_id` if the user is in a target app and the settings
+/// want one for `reason`.
+pub(crate) fn raise(
+    heads_up: &Mutex<HeadsUp>,
+    session_id: &str,
+    reason: HeadsUpReason,
+    sessions: &Mutex<HashMap<String, PtySession>>,
+) {
+    let Ok(mut state) = heads_up.lock() else { return };
+    let Some(hooks) = state.hooks.clone() else { return };
+    // Asked first: the user may simply be looking at Shelll
+    if !(hooks.in_target)() || !state.fire(session_id, &reason, Instant::now()) {
+        return;
+    }
+    let style = state.settings.style;
+    drop(state);
+
+    let label = sessions.lock().ok()
+        .and_then(|sessions| sessions.get(session_id).map(session_label))
+        .unwrap_or_else(|| "A session".to_string());
+    let title = match &reason {
+        HeadsUpReason::Bell => format!("{} rang the bell", label),
+        HeadsUpReason::Trigger(name) => format!("{} fired {}", label, name),
+    };
+    thread::spawn(move || {
+        let shown = match style {
+            HeadsUpStyle::Notification => notification::show_notification(&title, "Switch to Shelll to open it"),
+            HeadsUpStyle::Flash => Err(String::new()),
+        };
+        if shown.is_err() {
+            (hooks.flash)();
+        }
+    });
+}
+
+impl SessionManager {
+    pub fn set_heads_up_settings(&self, settings: HeadsUpSettings) -> Result<(), String> {
+        self.heads_up.lock().map_err(|_| "Lock poisoned")?.settings = settings;
+        Ok(())
+    }
+
+    pub fn set_heads_up_hooks(&self, hooks: HeadsUpHooks) -> Result<(), String> {
+        self.heads_up.lock().map_err(|_| "Lock poisoned")?.hooks = Some(hooks);
+        Ok(())
+    }
+
+    /// Call when Shelll comes to the front. If a heads-up went out recently, tells the
+    /// frontend to show its session (`focus-session`) and returns the session's id.
+    pub fn take_heads_up_jump(&self) -> Result<Option<String>, String> {
+        let pending = self.heads_up.lock().map_err(|_| "Lock poisoned")?.pending.take();
+        let Some((session_id, _)) = pending.filter(|(_, at)| at.elapsed() < JUMP_WINDOW) else {
+            return Ok(None);
+        };
+        if !self.sessions.lock().map_err(|_| "Lock poisoned")?.contains_key(&session_id) {
+            return Ok(None);
+        }
+        self.events.emit("focus-session", FocusSessionPayload { session_id: session_id.clone() });
+        Ok(Some(session_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_per_session_and_follows_settings() {
+        let mut heads_up = HeadsUp::default();
+        let now = Instant::now();
+        assert!(heads_up.fire("s1", &HeadsUpReason::Bell, now));
+        assert!(!heads_up.fire("s1", &HeadsUpReason::Bell, now + Duration::from_secs(2)));
+        assert!(heads_up.fire("s2", &HeadsUpReason::Trigger("done".into()), now + Duration::from_secs(2)));
+        assert_eq!(heads_up.pending.as_ref().map(|(id, _)| id.as_str()), Some("s2"));
+        assert!(heads_up.fire("s1", &HeadsUpReason::Bell, now + MIN_INTERVAL));
+
+        heads_up.settings.bell = false;
+        assert!(!heads_up.fire("s3", &HeadsUpReason::Bell, now));
+        heads_up.forget("s1");
+        assert_eq!(heads_up.pending, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flashes_only_in_target_apps_and_jumps_back() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        let in_target = Arc::new(AtomicBool::new(false));
+        let flashes = Arc::new(AtomicUsize::new(0));
+        manager.set_heads_up_settings(HeadsUpSettings { style: HeadsUpStyle::Flash, ..Default::default() }).unwrap();
+        manager.set_heads_up_hooks(HeadsUpHooks {
+            in_target: { let in_target = in_target.clone(); Arc::new(move || in_target.load(Ordering::SeqCst)) },
+            flash: { let flashes = flashes.clone(); Arc::new(move || { flashes.fetch_add(1, Ordering::SeqCst); }) },
+        }).unwrap();
+
+        raise(&manager.heads_up, &id, HeadsUpReason::Bell, &manager.sessions);
+        assert_eq!(manager.take_heads_up_jump().unwrap(), None);
+
+        in_target.store(true, Ordering::SeqCst);
+        raise(&manager.heads_up, &id, HeadsUpReason::Bell, &manager.sessions);
+        for _ in 0..100 {
+            if flashes.load(Ordering::SeqCst) > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(flashes.load(Ordering::SeqCst), 1);
+        assert_eq!(manager.take_heads_up_jump().unwrap(), Some(id.clone()));
+        assert_eq!(sink.named("focus-session")[0]["session_id"], id.as_str());
+        assert_eq!(manager.take_heads_up_jump().unwrap(), None);
+        manager.close(&id).unwrap();
+    }
+}
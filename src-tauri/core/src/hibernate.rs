@@ -0,0 +1,233 @@
+//! Idle-session hibernation: after a configurable period without input or output, a
+//! session's reader stops emitting (output is held back) and its processes can be
+//! SIGSTOPped. The next write revives it transparently.
+
+use crate::pty::{PtyOutputPayload, PtySession};
+use crate::stream::OutputStream;
+use crate::unix_now;
+use crate::SessionManager;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// Output produced while hibernated is held up to this size; older bytes are dropped.
+const MAX_HELD_BYTES: usize = 1024 * 1024;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HibernationPolicy {
+    // Seconds of inactivity before hibernating; 0 disables hibernation
+    pub idle_secs: u64,
+    // Also SIGSTOP the shell and its foreground job while hibernated
+    pub suspend_process: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct HibernationSnapshot {
+    pub hibernated_at: u64,
+    pub output_offset: u64,
+    pub suspended: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct HibernationPayload {
+    pub session_id: String,
+    pub hibernated: bool,
+    pub snapshot: Option<HibernationSnapshot>,
+}
+
+/// Sits between the PTY reader and the event sink, holding output back while paused.
+#[derive(Default)]
+pub(crate) struct ReaderGate {
+    paused: AtomicBool,
+    held: Mutex<Vec<u8>>,
+}
+
+impl ReaderGate {
+    // Returns the bytes to emit now, or None if they were held back.
+    pub(crate) fn pass(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let mut held = self.held.lock().ok()?;
+        if !self.paused.load(Ordering::SeqCst) {
+            return Some(data.to_vec());
+        }
+        held.extend_from_slice(data);
+        if held.len() > MAX_HELD_BYTES {
+            let excess = held.len() - MAX_HELD_BYTES;
+            held.drain(..excess);
+        }
+        None
+    }
+
+    pub(crate) fn pause(&self) {
+        if let Ok(_held) = self.held.lock() {
+            self.paused.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn resume(&self) -> Vec<u8> {
+        match self.held.lock() {
+            Ok(mut held) => {
+                self.paused.store(false, Ordering::SeqCst);
+                std::mem::take(&mut *held)
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+// The shell's process group and the terminal's foreground job (if different)
+#[cfg(unix)]
+pub(crate) fn session_groups(session: &PtySession) -> Vec<libc::pid_t> {
+    let mut groups = Vec::new();
+    if let Some(pid) = session.pid {
+        groups.push(pid as libc::pid_t);
+    }
+    if let Some(fg) = session.master.lock().ok().and_then(|m| m.process_group_leader()) {
+        if !groups.contains(&fg) {
+            groups.push(fg);
+        }
+    }
+    groups
+}
+
+#[cfg(unix)]
+pub(crate) fn signal_session(session: &PtySession, signal: libc::c_int) {
+    for pgid in session_groups(session) {
+        unsafe {
+            libc::kill(-pgid, signal);
+        }
+    }
+}
+
+impl SessionManager {
+    pub fn set_hibernation_policy(&self, policy: HibernationPolicy) -> Result<(), String> {
+        let mut current = self.hibernation_policy.lock().map_err(|_| "Lock poisoned")?;
+        *current = policy;
+        Ok(())
+    }
+
+    pub fn hibernation_policy(&self) -> Result<HibernationPolicy, String> {
+        let policy = self.hibernation_policy.lock().map_err(|_| "Lock poisoned")?;
+        Ok(policy.clone())
+    }
+
+    /// Periodically hibernates sessions that have been idle longer than the policy allows.
+    pub fn start_hibernation_sweeper(self: &std::sync::Arc<Self>) {
+        let manager = std::sync::Arc::downgrade(self);
+        thread::spawn(move || {
+            while let Some(manager) = manager.upgrade() {
+                let _ = manager.hibernate_idle_sessions();
+                drop(manager);
+                thread::sleep(SWEEP_INTERVAL);
+            }
+        });
+    }
+
+    pub fn hibernate_idle_sessions(&self) -> Result<Vec<String>, String> {
+        let policy = self.hibernation_policy()?;
+        if policy.idle_secs == 0 {
+            return Ok(Vec::new());
+        }
+        let now = unix_now();
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let mut hibernated = Vec::new();
+        for (id, session) in sessions.iter_mut() {
+            let idle = now.saturating_sub(session.last_activity.load(Ordering::SeqCst));
+            if session.hibernation.is_none() && session.pool_state.is_none() && idle >= policy.idle_secs {
+                self.hibernate(id, session, policy.suspend_process);
+                hibernated.push(id.clone());
+            }
+        }
+        Ok(hibernated)
+    }
+
+    pub fn hibernate_session(&self, session_id: &str, suspend_process: bool) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        if session.hibernation.is_none() {
+            self.hibernate(session_id, session, suspend_process);
+        }
+        Ok(())
+    }
+
+    pub fn revive_session(&self, session_id: &str) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        self.revive(session_id, session);
+        Ok(())
+    }
+
+    pub(crate) fn hibernate(&self, session_id: &str, session: &mut PtySession, suspend_process: bool) {
+        session.gate.pause();
+        #[cfg(unix)]
+        if suspend_process {
+            signal_session(session, libc::SIGSTOP);
+        }
+        let snapshot = HibernationSnapshot {
+            hibernated_at: unix_now(),
+            output_offset: session.output_offset.load(Ordering::SeqCst),
+            suspended: cfg!(unix) && suspend_process,
+        };
+        session.hibernation = Some(snapshot.clone());
+        self.events.emit("session-hibernation-changed", HibernationPayload {
+            session_id: session_id.to_string(),
+            hibernated: true,
+            snapshot: Some(snapshot),
+        });
+    }
+
+    pub(crate) fn revive(&self, session_id: &str, session: &mut PtySession) {
+        let Some(snapshot) = session.hibernation.take() else {
+            return;
+        };
+        #[cfg(unix)]
+        if snapshot.suspended {
+            signal_session(session, libc::SIGCONT);
+        }
+        session.last_activity.store(unix_now(), Ordering::SeqCst);
+
+        self.events.emit("session-hibernation-changed", HibernationPayload {
+            session_id: session_id.to_string(),
+            hibernated: false,
+            snapshot: Some(snapshot),
+        });
+        let held = session.gate.resume();
+        if !held.is_empty() {
+            self.events.emit("pty-output", PtyOutputPayload {
+                session_id: session_id.to_string(),
+                data: self.output_protocol.encode(held),
+                stream: OutputStream::Stdout,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gate_holds_output_while_paused() {
+        let gate = ReaderGate::default();
+        assert_eq!(gate.pass(b"a"), Some(b"a".to_vec()));
+        gate.pause();
+        assert_eq!(gate.pass(b"b"), None);
+        assert_eq!(gate.pass(b"c"), None);
+        assert_eq!(gate.resume(), b"bc".to_vec());
+        assert_eq!(gate.pass(b"d"), Some(b"d".to_vec()));
+    }
+
+    #[test]
+    fn gate_drops_oldest_bytes_beyond_cap() {
+        let gate = ReaderGate::default();
+        gate.pause();
+        gate.pass(&vec![b'x'; MAX_HELD_BYTES]);
+        gate.pass(b"tail");
+        let held = gate.resume();
+        assert_eq!(held.len(), MAX_HELD_BYTES);
+        assert!(held.ends_with(b"tail"));
+    }
+}
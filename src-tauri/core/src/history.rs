@@ -0,0 +1,437 @@
+use crate::unix_now;
+use crate::usage::CommandResources;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+const HISTORY_MAX_ENTRIES: usize = 10_000;
+pub const HISTORY_SEARCH_LIMIT: usize = 20;
+
+// Reconstructs the command line being typed from raw keystrokes so submitted commands
+// can be recorded. Lines edited with cursor keys are not recorded since their final text
+// can't be known from the input alone.
+#[derive(Clone, Default)]
+pub struct InputLineTracker {
+    line: String,
+    edited: bool,
+    escape: EscapeState,
+}
+
+#[derive(Clone, Default, PartialEq)]
+enum EscapeState {
+    #[default]
+    None,
+    Esc,
+    Csi,
+}
+
+impl InputLineTracker {
+    pub fn feed(&mut self, data: &str) -> Vec<String> {
+        let mut submitted = Vec::new();
+        for ch in data.chars() {
+            match self.escape {
+                EscapeState::Esc => {
+                    self.escape = if ch == '[' || ch == 'O' { EscapeState::Csi } else { EscapeState::None };
+                    continue;
+                }
+                EscapeState::Csi => {
+                    if ('@'..='~').contains(&ch) {
+                        self.escape = EscapeState::None;
+                        // Arrow keys, Home/End: the shell's line editor moved the cursor
+                        if matches!(ch, 'A' | 'B' | 'C' | 'D' | 'H' | 'F') {
+                            self.edited = true;
+                        }
+                    }
+                    continue;
+                }
+                EscapeState::None => {}
+            }
+
+            match ch {
+                '\x1b' => self.escape = EscapeState::Esc,
+                '\r' | '\n' => {
+                    let line = std::mem::take(&mut self.line);
+                    let edited = std::mem::take(&mut self.edited);
+                    if !edited && !line.trim().is_empty() {
+                        submitted.push(line.trim().to_string());
+                    }
+                }
+                '\x7f' | '\x08' => {
+                    self.line.pop();
+                }
+                // Ctrl-C / Ctrl-U discard the line
+                '\x03' | '\x15' => {
+                    self.line.clear();
+                    self.edited = false;
+                }
+                c if c.is_control() && c != '\t' => {}
+                c => self.line.push(c),
+            }
+        }
+        submitted
+    }
+
+    // The line Enter would submit now, if its text is known
+    pub(crate) fn pending(&self) -> Option<&str> {
+        let line = self.line.trim();
+        (!self.edited && self.escape == EscapeState::None && !line.is_empty()).then_some(line)
+    }
+}
+
+// Byte offset in `haystack` of the first match of the lowercased `needle`. Lowercasing can
+// change a character's length, so the haystack is compared char by char in place.
+fn find_ignoring_case(haystack: &str, needle: &str) -> Option<usize> {
+    let starts_here = |rest: &str| {
+        let mut lowered = rest.chars().flat_map(char::to_lowercase);
+        needle.chars().all(|c| lowered.next() == Some(c))
+    };
+    haystack.char_indices().map(|(i, _)| i)
+        .chain([haystack.len()])
+        .find(|&i| starts_here(&haystack[i..]))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub session_id: String,
+    pub cwd: Option<String>,
+    // Remote host or container the command was typed into (see `remote`)
+    #[serde(default)]
+    pub host: Option<String>,
+    pub exit_status: Option<i32>,
+    pub timestamp: u64,
+    // Approximate CPU time and peak memory, once the command has finished (see `usage`)
+    #[serde(default)]
+    pub resources: Option<CommandResources>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct HistoryMatch {
+    pub command: String,
+    pub score: f64,
+    // Byte offset of the query within `command`, for highlighting
+    pub match_start: usize,
+    pub use_count: u32,
+    pub last_used: u64,
+    pub cwd: Option<String>,
+    pub exit_status: Option<i32>,
+    pub session_id: String,
+    // Of the latest use
+    pub resources: Option<CommandResources>,
+}
+
+// Cross-session command history, persisted as JSON lines in the app data dir. An entry
+// updated after the fact (e.g. with its resource usage) is appended again and replaces
+// the earlier line on load. The file is rewritten without superseded or dropped lines
+// when loading finds any, or once it has grown to twice the cap.
+pub struct HistoryStore {
+    entries: VecDeque<HistoryEntry>,
+    // Lines in the file, superseded ones included
+    file_lines: usize,
+    // File I/O happens on this thread, off the keystroke path
+    writer: Option<(Sender<FileWrite>, JoinHandle<()>)>,
+}
+
+enum FileWrite {
+    Append(String),
+    // Replaces the whole file
+    Rewrite(Vec<String>),
+}
+
+fn open_for_append(path: &Path) -> Option<BufWriter<File>> {
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    OpenOptions::new().create(true).append(true).open(path).ok().map(BufWriter::new)
+}
+
+// Written next to the file and renamed over it, so a crash leaves one or the other
+fn replace_file(path: &Path, lines: &[String]) {
+    let tmp = path.with_extension("jsonl.tmp");
+    let written = File::create(&tmp).and_then(|file| {
+        let mut file = BufWriter::new(file);
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        file.flush()
+    });
+    if written.is_ok() {
+        let _ = fs::rename(&tmp, path);
+    } else {
+        let _ = fs::remove_file(&tmp);
+    }
+}
+
+// Writes everything queued, then flushes; exits once the store is dropped
+fn spawn_writer(path: PathBuf) -> (Sender<FileWrite>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let mut file: Option<BufWriter<File>> = None;
+        while let Ok(first) = rx.recv() {
+            for write in std::iter::once(first).chain(rx.try_iter()) {
+                match write {
+                    FileWrite::Append(line) => {
+                        if file.is_none() {
+                            file = open_for_append(&path);
+                        }
+                        if let Some(file) = file.as_mut() {
+                            let _ = writeln!(file, "{}", line);
+                        }
+                    }
+                    FileWrite::Rewrite(lines) => {
+                        // Flushes what was appended before it
+                        file = None;
+                        replace_file(&path, &lines);
+                    }
+                }
+            }
+            if let Some(file) = file.as_mut() {
+                let _ = file.flush();
+            }
+        }
+    });
+    (tx, handle)
+}
+
+impl HistoryStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let content = path.as_ref().and_then(|p| fs::read_to_string(p).ok()).unwrap_or_default();
+        let file_lines = content.lines().count();
+        let mut entries: VecDeque<HistoryEntry> = VecDeque::with_capacity(file_lines);
+        let mut index: HashMap<(String, u64, String), usize> = HashMap::new();
+        for entry in content.lines().filter_map(|l| serde_json::from_str::<HistoryEntry>(l).ok()) {
+            let key = (entry.session_id.clone(), entry.timestamp, entry.command.clone());
+            match index.get(&key) {
+                Some(&i) => entries[i] = entry,
+                None => {
+                    index.insert(key, entries.len());
+                    entries.push_back(entry);
+                }
+            }
+        }
+        if entries.len() > HISTORY_MAX_ENTRIES {
+            entries.drain(..entries.len() - HISTORY_MAX_ENTRIES);
+        }
+        let mut store = HistoryStore { entries, file_lines, writer: path.map(spawn_writer) };
+        if store.entries.len() < file_lines {
+            store.rewrite();
+        }
+        store
+    }
+
+    pub(crate) fn entries(&self) -> &VecDeque<HistoryEntry> {
+        &self.entries
+    }
+
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.entries.push_back(entry);
+        if self.entries.len() > HISTORY_MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        if let Some(entry) = self.entries.back().cloned() {
+            self.append(&entry);
+        }
+    }
+
+    /// Sets the resource usage of the entry `record`ed for `command` in `session_id` at
+    /// `timestamp`. False if it's no longer in the history.
+    pub fn set_resources(&mut self, session_id: &str, timestamp: u64, command: &str, resources: CommandResources) -> bool {
+        self.update(session_id, timestamp, command, |entry| entry.resources = Some(resources))
+    }
+
+    /// Sets the exit status of an entry, as reported by the shell (see `semantic`).
+    pub fn set_exit_status(&mut self, session_id: &str, timestamp: u64, command: &str, exit_status: i32) -> bool {
+        self.update(session_id, timestamp, command, |entry| entry.exit_status = Some(exit_status))
+    }
+
+    fn update(&mut self, session_id: &str, timestamp: u64, command: &str, change: impl FnOnce(&mut HistoryEntry)) -> bool {
+        let Some(entry) = self.entries.iter_mut().rev()
+            .find(|e| e.session_id == session_id && e.timestamp == timestamp && e.command == command)
+        else {
+            return false;
+        };
+        change(entry);
+        let entry = entry.clone();
+        self.append(&entry);
+        true
+    }
+
+    fn append(&mut self, entry: &HistoryEntry) {
+        let Some((writer, _)) = &self.writer else { return };
+        self.file_lines += 1;
+        if self.file_lines > 2 * HISTORY_MAX_ENTRIES {
+            self.rewrite();
+        } else if let Ok(line) = serde_json::to_string(entry) {
+            let _ = writer.send(FileWrite::Append(line));
+        }
+    }
+
+    fn rewrite(&mut self) {
+        let Some((writer, _)) = &self.writer else { return };
+        let lines = self.entries.iter().filter_map(|e| serde_json::to_string(e).ok()).collect();
+        self.file_lines = self.entries.len();
+        let _ = writer.send(FileWrite::Rewrite(lines));
+    }
+
+    // Frecency ranking: each use of a command contributes a weight that decays with age,
+    // and prefix matches rank above matches elsewhere in the line.
+    pub fn search(&self, query: &str, session_id: Option<&str>, limit: usize) -> Vec<HistoryMatch> {
+        let now = unix_now();
+        let needle = query.to_lowercase();
+        let mut by_command: HashMap<&str, HistoryMatch> = HashMap::new();
+
+        for entry in &self.entries {
+            if session_id.is_some_and(|sid| sid != entry.session_id) {
+                continue;
+            }
+            let Some(match_start) = find_ignoring_case(&entry.command, &needle) else {
+                continue;
+            };
+            let age = now.saturating_sub(entry.timestamp);
+            let weight = match age {
+                a if a < 60 * 60 => 4.0,
+                a if a < 24 * 60 * 60 => 2.0,
+                a if a < 7 * 24 * 60 * 60 => 1.0,
+                _ => 0.5,
+            };
+            let m = by_command.entry(entry.command.as_str()).or_insert_with(|| HistoryMatch {
+                command: entry.command.clone(),
+                score: 0.0,
+                match_start,
+                use_count: 0,
+                last_used: 0,
+                cwd: None,
+                exit_status: None,
+                session_id: String::new(),
+                resources: None,
+            });
+            m.score += weight;
+            m.use_count += 1;
+            if entry.timestamp >= m.last_used {
+                m.last_used = entry.timestamp;
+                m.cwd = entry.cwd.clone();
+                m.exit_status = entry.exit_status;
+                m.session_id = entry.session_id.clone();
+                m.resources = entry.resources.clone();
+            }
+        }
+
+        let mut matches: Vec<HistoryMatch> = by_command.into_values()
+            .map(|mut m| {
+                if m.match_start == 0 {
+                    m.score *= 2.0;
+                }
+                m
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.last_used.cmp(&a.last_used)));
+        matches.truncate(limit);
+        matches
+    }
+}
+
+// Waits for what's queued to reach the file
+impl Drop for HistoryStore {
+    fn drop(&mut self) {
+        if let Some((writer, handle)) = self.writer.take() {
+            drop(writer);
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, session_id: &str, age: u64) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            session_id: session_id.to_string(),
+            cwd: None,
+            host: None,
+            exit_status: None,
+            timestamp: unix_now() - age,
+            resources: None,
+        }
+    }
+
+    #[test]
+    fn tracker_records_submitted_lines() {
+        let mut tracker = InputLineTracker::default();
+        assert!(tracker.feed("git sta").is_empty());
+        assert_eq!(tracker.feed("tuss\x7f\r"), vec!["git status"]);
+        assert!(tracker.feed("   \r").is_empty());
+    }
+
+    #[test]
+    fn tracker_skips_lines_edited_with_cursor_keys() {
+        let mut tracker = InputLineTracker::default();
+        assert!(tracker.feed("ls\x1b[Dx\r").is_empty());
+        assert_eq!(tracker.feed("\x1b[Acat\x03pwd\r"), vec!["pwd"]);
+        assert_eq!(tracker.feed("echo hi\r"), vec!["echo hi"]);
+    }
+
+    #[test]
+    fn match_offsets_point_into_the_original_command() {
+        // The Kelvin sign lowercases to fewer bytes, 'İ' to more
+        let command = "echo \u{212A}elvin İstanbul cat";
+        let mut store = HistoryStore::load(None);
+        store.record(entry(command, "a", 10));
+        for (query, expected) in [("kelvin", "\u{212A}elvin"), ("İSTANBUL", "İstanbul"), ("CAT", "cat")] {
+            let m = &store.search(query, None, 10)[0];
+            assert!(command[m.match_start..].starts_with(expected), "{}", query);
+        }
+    }
+
+    #[test]
+    fn search_ranks_by_frecency_and_prefix() {
+        let mut store = HistoryStore::load(None);
+        store.record(entry("cargo test", "a", 10 * 24 * 60 * 60));
+        store.record(entry("cargo build", "a", 60));
+        store.record(entry("cargo build", "b", 30));
+        store.record(entry("make cargo", "b", 10));
+
+        let results = store.search("cargo", None, 10);
+        let commands: Vec<&str> = results.iter().map(|m| m.command.as_str()).collect();
+        assert_eq!(commands, vec!["cargo build", "make cargo", "cargo test"]);
+        assert_eq!(results[0].use_count, 2);
+        assert_eq!(results[0].session_id, "b");
+
+        let only_a = store.search("cargo", Some("a"), 10);
+        assert_eq!(only_a.len(), 2);
+        assert_eq!(only_a[0].use_count, 1);
+    }
+
+    #[test]
+    fn updated_entries_replace_their_earlier_line() {
+        let path = std::env::temp_dir().join(format!("shelll-history-{}.jsonl", uuid::Uuid::new_v4()));
+        let mut store = HistoryStore::load(Some(path.clone()));
+        let first = entry("cargo build", "a", 60);
+        let timestamp = first.timestamp;
+        store.record(first);
+        store.record(entry("ls", "a", 30));
+        let resources = CommandResources { cpu_ms: 1500, peak_rss_kb: 2048, wall_ms: 3000 };
+        assert!(store.set_resources("a", timestamp, "cargo build", resources.clone()));
+        assert!(!store.set_resources("b", timestamp, "cargo build", resources.clone()));
+        assert!(store.set_exit_status("a", timestamp, "cargo build", 101));
+        drop(store);
+        let line_count = || fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(line_count(), 4);
+
+        let reloaded = HistoryStore::load(Some(path.clone()));
+        let commands: Vec<&str> = reloaded.entries().iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["cargo build", "ls"]);
+        assert_eq!(reloaded.entries()[0].resources, Some(resources));
+        assert_eq!(reloaded.entries()[0].exit_status, Some(101));
+        // Loading rewrote the file without the superseded lines
+        drop(reloaded);
+        let lines = line_count();
+        let _ = fs::remove_file(&path);
+        assert_eq!(lines, 2);
+    }
+}
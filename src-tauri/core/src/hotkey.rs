@@ -0,0 +1,239 @@
+//! The global hotkey that shows and focuses the main window from anywhere, and hides it
+//! again. Chords use the global shortcut manager's accelerator syntax
+//! ("CmdOrCtrl+Shift+Space") and are normalized before they're compared, so a conflict
+//! is found however either side was written.
+
+use crate::config::{load_json, save_json};
+use crate::dropdown::DropdownOptions;
+use serde::{Deserialize, Serialize};
+use std::ffi::c_void;
+use std::path::PathBuf;
+
+pub const DEFAULT_CHORD: &str = "CmdOrCtrl+Shift+Space";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeySettings {
+    pub enabled: bool,
+    pub chord: String,
+    // Set to slide the window down from the top of the display instead of just showing it
+    pub dropdown: Option<DropdownOptions>,
+}
+
+impl Default for HotkeySettings {
+    fn default() -> Self {
+        HotkeySettings { enabled: true, chord: DEFAULT_CHORD.to_string(), dropdown: None }
+    }
+}
+
+// Hotkey settings persisted as JSON in the config dir
+pub struct HotkeyStore {
+    path: Option<PathBuf>,
+    pub settings: HotkeySettings,
+    // The accelerator registered with the shortcut manager right now
+    pub registered: Option<String>,
+}
+
+impl HotkeyStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        HotkeyStore { path, settings, registered: None }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+
+    pub fn status(&self) -> HotkeyStatus {
+        HotkeyStatus { settings: self.settings.clone(), registered: self.registered.clone() }
+    }
+}
+
+/// Also the `hotkey-changed` payload.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HotkeyStatus {
+    pub settings: HotkeySettings,
+    // The chord registered right now; None if the hotkey is off or failed to register
+    pub registered: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Super,
+}
+
+impl Modifier {
+    fn parse(name: &str) -> Option<Modifier> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => Modifier::Ctrl,
+            "alt" | "option" => Modifier::Alt,
+            "shift" => Modifier::Shift,
+            "super" | "cmd" | "command" | "meta" => Modifier::Super,
+            "cmdorctrl" | "commandorcontrol" if cfg!(target_os = "macos") => Modifier::Super,
+            "cmdorctrl" | "commandorcontrol" => Modifier::Ctrl,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Modifier::Ctrl => "Ctrl",
+            Modifier::Alt => "Alt",
+            Modifier::Shift => "Shift",
+            Modifier::Super => "Super",
+        }
+    }
+}
+
+/// A parsed chord: modifiers plus one key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chord {
+    modifiers: Vec<Modifier>,
+    key: String,
+}
+
+impl Chord {
+    pub fn parse(chord: &str) -> Result<Chord, String> {
+        let mut modifiers = Vec::new();
+        let mut key = None;
+        for part in chord.split('+').map(str::trim) {
+            if part.is_empty() {
+                return Err(format!("Invalid hotkey: {}", chord));
+            }
+            if let Some(modifier) = Modifier::parse(part) {
+                modifiers.push(modifier);
+            } else if key.replace(normalize_key(part)).is_some() {
+                return Err(format!("A hotkey has one key besides modifiers: {}", chord));
+            }
+        }
+        let key = key.ok_or_else(|| format!("A hotkey needs a key besides modifiers: {}", chord))?;
+        // A bare letter would be taken from every app the user types in
+        let function_key = key.len() > 1 && key.starts_with('F') && key[1..].parse::<u8>().is_ok();
+        if modifiers.is_empty() && !function_key {
+            return Err(format!("A hotkey needs a modifier unless it's a function key: {}", chord));
+        }
+        modifiers.sort();
+        modifiers.dedup();
+        Ok(Chord { modifiers, key })
+    }
+}
+
+impl std::fmt::Display for Chord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier.name())?;
+        }
+        f.write_str(&self.key)
+    }
+}
+
+fn normalize_key(key: &str) -> String {
+    match key.to_ascii_lowercase().as_str() {
+        "esc" => "Escape".to_string(),
+        "return" => "Enter".to_string(),
+        "`" | "backquote" => "Grave".to_string(),
+        k if k.chars().count() == 1 => k.to_ascii_uppercase(),
+        _ => {
+            let mut chars = key.chars();
+            chars.next().map(|c| c.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase()).unwrap_or_default()
+        }
+    }
+}
+
+// Shortcuts the OS keeps for itself (or that users rely on everywhere), as normalized chords
+#[cfg(target_os = "macos")]
+const RESERVED: &[(&str, &str)] = &[
+    ("Super+Space", "Spotlight"),
+    ("Ctrl+Space", "switching input sources"),
+    ("Super+Tab", "the app switcher"),
+    ("Super+Grave", "cycling windows"),
+    ("Super+Q", "quitting apps"),
+    ("Super+W", "closing windows"),
+    ("Super+H", "hiding apps"),
+    ("Super+M", "minimizing windows"),
+    ("Alt+Super+Escape", "Force Quit"),
+    ("Ctrl+Super+Q", "locking the screen"),
+    ("Shift+Super+3", "screenshots"),
+    ("Shift+Super+4", "screenshots"),
+    ("Shift+Super+5", "screenshots"),
+];
+
+#[cfg(not(target_os = "macos"))]
+const RESERVED: &[(&str, &str)] = &[
+    ("Alt+Tab", "the window switcher"),
+    ("Alt+F4", "closing windows"),
+    ("Alt+Space", "the window menu"),
+    ("Ctrl+Alt+Delete", "the system menu"),
+    ("Super+L", "locking the screen"),
+    ("Super+D", "showing the desktop"),
+    ("Super+Tab", "the task view"),
+    ("Super+Space", "switching input sources"),
+];
+
+/// What `chord` would clash with: a system shortcut, or one of `in_use` (what else has
+/// registered a global shortcut, and its chord).
+pub fn find_conflict(chord: &Chord, in_use: &[(String, String)]) -> Option<String> {
+    let normalized = chord.to_string();
+    if let Some((_, what)) = RESERVED.iter().find(|(reserved, _)| *reserved == normalized) {
+        return Some(format!("{} is the system shortcut for {}", normalized, what));
+    }
+    in_use.iter()
+        .find(|(_, other)| Chord::parse(other).is_ok_and(|other| other == *chord))
+        .map(|(owner, _)| format!("{} is already used by {}", normalized, owner))
+}
+
+/// Lets the window show up over other apps' full-screen spaces, so the hotkey works
+/// while the target app is full screen. Must be called on the main thread.
+#[cfg(target_os = "macos")]
+pub fn show_over_fullscreen(ns_window: *mut c_void) -> Result<(), String> {
+    use objc::runtime::Object;
+    use objc::{msg_send, sel, sel_impl};
+    // NSWindowCollectionBehaviorCanJoinAllSpaces | NSWindowCollectionBehaviorFullScreenAuxiliary
+    const BEHAVIOR: usize = (1 << 0) | (1 << 8);
+
+    let window = ns_window as *mut Object;
+    if window.is_null() {
+        return Err("Window not available".into());
+    }
+    unsafe {
+        let current: usize = msg_send![window, collectionBehavior];
+        let _: () = msg_send![window, setCollectionBehavior: current | BEHAVIOR];
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn show_over_fullscreen(_ns_window: *mut c_void) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_chords() {
+        let chord = Chord::parse("shift + Super+space").unwrap();
+        assert_eq!(chord.to_string(), "Shift+Super+Space");
+        assert_eq!(Chord::parse("Command+Shift+SPACE").unwrap(), chord);
+        assert_eq!(Chord::parse("Ctrl+esc").unwrap().to_string(), "Ctrl+Escape");
+        assert_eq!(Chord::parse("F12").unwrap().to_string(), "F12");
+        assert!(Chord::parse("A").is_err());
+        assert!(Chord::parse("Ctrl+Shift").is_err());
+        assert!(Chord::parse("Ctrl+A+B").is_err());
+        assert!(Chord::parse("Ctrl++").is_err());
+    }
+
+    #[test]
+    fn finds_conflicts() {
+        let in_use = vec![("the docked window".to_string(), "alt+shift+d".to_string())];
+        let chord = Chord::parse("Shift+Alt+D").unwrap();
+        assert_eq!(find_conflict(&chord, &in_use).unwrap(), "Alt+Shift+D is already used by the docked window");
+        assert!(find_conflict(&Chord::parse("Super+Tab").unwrap(), &[]).unwrap().contains("system shortcut"));
+        assert_eq!(find_conflict(&Chord::parse(DEFAULT_CHORD).unwrap(), &in_use), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! Renders terminal output (text with SGR colors and attributes, and OSC 8 links) as
+//! HTML, so every path that hands out HTML (scrollback exports, copying output as rich
+//! text) draws it the same way. Output is rendered as a stream rather than a screen:
+//! cursor movement and erasing are ignored, except that a carriage return that isn't part
+//! of a line ending starts the line over (so progress bars show their last state).
+
+use crate::colors::{ColorTheme, Rgb};
+use crate::pty::SessionManager;
+use crate::vt::{Scanner, Sequence};
+use serde::Serialize;
+
+// Link schemes that are safe to put in an href
+const LINK_SCHEMES: &[&str] = &["http://", "https://", "mailto:", "file://", "ftp://"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Color {
+    #[default]
+    Default,
+    Palette(u8),
+    Rgb(Rgb),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Style {
+    fg: Color,
+    bg: Color,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+    inverse: bool,
+    strike: bool,
+}
+
+// The color after `38`/`48`: `5;n` or `2;r;g;b`, in `;` or `:` form. Returns it and how
+// many of the following `;` groups it used.
+fn extended_color(sub: &[u32], rest: &[&str]) -> (Option<Color>, usize) {
+    let rgb = |v: &[u32]| Color::Rgb(Rgb::new(v[0].min(255) as u8, v[1].min(255) as u8, v[2].min(255) as u8));
+    if sub.len() > 1 {
+        // 38:2::r:g:b has a color space id before the components
+        return match sub[1] {
+            5 if sub.len() > 2 => (Some(Color::Palette(sub[2].min(255) as u8)), 0),
+            2 if sub.len() > 4 => (Some(rgb(&sub[sub.len() - 3..])), 0),
+            _ => (None, 0),
+        };
+    }
+    let rest: Vec<u32> = rest.iter().map(|p| p.parse().unwrap_or(0)).collect();
+    match rest.first() {
+        Some(5) if rest.len() > 1 => (Some(Color::Palette(rest[1].min(255) as u8)), 2),
+        Some(2) if rest.len() > 3 => (Some(rgb(&rest[1..4])), 4),
+        Some(_) => (None, 1),
+        None => (None, 0),
+    }
+}
+
+impl Style {
+    fn apply_sgr(&mut self, params: &str) {
+        let groups: Vec<&str> = if params.is_empty() { vec!["0"] } else { params.split(';').collect() };
+        let mut i = 0;
+        while i < groups.len() {
+            let sub: Vec<u32> = groups[i].split(':').map(|p| p.parse().unwrap_or(0)).collect();
+            i += 1;
+            match sub[0] {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                // 4:0 turns underlining off; other 4:n are underline styles
+                4 => self.underline = sub.get(1) != Some(&0),
+                7 => self.inverse = true,
+                9 => self.strike = true,
+                21 => self.underline = true,
+                22 => (self.bold, self.dim) = (false, false),
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                29 => self.strike = false,
+                n @ 30..=37 => self.fg = Color::Palette(n as u8 - 30),
+                n @ 90..=97 => self.fg = Color::Palette(n as u8 - 90 + 8),
+                39 => self.fg = Color::Default,
+                n @ 40..=47 => self.bg = Color::Palette(n as u8 - 40),
+                n @ 100..=107 => self.bg = Color::Palette(n as u8 - 100 + 8),
+                49 => self.bg = Color::Default,
+                n @ (38 | 48) => {
+                    let (color, used) = extended_color(&sub, &groups[i..]);
+                    i += used;
+                    if let Some(color) = color {
+                        if n == 38 {
+                            self.fg = color;
+                        } else {
+                            self.bg = color;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Inline CSS; empty for the default style
+    fn css(&self, theme: &ColorTheme) -> String {
+        let resolve = |color: Color, default: Rgb| match color {
+            Color::Default => default,
+            Color::Palette(i) => theme.palette(i),
+            Color::Rgb(rgb) => rgb,
+        };
+        let (mut fg, mut bg) = (
+            (self.fg != Color::Default).then(|| resolve(self.fg, theme.foreground)),
+            (self.bg != Color::Default).then(|| resolve(self.bg, theme.background)),
+        );
+        if self.inverse {
+            (fg, bg) = (
+                Some(bg.unwrap_or(theme.background)),
+                Some(fg.unwrap_or(theme.foreground)),
+            );
+        }
+        let mut css = Vec::new();
+        if let Some(fg) = fg {
+            css.push(format!("color:{}", String::from(fg)));
+        }
+        if let Some(bg) = bg {
+            css.push(format!("background-color:{}", String::from(bg)));
+        }
+        if self.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        if self.dim {
+            css.push("opacity:0.6".to_string());
+        }
+        if self.italic {
+            css.push("font-style:italic".to_string());
+        }
+        match (self.underline, self.strike) {
+            (true, true) => css.push("text-decoration:underline line-through".to_string()),
+            (true, false) => css.push("text-decoration:underline".to_string()),
+            (false, true) => css.push("text-decoration:line-through".to_string()),
+            (false, false) => {}
+        }
+        css.join(";")
+    }
+}
+
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+// The URI an OSC 8 body opens a link to; None for the closing `8;;`
+fn osc8_target(osc: &[u8]) -> Option<Option<String>> {
+    let osc = std::str::from_utf8(osc).ok()?;
+    let rest = osc.strip_prefix("8;")?;
+    let (_params, uri) = rest.split_once(';')?;
+    Some((!uri.is_empty()).then(|| uri.to_string()))
+}
+
+/// Output rendered as HTML, with the same text without markup.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RenderedOutput {
+    pub html: String,
+    pub text: String,
+}
+
+#[derive(Default)]
+struct Renderer {
+    style: Style,
+    link: Option<String>,
+    // What the current line's markup has open
+    open_style: Option<Style>,
+    open_link: Option<String>,
+    line_html: String,
+    line_text: String,
+    // A carriage return waiting to see whether a line feed follows
+    pending_cr: bool,
+    html: String,
+    text: String,
+}
+
+impl Renderer {
+    fn close(&mut self) {
+        if self.open_style.take().is_some() {
+            self.line_html.push_str("</span>");
+        }
+        if self.open_link.take().is_some() {
+            self.line_html.push_str("</a>");
+        }
+    }
+
+    fn push_text(&mut self, text: &str, theme: &ColorTheme) {
+        if std::mem::take(&mut self.pending_cr) {
+            self.line_html.clear();
+            self.line_text.clear();
+            self.open_style = None;
+            self.open_link = None;
+        }
+        let css = self.style.css(theme);
+        let style = (!css.is_empty()).then(|| self.style.clone());
+        if self.open_link != self.link || self.open_style != style {
+            self.close();
+            if let Some(link) = &self.link {
+                self.line_html.push_str("<a href=\"");
+                escape(link, &mut self.line_html);
+                self.line_html.push_str("\">");
+                self.open_link = Some(link.clone());
+            }
+            if style.is_some() {
+                self.line_html.push_str("<span style=\"");
+                self.line_html.push_str(&css);
+                self.line_html.push_str("\">");
+                self.open_style = style;
+            }
+        }
+        escape(text, &mut self.line_html);
+        self.line_text.push_str(text);
+    }
+
+    fn end_line(&mut self, newline: bool) {
+        self.pending_cr = false;
+        self.close();
+        self.html.push_str(&std::mem::take(&mut self.line_html));
+        self.text.push_str(&std::mem::take(&mut self.line_text));
+        if newline {
+            self.html.push('\n');
+            self.text.push('\n');
+        }
+    }
+
+    fn feed(&mut self, sequences: Vec<Sequence>, theme: &ColorTheme) {
+        for sequence in sequences {
+            match sequence {
+                Sequence::Text(bytes) => self.push_text(&String::from_utf8_lossy(&bytes), theme),
+                Sequence::Control(b'\n') => self.end_line(true),
+                Sequence::Control(b'\r') => self.pending_cr = true,
+                Sequence::Control(b'\t') => self.push_text("\t", theme),
+                Sequence::Csi { private: None, params, intermediates, final_byte: b'm' } if intermediates.is_empty() => {
+                    self.style.apply_sgr(&params)
+                }
+                Sequence::Osc(osc) => match osc8_target(&osc) {
+                    Some(Some(uri)) if LINK_SCHEMES.iter().any(|s| uri.to_ascii_lowercase().starts_with(s)) => {
+                        self.link = Some(uri)
+                    }
+                    Some(_) => self.link = None,
+                    None => {}
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Renders raw output (as the PTY produced it) into a `<pre>` block, colored with `theme`.
+pub fn render(data: &[u8], theme: &ColorTheme) -> RenderedOutput {
+    let mut renderer = Renderer::default();
+    renderer.feed(Scanner::with_text().feed(data), theme);
+    renderer.end_line(false);
+    let mut html = format!(
+        "<pre style=\"color:{};background-color:{};font-family:ui-monospace,Menlo,Consolas,monospace;white-space:pre-wrap\">",
+        String::from(theme.foreground),
+        String::from(theme.background),
+    );
+    html.push_str(&renderer.html);
+    html.push_str("</pre>");
+    RenderedOutput { html, text: renderer.text }
+}
+
+/// A standalone HTML page showing `data`.
+pub fn render_document(title: &str, data: &[u8], theme: &ColorTheme) -> String {
+    let mut escaped_title = String::new();
+    escape(title, &mut escaped_title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body style=\"margin:0;background-color:{}\">\n{}\n</body>\n</html>\n",
+        escaped_title,
+        String::from(theme.background),
+        render(data, theme).html,
+    )
+}
+
+impl SessionManager {
+    // The session's retained output between two stream offsets (all of it if None),
+    // redacted, with the colors it's drawn in
+    fn output_for_html(&self, session_id: &str, range: Option<(u64, u64)>) -> Result<(Vec<u8>, ColorTheme, String), String> {
+        let theme = self.color_theme.lock().map_err(|_| "Lock poisoned")?.clone();
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let log = session.output_log.lock().map_err(|_| "Lock poisoned")?;
+        let data = match range {
+            Some((start, end)) => log.range(start, end),
+            None => log.contents(),
+        };
+        let theme = session.terminal.lock().map_err(|_| "Lock poisoned")?.colors.apply(&theme);
+        let title = session.last_title.clone().unwrap_or_else(|| session.shell.clone());
+        let redactor = self.redactor.lock().map_err(|_| "Lock poisoned")?;
+        let data = redactor.redact(&String::from_utf8_lossy(&data)).into_owned().into_bytes();
+        Ok((data, theme, title))
+    }
+
+    /// The session's scrollback as a standalone HTML page, for exporting.
+    pub fn export_output_html(&self, session_id: &str) -> Result<String, String> {
+        let (data, theme, title) = self.output_for_html(session_id, None)?;
+        Ok(render_document(&title, &data, &theme))
+    }
+
+    /// Output between two stream offsets (e.g. marks) as HTML and plain text, for copying
+    /// as rich text. Styles set before `start` aren't known.
+    pub fn output_html(&self, session_id: &str, start: u64, end: u64) -> Result<RenderedOutput, String> {
+        let (data, theme, _) = self.output_for_html(session_id, Some((start, end)))?;
+        Ok(render(&data, &theme))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(data: &str) -> String {
+        let html = render(data.as_bytes(), &ColorTheme::default()).html;
+        let start = html.find('>').unwrap() + 1;
+        html[start..html.len() - "</pre>".len()].to_string()
+    }
+
+    #[test]
+    fn renders_colors_and_attributes() {
+        assert_eq!(body("\x1b[1;31mfail\x1b[0m ok"), "<span style=\"color:#cc0000;font-weight:bold\">fail</span> ok");
+        assert_eq!(body("\x1b[38;5;196mx\x1b[38:2::1:2:3my"), "<span style=\"color:#ff0000\">x</span><span style=\"color:#010203\">y</span>");
+        assert_eq!(body("\x1b[48;2;0;0;255;4mz\x1b[24;49m."), "<span style=\"background-color:#0000ff;text-decoration:underline\">z</span>.");
+        assert_eq!(body("\x1b[7mi"), "<span style=\"color:#000000;background-color:#ffffff\">i</span>");
+        assert_eq!(body("a<b> & \"c\""), "a&lt;b&gt; &amp; &quot;c&quot;");
+    }
+
+    #[test]
+    fn renders_links_and_line_endings() {
+        let html = body("\x1b]8;;https://example.com/?a=1&b=2\x1b\\site\x1b]8;;\x1b\\ \x1b]8;;javascript:alert(1)\x07x\x1b]8;;\x07");
+        assert_eq!(html, "<a href=\"https://example.com/?a=1&amp;b=2\">site</a> x");
+        assert_eq!(body("\x1b[32mone\r\ntwo\x1b[m"), "<span style=\"color:#4e9a06\">one</span>\n<span style=\"color:#4e9a06\">two</span>");
+        let progress = render(b"10%\r50%\r\x1b[Kdone\n", &ColorTheme::default());
+        assert_eq!(progress.text, "done\n");
+    }
+}
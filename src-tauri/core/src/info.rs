@@ -0,0 +1,138 @@
+//! What the frontend needs to know about sessions: the list to rebuild its tabs from after
+//! a reload, and per session what is running in it and where.
+
+use crate::pool::PoolState;
+use crate::process::{foreground_pid, process_cwd, process_name};
+use crate::pty::SessionManager;
+use crate::remote::RemoteContext;
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub created_at: u64,
+    pub rows: u16,
+    pub cols: u16,
+    // Program the session was started with
+    pub shell: String,
+    // False once the process has exited (the session stays until it is closed)
+    pub alive: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionInfo {
+    pub session_id: String,
+    // The shell (None for sessions without a process, e.g. a tailed file)
+    pub pid: Option<u32>,
+    // The shell's working directory, or where the session started if it can't be read
+    pub cwd: Option<String>,
+    pub foreground_pid: Option<u32>,
+    pub foreground_process: Option<String>,
+    // Something other than the shell is in the foreground (worth confirming before closing)
+    pub busy: bool,
+    // Last title set by the program through escape sequences
+    pub title: Option<String>,
+    // Host or container the foreground job is connected to
+    pub remote: Option<RemoteContext>,
+}
+
+impl SessionManager {
+    pub fn session_info(&self, session_id: &str) -> Result<SessionInfo, String> {
+        let (pid, foreground, title) = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            (session.pid, foreground_pid(session), session.title.clone())
+        };
+        Ok(SessionInfo {
+            session_id: session_id.to_string(),
+            pid,
+            cwd: pid.and_then(process_cwd).or(title.cwd),
+            foreground_pid: foreground,
+            foreground_process: foreground.and_then(process_name),
+            busy: pid.is_some() && foreground != pid,
+            title: title.osc_title,
+            remote: title.remote,
+        })
+    }
+
+    /// Every open session, oldest first. Warm shells still in the pool aren't listed.
+    pub fn list_sessions(&self) -> Result<Vec<SessionSummary>, String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let mut summaries: Vec<SessionSummary> = sessions.iter_mut()
+            .filter(|(_, session)| session.pool_state != Some(PoolState::Idle))
+            .map(|(id, session)| {
+                let size = session.master.lock().ok().and_then(|m| m.get_size().ok());
+                let running = match session.child.as_mut() {
+                    Some(child) => matches!(child.try_wait(), Ok(None)),
+                    // Reaped, or never had a process (e.g. a tailed file)
+                    None => session.pid.is_none(),
+                };
+                SessionSummary {
+                    session_id: id.clone(),
+                    created_at: session.created_at,
+                    rows: size.map_or(0, |s| s.rows),
+                    cols: size.map_or(0, |s| s.cols),
+                    shell: session.shell.clone(),
+                    alive: running && !session.exited.load(Ordering::SeqCst),
+                }
+            })
+            .collect();
+        summaries.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.session_id.cmp(&b.session_id)));
+        Ok(summaries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use portable_pty::CommandBuilder;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn lists_sessions_with_size_and_liveness() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.resize(&id, 40, 120).unwrap();
+        let listed = manager.list_sessions().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].rows, listed[0].cols), (40, 120));
+        assert_eq!(listed[0].shell, "sh");
+        assert!(listed[0].alive);
+
+        manager.write(&id, "exit\r").unwrap();
+        for _ in 0..100 {
+            if !manager.list_sessions().unwrap()[0].alive {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!manager.list_sessions().unwrap()[0].alive);
+        manager.close(&id).unwrap();
+        assert!(manager.list_sessions().unwrap().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reports_the_foreground_job_and_cwd() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        manager.write(&id, &format!("cd {}\r", dir.display())).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let info = manager.session_info(&id).unwrap();
+        assert_eq!(info.cwd.as_deref(), Some(dir.to_str().unwrap()));
+        assert!(!info.busy);
+
+        manager.write(&id, "sleep 5\r").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let info = manager.session_info(&id).unwrap();
+        assert_eq!(info.foreground_process.as_deref(), Some("sleep"));
+        assert!(info.busy);
+        manager.close(&id).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+//! Recent input per session: the last lines submitted through `write`, kept in memory for
+//! per-tab recall that doesn't depend on the shell's own history (which a program like
+//! `psql` or a remote shell doesn't share). A line can be sent again, and the last send
+//! taken back while it still sits unsubmitted at the prompt.
+
+use crate::pty::SessionManager;
+use crate::unix_now;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+pub const MAX_RECENT_INPUTS: usize = 50;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RecentInput {
+    pub id: u64,
+    pub line: String,
+    pub sent_at: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct InputRing {
+    entries: VecDeque<RecentInput>,
+    next_id: u64,
+    // Characters the last write put on the prompt without submitting them, if it was
+    // plain text that backspaces can remove
+    last_send: Option<usize>,
+}
+
+impl InputRing {
+    pub(crate) fn record(&mut self, data: &str, submitted: &[String]) {
+        for line in submitted {
+            self.next_id += 1;
+            self.entries.push_back(RecentInput { id: self.next_id, line: line.clone(), sent_at: unix_now() });
+            if self.entries.len() > MAX_RECENT_INPUTS {
+                self.entries.pop_front();
+            }
+        }
+        self.last_send = (!data.is_empty() && !data.chars().any(char::is_control)).then(|| data.chars().count());
+    }
+
+    pub(crate) fn last(&self) -> Option<&RecentInput> {
+        self.entries.back()
+    }
+}
+
+impl SessionManager {
+    /// The session's submitted lines, newest first.
+    pub fn recent_inputs(&self, session_id: &str) -> Result<Vec<RecentInput>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        Ok(session.recent_inputs.entries.iter().rev().cloned().collect())
+    }
+
+    /// Submits a recent line again.
+    pub fn resend_input(&self, session_id: &str, input_id: u64) -> Result<(), String> {
+        let line = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            let input = session.recent_inputs.entries.iter().find(|i| i.id == input_id).ok_or("Input not found")?;
+            input.line.clone()
+        };
+        self.write(session_id, &format!("{}\r", line))
+    }
+
+    /// Erases what the last write typed, if it was plain text and nothing has been sent
+    /// since. Returns whether there was anything to take back.
+    pub fn undo_last_send(&self, session_id: &str) -> Result<bool, String> {
+        let count = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            session.recent_inputs.last_send
+        };
+        match count {
+            Some(count) => {
+                self.write(session_id, &"\x7f".repeat(count))?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_lines_and_the_undoable_send() {
+        let mut ring = InputRing::default();
+        for i in 0..MAX_RECENT_INPUTS + 5 {
+            ring.record("\r", &[format!("echo {}", i)]);
+        }
+        assert_eq!(ring.entries.len(), MAX_RECENT_INPUTS);
+        assert_eq!(ring.entries.front().unwrap().line, "echo 5");
+        assert_eq!(ring.last_send, None);
+
+        ring.record("git push --förce", &[]);
+        assert_eq!(ring.last_send, Some(16));
+        ring.record("\x1b[A", &[]);
+        assert_eq!(ring.last_send, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resends_and_undoes_through_the_session() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+        use std::sync::Arc;
+
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.write(&id, "true first\r").unwrap();
+        manager.write(&id, "true second\r").unwrap();
+        let inputs = manager.recent_inputs(&id).unwrap();
+        assert_eq!(inputs.iter().map(|i| i.line.as_str()).collect::<Vec<_>>(), ["true second", "true first"]);
+
+        manager.resend_input(&id, inputs[1].id).unwrap();
+        assert_eq!(manager.recent_inputs(&id).unwrap()[0].line, "true first");
+        assert!(manager.resend_input(&id, 999).is_err());
+
+        manager.write(&id, "rm -rf build").unwrap();
+        assert!(manager.undo_last_send(&id).unwrap());
+        // The backspaces themselves can't be undone
+        assert!(!manager.undo_last_send(&id).unwrap());
+        manager.close(&id).unwrap();
+    }
+}
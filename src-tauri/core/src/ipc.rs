@@ -0,0 +1,103 @@
+//! Wire format of `pty-output`. Tauri 1 events only carry JSON, and JSON has no bytes
+//! type: protocol 1 sends `data` as an array of numbers (up to four characters per byte,
+//! slow to parse), protocol 2 as a base64 string. Sessions use protocol 1 until the
+//! frontend negotiates something better, so an older frontend keeps working.
+
+use crate::pty::SessionManager;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+pub const PROTOCOL_JSON_ARRAY: u32 = 1;
+pub const PROTOCOL_BASE64: u32 = 2;
+pub const SUPPORTED_PROTOCOLS: &[u32] = &[PROTOCOL_JSON_ARRAY, PROTOCOL_BASE64];
+
+/// Output bytes as the negotiated protocol sends them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum OutputData {
+    Bytes(Vec<u8>),
+    Base64(String),
+}
+
+// The protocol in use, shared with every session's flusher
+#[derive(Clone)]
+pub(crate) struct OutputProtocol(Arc<AtomicU32>);
+
+impl Default for OutputProtocol {
+    fn default() -> Self {
+        OutputProtocol(Arc::new(AtomicU32::new(PROTOCOL_JSON_ARRAY)))
+    }
+}
+
+impl OutputProtocol {
+    pub(crate) fn version(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn encode(&self, data: Vec<u8>) -> OutputData {
+        match self.version() {
+            PROTOCOL_BASE64 => OutputData::Base64(BASE64.encode(data)),
+            _ => OutputData::Bytes(data),
+        }
+    }
+}
+
+impl SessionManager {
+    /// Switches `pty-output` to the newest protocol in `supported` that this side also
+    /// speaks, and returns it. Applies to output emitted from now on, in every session.
+    pub fn negotiate_output_protocol(&self, supported: &[u32]) -> Result<u32, String> {
+        let version = supported.iter()
+            .copied()
+            .filter(|v| SUPPORTED_PROTOCOLS.contains(v))
+            .max()
+            .ok_or_else(|| format!("No common output protocol; this version supports {:?}", SUPPORTED_PROTOCOLS))?;
+        self.output_protocol.0.store(version, Ordering::Relaxed);
+        Ok(version)
+    }
+
+    pub fn output_protocol(&self) -> u32 {
+        self.output_protocol.version()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::spawn_flusher;
+    use crate::events::testing::RecordingSink;
+    use crate::flow::FlowControl;
+    use crate::history::HistoryStore;
+    use crate::stream::OutputStream;
+
+    #[test]
+    fn negotiates_the_newest_common_protocol() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        assert_eq!(manager.output_protocol(), PROTOCOL_JSON_ARRAY);
+        assert_eq!(manager.negotiate_output_protocol(&[1, 2, 7]), Ok(PROTOCOL_BASE64));
+        assert!(manager.negotiate_output_protocol(&[7]).is_err());
+        assert_eq!(manager.output_protocol(), PROTOCOL_BASE64);
+        assert_eq!(manager.negotiate_output_protocol(&[1]), Ok(PROTOCOL_JSON_ARRAY));
+    }
+
+    #[test]
+    fn base64_output_round_trips() {
+        let sink = Arc::new(RecordingSink::default());
+        let protocol = OutputProtocol::default();
+        let (batcher, flusher) = spawn_flusher("s1".into(), sink.clone(), protocol.clone(), FlowControl::default());
+        batcher.push(OutputStream::Stdout, &[0x1b, b'[', b'm', 0xff, 0x00]);
+        batcher.close();
+        flusher.join().unwrap();
+        assert_eq!(sink.named("pty-output")[0]["data"], serde_json::json!([0x1b, b'[', b'm', 0xff, 0x00]));
+
+        protocol.0.store(PROTOCOL_BASE64, Ordering::Relaxed);
+        let (batcher, flusher) = spawn_flusher("s1".into(), sink.clone(), protocol, FlowControl::default());
+        batcher.push(OutputStream::Stdout, &[0x1b, b'[', b'm', 0xff, 0x00]);
+        batcher.close();
+        flusher.join().unwrap();
+        let encoded = sink.named("pty-output")[1]["data"].as_str().unwrap().to_string();
+        assert_eq!(BASE64.decode(encoded).unwrap(), [0x1b, b'[', b'm', 0xff, 0x00]);
+    }
+}
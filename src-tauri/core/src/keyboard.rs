@@ -0,0 +1,520 @@
+//! Keystroke injection into other apps (macOS). Each character is posted as the key that
+//! produces it on the current keyboard layout, so shortcuts and per-key handling in the
+//! target app behave as if it had been typed. Everything else goes in as text: characters
+//! with no key or behind a dead key, characters with combining marks, and all text while
+//! an input method (CJK and the like) is active, since keys would be composed by it again.
+//! Text is sent in Unicode keyboard events or, on request, inserted into the focused
+//! element through Accessibility.
+//!
+//! The character → key table is built from the layout with UCKeyTranslate and cached.
+//! Text Input Sources may only be queried on the main thread, so the table is (re)built
+//! there: once when the watcher starts and again on every layout switch notification.
+//! Injection itself can run on any thread.
+
+use crate::events::EventSink;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+#[cfg(any(target_os = "macos", test))]
+use std::collections::HashMap;
+
+#[derive(Clone, Serialize)]
+pub struct KeyboardLayoutPayload {
+    pub layout_id: String,
+    // The selected input source is an input method rather than a plain layout
+    pub ime_active: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionMethod {
+    // Layout keys where possible, Unicode events for the rest
+    #[default]
+    Auto,
+    // Unicode events only (Return and Tab are still keys)
+    Unicode,
+    // Set the focused element's selected text; falls back to Unicode events in apps
+    // without accessible text fields
+    Accessibility,
+}
+
+#[cfg(any(target_os = "macos", test))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct KeyStroke {
+    keycode: u16,
+    shift: bool,
+    option: bool,
+}
+
+#[cfg(any(target_os = "macos", test))]
+struct KeyMap {
+    layout_id: String,
+    strokes: HashMap<char, KeyStroke>,
+    ime_active: bool,
+}
+
+#[cfg(any(target_os = "macos", test))]
+impl KeyMap {
+    // `translate(keycode, shift, option)` is the character the key produces, if any.
+    // Keycodes 0-127 cover the keyboard; the main block comes before the keypad, and
+    // combinations with fewer modifiers win.
+    fn build(layout_id: String, mut translate: impl FnMut(u16, bool, bool) -> Option<char>) -> KeyMap {
+        let mut strokes = HashMap::new();
+        for (shift, option) in [(false, false), (true, false), (false, true), (true, true)] {
+            for keycode in 0..128 {
+                if let Some(c) = translate(keycode, shift, option) {
+                    if !c.is_control() || matches!(c, '\r' | '\t') {
+                        strokes.entry(c).or_insert(KeyStroke { keycode, shift, option });
+                    }
+                }
+            }
+        }
+        KeyMap { layout_id, strokes, ime_active: false }
+    }
+
+    fn stroke(&self, c: char) -> Option<KeyStroke> {
+        // Newlines are typed with Return
+        self.strokes.get(&if c == '\n' { '\r' } else { c }).copied()
+    }
+}
+
+// Layout-independent virtual keycodes
+#[cfg(any(target_os = "macos", test))]
+const RETURN_KEY: KeyStroke = KeyStroke { keycode: 36, shift: false, option: false };
+#[cfg(any(target_os = "macos", test))]
+const TAB_KEY: KeyStroke = KeyStroke { keycode: 48, shift: false, option: false };
+// A Unicode keyboard event carries at most this many UTF-16 units
+#[cfg(any(target_os = "macos", test))]
+const MAX_EVENT_UNITS: usize = 20;
+
+#[cfg(any(target_os = "macos", test))]
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Key(KeyStroke),
+    Text(String),
+}
+
+// Combining marks, variation selectors and skin tone modifiers belong to the character
+// before them
+#[cfg(any(target_os = "macos", test))]
+fn extends_cluster(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0xFE20..=0xFE2F
+            | 0xFE00..=0xFE0F | 0x200D | 0x1F3FB..=0x1F3FF | 0xE0100..=0xE01EF
+    )
+}
+
+// Splits `text` into keys and runs of text. Without a keymap only Return and Tab are keys.
+#[cfg(any(target_os = "macos", test))]
+fn segments(text: &str, keymap: Option<&KeyMap>) -> Vec<Segment> {
+    let mut clusters: Vec<String> = Vec::new();
+    let mut after_joiner = false;
+    for c in text.chars() {
+        match clusters.last_mut() {
+            Some(last) if extends_cluster(c) || after_joiner => last.push(c),
+            _ => clusters.push(c.to_string()),
+        }
+        after_joiner = c == '\u{200D}';
+    }
+
+    let mut out = Vec::new();
+    for cluster in clusters {
+        let mut chars = cluster.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some('\n' | '\r'), None) => Some(RETURN_KEY),
+            (Some('\t'), None) => Some(TAB_KEY),
+            (Some(c), None) => keymap.filter(|k| !k.ime_active).and_then(|k| k.stroke(c)),
+            _ => None,
+        };
+        if let Some(key) = key {
+            out.push(Segment::Key(key));
+            continue;
+        }
+        if let Some(Segment::Text(run)) = out.last_mut() {
+            if run.encode_utf16().count() + cluster.encode_utf16().count() <= MAX_EVENT_UNITS {
+                run.push_str(&cluster);
+                continue;
+            }
+        }
+        out.push(Segment::Text(cluster));
+    }
+    out
+}
+
+#[cfg(target_os = "macos")]
+static KEYMAP: Mutex<Option<KeyMap>> = Mutex::new(None);
+static LAYOUT_EVENTS: Mutex<Option<Arc<dyn EventSink>>> = Mutex::new(None);
+
+#[cfg(target_os = "macos")]
+mod mac {
+    use super::{KeyMap, KeyStroke};
+    use std::ffi::{c_void, CStr};
+    use std::os::raw::c_char;
+    use std::ptr;
+
+    type CFStringRef = *const c_void;
+    type CFNotificationCallback = extern "C" fn(*const c_void, *mut c_void, CFStringRef, *const c_void, *const c_void);
+
+    const UTF8: u32 = 0x0800_0100;
+    const DELIVER_IMMEDIATELY: isize = 4;
+    const HID_EVENT_TAP: u32 = 0;
+    const FLAG_SHIFT: u64 = 0x0002_0000;
+    const FLAG_OPTION: u64 = 0x0008_0000;
+    // UCKeyTranslate modifier state: (EventRecord modifiers >> 8)
+    const UC_SHIFT: u32 = 0x02;
+    const UC_OPTION: u32 = 0x08;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: *const c_void);
+        fn CFStringGetCString(s: CFStringRef, buffer: *mut c_char, size: isize, encoding: u32) -> u8;
+        fn CFStringCreateWithCString(allocator: *const c_void, s: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFStringCreateWithCharacters(allocator: *const c_void, chars: *const u16, length: isize) -> CFStringRef;
+        fn CFEqual(a: *const c_void, b: *const c_void) -> u8;
+        fn CFDataGetBytePtr(data: *const c_void) -> *const u8;
+        fn CFNotificationCenterGetDistributedCenter() -> *const c_void;
+        fn CFNotificationCenterAddObserver(
+            center: *const c_void,
+            observer: *const c_void,
+            callback: CFNotificationCallback,
+            name: CFStringRef,
+            object: *const c_void,
+            behavior: isize,
+        );
+    }
+
+    #[link(name = "Carbon", kind = "framework")]
+    extern "C" {
+        static kTISPropertyInputSourceID: CFStringRef;
+        static kTISPropertyUnicodeKeyLayoutData: CFStringRef;
+        static kTISPropertyInputSourceType: CFStringRef;
+        static kTISTypeKeyboardLayout: CFStringRef;
+        fn TISCopyCurrentKeyboardInputSource() -> *const c_void;
+        fn TISCopyCurrentKeyboardLayoutInputSource() -> *const c_void;
+        fn TISGetInputSourceProperty(source: *const c_void, key: CFStringRef) -> *const c_void;
+        fn LMGetKbdType() -> u8;
+        fn UCKeyTranslate(
+            layout: *const u8,
+            keycode: u16,
+            action: u16,
+            modifiers: u32,
+            keyboard_type: u32,
+            options: u32,
+            dead_key_state: *mut u32,
+            max_length: usize,
+            actual_length: *mut usize,
+            chars: *mut u16,
+        ) -> i32;
+    }
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXIsProcessTrusted() -> u8;
+        fn AXUIElementCreateSystemWide() -> *const c_void;
+        fn AXUIElementCopyAttributeValue(element: *const c_void, attribute: CFStringRef, value: *mut *const c_void) -> i32;
+        fn AXUIElementSetAttributeValue(element: *const c_void, attribute: CFStringRef, value: *const c_void) -> i32;
+        fn CGEventCreateKeyboardEvent(source: *const c_void, keycode: u16, down: bool) -> *mut c_void;
+        fn CGEventSetFlags(event: *mut c_void, flags: u64);
+        fn CGEventKeyboardSetUnicodeString(event: *mut c_void, length: usize, chars: *const u16);
+        fn CGEventPost(tap: u32, event: *mut c_void);
+    }
+
+    fn cf_string(s: CFStringRef) -> Option<String> {
+        let mut buffer = [0 as c_char; 256];
+        unsafe {
+            (CFStringGetCString(s, buffer.as_mut_ptr(), buffer.len() as isize, UTF8) != 0)
+                .then(|| CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+
+    /// Main thread only.
+    pub(super) fn current_keymap() -> Option<KeyMap> {
+        unsafe {
+            let source = TISCopyCurrentKeyboardLayoutInputSource();
+            if source.is_null() {
+                return None;
+            }
+            let id = TISGetInputSourceProperty(source, kTISPropertyInputSourceID);
+            let layout_id = if id.is_null() { String::new() } else { cf_string(id).unwrap_or_default() };
+            let data = TISGetInputSourceProperty(source, kTISPropertyUnicodeKeyLayoutData);
+            let keymap = (!data.is_null()).then(|| {
+                let layout = CFDataGetBytePtr(data);
+                let keyboard_type = LMGetKbdType() as u32;
+                KeyMap::build(layout_id, |keycode, shift, option| {
+                    let modifiers = if shift { UC_SHIFT } else { 0 } | if option { UC_OPTION } else { 0 };
+                    let mut dead_key_state = 0u32;
+                    let mut length = 0usize;
+                    let mut chars = [0u16; 4];
+                    let status = UCKeyTranslate(
+                        layout,
+                        keycode,
+                        0,
+                        modifiers,
+                        keyboard_type,
+                        0,
+                        &mut dead_key_state,
+                        chars.len(),
+                        &mut length,
+                        chars.as_mut_ptr(),
+                    );
+                    // Dead keys produce nothing until the next key
+                    if status != 0 || length != 1 {
+                        return None;
+                    }
+                    char::from_u32(chars[0] as u32)
+                })
+            });
+            CFRelease(source);
+            keymap.map(|mut keymap| {
+                keymap.ime_active = input_method_active();
+                keymap
+            })
+        }
+    }
+
+    // Input modes (Kotoeri, Pinyin, ...) are a different input source type than layouts
+    fn input_method_active() -> bool {
+        unsafe {
+            let source = TISCopyCurrentKeyboardInputSource();
+            if source.is_null() {
+                return false;
+            }
+            let kind = TISGetInputSourceProperty(source, kTISPropertyInputSourceType);
+            let active = !kind.is_null() && CFEqual(kind, kTISTypeKeyboardLayout) == 0;
+            CFRelease(source);
+            active
+        }
+    }
+
+    extern "C" fn layout_changed(
+        _center: *const c_void,
+        _observer: *mut c_void,
+        _name: CFStringRef,
+        _object: *const c_void,
+        _info: *const c_void,
+    ) {
+        super::refresh_layout();
+    }
+
+    /// Must run on the main thread; notifications are delivered on its run loop.
+    pub(super) fn observe_layout_changes() {
+        unsafe {
+            // Registered once and never removed, so the name is never released
+            let name = CFStringCreateWithCString(
+                ptr::null(),
+                c"com.apple.Carbon.TISNotifySelectedKeyboardInputSourceChanged".as_ptr(),
+                UTF8,
+            );
+            CFNotificationCenterAddObserver(
+                CFNotificationCenterGetDistributedCenter(),
+                ptr::null(),
+                layout_changed,
+                name,
+                ptr::null(),
+                DELIVER_IMMEDIATELY,
+            );
+        }
+    }
+
+    pub(super) fn accessibility_trusted() -> bool {
+        unsafe { AXIsProcessTrusted() != 0 }
+    }
+
+    fn post_event(keycode: u16, flags: u64, text: &[u16]) -> Result<(), String> {
+        for down in [true, false] {
+            unsafe {
+                let event = CGEventCreateKeyboardEvent(ptr::null(), keycode, down);
+                if event.is_null() {
+                    return Err("Failed to create keyboard event".into());
+                }
+                CGEventSetFlags(event, flags);
+                if !text.is_empty() {
+                    CGEventKeyboardSetUnicodeString(event, text.len(), text.as_ptr());
+                }
+                CGEventPost(HID_EVENT_TAP, event);
+                CFRelease(event as *const c_void);
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn post_key(stroke: KeyStroke) -> Result<(), String> {
+        let flags = if stroke.shift { FLAG_SHIFT } else { 0 } | if stroke.option { FLAG_OPTION } else { 0 };
+        post_event(stroke.keycode, flags, &[])
+    }
+
+    // At most MAX_EVENT_UNITS UTF-16 units
+    pub(super) fn post_text(text: &str) -> Result<(), String> {
+        let units: Vec<u16> = text.encode_utf16().collect();
+        post_event(0, 0, &units)
+    }
+
+    fn cf_str(s: &CStr) -> CFStringRef {
+        unsafe { CFStringCreateWithCString(ptr::null(), s.as_ptr(), UTF8) }
+    }
+
+    /// Replaces the selection in the focused text element. False if there is none, or it
+    /// doesn't accept text this way.
+    pub(super) fn insert_text(text: &str) -> bool {
+        unsafe {
+            let system = AXUIElementCreateSystemWide();
+            if system.is_null() {
+                return false;
+            }
+            let focused_attr = cf_str(c"AXFocusedUIElement");
+            let selected_attr = cf_str(c"AXSelectedText");
+            let mut focused: *const c_void = ptr::null();
+            let mut inserted = false;
+            if AXUIElementCopyAttributeValue(system, focused_attr, &mut focused) == 0 && !focused.is_null() {
+                let units: Vec<u16> = text.encode_utf16().collect();
+                let value = CFStringCreateWithCharacters(ptr::null(), units.as_ptr(), units.len() as isize);
+                inserted = AXUIElementSetAttributeValue(focused, selected_attr, value) == 0;
+                CFRelease(value);
+                CFRelease(focused);
+            }
+            CFRelease(focused_attr);
+            CFRelease(selected_attr);
+            CFRelease(system);
+            inserted
+        }
+    }
+}
+
+// Rebuilds the table for the layout now in use and reports it. Main thread.
+#[cfg(target_os = "macos")]
+fn refresh_layout() {
+    let Some(keymap) = mac::current_keymap() else {
+        return;
+    };
+    let layout_id = keymap.layout_id.clone();
+    let ime_active = keymap.ime_active;
+    let changed = match KEYMAP.lock() {
+        Ok(mut current) => {
+            let changed = current.as_ref().is_none_or(|k| k.layout_id != layout_id || k.ime_active != ime_active);
+            *current = Some(keymap);
+            changed
+        }
+        Err(_) => return,
+    };
+    let events = LAYOUT_EVENTS.lock().ok().and_then(|e| e.clone());
+    if let (true, Some(events)) = (changed, events) {
+        events.emit("keyboard-layout-changed", KeyboardLayoutPayload { layout_id, ime_active });
+    }
+}
+
+/// Starts tracking the keyboard layout. Call once, on the main thread.
+pub fn watch_keyboard_layout(events: Arc<dyn EventSink>) {
+    if let Ok(mut current) = LAYOUT_EVENTS.lock() {
+        *current = Some(events);
+    }
+    #[cfg(target_os = "macos")]
+    {
+        refresh_layout();
+        mac::observe_layout_changes();
+    }
+}
+
+/// Input source ID of the current layout (e.g. "com.apple.keylayout.German"), once the
+/// watcher has started.
+pub fn keyboard_layout() -> Option<String> {
+    #[cfg(target_os = "macos")]
+    return KEYMAP.lock().ok().and_then(|k| k.as_ref().map(|k| k.layout_id.clone()));
+    #[cfg(not(target_os = "macos"))]
+    None
+}
+
+/// Whether the app has the Accessibility permission (always true where there is none).
+pub fn accessibility_trusted() -> bool {
+    #[cfg(target_os = "macos")]
+    return mac::accessibility_trusted();
+    #[cfg(not(target_os = "macos"))]
+    true
+}
+
+/// Types `text` into the frontmost app. Requires the Accessibility permission.
+#[cfg(target_os = "macos")]
+pub fn inject_text(text: &str, method: InjectionMethod) -> Result<(), String> {
+    if !mac::accessibility_trusted() {
+        return Err("Typing into other apps requires the Accessibility permission".into());
+    }
+    if method == InjectionMethod::Accessibility && mac::insert_text(text) {
+        return Ok(());
+    }
+    let segments = {
+        let keymap = KEYMAP.lock().map_err(|_| "Lock poisoned")?;
+        let keymap = keymap.as_ref().filter(|_| method == InjectionMethod::Auto);
+        segments(text, keymap)
+    };
+    for segment in segments {
+        match segment {
+            Segment::Key(stroke) => mac::post_key(stroke)?,
+            Segment::Text(run) => mac::post_text(&run)?,
+        }
+        // Some apps drop events that arrive back to back
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn inject_text(_text: &str, _method: InjectionMethod) -> Result<(), String> {
+    Err("Typing into other apps is only supported on macOS".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A few keys of a German layout: y and z swapped, ß on its own key
+    fn german(keycode: u16, shift: bool, option: bool) -> Option<char> {
+        let c = match (keycode, option) {
+            (16, false) => 'z',
+            (6, false) => 'y',
+            (27, false) => 'ß',
+            (37, true) => '@',
+            (36, false) => '\r',
+            // A keypad key producing the same character as the main block
+            (89, false) => '7',
+            (26, false) => '7',
+            _ => return None,
+        };
+        Some(if shift { c.to_ascii_uppercase() } else { c })
+    }
+
+    #[test]
+    fn maps_characters_to_the_layouts_keys() {
+        let keymap = KeyMap::build("com.apple.keylayout.German".into(), german);
+        assert_eq!(keymap.layout_id, "com.apple.keylayout.German");
+        assert_eq!(keymap.stroke('z'), Some(KeyStroke { keycode: 16, shift: false, option: false }));
+        assert_eq!(keymap.stroke('Y'), Some(KeyStroke { keycode: 6, shift: true, option: false }));
+        assert_eq!(keymap.stroke('@'), Some(KeyStroke { keycode: 37, shift: false, option: true }));
+        assert_eq!(keymap.stroke('7').map(|s| s.keycode), Some(26));
+        assert_eq!(keymap.stroke('\n').map(|s| s.keycode), Some(36));
+        assert_eq!(keymap.stroke('é'), None);
+    }
+
+    #[test]
+    fn sends_composed_and_unmapped_text_as_unicode() {
+        let keymap = KeyMap::build("com.apple.keylayout.German".into(), german);
+        // e + combining acute stays together and goes in as text
+        assert_eq!(segments("ze\u{301}z\n", Some(&keymap)), vec![
+            Segment::Key(KeyStroke { keycode: 16, shift: false, option: false }),
+            Segment::Text("e\u{301}".into()),
+            Segment::Key(KeyStroke { keycode: 16, shift: false, option: false }),
+            Segment::Key(RETURN_KEY),
+        ]);
+        assert_eq!(segments("日本語z", Some(&keymap)), vec![
+            Segment::Text("日本語".into()),
+            Segment::Key(KeyStroke { keycode: 16, shift: false, option: false }),
+        ]);
+        // A ZWJ emoji sequence is one cluster
+        assert_eq!(segments("👩\u{200D}💻", None), vec![Segment::Text("👩\u{200D}💻".into())]);
+        // Runs are split to fit in one event
+        let long = "ü".repeat(25);
+        assert_eq!(segments(&long, Some(&keymap)).len(), 2);
+
+        let ime = KeyMap { ime_active: true, ..KeyMap::build("com.apple.keylayout.German".into(), german) };
+        assert_eq!(segments("zy", Some(&ime)), vec![Segment::Text("zy".into())]);
+    }
+}
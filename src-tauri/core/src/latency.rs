@@ -0,0 +1,148 @@
+//! Opt-in echo-latency probe: keystrokes written to a session are timestamped and
+//! matched against the next output read from it, which over SSH is almost always the
+//! echo. Gives numbers for "typing feels laggy" reports.
+
+use crate::pty::SessionManager;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const MAX_SAMPLES: usize = 1000;
+const MAX_PENDING: usize = 64;
+// Keystrokes that never produced output (e.g. typed at a password prompt) are dropped
+const PENDING_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Default)]
+pub(crate) struct LatencyProbe {
+    enabled: bool,
+    pending: VecDeque<Instant>,
+    // Round trips in microseconds, oldest first
+    samples: VecDeque<u64>,
+}
+
+impl LatencyProbe {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.pending.clear();
+            self.samples.clear();
+        }
+        self.enabled = enabled;
+    }
+
+    // Only single printable keystrokes are timed; pastes and control keys don't echo 1:1
+    pub(crate) fn input(&mut self, data: &str) {
+        let mut chars = data.chars();
+        let single_printable = matches!((chars.next(), chars.next()), (Some(c), None) if !c.is_control());
+        if !self.enabled || !single_printable {
+            return;
+        }
+        if self.pending.len() == MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(Instant::now());
+    }
+
+    // An output chunk of n bytes can echo at most n pending keystrokes
+    pub(crate) fn output(&mut self, len: usize) {
+        if !self.enabled || self.pending.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        while self.pending.front().is_some_and(|t| now.duration_since(*t) > PENDING_TIMEOUT) {
+            self.pending.pop_front();
+        }
+        for _ in 0..len.min(self.pending.len()) {
+            let Some(sent) = self.pending.pop_front() else { break };
+            if self.samples.len() == MAX_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(now.duration_since(sent).as_micros() as u64);
+        }
+    }
+
+    fn stats(&self) -> Option<LatencyStats> {
+        let mut sorted: Vec<u64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let percentile = |p: usize| {
+            // Nearest-rank
+            let rank = (p * sorted.len()).div_ceil(100).max(1);
+            sorted[rank - 1] as f64 / 1000.0
+        };
+        let max = *sorted.last()?;
+        Some(LatencyStats {
+            samples: sorted.len(),
+            p50_ms: percentile(50),
+            p90_ms: percentile(90),
+            p99_ms: percentile(99),
+            max_ms: max as f64 / 1000.0,
+        })
+    }
+}
+
+impl SessionManager {
+    /// Turning the probe off discards collected samples. It can't be turned on in
+    /// low-memory mode.
+    pub fn set_latency_probe(&self, session_id: &str, enabled: bool) -> Result<(), String> {
+        if enabled && self.low_memory_status()?.enabled {
+            return Err("Latency probes are off in low-memory mode".to_string());
+        }
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        session.latency.lock().map_err(|_| "Lock poisoned")?.set_enabled(enabled);
+        Ok(())
+    }
+
+    /// Percentiles of the echo round trips measured so far; None until there's a sample.
+    pub fn latency_stats(&self, session_id: &str) -> Result<Option<LatencyStats>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let probe = session.latency.lock().map_err(|_| "Lock poisoned")?;
+        Ok(probe.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_keystrokes_to_output() {
+        let mut probe = LatencyProbe::default();
+        probe.input("a");
+        probe.output(1);
+        assert!(probe.stats().is_none(), "disabled probe records nothing");
+
+        probe.set_enabled(true);
+        probe.input("a");
+        probe.input("b");
+        probe.input("pasted text");
+        probe.input("\r");
+        probe.output(1);
+        assert_eq!(probe.stats().unwrap().samples, 1);
+        probe.output(10);
+        assert_eq!(probe.stats().unwrap().samples, 2);
+        assert!(probe.pending.is_empty());
+    }
+
+    #[test]
+    fn reports_nearest_rank_percentiles() {
+        let probe = LatencyProbe {
+            samples: (1..=100).map(|ms| ms * 1000).collect(),
+            ..LatencyProbe::default()
+        };
+        let stats = probe.stats().unwrap();
+        assert_eq!(stats.p50_ms, 50.0);
+        assert_eq!(stats.p90_ms, 90.0);
+        assert_eq!(stats.p99_ms, 99.0);
+        assert_eq!(stats.max_ms, 100.0);
+    }
+}
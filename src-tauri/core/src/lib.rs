@@ -0,0 +1,102 @@
+#![allow(unexpected_cfgs)]
+
+//! Platform-independent core of shelll: PTY sessions, focus tracking, history and
+//! persisted state. The Tauri binary wraps these in commands; anything else (a CLI
+//! companion, integration tests) can link against this crate directly.
+
+pub mod accessibility;
+pub mod activity;
+pub mod archive;
+pub mod automation;
+pub mod batch;
+pub mod bell;
+pub mod buffer;
+pub mod charset;
+pub mod chrome;
+pub mod clipboard;
+pub mod cmdline;
+pub mod colors;
+pub mod compose;
+pub mod config;
+pub mod cwd;
+pub mod diagnostics;
+pub mod display;
+pub mod dock;
+pub mod dock_menu;
+pub mod dropdown;
+pub mod env;
+pub mod eol;
+pub mod events;
+pub mod feedback;
+pub mod files;
+pub mod find;
+pub mod flow;
+pub mod focus;
+pub mod follow;
+pub mod forward;
+pub mod guest;
+pub mod heads_up;
+pub mod hibernate;
+pub mod history;
+pub mod hotkey;
+pub mod html;
+pub mod info;
+pub mod inputs;
+pub mod ipc;
+pub mod keyboard;
+pub mod latency;
+pub mod lifecycle;
+pub mod links;
+pub mod long_command;
+pub mod memory;
+pub mod notification;
+pub mod permissions;
+pub mod pipe;
+pub mod pool;
+pub mod predict;
+pub mod preexec;
+pub mod process;
+pub mod profiles;
+pub mod progress;
+pub mod project;
+pub mod pty;
+pub mod recent_dirs;
+pub mod recording;
+pub mod redact;
+pub mod remote;
+pub mod remote_edit;
+pub mod responder;
+pub mod rules;
+pub mod scratchpad;
+pub mod screen;
+pub mod semantic;
+pub mod shell;
+pub mod shell_integration;
+pub mod signal;
+pub mod snapshot;
+pub mod stream;
+pub mod tail;
+pub mod target_title;
+pub mod template;
+pub mod terminal;
+pub mod terminfo;
+pub mod timeline;
+pub mod title;
+pub mod tray;
+pub mod tmpdir;
+pub mod update;
+pub mod usage;
+pub mod vt;
+pub mod watchdog;
+
+pub use events::EventSink;
+pub use pty::SessionManager;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
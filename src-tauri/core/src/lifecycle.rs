@@ -0,0 +1,169 @@
+//! What happens to sessions when the window closes or the machine sleeps, chosen per
+//! profile: keep running, hang up (SIGHUP), or suspend until the next wake/input.
+
+use crate::pool::PoolState;
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepAlive {
+    #[default]
+    KeepRunning,
+    Hangup,
+    Suspend,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeepAlivePolicy {
+    pub on_window_close: KeepAlive,
+    pub on_sleep: KeepAlive,
+}
+
+impl Default for KeepAlivePolicy {
+    // Closing the window ends its shells, as in other terminals; sleep leaves them alone
+    fn default() -> Self {
+        KeepAlivePolicy { on_window_close: KeepAlive::Hangup, on_sleep: KeepAlive::KeepRunning }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    WindowClosed,
+    Sleep,
+    Wake,
+}
+
+impl SessionManager {
+    /// Applies each session's policy for `event`. Returns how many sessions are still
+    /// alive afterwards (running or suspended), so the caller can decide whether to quit.
+    pub fn handle_lifecycle(&self, event: LifecycleEvent) -> Result<usize, String> {
+        let mut hangup = Vec::new();
+        {
+            let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            // Warm shells aren't the user's; they're replaced when the pool refills
+            for (id, session) in sessions.iter_mut().filter(|(_, s)| s.pool_state.is_none()) {
+                let action = match event {
+                    LifecycleEvent::WindowClosed => session.keep_alive.on_window_close,
+                    LifecycleEvent::Sleep => session.keep_alive.on_sleep,
+                    LifecycleEvent::Wake => {
+                        if std::mem::take(&mut session.suspended_for_sleep) {
+                            self.revive(id, session);
+                        }
+                        continue;
+                    }
+                };
+                match action {
+                    KeepAlive::KeepRunning => {}
+                    KeepAlive::Hangup => hangup.push(id.clone()),
+                    KeepAlive::Suspend if session.hibernation.is_none() => {
+                        self.hibernate(id, session, true);
+                        session.suspended_for_sleep = event == LifecycleEvent::Sleep;
+                    }
+                    KeepAlive::Suspend => {}
+                }
+            }
+        }
+
+        for id in hangup {
+            self.hangup(&id)?;
+        }
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        Ok(sessions.values().filter(|s| s.pool_state != Some(PoolState::Idle)).count())
+    }
+
+    fn hangup(&self, session_id: &str) -> Result<(), String> {
+        #[cfg(unix)]
+        {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            if let Some(session) = sessions.get(session_id) {
+                crate::hibernate::signal_session(session, libc::SIGHUP);
+            }
+        }
+        self.close(session_id)
+    }
+}
+
+/// Calls `handler` with `Sleep`/`Wake` as the system sleeps and wakes. Notifications are
+/// delivered on the main run loop, so call this from the main thread.
+#[cfg(target_os = "macos")]
+pub fn observe_power_events(handler: impl Fn(LifecycleEvent) + Send + Sync + 'static) {
+    use objc::declare::ClassDecl;
+    use objc::runtime::{Object, Sel};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::CString;
+    use std::sync::OnceLock;
+
+    static HANDLER: OnceLock<Box<dyn Fn(LifecycleEvent) + Send + Sync>> = OnceLock::new();
+    if HANDLER.set(Box::new(handler)).is_err() {
+        return;
+    }
+
+    extern "C" fn will_sleep(_: &Object, _: Sel, _: *mut Object) {
+        if let Some(handler) = HANDLER.get() {
+            handler(LifecycleEvent::Sleep);
+        }
+    }
+    extern "C" fn did_wake(_: &Object, _: Sel, _: *mut Object) {
+        if let Some(handler) = HANDLER.get() {
+            handler(LifecycleEvent::Wake);
+        }
+    }
+
+    unsafe {
+        let Some(mut decl) = ClassDecl::new("ShelllPowerObserver", class!(NSObject)) else {
+            return;
+        };
+        decl.add_method(sel!(willSleep:), will_sleep as extern "C" fn(&Object, Sel, *mut Object));
+        decl.add_method(sel!(didWake:), did_wake as extern "C" fn(&Object, Sel, *mut Object));
+        let observer_class = decl.register();
+        let observer: *mut Object = msg_send![observer_class, new];
+
+        let workspace: *mut Object = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let center: *mut Object = msg_send![workspace, notificationCenter];
+        for (selector, name) in [
+            (sel!(willSleep:), "NSWorkspaceWillSleepNotification"),
+            (sel!(didWake:), "NSWorkspaceDidWakeNotification"),
+        ] {
+            let name = CString::new(name).unwrap_or_default();
+            let name: *mut Object = msg_send![class!(NSString), stringWithUTF8String: name.as_ptr()];
+            let nil: *mut Object = std::ptr::null_mut();
+            let _: () = msg_send![center, addObserver: observer selector: selector name: name object: nil];
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn observe_power_events(_handler: impl Fn(LifecycleEvent) + Send + Sync + 'static) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use portable_pty::CommandBuilder;
+    use std::sync::Arc;
+
+    fn spawn(manager: &SessionManager, keep_alive: KeepAlivePolicy) -> String {
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.sessions.lock().unwrap().get_mut(&id).unwrap().keep_alive = keep_alive;
+        id
+    }
+
+    #[test]
+    fn applies_per_session_policies() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let server = spawn(&manager, KeepAlivePolicy { on_window_close: KeepAlive::KeepRunning, on_sleep: KeepAlive::Suspend });
+        let local = spawn(&manager, KeepAlivePolicy::default());
+
+        assert_eq!(manager.handle_lifecycle(LifecycleEvent::Sleep).unwrap(), 2);
+        assert!(manager.sessions.lock().unwrap()[&server].hibernation.is_some());
+        manager.handle_lifecycle(LifecycleEvent::Wake).unwrap();
+        assert!(manager.sessions.lock().unwrap()[&server].hibernation.is_none());
+
+        assert_eq!(manager.handle_lifecycle(LifecycleEvent::WindowClosed).unwrap(), 1);
+        assert!(!manager.sessions.lock().unwrap().contains_key(&local));
+        manager.close(&server).unwrap();
+    }
+}
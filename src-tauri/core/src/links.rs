@@ -0,0 +1,186 @@
+//! OSC 8 hyperlinks (`ESC ] 8 ; params ; URI ST text ESC ] 8 ; ; ST`), picked out of the
+//! output so the frontend gets each link's target and text with `hyperlinks-detected`,
+//! and `open_url` for following one: only schemes that are safe to hand to the system
+//! opener (http, https, file, mailto, plus any the user adds) go through.
+
+use crate::config::{load_json, save_json};
+use crate::files;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub const DEFAULT_SCHEMES: &[&str] = &["http", "https", "file", "mailto"];
+const MAX_URI_LEN: usize = 2083;
+// Link text past this is cut
+const MAX_TEXT_CHARS: usize = 500;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkSettings {
+    // Allowed on top of DEFAULT_SCHEMES, e.g. "vscode" or "x-man-page"
+    pub extra_schemes: Vec<String>,
+}
+
+// Link settings persisted as JSON in the config dir
+pub struct LinkStore {
+    path: Option<PathBuf>,
+    pub settings: LinkSettings,
+}
+
+impl LinkStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        LinkStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Hyperlink {
+    pub uri: String,
+    // The `id=` parameter, tying together the pieces of a link split across lines
+    pub id: Option<String>,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HyperlinksPayload {
+    pub session_id: String,
+    pub links: Vec<Hyperlink>,
+}
+
+// (id, uri) of an OSC 8 body; an empty uri ends the link
+fn parse_osc8(osc: &[u8]) -> Option<(Option<String>, String)> {
+    let rest = std::str::from_utf8(osc.strip_prefix(b"8;")?).ok()?;
+    let (params, uri) = rest.split_once(';')?;
+    let id = params.split(':').find_map(|param| param.strip_prefix("id=")).map(str::to_string);
+    Some((id, uri.chars().take(MAX_URI_LEN).collect()))
+}
+
+/// Follows OSC 8 opens and closes through a session's output, collecting each link with
+/// the text printed inside it.
+#[derive(Default)]
+pub(crate) struct HyperlinkTracker {
+    open: Option<Hyperlink>,
+    done: Vec<Hyperlink>,
+}
+
+impl HyperlinkTracker {
+    /// Returns false if `osc` isn't OSC 8.
+    pub(crate) fn osc(&mut self, osc: &[u8]) -> bool {
+        let Some((id, uri)) = parse_osc8(osc) else { return false };
+        // A new link closes the previous one
+        self.close();
+        if !uri.is_empty() {
+            self.open = Some(Hyperlink { uri, id, text: String::new() });
+        }
+        true
+    }
+
+    pub(crate) fn text(&mut self, text: &str) {
+        if let Some(link) = &mut self.open {
+            let room = MAX_TEXT_CHARS.saturating_sub(link.text.chars().count());
+            link.text.extend(text.chars().filter(|c| !c.is_control()).take(room));
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(link) = self.open.take().filter(|link| !link.text.is_empty()) {
+            self.done.push(link);
+        }
+    }
+
+    /// Links closed since the last call.
+    pub(crate) fn take(&mut self) -> Vec<Hyperlink> {
+        std::mem::take(&mut self.done)
+    }
+
+    /// After a reset: whatever was open is dropped.
+    pub(crate) fn reset(&mut self) {
+        self.open = None;
+    }
+}
+
+/// Checks `url` against the allowed schemes; returns it trimmed. `file` URLs must be local.
+pub fn validate_url(url: &str, settings: &LinkSettings) -> Result<String, String> {
+    let url = url.trim();
+    if url.is_empty() || url.len() > MAX_URI_LEN || url.chars().any(|c| c.is_control() || c.is_whitespace()) {
+        return Err("Not a valid URL".into());
+    }
+    let (scheme, rest) = url.split_once(':').ok_or("Not a valid URL")?;
+    let scheme_ok = scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    if !scheme_ok {
+        return Err("Not a valid URL".into());
+    }
+    let scheme = scheme.to_ascii_lowercase();
+    let allowed = DEFAULT_SCHEMES.contains(&scheme.as_str())
+        || settings.extra_schemes.iter().any(|extra| extra.eq_ignore_ascii_case(&scheme));
+    if !allowed {
+        return Err(format!("Links with the {} scheme are not allowed", scheme));
+    }
+    if scheme == "file" {
+        let host = rest.strip_prefix("//").ok_or("Not a valid file URL")?.split('/').next().unwrap_or_default();
+        if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+            return Err(format!("{} is a file on {}, not this machine", url, host));
+        }
+    }
+    Ok(url.to_string())
+}
+
+/// Opens `url` with the system's handler for its scheme, if it's allowed.
+pub fn open_url(url: &str, settings: &LinkSettings) -> Result<(), String> {
+    let url = validate_url(url, settings)?;
+    files::system_opener()
+        .arg(&url)
+        .spawn()
+        .map_err(|e| format!("Failed to open {}: {}", url, e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_link_text() {
+        let mut tracker = HyperlinkTracker::default();
+        assert!(tracker.osc(b"8;id=a1;https://example.com/docs"));
+        tracker.text("the ");
+        tracker.text("docs");
+        assert!(tracker.osc(b"8;;"));
+        tracker.text("plain");
+        assert!(!tracker.osc(b"2;title"));
+        assert!(tracker.osc(b"8;;file:///tmp/a.log"));
+        tracker.text("a.log");
+        assert!(tracker.osc(b"8;;mailto:me@example.com"));
+        assert!(tracker.osc(b"8;;"));
+
+        let links = tracker.take();
+        assert_eq!(links, vec![
+            Hyperlink { uri: "https://example.com/docs".into(), id: Some("a1".into()), text: "the docs".into() },
+            Hyperlink { uri: "file:///tmp/a.log".into(), id: None, text: "a.log".into() },
+        ]);
+        assert!(tracker.take().is_empty());
+    }
+
+    #[test]
+    fn allows_only_safe_schemes() {
+        let mut settings = LinkSettings::default();
+        assert_eq!(validate_url(" https://example.com ", &settings).unwrap(), "https://example.com");
+        assert!(validate_url("mailto:me@example.com", &settings).is_ok());
+        assert!(validate_url("file:///Users/me/notes.txt", &settings).is_ok());
+        assert!(validate_url("file://localhost/tmp", &settings).is_ok());
+        assert!(validate_url("file://devbox/home/me/notes.txt", &settings).is_err());
+        assert!(validate_url("javascript:alert(1)", &settings).is_err());
+        assert!(validate_url("x-man-page://ls", &settings).is_err());
+        assert!(validate_url("https://example.com/a b", &settings).is_err());
+        assert!(validate_url("/etc/passwd", &settings).is_err());
+
+        settings.extra_schemes.push("X-Man-Page".into());
+        assert!(validate_url("x-man-page://ls", &settings).is_ok());
+    }
+}
@@ -0,0 +1,171 @@
+//! Notifications for long commands: when a command the shell marked (see `semantic`) ran
+//! past the threshold and Shelll isn't in front, a native notification with the command
+//! line and how it ended goes out (clicking it shows the session), along with
+//! `long-command-finished`. Title and body follow the notification templates.
+
+use crate::config::{load_json, save_json};
+use crate::events::EventSink;
+use crate::notification::{self, TerminalNotificationPayload};
+use crate::pty::{PtySession, SessionManager};
+use crate::semantic::{CommandFinishedPayload, CommandRecord};
+use crate::template::{TemplateContext, TemplateSettings};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LongCommandSettings {
+    pub enabled: bool,
+    // Commands that ran at least this long count
+    pub min_duration_secs: u64,
+}
+
+impl Default for LongCommandSettings {
+    fn default() -> Self {
+        LongCommandSettings { enabled: true, min_duration_secs: 10 }
+    }
+}
+
+// Long-command settings persisted as JSON in the config dir
+pub struct LongCommandStore {
+    path: Option<PathBuf>,
+    pub settings: LongCommandSettings,
+}
+
+impl LongCommandStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        LongCommandStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct LongCommands {
+    settings: LongCommandSettings,
+    // Whether Shelll is in front; without it nothing is sent
+    app_active: Option<Arc<dyn Fn() -> bool + Send + Sync>>,
+}
+
+impl LongCommands {
+    fn wanted(&self, command: &CommandRecord) -> bool {
+        self.settings.enabled
+            && command.duration_ms >= self.settings.min_duration_secs * 1000
+            && self.app_active.as_ref().is_some_and(|active| !active())
+    }
+}
+
+// Title and body, from the templates, for `command` finishing in `session`
+fn render(session: &PtySession, templates: &TemplateSettings, command: &CommandRecord) -> (String, String) {
+    let context = TemplateContext {
+        // The program that ran, not the shell that's back in the foreground now
+        process: command.command.as_deref().and_then(|c| c.split_whitespace().next()).map(str::to_string),
+        cwd: command.cwd.clone().or_else(|| session.title.cwd.clone()),
+        command: command.command.clone(),
+        exit_code: command.exit_code,
+        duration: Some(Duration::from_millis(command.duration_ms)),
+        ..TemplateContext::from(&session.title)
+    };
+    let title = context.render(&templates.notification_title).0;
+    let body = context.render(&templates.notification_body).0;
+    match &command.command {
+        Some(line) if !templates.notification_body.contains("{command") => (title, format!("{}\n{}", line, body)),
+        _ => (title, body),
+    }
+}
+
+// From the reader thread: notifies about `command` if it ran long while Shelll was away
+pub(crate) fn check(
+    long_commands: &Mutex<LongCommands>,
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    templates: &Mutex<TemplateSettings>,
+    session_id: &str,
+    command: &CommandRecord,
+    events: &Arc<dyn EventSink>,
+) {
+    if !long_commands.lock().is_ok_and(|l| l.wanted(command)) {
+        return;
+    }
+    let rendered = {
+        let (Ok(sessions), Ok(templates)) = (sessions.lock(), templates.lock()) else { return };
+        let Some(session) = sessions.get(session_id) else { return };
+        render(session, &templates, command)
+    };
+    let payload = TerminalNotificationPayload { session_id: session_id.to_string(), title: rendered.0, body: rendered.1 };
+    if let Err(e) = notification::post_native(&payload) {
+        eprintln!("Failed to show a notification: {}", e);
+    }
+    events.emit("long-command-finished", CommandFinishedPayload {
+        session_id: session_id.to_string(),
+        command: command.clone(),
+    });
+}
+
+impl SessionManager {
+    pub fn set_long_command_settings(&self, settings: LongCommandSettings) -> Result<(), String> {
+        self.long_commands.lock().map_err(|_| "Lock poisoned")?.settings = settings;
+        Ok(())
+    }
+
+    /// How to tell whether Shelll is in front; long commands only notify while it isn't.
+    pub fn set_long_command_focus_check(&self, app_active: Arc<dyn Fn() -> bool + Send + Sync>) -> Result<(), String> {
+        self.long_commands.lock().map_err(|_| "Lock poisoned")?.app_active = Some(app_active);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(duration_ms: u64) -> CommandRecord {
+        CommandRecord {
+            command: Some("cargo test --workspace".into()),
+            cwd: Some("/srv/app".into()),
+            started_at: 0,
+            duration_ms,
+            exit_code: Some(101),
+            output_start: 0,
+            output_end: 0,
+        }
+    }
+
+    #[test]
+    fn notifies_only_for_long_commands_in_the_background() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let mut long_commands = LongCommands::default();
+        assert!(!long_commands.wanted(&record(60_000)));
+        let active = Arc::new(AtomicBool::new(false));
+        let is_active = active.clone();
+        long_commands.app_active = Some(Arc::new(move || is_active.load(Ordering::SeqCst)));
+        assert!(long_commands.wanted(&record(60_000)));
+        assert!(!long_commands.wanted(&record(9_999)));
+        active.store(true, Ordering::SeqCst);
+        assert!(!long_commands.wanted(&record(60_000)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn renders_the_command_and_result() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        let sessions = manager.sessions.lock().unwrap();
+        let (title, body) = render(&sessions[&id], &TemplateSettings::default(), &record(185_000));
+        assert_eq!(title, "cargo finished");
+        assert_eq!(body, "cargo test --workspace\nExited with 101 after 3m 5s in app");
+        drop(sessions);
+        manager.close(&id).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! Low-memory mode: while the OS reports memory pressure (or the user asks for it),
+//! sessions keep far less scrollback, latency probes are switched off, and sessions
+//! that sit idle are trimmed further and hibernated. `low-memory-changed` tells the UI
+//! that older output may be gone.
+
+use crate::buffer::OutputLog;
+use crate::pty::SessionManager;
+use crate::unix_now;
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+// Scrollback kept per session in low-memory mode (2MB otherwise)
+pub const LOW_MEMORY_RETAINED_BYTES: usize = 256 * 1024;
+// What an idle session keeps once compacted
+const IDLE_RETAINED_BYTES: usize = 64 * 1024;
+const IDLE_SECS: u64 = 5 * 60;
+const PRESSURE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryPressure {
+    Normal,
+    Warning,
+    Critical,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LowMemoryReason {
+    Manual,
+    // Turned on by the pressure watcher, and off again once pressure is back to normal
+    Pressure,
+}
+
+/// Also the `low-memory-changed` payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LowMemoryStatus {
+    pub enabled: bool,
+    pub reason: Option<LowMemoryReason>,
+    // Scrollback each session keeps at most
+    pub retained_bytes: usize,
+}
+
+#[cfg(target_os = "macos")]
+pub fn memory_pressure() -> Option<MemoryPressure> {
+    let mut level: libc::c_int = 0;
+    let mut size = std::mem::size_of::<libc::c_int>();
+    let status = unsafe {
+        libc::sysctlbyname(
+            c"kern.memorystatus_vm_pressure_level".as_ptr(),
+            &mut level as *mut libc::c_int as *mut libc::c_void,
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    // Same levels as DISPATCH_MEMORYPRESSURE_*
+    match (status, level) {
+        (0, 1) => Some(MemoryPressure::Normal),
+        (0, 2) => Some(MemoryPressure::Warning),
+        (0, 4) => Some(MemoryPressure::Critical),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn memory_pressure() -> Option<MemoryPressure> {
+    pressure_from_meminfo(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn memory_pressure() -> Option<MemoryPressure> {
+    None
+}
+
+// Judged by how much of the RAM is still available, since /proc/pressure isn't always there
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn pressure_from_meminfo(meminfo: &str) -> Option<MemoryPressure> {
+    let field = |name: &str| -> Option<u64> {
+        let line = meminfo.lines().find(|l| l.starts_with(name))?;
+        line[name.len()..].trim().trim_end_matches("kB").trim().parse().ok()
+    };
+    let total = field("MemTotal:").filter(|&t| t > 0)?;
+    let available = field("MemAvailable:")?;
+    Some(match available * 100 / total {
+        0..=4 => MemoryPressure::Critical,
+        5..=9 => MemoryPressure::Warning,
+        _ => MemoryPressure::Normal,
+    })
+}
+
+impl SessionManager {
+    pub fn low_memory_status(&self) -> Result<LowMemoryStatus, String> {
+        let reason = *self.low_memory.lock().map_err(|_| "Lock poisoned")?;
+        Ok(status(reason))
+    }
+
+    /// Turning it off also ends a low-memory mode the pressure watcher started.
+    pub fn set_low_memory(&self, enabled: bool) -> Result<LowMemoryStatus, String> {
+        self.apply_low_memory(enabled.then_some(LowMemoryReason::Manual))
+    }
+
+    /// Enters low-memory mode under pressure and leaves it once pressure is gone, unless
+    /// the user turned it on.
+    pub fn memory_pressure_changed(&self, pressure: MemoryPressure) -> Result<LowMemoryStatus, String> {
+        let current = *self.low_memory.lock().map_err(|_| "Lock poisoned")?;
+        match (pressure, current) {
+            (MemoryPressure::Normal, Some(LowMemoryReason::Pressure)) => self.apply_low_memory(None),
+            (MemoryPressure::Warning | MemoryPressure::Critical, None) => {
+                self.apply_low_memory(Some(LowMemoryReason::Pressure))
+            }
+            _ => Ok(status(current)),
+        }
+    }
+
+    /// Polls the OS for memory pressure and compacts idle sessions while memory is low.
+    pub fn start_memory_pressure_watcher(self: &std::sync::Arc<Self>) {
+        let manager = std::sync::Arc::downgrade(self);
+        thread::spawn(move || {
+            while let Some(manager) = manager.upgrade() {
+                if let Some(pressure) = memory_pressure() {
+                    let _ = manager.memory_pressure_changed(pressure);
+                }
+                let _ = manager.compact_idle_sessions();
+                drop(manager);
+                thread::sleep(PRESSURE_POLL_INTERVAL);
+            }
+        });
+    }
+
+    /// In low-memory mode, trims the scrollback of sessions idle for a while and
+    /// hibernates them (without stopping their processes). Returns the sessions it
+    /// hibernated.
+    pub fn compact_idle_sessions(&self) -> Result<Vec<String>, String> {
+        if self.low_memory.lock().map_err(|_| "Lock poisoned")?.is_none() {
+            return Ok(Vec::new());
+        }
+        let now = unix_now();
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let mut compacted = Vec::new();
+        for (id, session) in sessions.iter_mut() {
+            let idle = now.saturating_sub(session.last_activity.load(Ordering::SeqCst));
+            if session.hibernation.is_some() || session.pool_state.is_some() || idle < IDLE_SECS {
+                continue;
+            }
+            if let Ok(mut log) = session.output_log.lock() {
+                log.trim_to(IDLE_RETAINED_BYTES);
+            }
+            self.hibernate(id, session, false);
+            compacted.push(id.clone());
+        }
+        Ok(compacted)
+    }
+
+    // The log for a new session, capped if memory is low
+    pub(crate) fn new_output_log(&self) -> OutputLog {
+        let mut log = OutputLog::default();
+        if self.low_memory.lock().is_ok_and(|reason| reason.is_some()) {
+            log.set_limit(Some(LOW_MEMORY_RETAINED_BYTES));
+        }
+        log
+    }
+
+    fn apply_low_memory(&self, reason: Option<LowMemoryReason>) -> Result<LowMemoryStatus, String> {
+        let mut current = self.low_memory.lock().map_err(|_| "Lock poisoned")?;
+        let previous = std::mem::replace(&mut *current, reason);
+        drop(current);
+        let status = status(reason);
+        if previous == reason {
+            return Ok(status);
+        }
+
+        if previous.is_some() != status.enabled {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            for session in sessions.values() {
+                let limit = status.enabled.then_some(LOW_MEMORY_RETAINED_BYTES);
+                session.output_log.lock().map_err(|_| "Lock poisoned")?.set_limit(limit);
+                if status.enabled {
+                    session.latency.lock().map_err(|_| "Lock poisoned")?.set_enabled(false);
+                }
+            }
+        }
+        self.events.emit("low-memory-changed", status.clone());
+        if status.enabled {
+            self.compact_idle_sessions()?;
+        }
+        Ok(status)
+    }
+}
+
+fn status(reason: Option<LowMemoryReason>) -> LowMemoryStatus {
+    LowMemoryStatus {
+        enabled: reason.is_some(),
+        reason,
+        retained_bytes: match reason {
+            Some(_) => LOW_MEMORY_RETAINED_BYTES,
+            None => crate::buffer::RETAINED_BYTES,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_pressure_from_meminfo() {
+        let meminfo = |available: u64| format!("MemTotal:       1000000 kB\nMemFree:   1000 kB\nMemAvailable:   {} kB\n", available);
+        assert_eq!(pressure_from_meminfo(&meminfo(500000)), Some(MemoryPressure::Normal));
+        assert_eq!(pressure_from_meminfo(&meminfo(80000)), Some(MemoryPressure::Warning));
+        assert_eq!(pressure_from_meminfo(&meminfo(20000)), Some(MemoryPressure::Critical));
+        assert_eq!(pressure_from_meminfo("MemTotal: 1000 kB\n"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pressure_toggles_the_mode_unless_the_user_set_it() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+        use std::sync::Arc;
+
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.set_latency_probe(&id, true).unwrap();
+
+        let status = manager.memory_pressure_changed(MemoryPressure::Warning).unwrap();
+        assert_eq!((status.enabled, status.reason), (true, Some(LowMemoryReason::Pressure)));
+        assert!(manager.set_latency_probe(&id, true).is_err());
+        manager.sessions.lock().unwrap()[&id].output_log.lock().unwrap().push(&vec![b'x'; LOW_MEMORY_RETAINED_BYTES * 2]);
+        let buffer = manager.session_buffer(&id).unwrap();
+        assert_eq!(buffer.end_offset - buffer.start_offset, LOW_MEMORY_RETAINED_BYTES as u64);
+        assert!(!manager.memory_pressure_changed(MemoryPressure::Normal).unwrap().enabled);
+
+        manager.set_low_memory(true).unwrap();
+        assert!(manager.memory_pressure_changed(MemoryPressure::Normal).unwrap().enabled);
+        let events = sink.named("low-memory-changed");
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2]["reason"], "manual");
+        manager.close(&id).unwrap();
+    }
+}
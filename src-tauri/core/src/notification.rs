@@ -0,0 +1,297 @@
+//! Desktop notifications shown by the backend, for events that happen while no window is
+//! looking (rules, finished commands in the background), and ones programs ask for: OSC 9
+//! (`ESC ] 9 ; message`, as in iTerm2) and OSC 777 (`ESC ] 777 ; notify ; title ; body`, as
+//! in urxvt and foot). Those also become `terminal-notification` events, and on macOS go
+//! through UNUserNotificationCenter so clicking one brings its session forward.
+
+use crate::events::EventSink;
+use crate::pty::PtySession;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// A session's notifications closer together than this are dropped
+pub(crate) const MIN_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_TITLE_CHARS: usize = 100;
+const MAX_BODY_CHARS: usize = 1000;
+
+#[cfg(target_os = "macos")]
+pub fn show_notification(title: &str, body: &str) -> Result<(), String> {
+    use crate::focus::{applescript_quote, run_applescript};
+    run_applescript(&format!(
+        "display notification {} with title {}",
+        applescript_quote(body),
+        applescript_quote(title)
+    ))
+}
+
+#[cfg(target_os = "linux")]
+pub fn show_notification(title: &str, body: &str) -> Result<(), String> {
+    let status = std::process::Command::new("notify-send")
+        .args(["--app-name", "Shelll", title, body])
+        .status()
+        .map_err(|e| format!("Failed to run notify-send: {}", e))?;
+    if !status.success() {
+        return Err(format!("notify-send exited with {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn show_notification(_title: &str, _body: &str) -> Result<(), String> {
+    Err("Notifications are not supported on this platform".into())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TerminalNotification {
+    pub(crate) title: Option<String>,
+    pub(crate) body: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TerminalNotificationPayload {
+    pub session_id: String,
+    pub title: String,
+    pub body: String,
+}
+
+fn clean(text: &str, max_chars: usize) -> String {
+    text.chars().filter(|c| !c.is_control()).take(max_chars).collect::<String>().trim().to_string()
+}
+
+/// The notification in an OSC body (without `ESC ] ` and the terminator), if it is one.
+pub(crate) fn parse_notification(osc: &[u8]) -> Option<TerminalNotification> {
+    let osc = std::str::from_utf8(osc).ok()?;
+    let notification = if let Some(message) = osc.strip_prefix("9;") {
+        // ConEmu uses OSC 9 ; <number> ; ... for other things, progress among them
+        let code = message.split(';').next().unwrap_or_default();
+        if !code.is_empty() && code.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        TerminalNotification { title: None, body: clean(message, MAX_BODY_CHARS) }
+    } else {
+        let rest = osc.strip_prefix("777;notify;")?;
+        let (title, body) = rest.split_once(';').unwrap_or((rest, ""));
+        TerminalNotification { title: Some(clean(title, MAX_TITLE_CHARS)).filter(|t| !t.is_empty()), body: clean(body, MAX_BODY_CHARS) }
+    };
+    (!notification.body.is_empty() || notification.title.is_some()).then_some(notification)
+}
+
+// From the reader thread: titled after the session's tab unless the program gave a title
+pub(crate) fn post(
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    session_id: &str,
+    notification: TerminalNotification,
+    events: &Arc<dyn EventSink>,
+) {
+    let tab_title = sessions.lock().ok().and_then(|s| s.get(session_id).and_then(|s| s.last_title.clone()));
+    let payload = TerminalNotificationPayload {
+        session_id: session_id.to_string(),
+        title: notification.title.or(tab_title).unwrap_or_else(|| "shelll".into()),
+        body: notification.body,
+    };
+    if let Err(e) = post_native(&payload) {
+        eprintln!("Failed to show a notification: {}", e);
+    }
+    events.emit("terminal-notification", payload);
+}
+
+/// Asks for permission to show notifications and calls `on_click` with the session id of
+/// each one the user clicks. Call once, from Tauri's `setup`.
+#[cfg(target_os = "macos")]
+pub fn install_notification_handler(on_click: impl Fn(String) + Send + Sync + 'static) -> Result<(), String> {
+    mac::install(Box::new(on_click))
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn install_notification_handler(_on_click: impl Fn(String) + Send + Sync + 'static) -> Result<(), String> {
+    Err("Native notifications are only supported on macOS".into())
+}
+
+// Clickable where it can be; outside the app bundle, the plain kind
+pub(crate) fn post_native(payload: &TerminalNotificationPayload) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    if mac::post(payload).is_ok() {
+        return Ok(());
+    }
+    show_notification(&payload.title, &payload.body)
+}
+
+#[cfg(target_os = "macos")]
+mod mac {
+    use super::TerminalNotificationPayload;
+    use objc::declare::ClassDecl;
+    use objc::runtime::{Object, Sel, BOOL};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::{c_char, c_void, CStr, CString};
+    use std::sync::OnceLock;
+
+    #[link(name = "UserNotifications", kind = "framework")]
+    extern "C" {}
+
+    extern "C" {
+        static _NSConcreteGlobalBlock: c_void;
+    }
+
+    const SESSION_ID_KEY: &CStr = c"session_id";
+    // UNAuthorizationOptionSound | UNAuthorizationOptionAlert
+    const AUTHORIZATION_OPTIONS: usize = 2 | 4;
+    const BLOCK_IS_GLOBAL: i32 = 1 << 28;
+
+    type OnClick = Box<dyn Fn(String) + Send + Sync>;
+    static ON_CLICK: OnceLock<OnClick> = OnceLock::new();
+
+    // The start of every block's layout; `invoke` takes the block, then the arguments
+    #[repr(C)]
+    struct BlockHeader {
+        isa: *const c_void,
+        flags: i32,
+        reserved: i32,
+        invoke: *const c_void,
+    }
+
+    #[repr(C)]
+    struct BlockDescriptor {
+        reserved: usize,
+        size: usize,
+    }
+
+    #[repr(C)]
+    struct GlobalBlock {
+        header: BlockHeader,
+        descriptor: *const BlockDescriptor,
+    }
+
+    // A block that lives for the rest of the process, calling `invoke`
+    fn global_block(invoke: *const c_void) -> *mut c_void {
+        let descriptor = Box::leak(Box::new(BlockDescriptor { reserved: 0, size: std::mem::size_of::<GlobalBlock>() }));
+        let block = GlobalBlock {
+            header: BlockHeader {
+                isa: std::ptr::addr_of!(_NSConcreteGlobalBlock),
+                flags: BLOCK_IS_GLOBAL,
+                reserved: 0,
+                invoke,
+            },
+            descriptor,
+        };
+        Box::leak(Box::new(block)) as *mut GlobalBlock as *mut c_void
+    }
+
+    unsafe fn ns_string(s: &str) -> *mut Object {
+        let s = CString::new(s.replace('\0', "")).unwrap_or_default();
+        msg_send![class!(NSString), stringWithUTF8String: s.as_ptr()]
+    }
+
+    unsafe fn rust_string(s: *mut Object) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        let utf8: *const c_char = msg_send![s, UTF8String];
+        (!utf8.is_null()).then(|| CStr::from_ptr(utf8).to_string_lossy().into_owned())
+    }
+
+    // Called off the main thread, so autoreleased objects need a pool of their own
+    fn with_pool<T>(f: impl FnOnce() -> T) -> T {
+        unsafe {
+            let pool: *mut Object = msg_send![class!(NSAutoreleasePool), new];
+            let result = f();
+            let _: () = msg_send![pool, drain];
+            result
+        }
+    }
+
+    // UNUserNotificationCenter throws outside an app bundle (e.g. `cargo run`)
+    unsafe fn center() -> Result<*mut Object, String> {
+        let bundle: *mut Object = msg_send![class!(NSBundle), mainBundle];
+        let bundle_id: *mut Object = msg_send![bundle, bundleIdentifier];
+        if bundle_id.is_null() {
+            return Err("Notifications need the app bundle".into());
+        }
+        Ok(msg_send![class!(UNUserNotificationCenter), currentNotificationCenter])
+    }
+
+    extern "C" fn authorized(_block: *mut c_void, granted: BOOL, _error: *mut Object) {
+        if granted == objc::runtime::NO {
+            eprintln!("Notifications are turned off for shelll in System Settings");
+        }
+    }
+
+    extern "C" fn did_receive(_: &Object, _: Sel, _center: *mut Object, response: *mut Object, completion: *mut c_void) {
+        unsafe {
+            let notification: *mut Object = msg_send![response, notification];
+            let request: *mut Object = msg_send![notification, request];
+            let content: *mut Object = msg_send![request, content];
+            let info: *mut Object = msg_send![content, userInfo];
+            let key = ns_string(SESSION_ID_KEY.to_str().unwrap_or_default());
+            let session_id: *mut Object = msg_send![info, objectForKey: key];
+            if let (Some(session_id), Some(on_click)) = (rust_string(session_id), ON_CLICK.get()) {
+                on_click(session_id);
+            }
+            if !completion.is_null() {
+                let header = &*(completion as *const BlockHeader);
+                let invoke: extern "C" fn(*mut c_void) = std::mem::transmute(header.invoke);
+                invoke(completion);
+            }
+        }
+    }
+
+    pub fn install(on_click: OnClick) -> Result<(), String> {
+        if ON_CLICK.set(on_click).is_err() {
+            return Err("The notification handler is already installed".into());
+        }
+        unsafe {
+            let center = center()?;
+            let mut decl = ClassDecl::new("ShelllNotificationDelegate", class!(NSObject))
+                .ok_or("The notification handler is already installed")?;
+            decl.add_method(
+                sel!(userNotificationCenter:didReceiveNotificationResponse:withCompletionHandler:),
+                did_receive as extern "C" fn(&Object, Sel, *mut Object, *mut Object, *mut c_void),
+            );
+            let delegate: *mut Object = msg_send![decl.register(), new];
+            let _: () = msg_send![center, setDelegate: delegate];
+            let completion = global_block(authorized as *const c_void);
+            let _: () = msg_send![center, requestAuthorizationWithOptions: AUTHORIZATION_OPTIONS completionHandler: completion];
+        }
+        Ok(())
+    }
+
+    pub fn post(payload: &TerminalNotificationPayload) -> Result<(), String> {
+        with_pool(|| unsafe {
+            let center = center()?;
+            let content: *mut Object = msg_send![class!(UNMutableNotificationContent), new];
+            let _: () = msg_send![content, setTitle: ns_string(&payload.title)];
+            let _: () = msg_send![content, setBody: ns_string(&payload.body)];
+            let info: *mut Object = msg_send![class!(NSDictionary),
+                dictionaryWithObject: ns_string(&payload.session_id)
+                forKey: ns_string(SESSION_ID_KEY.to_str().unwrap_or_default())];
+            let _: () = msg_send![content, setUserInfo: info];
+            let identifier = ns_string(&uuid::Uuid::new_v4().to_string());
+            let nil: *mut Object = std::ptr::null_mut();
+            let request: *mut Object = msg_send![class!(UNNotificationRequest),
+                requestWithIdentifier: identifier
+                content: content
+                trigger: nil];
+            let _: () = msg_send![content, release];
+            let _: () = msg_send![center, addNotificationRequest: request withCompletionHandler: nil];
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_notification_sequences() {
+        let notification = |title: Option<&str>, body: &str| Some(TerminalNotification { title: title.map(String::from), body: body.into() });
+        assert_eq!(parse_notification(b"9;Build finished"), notification(None, "Build finished"));
+        assert_eq!(parse_notification(b"777;notify;cargo;tests passed"), notification(Some("cargo"), "tests passed"));
+        assert_eq!(parse_notification(b"777;notify;Done"), notification(Some("Done"), ""));
+        assert_eq!(parse_notification(b"9;4;1;50"), None);
+        assert_eq!(parse_notification(b"9;"), None);
+        assert_eq!(parse_notification(b"777;preexec"), None);
+        assert_eq!(parse_notification(b"2;title"), None);
+    }
+}
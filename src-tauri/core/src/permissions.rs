@@ -0,0 +1,227 @@
+//! Capability model for automation clients (CLI, WebSocket, MCP). Each client holds a
+//! token that grants a set of operations on a set of sessions; the webview itself is
+//! trusted and doesn't go through this layer.
+
+use crate::buffer::OutputChunk;
+use crate::profiles::Profile;
+use crate::pty::{OutputMark, SessionOptions};
+use crate::scratchpad;
+use crate::unix_now;
+use crate::SessionManager;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    // Observe output, titles and marks
+    Read,
+    // Type into the session
+    Input,
+    Resize,
+    // Create and close sessions
+    Manage,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Grant {
+    pub token: String,
+    pub label: String,
+    // None grants access to every session, including ones created later
+    pub sessions: Option<HashSet<String>>,
+    pub operations: HashSet<Operation>,
+    pub created_at: u64,
+}
+
+impl Grant {
+    pub fn allows(&self, session_id: Option<&str>, operation: Operation) -> bool {
+        if !self.operations.contains(&operation) {
+            return false;
+        }
+        match (&self.sessions, session_id) {
+            (None, _) => true,
+            (Some(sessions), Some(id)) => sessions.contains(id),
+            // Session-less operations (e.g. creating a session) need an unscoped grant
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PermissionRegistry {
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+impl PermissionRegistry {
+    pub fn issue(
+        &self,
+        label: String,
+        sessions: Option<Vec<String>>,
+        operations: Vec<Operation>,
+    ) -> Result<Grant, String> {
+        if operations.is_empty() {
+            return Err("A token needs at least one operation".into());
+        }
+        let grant = Grant {
+            token: Uuid::new_v4().simple().to_string(),
+            label,
+            sessions: sessions.map(|s| s.into_iter().collect()),
+            operations: operations.into_iter().collect(),
+            created_at: unix_now(),
+        };
+        let mut grants = self.grants.lock().map_err(|_| "Lock poisoned")?;
+        grants.insert(grant.token.clone(), grant.clone());
+        Ok(grant)
+    }
+
+    pub fn revoke(&self, token: &str) -> Result<bool, String> {
+        let mut grants = self.grants.lock().map_err(|_| "Lock poisoned")?;
+        Ok(grants.remove(token).is_some())
+    }
+
+    pub fn list(&self) -> Result<Vec<Grant>, String> {
+        let grants = self.grants.lock().map_err(|_| "Lock poisoned")?;
+        let mut list: Vec<Grant> = grants.values().cloned().collect();
+        list.sort_by_key(|g| g.created_at);
+        Ok(list)
+    }
+
+    pub fn authorize(&self, token: &str, session_id: Option<&str>, operation: Operation) -> Result<Grant, String> {
+        let grants = self.grants.lock().map_err(|_| "Lock poisoned")?;
+        let grant = grants.get(token).ok_or("Invalid token")?;
+        if !grant.allows(session_id, operation) {
+            return Err(format!("Token '{}' is not allowed to {:?} this session", grant.label, operation));
+        }
+        Ok(grant.clone())
+    }
+}
+
+/// The command router for automation surfaces: every call is checked against the
+/// caller's token before it reaches the session manager.
+pub struct ScopedSessions<'a> {
+    manager: &'a SessionManager,
+    permissions: &'a PermissionRegistry,
+    token: String,
+}
+
+impl<'a> ScopedSessions<'a> {
+    pub fn new(manager: &'a SessionManager, permissions: &'a PermissionRegistry, token: &str) -> Self {
+        ScopedSessions { manager, permissions, token: token.to_string() }
+    }
+
+    fn check(&self, session_id: Option<&str>, operation: Operation) -> Result<(), String> {
+        self.permissions.authorize(&self.token, session_id, operation).map(|_| ())
+    }
+
+    // The session a (possibly scratchpad-named) id addresses, once the token may use it
+    fn check_session(&self, session_id: &str, operation: Operation) -> Result<String, String> {
+        let id = {
+            let sessions = self.manager.sessions.lock().map_err(|_| "Lock poisoned")?;
+            scratchpad::resolve_id(&sessions, session_id)
+        };
+        self.check(Some(&id), operation)?;
+        Ok(id)
+    }
+
+    pub fn create_session(&self, profile: &Profile, options: &SessionOptions) -> Result<String, String> {
+        self.check(None, Operation::Manage)?;
+        self.manager.create_session(profile, options)
+    }
+
+    pub fn close(&self, session_id: &str) -> Result<(), String> {
+        let id = self.check_session(session_id, Operation::Manage)?;
+        self.manager.close(&id)
+    }
+
+    pub fn write(&self, session_id: &str, data: &str) -> Result<(), String> {
+        let id = self.check_session(session_id, Operation::Input)?;
+        self.manager.write(&id, data)
+    }
+
+    pub fn resize(&self, session_id: &str, rows: u16, cols: u16) -> Result<(), String> {
+        let id = self.check_session(session_id, Operation::Resize)?;
+        self.manager.resize(&id, rows, cols)
+    }
+
+    pub fn tab_title(&self, session_id: &str) -> Result<String, String> {
+        let id = self.check_session(session_id, Operation::Read)?;
+        self.manager.tab_title(&id)
+    }
+
+    pub fn list_marks(&self, session_id: &str) -> Result<Vec<OutputMark>, String> {
+        let id = self.check_session(session_id, Operation::Read)?;
+        self.manager.list_marks(&id)
+    }
+
+    pub fn read_output_since(&self, session_id: &str, offset: u64, max_bytes: usize) -> Result<OutputChunk, String> {
+        let id = self.check_session(session_id, Operation::Read)?;
+        self.manager.read_output_since(&id, offset, max_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use portable_pty::CommandBuilder;
+    use std::sync::Arc;
+
+    #[test]
+    fn read_only_token_cannot_type() {
+        let registry = PermissionRegistry::default();
+        let grant = registry.issue("log watcher".into(), Some(vec!["s1".into()]), vec![Operation::Read]).unwrap();
+
+        assert!(registry.authorize(&grant.token, Some("s1"), Operation::Read).is_ok());
+        assert!(registry.authorize(&grant.token, Some("s1"), Operation::Input).is_err());
+        assert!(registry.authorize(&grant.token, Some("s2"), Operation::Read).is_err());
+        assert!(registry.authorize(&grant.token, None, Operation::Read).is_err());
+    }
+
+    #[test]
+    fn unscoped_token_covers_all_sessions() {
+        let registry = PermissionRegistry::default();
+        let grant = registry.issue("bot".into(), None, vec![Operation::Input, Operation::Manage]).unwrap();
+        assert!(registry.authorize(&grant.token, Some("anything"), Operation::Input).is_ok());
+        assert!(registry.authorize(&grant.token, None, Operation::Manage).is_ok());
+    }
+
+    #[test]
+    fn revoked_tokens_are_rejected() {
+        let registry = PermissionRegistry::default();
+        let grant = registry.issue("tmp".into(), None, vec![Operation::Read]).unwrap();
+        assert!(registry.revoke(&grant.token).unwrap());
+        assert!(registry.authorize(&grant.token, Some("s1"), Operation::Read).is_err());
+        assert!(registry.issue("empty".into(), None, vec![]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scoped_sessions_enforce_grants() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let a = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        let b = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.sessions.lock().unwrap().get_mut(&b).unwrap().name = Some("scratchpad".into());
+        let registry = PermissionRegistry::default();
+
+        let watcher = registry.issue("watcher".into(), Some(vec![a.clone()]), vec![Operation::Read]).unwrap();
+        let watcher = ScopedSessions::new(&manager, &registry, &watcher.token);
+        assert!(watcher.tab_title(&a).is_ok());
+        assert!(watcher.write(&a, "true\r").is_err());
+        assert!(watcher.close(&a).is_err());
+        assert!(watcher.create_session(&Profile::default(), &SessionOptions::default()).is_err());
+
+        let typist = registry.issue("typist".into(), Some(vec![a.clone()]), vec![Operation::Input]).unwrap();
+        let typist = ScopedSessions::new(&manager, &registry, &typist.token);
+        assert!(typist.write(&a, "true\r").is_ok());
+        assert!(typist.write(&b, "true\r").is_err());
+        // Addressed by name, the scratchpad is still session B
+        assert!(typist.write("scratchpad", "true\r").is_err());
+        assert!(typist.close(&a).is_err());
+
+        manager.close(&a).unwrap();
+        manager.close(&b).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+//! Session-to-session pipes: complete output lines of one session, optionally filtered
+//! by a regex, are typed into another (e.g. a log tail feeding a REPL in another tab).
+//! Pipes that would form a loop are refused, and a pipe that floods its destination is
+//! stopped.
+
+use crate::events::EventSink;
+use crate::pty::{PtySession, SessionManager};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// More lines than this in RATE_WINDOW stops the pipe
+const MAX_LINES_PER_WINDOW: usize = 500;
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+// Longer lines are cut; an unterminated line is forwarded once it gets this long
+const MAX_LINE_BYTES: usize = 4096;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PipeInfo {
+    pub id: String,
+    pub source_id: String,
+    pub dest_id: String,
+    pub filter: Option<String>,
+    pub lines_forwarded: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipeStopReason {
+    Removed,
+    SessionClosed,
+    // Went over MAX_LINES_PER_WINDOW
+    RateLimited,
+    // The destination's input is locked, or writing to it failed
+    DestUnavailable,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PipeStoppedPayload {
+    pub pipe_id: String,
+    pub source_id: String,
+    pub dest_id: String,
+    pub reason: PipeStopReason,
+}
+
+struct Pipe {
+    info: PipeInfo,
+    filter: Option<Regex>,
+    // Output since the last line break
+    partial: String,
+    window_start: Instant,
+    window_lines: usize,
+}
+
+impl Pipe {
+    // Complete lines in `text`, filtered; Err if the pipe went over its rate
+    fn lines(&mut self, text: &str, now: Instant) -> Result<Vec<String>, PipeStopReason> {
+        let mut lines = Vec::new();
+        for c in text.chars() {
+            match c {
+                '\n' => lines.push(std::mem::take(&mut self.partial)),
+                '\r' => {}
+                c => {
+                    self.partial.push(c);
+                    if self.partial.len() >= MAX_LINE_BYTES {
+                        lines.push(std::mem::take(&mut self.partial));
+                    }
+                }
+            }
+        }
+        lines.retain(|line| !line.is_empty() && self.filter.as_ref().is_none_or(|f| f.is_match(line)));
+        if now.duration_since(self.window_start) >= RATE_WINDOW {
+            self.window_start = now;
+            self.window_lines = 0;
+        }
+        self.window_lines += lines.len();
+        if self.window_lines > MAX_LINES_PER_WINDOW {
+            return Err(PipeStopReason::RateLimited);
+        }
+        Ok(lines)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Pipes {
+    pipes: Vec<Pipe>,
+}
+
+impl Pipes {
+    // Whether output of `from` already reaches `to` through existing pipes
+    fn reaches(&self, from: &str, to: &str) -> bool {
+        let mut pending = vec![from];
+        let mut seen = Vec::new();
+        while let Some(id) = pending.pop() {
+            if id == to {
+                return true;
+            }
+            if seen.contains(&id) {
+                continue;
+            }
+            seen.push(id);
+            pending.extend(self.pipes.iter().filter(|p| p.info.source_id == id).map(|p| p.info.dest_id.as_str()));
+        }
+        false
+    }
+
+    fn remove(&mut self, keep: impl Fn(&PipeInfo) -> bool) -> Vec<PipeInfo> {
+        let (kept, removed) = std::mem::take(&mut self.pipes).into_iter().partition(|p| keep(&p.info));
+        self.pipes = kept;
+        removed.into_iter().map(|p: Pipe| p.info).collect()
+    }
+}
+
+fn emit_stopped(events: &Arc<dyn EventSink>, info: &PipeInfo, reason: PipeStopReason) {
+    events.emit("pipe-stopped", PipeStoppedPayload {
+        pipe_id: info.id.clone(),
+        source_id: info.source_id.clone(),
+        dest_id: info.dest_id.clone(),
+        reason,
+    });
+}
+
+/// Forwards the lines completed by `text` (output of `session_id`) down its pipes.
+pub(crate) fn forward(
+    pipes: &Mutex<Pipes>,
+    session_id: &str,
+    text: &str,
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    events: &Arc<dyn EventSink>,
+) {
+    let Ok(mut pipes) = pipes.lock() else { return };
+    let now = Instant::now();
+    let mut stopped = Vec::new();
+    for pipe in pipes.pipes.iter_mut().filter(|p| p.info.source_id == session_id) {
+        let lines = match pipe.lines(text, now) {
+            Ok(lines) if lines.is_empty() => continue,
+            Ok(lines) => lines,
+            Err(reason) => {
+                stopped.push((pipe.info.id.clone(), reason));
+                continue;
+            }
+        };
+        let Ok(sessions) = sessions.lock() else { return };
+        let written = sessions.get(&pipe.info.dest_id)
+            .filter(|dest| !dest.input_locked)
+            .and_then(|dest| dest.writer.lock().ok())
+            .is_some_and(|mut writer| lines.iter().all(|line| write!(writer, "{}\r", line).is_ok()));
+        if written {
+            pipe.info.lines_forwarded += lines.len() as u64;
+        } else {
+            stopped.push((pipe.info.id.clone(), PipeStopReason::DestUnavailable));
+        }
+    }
+    for (id, reason) in stopped {
+        for info in pipes.remove(|p| p.id != id) {
+            emit_stopped(events, &info, reason);
+        }
+    }
+}
+
+impl SessionManager {
+    /// Types each output line of `source_id` matching `filter` (all lines if None) into
+    /// `dest_id`. Returns the pipe's id. Refused if it would feed a session its own output.
+    pub fn pipe_sessions(&self, source_id: &str, dest_id: &str, filter: Option<&str>) -> Result<String, String> {
+        if source_id == dest_id {
+            return Err("A session can't be piped into itself".to_string());
+        }
+        let filter = filter.filter(|f| !f.is_empty());
+        let regex = filter.map(Regex::new).transpose().map_err(|e| format!("Invalid filter: {}", e))?;
+        {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            if !sessions.contains_key(source_id) || !sessions.contains_key(dest_id) {
+                return Err("Session not found".to_string());
+            }
+        }
+        let mut pipes = self.pipes.lock().map_err(|_| "Lock poisoned")?;
+        if pipes.reaches(dest_id, source_id) {
+            return Err("The destination already pipes into the source".to_string());
+        }
+        let id = Uuid::new_v4().to_string();
+        pipes.pipes.push(Pipe {
+            info: PipeInfo {
+                id: id.clone(),
+                source_id: source_id.to_string(),
+                dest_id: dest_id.to_string(),
+                filter: filter.map(str::to_string),
+                lines_forwarded: 0,
+            },
+            filter: regex,
+            partial: String::new(),
+            window_start: Instant::now(),
+            window_lines: 0,
+        });
+        Ok(id)
+    }
+
+    pub fn unpipe_sessions(&self, pipe_id: &str) -> Result<(), String> {
+        let removed = self.pipes.lock().map_err(|_| "Lock poisoned")?.remove(|p| p.id != pipe_id);
+        let info = removed.first().ok_or("Pipe not found")?;
+        emit_stopped(&self.events, info, PipeStopReason::Removed);
+        Ok(())
+    }
+
+    pub fn list_pipes(&self) -> Result<Vec<PipeInfo>, String> {
+        let pipes = self.pipes.lock().map_err(|_| "Lock poisoned")?;
+        Ok(pipes.pipes.iter().map(|p| p.info.clone()).collect())
+    }
+
+    // Pipes from or to a closed session
+    pub(crate) fn remove_session_pipes(&self, session_id: &str) {
+        let Ok(mut pipes) = self.pipes.lock() else { return };
+        for info in pipes.remove(|p| p.source_id != session_id && p.dest_id != session_id) {
+            emit_stopped(&self.events, &info, PipeStopReason::SessionClosed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipe(filter: Option<&str>) -> Pipe {
+        Pipe {
+            info: PipeInfo {
+                id: "p".into(),
+                source_id: "a".into(),
+                dest_id: "b".into(),
+                filter: filter.map(String::from),
+                lines_forwarded: 0,
+            },
+            filter: filter.map(|f| Regex::new(f).unwrap()),
+            partial: String::new(),
+            window_start: Instant::now(),
+            window_lines: 0,
+        }
+    }
+
+    #[test]
+    fn splits_filters_and_limits_lines() {
+        let mut pipe = pipe(Some("ERROR"));
+        let now = Instant::now();
+        assert_eq!(pipe.lines("ok\r\nERROR one\r\nERR", now), Ok(vec!["ERROR one".to_string()]));
+        assert_eq!(pipe.lines("OR two\r\n", now), Ok(vec!["ERROR two".to_string()]));
+
+        let flood = "ERROR\n".repeat(MAX_LINES_PER_WINDOW);
+        assert_eq!(pipe.lines(&flood, now), Err(PipeStopReason::RateLimited));
+        assert!(pipe.lines("ERROR\n", now + RATE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn finds_loops_through_other_pipes() {
+        let mut pipes = Pipes::default();
+        pipes.pipes.push(pipe(None));
+        let mut second = pipe(None);
+        second.info.source_id = "b".into();
+        second.info.dest_id = "c".into();
+        pipes.pipes.push(second);
+        assert!(pipes.reaches("a", "c"));
+        assert!(!pipes.reaches("c", "a"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn forwards_lines_into_another_session() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let source = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        let dest = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        let id = manager.pipe_sessions(&source, &dest, Some("^piped")).unwrap();
+        assert!(manager.pipe_sessions(&dest, &source, None).is_err());
+
+        manager.write(&source, "echo piped-'echo got-'$((6*7))\n").unwrap();
+        let mut forwarded = false;
+        for _ in 0..100 {
+            let buffer = manager.session_buffer(&dest).unwrap();
+            let crate::ipc::OutputData::Bytes(data) = buffer.data else { panic!("expected bytes") };
+            if String::from_utf8_lossy(&data).contains("got-42") {
+                forwarded = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(forwarded, "the line never reached the destination");
+        assert_eq!(manager.list_pipes().unwrap()[0].id, id);
+
+        manager.close(&dest).unwrap();
+        assert!(manager.list_pipes().unwrap().is_empty());
+        assert_eq!(sink.named("pipe-stopped")[0]["reason"], "session_closed");
+        manager.close(&source).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+//! Warm shells for instant new tabs. A few sessions of the default profile are started in
+//! the background and handed out by `create_session` when the request doesn't ask for
+//! anything special, so a new tab doesn't wait for the shell's startup files. A pooled
+//! shell's output (its first prompt) is held until the frontend sizes the new tab's
+//! terminal, which is also when the prompt would have been drawn at the right width.
+//! Changing the profile or the pool size replaces the idle shells.
+
+use crate::config::{load_json, save_json};
+use crate::profiles::Profile;
+use crate::pty::{PtyOutputPayload, PtySession, SessionManager, SessionOptions};
+use crate::stream::OutputStream;
+use crate::unix_now;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+
+pub const MAX_POOL_SIZE: usize = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolSettings {
+    // Idle shells kept ready; 0 disables the pool
+    pub size: usize,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        PoolSettings { size: 1 }
+    }
+}
+
+// Pool settings persisted as JSON in the config dir
+pub struct PoolStore {
+    path: Option<PathBuf>,
+    pub settings: PoolSettings,
+}
+
+impl PoolStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        PoolStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PoolState {
+    // Waiting in the pool
+    Idle,
+    // Handed out; output is held until the tab's terminal is sized
+    Claimed,
+}
+
+#[derive(Default)]
+pub(crate) struct WarmPool {
+    size: usize,
+    profile: Option<Profile>,
+    idle: Vec<String>,
+    filling: bool,
+}
+
+// Only sessions that would be started exactly like the pooled ones can be swapped in
+fn poolable(options: &SessionOptions) -> bool {
+    options.shell.is_none()
+        && options.args.is_none()
+        && options.cwd.is_none()
+        && options.env.is_empty()
+        && options.private_tmp != Some(false)
+        && options.split_stderr != Some(true)
+}
+
+// Profiles have no PartialEq; two are the same if they serialize the same
+fn same_profile(a: &Profile, b: &Profile) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+impl SessionManager {
+    /// Keeps `size` shells of `profile` ready. Idle shells of another profile, or beyond the
+    /// new size, are closed; the pool refills in the background.
+    pub fn configure_pool(self: &Arc<Self>, settings: &PoolSettings, profile: &Profile) -> Result<(), String> {
+        let stale = {
+            let mut pool = self.pool.lock().map_err(|_| "Lock poisoned")?;
+            pool.size = settings.size.min(MAX_POOL_SIZE);
+            let mut stale = Vec::new();
+            if !pool.profile.as_ref().is_some_and(|p| same_profile(p, profile)) {
+                pool.profile = Some(profile.clone());
+                stale.append(&mut pool.idle);
+            }
+            let size = pool.size;
+            if pool.idle.len() > size {
+                stale.extend(pool.idle.drain(size..));
+            }
+            stale
+        };
+        for id in stale {
+            self.close(&id)?;
+        }
+        self.fill_pool();
+        Ok(())
+    }
+
+    /// Starts shells in the background until the pool is full. Call after taking from it.
+    pub fn fill_pool(self: &Arc<Self>) {
+        match self.pool.lock() {
+            Ok(mut pool) if !pool.filling && pool.idle.len() < pool.size => pool.filling = true,
+            _ => return,
+        }
+        let manager = Arc::downgrade(self);
+        thread::spawn(move || {
+            while let Some(manager) = manager.upgrade() {
+                let Ok(mut pool) = manager.pool.lock() else { return };
+                let profile = match &pool.profile {
+                    Some(profile) if pool.idle.len() < pool.size => profile.clone(),
+                    _ => {
+                        pool.filling = false;
+                        return;
+                    }
+                };
+                drop(pool);
+                let started = manager.start_session(&profile, &SessionOptions::default(), true);
+                let Ok(mut pool) = manager.pool.lock() else { return };
+                match started {
+                    // Reconfigured while starting: this one is already stale
+                    Ok(id) if !pool.profile.as_ref().is_some_and(|p| same_profile(p, &profile)) => {
+                        drop(pool);
+                        let _ = manager.close(&id);
+                    }
+                    Ok(id) => pool.idle.push(id),
+                    Err(e) => {
+                        eprintln!("Failed to start a pooled shell: {}", e);
+                        pool.filling = false;
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Hands out an idle shell if `profile` and `options` match the pool's, resized to the
+    /// requested size.
+    pub(crate) fn take_pooled(&self, profile: &Profile, options: &SessionOptions) -> Result<Option<String>, String> {
+        if !poolable(options) {
+            return Ok(None);
+        }
+        loop {
+            let id = {
+                let mut pool = self.pool.lock().map_err(|_| "Lock poisoned")?;
+                if !pool.profile.as_ref().is_some_and(|p| same_profile(p, profile)) {
+                    return Ok(None);
+                }
+                match pool.idle.pop() {
+                    Some(id) => id,
+                    None => return Ok(None),
+                }
+            };
+            let alive = {
+                let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+                sessions.get(&id).is_some_and(|s| !s.exited.load(Ordering::SeqCst))
+            };
+            if !alive {
+                self.close(&id)?;
+                continue;
+            }
+            let (rows, cols) = options.size();
+            self.resize(&id, rows, cols)?;
+            let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            if let Some(session) = sessions.get_mut(&id) {
+                session.pool_state = Some(PoolState::Claimed);
+                session.created_at = unix_now();
+                session.last_activity.store(unix_now(), Ordering::SeqCst);
+            }
+            return Ok(Some(id));
+        }
+    }
+
+    // Lets a claimed shell's held output through, now that something is showing it
+    pub(crate) fn release_pooled(&self, session_id: &str, session: &mut PtySession) {
+        if session.pool_state != Some(PoolState::Claimed) {
+            return;
+        }
+        session.pool_state = None;
+        let held = session.gate.resume();
+        if !held.is_empty() {
+            self.events.emit("pty-output", PtyOutputPayload {
+                session_id: session_id.to_string(),
+                data: self.output_protocol.encode(held),
+                stream: OutputStream::Stdout,
+            });
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use std::time::Duration;
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        for _ in 0..250 {
+            if done() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("timed out");
+    }
+
+    fn idle(manager: &SessionManager) -> Vec<String> {
+        manager.pool.lock().unwrap().idle.clone()
+    }
+
+    #[test]
+    fn hands_out_warm_shells_and_refills() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = Arc::new(SessionManager::new(sink.clone(), HistoryStore::load(None)));
+        let profile = Profile::default();
+        manager.configure_pool(&PoolSettings { size: 1 }, &profile).unwrap();
+        wait_for(|| idle(&manager).len() == 1);
+        let warm = idle(&manager)[0].clone();
+        assert!(manager.list_sessions().unwrap().is_empty());
+
+        // Anything unusual gets a fresh shell
+        let custom = SessionOptions { shell: Some("sh".into()), ..Default::default() };
+        let fresh = manager.create_session(&profile, &custom).unwrap();
+        assert_ne!(fresh, warm);
+
+        let options = SessionOptions { rows: Some(40), cols: Some(120), ..Default::default() };
+        assert_eq!(manager.create_session(&profile, &options).unwrap(), warm);
+        assert!(idle(&manager).is_empty());
+        let size = manager.sessions.lock().unwrap()[&warm].master.lock().unwrap().get_size().unwrap();
+        assert_eq!((size.rows, size.cols), (40, 120));
+        manager.resize(&warm, 40, 120).unwrap();
+        assert_eq!(manager.sessions.lock().unwrap()[&warm].pool_state, None);
+
+        manager.fill_pool();
+        wait_for(|| idle(&manager).len() == 1);
+        let replacement = idle(&manager)[0].clone();
+        manager.configure_pool(&PoolSettings { size: 0 }, &profile).unwrap();
+        assert!(!manager.sessions.lock().unwrap().contains_key(&replacement));
+        manager.close_all();
+    }
+}
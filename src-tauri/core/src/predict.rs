@@ -0,0 +1,229 @@
+//! Predictive local echo (mosh-style) for high-latency sessions. Printable keystrokes are
+//! reported as predictions right away so the frontend can draw them tentatively, then
+//! reconciled against the real echo: confirmed predictions are replaced by the output,
+//! wrong ones are reported as discarded so the frontend erases them.
+//!
+//! Predictions are only shown once echo has been seen working on the current line, so
+//! typing at a password prompt (no echo) never shows anything.
+
+use crate::events::EventSink;
+use crate::process;
+use crate::pty::{PtySession, SessionManager};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const MAX_PENDING: usize = 256;
+// A prediction without an echo by then means echo is off (or the link is very slow)
+const PENDING_TIMEOUT: Duration = Duration::from_secs(1);
+// How long the foreground-process check for `Ssh` mode is reused
+const SSH_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PredictionMode {
+    #[default]
+    Off,
+    // Only while ssh or mosh is the foreground process
+    Ssh,
+    Always,
+}
+
+#[derive(Clone, Serialize)]
+pub struct EchoPredictedPayload {
+    pub session_id: String,
+    pub text: String,
+}
+
+// Counts refer to predictions previously reported in `echo-predicted`, oldest first
+#[derive(Clone, Serialize)]
+pub struct EchoReconciledPayload {
+    pub session_id: String,
+    pub confirmed: usize,
+    pub discarded: usize,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct PredictionUpdate {
+    pub(crate) predicted: String,
+    pub(crate) confirmed: usize,
+    pub(crate) discarded: usize,
+}
+
+struct Prediction {
+    c: char,
+    // Reported to the frontend (echo was known to work when it was typed)
+    shown: bool,
+    typed_at: Instant,
+}
+
+#[derive(Default)]
+pub(crate) struct EchoPredictor {
+    mode: PredictionMode,
+    pending: VecDeque<Prediction>,
+    // The last prediction on this line was echoed
+    echo_confirmed: bool,
+    ssh_checked: Option<(Instant, bool)>,
+}
+
+impl EchoPredictor {
+    fn discard_all(&mut self, update: &mut PredictionUpdate) {
+        update.discarded += self.pending.drain(..).filter(|p| p.shown).count();
+        self.echo_confirmed = false;
+    }
+
+    fn active(&mut self, is_ssh: impl FnOnce() -> bool) -> bool {
+        match self.mode {
+            PredictionMode::Off => false,
+            PredictionMode::Always => true,
+            PredictionMode::Ssh => {
+                let now = Instant::now();
+                match self.ssh_checked {
+                    Some((at, ssh)) if now.duration_since(at) < SSH_CHECK_INTERVAL => ssh,
+                    _ => {
+                        let ssh = is_ssh();
+                        self.ssh_checked = Some((now, ssh));
+                        ssh
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn input(&mut self, data: &str, is_ssh: impl FnOnce() -> bool) -> PredictionUpdate {
+        let mut update = PredictionUpdate::default();
+        if !self.active(is_ssh) {
+            self.discard_all(&mut update);
+            return update;
+        }
+        // Enter, editing keys and escape sequences: the line may change in ways we can't
+        // predict, and the next program may not echo at all
+        if data.chars().any(char::is_control) {
+            self.discard_all(&mut update);
+            return update;
+        }
+        let now = Instant::now();
+        for c in data.chars() {
+            if self.pending.len() == MAX_PENDING {
+                self.discard_all(&mut update);
+            }
+            self.pending.push_back(Prediction { c, shown: self.echo_confirmed, typed_at: now });
+            if self.echo_confirmed {
+                update.predicted.push(c);
+            }
+        }
+        update
+    }
+
+    // `text` is the printed output (escape sequences removed)
+    pub(crate) fn output(&mut self, text: &str) -> PredictionUpdate {
+        let mut update = PredictionUpdate::default();
+        if self.pending.front().is_some_and(|p| p.typed_at.elapsed() > PENDING_TIMEOUT) {
+            self.discard_all(&mut update);
+        }
+        for c in text.chars() {
+            let Some(next) = self.pending.front() else { break };
+            if next.c != c {
+                self.discard_all(&mut update);
+                break;
+            }
+            if next.shown {
+                update.confirmed += 1;
+            }
+            self.pending.pop_front();
+            self.echo_confirmed = true;
+        }
+        update
+    }
+}
+
+fn foreground_is_ssh(session: &PtySession) -> bool {
+    process::foreground_pid(session)
+        .and_then(process::process_name)
+        .is_some_and(|name| matches!(name.rsplit('/').next(), Some("ssh" | "mosh-client")))
+}
+
+impl SessionManager {
+    pub fn set_echo_prediction(&self, session_id: &str, mode: PredictionMode) -> Result<(), String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let mut predictor = session.predictor.lock().map_err(|_| "Lock poisoned")?;
+        *predictor = EchoPredictor { mode, ..EchoPredictor::default() };
+        Ok(())
+    }
+
+    // Called from `write` with the sessions lock held
+    pub(crate) fn predict_input(&self, session_id: &str, session: &PtySession, data: &str) {
+        let update = match session.predictor.lock() {
+            Ok(mut predictor) => predictor.input(data, || foreground_is_ssh(session)),
+            Err(_) => return,
+        };
+        emit_update(&self.events, session_id, update);
+    }
+}
+
+pub(crate) fn emit_update(events: &Arc<dyn EventSink>, session_id: &str, update: PredictionUpdate) {
+    if update.confirmed > 0 || update.discarded > 0 {
+        events.emit("echo-reconciled", EchoReconciledPayload {
+            session_id: session_id.to_string(),
+            confirmed: update.confirmed,
+            discarded: update.discarded,
+        });
+    }
+    if !update.predicted.is_empty() {
+        events.emit("echo-predicted", EchoPredictedPayload {
+            session_id: session_id.to_string(),
+            text: update.predicted,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predictor() -> EchoPredictor {
+        EchoPredictor { mode: PredictionMode::Always, ..EchoPredictor::default() }
+    }
+
+    #[test]
+    fn shows_predictions_once_echo_is_confirmed() {
+        let mut p = predictor();
+        // First keystroke on a line is tracked silently
+        assert_eq!(p.input("l", || false).predicted, "");
+        assert_eq!(p.output("l").confirmed, 0);
+        assert_eq!(p.input("s", || false).predicted, "s");
+        assert_eq!(p.input(" -", || false).predicted, " -");
+        let update = p.output("s -");
+        assert_eq!((update.confirmed, update.discarded), (3, 0));
+    }
+
+    #[test]
+    fn discards_mismatched_and_control_input() {
+        let mut p = predictor();
+        p.input("a", || false);
+        p.output("a");
+        p.input("bc", || false);
+        assert_eq!(p.output("x").discarded, 2);
+
+        p.input("d", || false);
+        p.output("d");
+        p.input("e", || false);
+        assert_eq!(p.input("\r", || false).discarded, 1);
+        // Echo is unknown again after Enter (e.g. a password prompt)
+        assert_eq!(p.input("secret", || false).predicted, "");
+    }
+
+    #[test]
+    fn ssh_mode_follows_the_foreground_process() {
+        let mut p = EchoPredictor { mode: PredictionMode::Ssh, ..EchoPredictor::default() };
+        p.input("a", || false);
+        assert!(p.pending.is_empty());
+        p.ssh_checked = None;
+        p.input("a", || true);
+        p.output("a");
+        // The check is cached, so this closure isn't consulted
+        assert_eq!(p.input("b", || false).predicted, "b");
+    }
+}
@@ -0,0 +1,332 @@
+//! Pre-exec hooks: opt-in rewrites of a command line after Enter and before the shell
+//! gets it, e.g. adding `--dry-run` to deploys in a production profile or expanding
+//! aliases in every shell. A hook is a regex replacement or a script that reads the line
+//! on stdin and prints the line to run. Every rewrite is announced with
+//! `command-rewritten` (the line before and after), so nothing changes silently.
+//!
+//! Only lines typed at the shell's prompt are rewritten: not lines edited with cursor
+//! keys (their text isn't known), pastes, or input to a program running in the
+//! foreground. The typed line is erased with Ctrl-U and the rewritten one typed instead.
+
+use crate::config::{load_json, save_json};
+use crate::process;
+use crate::pty::SessionManager;
+use crate::scratchpad;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A script that takes longer is killed and the line left alone
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(2);
+const PASTE_START: &str = "\x1b[200~";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RewriteAction {
+    // Replaces the first match of `pattern`; `$1` etc. in `replacement` are its groups
+    Replace { pattern: String, replacement: String },
+    // Run with `sh -c` (`cmd /C` on Windows); gets the line on stdin and in
+    // SHELLL_COMMAND and prints the line to run. Printing nothing keeps the line.
+    Script { command: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreExecHook {
+    pub name: String,
+    // Profiles whose sessions it applies to; every session if empty
+    #[serde(default)]
+    pub profiles: Vec<String>,
+    #[serde(flatten)]
+    pub action: RewriteAction,
+}
+
+/// Hooks run in order, each on what the previous one printed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreExecSettings {
+    pub enabled: bool,
+    pub hooks: Vec<PreExecHook>,
+}
+
+// Pre-exec hooks persisted as JSON in the config dir
+pub struct PreExecStore {
+    path: Option<PathBuf>,
+    pub settings: PreExecSettings,
+}
+
+impl PreExecStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        PreExecStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct CommandRewrittenPayload {
+    pub session_id: String,
+    pub hook: String,
+    pub original: String,
+    pub rewritten: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PreExecFailedPayload {
+    pub session_id: String,
+    pub hook: String,
+    pub error: String,
+}
+
+#[derive(Clone)]
+struct CompiledHook {
+    hook: PreExecHook,
+    pattern: Option<Regex>,
+}
+
+impl CompiledHook {
+    fn applies_to(&self, profile: Option<&str>) -> bool {
+        self.hook.profiles.is_empty() || profile.is_some_and(|p| self.hook.profiles.iter().any(|h| h == p))
+    }
+
+    // What the hook turns `line` into; `env` is passed to scripts
+    fn rewrite(&self, line: &str, env: &[(&str, String)], cwd: Option<&str>) -> Result<String, String> {
+        match (&self.hook.action, &self.pattern) {
+            (RewriteAction::Replace { replacement, .. }, Some(pattern)) => {
+                Ok(pattern.replace(line, replacement.as_str()).into_owned())
+            }
+            (RewriteAction::Script { command }, _) => run_script(command, line, env, cwd),
+            (RewriteAction::Replace { .. }, None) => Ok(line.to_string()),
+        }
+    }
+}
+
+// Enabled hooks, compiled; empty while pre-exec hooks are off
+#[derive(Default)]
+pub(crate) struct PreExecHooks {
+    hooks: Vec<CompiledHook>,
+}
+
+impl PreExecHooks {
+    fn new(settings: &PreExecSettings) -> Result<Self, String> {
+        if !settings.enabled {
+            return Ok(PreExecHooks::default());
+        }
+        let hooks = settings.hooks.iter()
+            .map(|hook| {
+                let pattern = match &hook.action {
+                    RewriteAction::Replace { pattern, .. } => Some(
+                        Regex::new(pattern).map_err(|e| format!("Invalid pattern in hook '{}': {}", hook.name, e))?,
+                    ),
+                    RewriteAction::Script { .. } => None,
+                };
+                Ok(CompiledHook { hook: hook.clone(), pattern })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(PreExecHooks { hooks })
+    }
+}
+
+fn run_script(command: &str, line: &str, env: &[(&str, String)], cwd: Option<&str>) -> Result<String, String> {
+    let mut cmd = process::shell_command(command);
+    for (name, value) in env {
+        cmd.env(name, value);
+    }
+    if let Some(cwd) = cwd.filter(|c| std::path::Path::new(c).is_dir()) {
+        cmd.current_dir(cwd);
+    }
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run '{}': {}", command, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = writeln!(stdin, "{}", line);
+    }
+    let deadline = Instant::now() + SCRIPT_TIMEOUT;
+    let status = loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => break status,
+            None if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("'{}' took longer than {}s", command, SCRIPT_TIMEOUT.as_secs()));
+            }
+        }
+    };
+    if !status.success() {
+        return Err(format!("'{}' exited with {}", command, status));
+    }
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        stdout.read_to_string(&mut output).map_err(|e| e.to_string())?;
+    }
+    let output = output.trim_end_matches(['\r', '\n']);
+    if output.is_empty() {
+        return Ok(line.to_string());
+    }
+    // Anything else would type more than the one line into the shell
+    if output.chars().any(|c| c.is_control() && c != '\t') {
+        return Err(format!("'{}' printed more than one line", command));
+    }
+    Ok(output.to_string())
+}
+
+impl SessionManager {
+    pub fn set_pre_exec_settings(&self, settings: &PreExecSettings) -> Result<(), String> {
+        let hooks = PreExecHooks::new(settings)?;
+        *self.pre_exec.lock().map_err(|_| "Lock poisoned")? = hooks;
+        Ok(())
+    }
+
+    // `data` with each line it submits at the shell's prompt rewritten by the hooks
+    pub(crate) fn rewrite_submitted<'a>(&self, session_id: &str, data: &'a str) -> Result<Cow<'a, str>, String> {
+        if !data.contains(['\r', '\n']) || data.contains(PASTE_START) {
+            return Ok(Cow::Borrowed(data));
+        }
+        let (session_id, profile, cwd, mut tracker) = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session_id = scratchpad::resolve_id(&sessions, session_id);
+            let Some(session) = sessions.get(&session_id) else { return Ok(Cow::Borrowed(data)) };
+            let at_prompt = session.pid.is_some() && process::foreground_pid(session) == session.pid;
+            if session.input_locked || !at_prompt {
+                return Ok(Cow::Borrowed(data));
+            }
+            (session_id, session.profile.clone(), session.title.cwd.clone(), session.input_line.clone())
+        };
+        let hooks: Vec<CompiledHook> = self.pre_exec.lock().map_err(|_| "Lock poisoned")?
+            .hooks.iter()
+            .filter(|h| h.applies_to(profile.as_deref()))
+            .cloned()
+            .collect();
+        if hooks.is_empty() {
+            return Ok(Cow::Borrowed(data));
+        }
+
+        let mut env = vec![
+            ("SHELLL_SESSION_ID", session_id.clone()),
+            ("SHELLL_PROFILE", profile.unwrap_or_default()),
+            ("SHELLL_CWD", cwd.clone().unwrap_or_default()),
+        ];
+        let mut out = String::with_capacity(data.len());
+        let mut changed = false;
+        let mut rest = data;
+        while let Some(enter) = rest.find(['\r', '\n']) {
+            let typed = &rest[..enter];
+            tracker.feed(typed);
+            out.push_str(typed);
+            if let Some(original) = tracker.pending().map(str::to_string) {
+                let mut line = original.clone();
+                for hook in &hooks {
+                    env.retain(|(name, _)| *name != "SHELLL_COMMAND");
+                    env.push(("SHELLL_COMMAND", line.clone()));
+                    match hook.rewrite(&line, &env, cwd.as_deref()) {
+                        Ok(rewritten) if rewritten != line => {
+                            self.events.emit("command-rewritten", CommandRewrittenPayload {
+                                session_id: session_id.clone(),
+                                hook: hook.hook.name.clone(),
+                                original: line,
+                                rewritten: rewritten.clone(),
+                            });
+                            line = rewritten;
+                        }
+                        Ok(_) => {}
+                        Err(error) => self.events.emit("pre-exec-hook-failed", PreExecFailedPayload {
+                            session_id: session_id.clone(),
+                            hook: hook.hook.name.clone(),
+                            error,
+                        }),
+                    }
+                }
+                if line != original {
+                    out.push('\x15');
+                    out.push_str(&line);
+                    changed = true;
+                }
+            }
+            tracker.feed(&rest[enter..enter + 1]);
+            out.push_str(&rest[enter..enter + 1]);
+            rest = &rest[enter + 1..];
+        }
+        if !changed {
+            return Ok(Cow::Borrowed(data));
+        }
+        out.push_str(rest);
+        Ok(Cow::Owned(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(name: &str, profiles: &[&str], action: RewriteAction) -> PreExecHook {
+        PreExecHook { name: name.into(), profiles: profiles.iter().map(|p| p.to_string()).collect(), action }
+    }
+
+    #[test]
+    fn hooks_apply_to_their_profiles_only_when_enabled() {
+        let dry_run = hook("dry run", &["prod"], RewriteAction::Replace {
+            pattern: "^(kubectl apply .*)$".into(),
+            replacement: "$1 --dry-run=server".into(),
+        });
+        let mut settings = PreExecSettings { enabled: false, hooks: vec![dry_run] };
+        assert!(PreExecHooks::new(&settings).unwrap().hooks.is_empty());
+
+        settings.enabled = true;
+        let hooks = PreExecHooks::new(&settings).unwrap();
+        let hook = &hooks.hooks[0];
+        assert!(hook.applies_to(Some("prod")) && !hook.applies_to(Some("default")) && !hook.applies_to(None));
+        assert_eq!(hook.rewrite("kubectl apply -f x.yaml", &[], None).unwrap(), "kubectl apply -f x.yaml --dry-run=server");
+        assert_eq!(hook.rewrite("kubectl get pods", &[], None).unwrap(), "kubectl get pods");
+
+        settings.hooks[0].action = RewriteAction::Replace { pattern: "(".into(), replacement: String::new() };
+        assert!(PreExecHooks::new(&settings).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scripts_print_the_line_to_run() {
+        let env = [("SHELLL_PROFILE", "prod".to_string())];
+        assert_eq!(run_script("sed 's/^ll/ls -l/'", "ll /tmp", &env, None).unwrap(), "ls -l /tmp");
+        assert_eq!(run_script("echo \"$SHELLL_PROFILE: $(cat)\"", "make", &env, None).unwrap(), "prod: make");
+        assert_eq!(run_script("true", "make", &env, None).unwrap(), "make");
+        assert!(run_script("exit 3", "make", &env, None).is_err());
+        assert!(run_script("printf 'a\\nb\\n'", "make", &env, None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rewrites_lines_submitted_at_the_prompt() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+        use std::sync::Arc;
+
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        manager.set_pre_exec_settings(&PreExecSettings {
+            enabled: true,
+            hooks: vec![hook("alias", &[], RewriteAction::Replace { pattern: "^greet$".into(), replacement: "echo hello".into() })],
+        }).unwrap();
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        manager.write(&id, "gre").unwrap();
+        manager.write(&id, "et\r").unwrap();
+        let history = manager.history.lock().unwrap();
+        assert_eq!(history.entries().back().unwrap().command, "echo hello");
+        drop(history);
+        let events = sink.named("command-rewritten");
+        assert_eq!((events[0]["original"].as_str(), events[0]["rewritten"].as_str()), (Some("greet"), Some("echo hello")));
+        assert_eq!(manager.rewrite_submitted(&id, "ls\r").unwrap(), "ls\r");
+        manager.close(&id).unwrap();
+    }
+}
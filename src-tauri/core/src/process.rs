@@ -0,0 +1,258 @@
+//! Inspection of the processes running inside sessions. Uses `ps`, which behaves the
+//! same on macOS and Linux (macOS has no /proc).
+//!
+//! Processes are attributed to sessions through the process tree where possible, and
+//! otherwise through the `SHELLL_SESSION_ID` variable every shell is started with, which
+//! survives daemonizing, `nohup` and app restarts.
+
+use crate::env::EnvMap;
+use crate::pty::{PtySession, SessionManager, SESSION_ID_VAR};
+use serde::Serialize;
+use std::process::Command;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TaggedProcess {
+    pub pid: u32,
+    pub name: Option<String>,
+    pub session_id: String,
+}
+
+/// `command` as the platform shell runs it, for user-configured hooks and rule actions.
+pub(crate) fn shell_command(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    }
+    #[cfg(not(windows))]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
+
+/// Pid of the terminal's foreground job, falling back to the shell.
+pub(crate) fn foreground_pid(session: &PtySession) -> Option<u32> {
+    #[cfg(unix)]
+    if let Some(pgid) = session.master.lock().ok().and_then(|m| m.process_group_leader()) {
+        return Some(pgid as u32);
+    }
+    session.pid
+}
+
+fn ps_field(pid: u32, field: &str) -> Option<String> {
+    let output = Command::new("ps")
+        .args(["-o", &format!("{}=", field), "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+/// Command line of `pid`, split on whitespace (ps doesn't preserve quoting).
+pub fn process_args(pid: u32) -> Option<Vec<String>> {
+    ps_field(pid, "args").map(|args| args.split_whitespace().map(String::from).collect())
+}
+
+/// Executable name of `pid`, without its directory.
+pub fn process_name(pid: u32) -> Option<String> {
+    let comm = ps_field(pid, "comm")?;
+    Some(comm.rsplit('/').next().unwrap_or(&comm).trim_start_matches('-').to_string())
+}
+
+fn parent_pid(pid: u32) -> Option<u32> {
+    ps_field(pid, "ppid")?.parse().ok()
+}
+
+/// Whether `pid` is `root` or one of its descendants.
+pub(crate) fn is_descendant(pid: u32, root: u32) -> bool {
+    let mut current = pid;
+    // Bounded in case of a pid reused mid-walk
+    for _ in 0..64 {
+        if current == root {
+            return true;
+        }
+        match parent_pid(current) {
+            Some(parent) if parent != current && parent > 1 => current = parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
+// NUL-separated NAME=value entries
+#[cfg(any(target_os = "linux", target_os = "macos", test))]
+fn parse_environ(data: &[u8]) -> EnvMap {
+    data.split(|&b| b == 0)
+        .filter_map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let (name, value) = entry.split_once('=')?;
+            (!name.is_empty()).then(|| (name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Environment `pid` was started with (later `setenv` calls in the process aren't
+/// visible). Only works for processes of the same user.
+#[cfg(target_os = "linux")]
+pub fn process_env(pid: u32) -> Option<EnvMap> {
+    std::fs::read(format!("/proc/{}/environ", pid)).ok().map(|data| parse_environ(&data))
+}
+
+// KERN_PROCARGS2: argc, the executable path, padding, argv, then the environment
+#[cfg(target_os = "macos")]
+pub fn process_env(pid: u32) -> Option<EnvMap> {
+    let mut mib = [libc::CTL_KERN, libc::KERN_PROCARGS2, pid as libc::c_int];
+    let mut size: libc::size_t = 0;
+    let ok = unsafe { libc::sysctl(mib.as_mut_ptr(), 3, std::ptr::null_mut(), &mut size, std::ptr::null_mut(), 0) };
+    if ok != 0 || size < 4 {
+        return None;
+    }
+    let mut buf = vec![0u8; size];
+    let ok = unsafe { libc::sysctl(mib.as_mut_ptr(), 3, buf.as_mut_ptr().cast(), &mut size, std::ptr::null_mut(), 0) };
+    if ok != 0 {
+        return None;
+    }
+    buf.truncate(size);
+    let argc = i32::from_ne_bytes(buf[..4].try_into().ok()?) as usize;
+    let mut rest = &buf[4..];
+    // The executable path and its NUL padding
+    let path_end = rest.iter().position(|&b| b == 0)?;
+    rest = &rest[path_end..];
+    let start = rest.iter().position(|&b| b != 0)?;
+    rest = &rest[start..];
+    for _ in 0..argc {
+        let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        rest = rest.get(end + 1..).unwrap_or_default();
+    }
+    // The environment ends at the first empty string
+    let end = rest.windows(2).position(|w| w == [0, 0]).map(|i| i + 1).unwrap_or(rest.len());
+    Some(parse_environ(&rest[..end]))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn process_env(_pid: u32) -> Option<EnvMap> {
+    None
+}
+
+/// Current working directory of `pid`. Only works for processes of the same user.
+#[cfg(target_os = "linux")]
+pub fn process_cwd(pid: u32) -> Option<String> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid)).ok().map(|p| p.to_string_lossy().into_owned())
+}
+
+#[cfg(target_os = "macos")]
+pub fn process_cwd(pid: u32) -> Option<String> {
+    let mut info: libc::proc_vnodepathinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_vnodepathinfo>() as libc::c_int;
+    let read = unsafe {
+        libc::proc_pidinfo(pid as libc::c_int, libc::PROC_PIDVNODEPATHINFO, 0, (&mut info as *mut libc::proc_vnodepathinfo).cast(), size)
+    };
+    if read != size {
+        return None;
+    }
+    // A MAXPATHLEN buffer, split into rows by the libc bindings
+    let path = unsafe { std::ffi::CStr::from_ptr(info.pvi_cdir.vip_path.as_ptr().cast()) };
+    let path = path.to_string_lossy();
+    (!path.is_empty()).then(|| path.into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn process_cwd(_pid: u32) -> Option<String> {
+    None
+}
+
+fn all_pids() -> Vec<u32> {
+    Command::new("ps")
+        .args(["-A", "-o", "pid="])
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).split_whitespace().filter_map(|p| p.parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// The session `pid` was started from, per its environment.
+pub(crate) fn session_tag(pid: u32) -> Option<String> {
+    process_env(pid)?.remove(SESSION_ID_VAR)
+}
+
+// Every readable process carrying a session tag (other users' processes can't be read)
+fn tagged_processes() -> Vec<TaggedProcess> {
+    let own = std::process::id();
+    all_pids().into_iter()
+        .filter(|&pid| pid != own)
+        .filter_map(|pid| {
+            let session_id = session_tag(pid)?;
+            Some(TaggedProcess { pid, name: process_name(pid), session_id })
+        })
+        .collect()
+}
+
+impl SessionManager {
+    /// Processes started from `session_id`, including ones that have left its process tree.
+    pub fn session_processes(&self, session_id: &str) -> Result<Vec<TaggedProcess>, String> {
+        if !self.sessions.lock().map_err(|_| "Lock poisoned")?.contains_key(session_id) {
+            return Err("Session not found".into());
+        }
+        Ok(tagged_processes().into_iter().filter(|p| p.session_id == session_id).collect())
+    }
+
+    /// Processes started from sessions that no longer exist: left running when a tab was
+    /// closed, or by an earlier run of the app.
+    pub fn orphaned_processes(&self) -> Result<Vec<TaggedProcess>, String> {
+        let live: Vec<String> = self.sessions.lock().map_err(|_| "Lock poisoned")?.keys().cloned().collect();
+        Ok(tagged_processes().into_iter().filter(|p| !live.contains(&p.session_id)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inspects_own_process() {
+        let pid = std::process::id();
+        assert!(!process_args(pid).unwrap().is_empty());
+        assert!(process_name(pid).is_some());
+        assert!(process_args(u32::MAX / 2).is_none());
+    }
+
+    #[test]
+    fn walks_the_process_tree() {
+        let pid = std::process::id();
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        assert!(is_descendant(child.id(), pid));
+        assert!(!is_descendant(pid, child.id()));
+        child.kill().unwrap();
+        let _ = child.wait();
+    }
+
+    #[test]
+    fn finds_processes_by_session_tag() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use std::sync::Arc;
+
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.spawn_session(portable_pty::CommandBuilder::new("sh"), "sh").unwrap();
+        let shell = manager.sessions.lock().unwrap()[&id].pid.unwrap();
+        let mut stray = Command::new("sleep").arg("5").env(SESSION_ID_VAR, "closed-session").spawn().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        assert!(manager.session_processes(&id).unwrap().iter().any(|p| p.pid == shell));
+        let orphans = manager.orphaned_processes().unwrap();
+        assert!(orphans.iter().any(|p| p.pid == stray.id() && p.session_id == "closed-session"));
+        assert!(!orphans.iter().any(|p| p.pid == shell));
+        stray.kill().unwrap();
+        let _ = stray.wait();
+        manager.close(&id).unwrap();
+    }
+
+    #[test]
+    fn parses_environ_blocks() {
+        let env = parse_environ(b"PATH=/usr/bin\0EDITOR=vim\0EMPTY=\0junk\0");
+        assert_eq!(env.len(), 3);
+        assert_eq!(env["EDITOR"], "vim");
+    }
+}
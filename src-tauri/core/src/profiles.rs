@@ -0,0 +1,115 @@
+use crate::config::{load_json, save_json};
+use crate::lifecycle::KeepAlivePolicy;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub const DEFAULT_PROFILE: &str = "default";
+pub const DEFAULT_TERM: &str = "xterm-256color";
+
+/// Named session settings chosen at session creation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    // TERM exported to the shell: xterm-256color, tmux-256color, shelll, or anything custom
+    pub term: String,
+    pub keep_alive: KeepAlivePolicy,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            name: DEFAULT_PROFILE.to_string(),
+            term: DEFAULT_TERM.to_string(),
+            keep_alive: KeepAlivePolicy::default(),
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ProfileFile {
+    profiles: Vec<Profile>,
+}
+
+// Profiles persisted as JSON in the config dir. The default profile always exists.
+pub struct ProfileStore {
+    path: Option<PathBuf>,
+    profiles: Vec<Profile>,
+}
+
+impl ProfileStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file: ProfileFile = load_json(path.as_deref());
+        let mut store = ProfileStore { path, profiles: file.profiles };
+        if store.get(DEFAULT_PROFILE).is_none() {
+            store.profiles.insert(0, Profile::default());
+        }
+        store
+    }
+
+    pub fn list(&self) -> Vec<Profile> {
+        self.profiles.clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// The named profile, or the default profile when `name` is None.
+    pub fn resolve(&self, name: Option<&str>) -> Result<Profile, String> {
+        let name = name.unwrap_or(DEFAULT_PROFILE);
+        self.get(name).cloned().ok_or_else(|| format!("Unknown profile '{}'", name))
+    }
+
+    pub fn save_profile(&mut self, profile: Profile) -> Result<(), String> {
+        if profile.name.trim().is_empty() {
+            return Err("Profile name cannot be empty".into());
+        }
+        if profile.term.trim().is_empty() || profile.term.contains(char::is_whitespace) {
+            return Err(format!("Invalid TERM value '{}'", profile.term));
+        }
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+        self.save()
+    }
+
+    pub fn delete_profile(&mut self, name: &str) -> Result<(), String> {
+        if name == DEFAULT_PROFILE {
+            return Err("The default profile cannot be deleted".into());
+        }
+        self.profiles.retain(|p| p.name != name);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        match &self.path {
+            Some(path) => save_json(path, &ProfileFile { profiles: self.profiles.clone() }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_profile_always_exists() {
+        let mut store = ProfileStore::load(None);
+        assert_eq!(store.resolve(None).unwrap().term, DEFAULT_TERM);
+        assert!(store.delete_profile(DEFAULT_PROFILE).is_err());
+
+        store.save_profile(Profile { name: "tmux".into(), term: "tmux-256color".into(), ..Profile::default() }).unwrap();
+        assert_eq!(store.resolve(Some("tmux")).unwrap().term, "tmux-256color");
+        assert!(store.resolve(Some("missing")).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_term() {
+        let mut store = ProfileStore::load(None);
+        let result = store.save_profile(Profile { name: "bad".into(), term: "xterm 256".into(), ..Profile::default() });
+        assert!(result.is_err());
+    }
+}
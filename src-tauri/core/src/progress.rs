@@ -0,0 +1,204 @@
+//! Progress reported by programs through the ConEmu / Windows Terminal sequence
+//! `ESC ] 9 ; 4 ; <state> ; <percent>` (winget, some build tools and shell prompts send
+//! it). Each change becomes `session-progress`, and the sessions' combined progress is
+//! drawn as a bar on the Dock icon, so a long build shows how far along it is even while
+//! the window is hidden.
+
+use crate::events::EventSink;
+use crate::pty::{PtySession, SessionManager};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressState {
+    // No progress to show (state 0)
+    #[default]
+    Hidden,
+    Normal,
+    Error,
+    // Busy, with no idea how long it'll take
+    Indeterminate,
+    Paused,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SessionProgress {
+    pub state: ProgressState,
+    // 0-100; meaningless when hidden or indeterminate
+    pub percent: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionProgressPayload {
+    pub session_id: String,
+    pub state: ProgressState,
+    pub percent: u8,
+}
+
+/// The progress in an OSC body (without `ESC ]` and the terminator), if it's OSC 9;4.
+/// A missing or out-of-range percentage reads as 0 or is capped at 100.
+pub(crate) fn parse_osc9_4(osc: &[u8]) -> Option<SessionProgress> {
+    let rest = std::str::from_utf8(osc.strip_prefix(b"9;4;")?).ok()?;
+    let (state, percent) = rest.split_once(';').unwrap_or((rest, ""));
+    let state = match state {
+        "0" => ProgressState::Hidden,
+        "1" => ProgressState::Normal,
+        "2" => ProgressState::Error,
+        "3" => ProgressState::Indeterminate,
+        "4" => ProgressState::Paused,
+        _ => return None,
+    };
+    let percent = percent.parse::<u32>().map_or(0, |p| p.min(100) as u8);
+    Some(SessionProgress { state, percent })
+}
+
+/// What the Dock shows for several sessions' progress: an error wins, then running,
+/// paused and indeterminate ones; the percentage is that of the session furthest behind.
+pub fn combine(progress: impl IntoIterator<Item = SessionProgress>) -> SessionProgress {
+    let shown: Vec<SessionProgress> = progress.into_iter().filter(|p| p.state != ProgressState::Hidden).collect();
+    let state = [ProgressState::Error, ProgressState::Normal, ProgressState::Paused, ProgressState::Indeterminate]
+        .into_iter()
+        .find(|state| shown.iter().any(|p| p.state == *state))
+        .unwrap_or(ProgressState::Hidden);
+    let percent = shown.iter()
+        .filter(|p| p.state != ProgressState::Indeterminate)
+        .map(|p| p.percent)
+        .min()
+        .unwrap_or(0);
+    SessionProgress { state, percent }
+}
+
+// From the reader thread: records the session's progress and reports it if it changed
+pub(crate) fn apply(
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    session_id: &str,
+    progress: SessionProgress,
+    events: &Arc<dyn EventSink>,
+) {
+    let Ok(mut sessions) = sessions.lock() else { return };
+    let Some(session) = sessions.get_mut(session_id) else { return };
+    if session.progress == progress {
+        return;
+    }
+    session.progress = progress;
+    events.emit("session-progress", SessionProgressPayload {
+        session_id: session_id.to_string(),
+        state: progress.state,
+        percent: progress.percent,
+    });
+}
+
+/// Draws `progress` as a bar over the Dock icon, or puts the plain icon back when it's
+/// hidden. Must be called from the main thread; failures show in the badge (see
+/// `bell::dock_badge`).
+#[cfg(target_os = "macos")]
+pub fn show_dock_progress(progress: SessionProgress) -> Result<(), String> {
+    use objc::runtime::{Object, NO, YES};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::sync::OnceLock;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct NSSize {
+        width: f64,
+        height: f64,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct NSRect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    }
+
+    // (content view, progress bar), built on first use and kept for the life of the app
+    static VIEWS: OnceLock<(usize, usize)> = OnceLock::new();
+
+    unsafe {
+        let app: *mut Object = msg_send![class!(NSApplication), sharedApplication];
+        let tile: *mut Object = msg_send![app, dockTile];
+        if tile.is_null() {
+            return Err("The app has no Dock tile".into());
+        }
+        let &(view, bar) = VIEWS.get_or_init(|| {
+            let size: NSSize = msg_send![tile, size];
+            let view: *mut Object = msg_send![class!(NSImageView), alloc];
+            let view: *mut Object = msg_send![view,
+                initWithFrame: NSRect { x: 0.0, y: 0.0, width: size.width, height: size.height }];
+            let icon: *mut Object = msg_send![app, applicationIconImage];
+            let _: () = msg_send![view, setImage: icon];
+            let bar: *mut Object = msg_send![class!(NSProgressIndicator), alloc];
+            let bar: *mut Object = msg_send![bar, initWithFrame: NSRect {
+                x: size.width * 0.1,
+                y: size.height * 0.06,
+                width: size.width * 0.8,
+                height: size.height * 0.12,
+            }];
+            // NSProgressIndicatorStyleBar
+            let _: () = msg_send![bar, setStyle: 0isize];
+            let _: () = msg_send![bar, setMinValue: 0.0f64];
+            let _: () = msg_send![bar, setMaxValue: 100.0f64];
+            let _: () = msg_send![view, addSubview: bar];
+            (view as usize, bar as usize)
+        });
+        let (view, bar) = (view as *mut Object, bar as *mut Object);
+
+        if progress.state == ProgressState::Hidden {
+            let _: () = msg_send![tile, setContentView: std::ptr::null_mut::<Object>()];
+        } else {
+            let indeterminate = progress.state == ProgressState::Indeterminate;
+            let _: () = msg_send![bar, setIndeterminate: if indeterminate { YES } else { NO }];
+            let _: () = msg_send![bar, setDoubleValue: f64::from(progress.percent)];
+            let _: () = msg_send![tile, setContentView: view];
+        }
+        let _: () = msg_send![tile, display];
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn show_dock_progress(_progress: SessionProgress) -> Result<(), String> {
+    Err("Dock progress is only available on macOS".into())
+}
+
+impl SessionManager {
+    /// The open sessions' progress combined (see `combine`), for the Dock icon.
+    pub fn dock_progress(&self) -> Result<SessionProgress, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        Ok(combine(sessions.values().map(|s| s.progress)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(state: ProgressState, percent: u8) -> SessionProgress {
+        SessionProgress { state, percent }
+    }
+
+    #[test]
+    fn parses_osc9_4() {
+        assert_eq!(parse_osc9_4(b"9;4;1;42"), Some(at(ProgressState::Normal, 42)));
+        assert_eq!(parse_osc9_4(b"9;4;2;250"), Some(at(ProgressState::Error, 100)));
+        assert_eq!(parse_osc9_4(b"9;4;3"), Some(at(ProgressState::Indeterminate, 0)));
+        assert_eq!(parse_osc9_4(b"9;4;0;0"), Some(at(ProgressState::Hidden, 0)));
+        assert_eq!(parse_osc9_4(b"9;4;7;10"), None);
+        assert_eq!(parse_osc9_4(b"9;Build finished"), None);
+    }
+
+    #[test]
+    fn combines_sessions() {
+        assert_eq!(combine([]), at(ProgressState::Hidden, 0));
+        assert_eq!(
+            combine([at(ProgressState::Normal, 80), at(ProgressState::Indeterminate, 0), at(ProgressState::Paused, 30)]),
+            at(ProgressState::Normal, 30),
+        );
+        assert_eq!(combine([at(ProgressState::Normal, 80), at(ProgressState::Error, 90)]), at(ProgressState::Error, 80));
+        assert_eq!(combine([at(ProgressState::Hidden, 0), at(ProgressState::Indeterminate, 0)]), at(ProgressState::Indeterminate, 0));
+    }
+}
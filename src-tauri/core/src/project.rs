@@ -0,0 +1,233 @@
+//! The project open in a target editor or IDE, worked out from its focused window title
+//! (see `target_title`): VS Code and its forks show the folder name, JetBrains IDEs the
+//! project name and sometimes its path, Xcode the project name. Names are matched against
+//! recent directories and the usual project folders. A session can follow the target's
+//! project, cd'ing whenever it changes while the shell is at its prompt.
+
+use crate::pty::SessionManager;
+use crate::process;
+use crate::recent_dirs::RecentDir;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Folders in the home directory projects usually live in, searched for a project by name
+const PROJECT_ROOTS: &[&str] = &["src", "code", "Code", "Projects", "projects", "Developer", "dev", "repos", "work", "git"];
+// Files marking the top of a project, for paths that point into one
+const PROJECT_MARKERS: &[&str] = &[".git", ".hg", ".idea", ".vscode", "Cargo.toml", "package.json", "go.mod", "pyproject.toml"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TitleFormat {
+    // "● main.rs — shelll — Visual Studio Code"
+    VsCode,
+    // "shelll [~/src/shelll] – main.rs", or without the path
+    JetBrains,
+    // "shelll — main.rs"
+    Xcode,
+    Other,
+}
+
+fn title_format(bundle_id: &str) -> TitleFormat {
+    let bundle_id = bundle_id.to_ascii_lowercase();
+    if bundle_id.starts_with("com.microsoft.vscode") || bundle_id == "com.vscodium" || bundle_id == "com.todesktop.230313mzl4w4u92" {
+        TitleFormat::VsCode
+    } else if bundle_id.starts_with("com.jetbrains.") || bundle_id == "com.google.android.studio" {
+        TitleFormat::JetBrains
+    } else if bundle_id == "com.apple.dt.xcode" {
+        TitleFormat::Xcode
+    } else {
+        TitleFormat::Other
+    }
+}
+
+/// What a window title says about the open project.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProjectHint {
+    pub path: Option<String>,
+    pub name: Option<String>,
+}
+
+fn title_parts(title: &str) -> Vec<&str> {
+    title.split(" — ").flat_map(|part| part.split(" – ")).flat_map(|part| part.split(" - "))
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+fn looks_like_path(s: &str) -> bool {
+    s.starts_with('/') || s.starts_with("~/")
+}
+
+/// Reads the project out of a window title in the format `bundle_id`'s app uses.
+pub fn parse_project_title(bundle_id: &str, title: &str) -> ProjectHint {
+    let parts = title_parts(title.trim_start_matches(['●', '•', '*', ' ']));
+    let path = parts.iter().find(|part| looks_like_path(part)).map(|part| part.to_string());
+    let name = match title_format(bundle_id) {
+        TitleFormat::VsCode => {
+            // The last part is the app; the one before it the folder (or workspace)
+            let root = match parts.len() {
+                0 | 1 => None,
+                n => Some(parts[n - 2]),
+            };
+            // Remote folders ("shelll [SSH: devbox]") have no local directory
+            root.filter(|root| !root.ends_with(']'))
+                .map(|root| root.trim_end_matches(" (Workspace)").to_string())
+        }
+        TitleFormat::JetBrains => parts.first().map(|first| match first.split_once(" [") {
+            Some((name, _)) => name.to_string(),
+            None => first.to_string(),
+        }),
+        TitleFormat::Xcode => parts.first().filter(|_| parts.len() > 1).map(|first| first.to_string()),
+        TitleFormat::Other => None,
+    };
+    let bracketed = title.split_once(" [")
+        .and_then(|(_, rest)| rest.split_once(']'))
+        .map(|(inside, _)| inside)
+        .filter(|inside| looks_like_path(inside))
+        .map(str::to_string);
+    ProjectHint { path: bracketed.or(path), name: name.filter(|n| !n.is_empty() && !looks_like_path(n)) }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+// The nearest directory at or above `path` with a project marker, else `path`'s directory
+fn project_root(path: &Path) -> Option<PathBuf> {
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    if !dir.is_dir() {
+        return None;
+    }
+    let home = std::env::var("HOME").map(PathBuf::from).ok();
+    let marked = dir.ancestors()
+        .take_while(|ancestor| home.as_deref() != Some(*ancestor) && ancestor.parent().is_some())
+        .find(|ancestor| PROJECT_MARKERS.iter().any(|marker| ancestor.join(marker).exists()));
+    Some(marked.unwrap_or(dir).to_path_buf())
+}
+
+/// The directory `hint` points to: its path if it has one, else a recent directory or one
+/// in the usual project folders under `home` with the project's name.
+pub fn resolve_project_dir(hint: &ProjectHint, recent: &[RecentDir], home: Option<&Path>) -> Option<PathBuf> {
+    if let Some(dir) = hint.path.as_deref().map(expand_home).and_then(|path| project_root(&path)) {
+        return Some(dir);
+    }
+    let name = hint.name.as_deref()?;
+    // A recent directory in the project counts too: /src/shelll/src-tauri for "shelll"
+    let from_recent = recent.iter().find_map(|dir| {
+        Path::new(&dir.path).ancestors().find(|a| a.file_name().is_some_and(|f| f == name)).map(Path::to_path_buf)
+    });
+    from_recent.filter(|dir| dir.is_dir()).or_else(|| {
+        let home = home?;
+        PROJECT_ROOTS.iter().map(|root| home.join(root).join(name)).find(|dir| dir.is_dir())
+    })
+}
+
+/// The `project-auto-cd` payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProjectAutoCdPayload {
+    pub session_id: String,
+    pub dir: String,
+}
+
+fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+impl SessionManager {
+    /// The project directory open in `bundle_id`'s focused window, if it can be told.
+    pub fn target_project_dir(&self, bundle_id: &str, title: &str) -> Result<Option<String>, String> {
+        let recent = self.recent_dirs()?;
+        let home = std::env::var("HOME").ok().filter(|h| !h.is_empty()).map(PathBuf::from);
+        let dir = resolve_project_dir(&parse_project_title(bundle_id, title), &recent, home.as_deref());
+        Ok(dir.map(|dir| dir.to_string_lossy().into_owned()))
+    }
+
+    /// Makes `session_id` follow the target app's project (None stops following).
+    pub fn set_project_auto_cd(&self, session_id: Option<&str>) -> Result<(), String> {
+        if let Some(session_id) = session_id {
+            if !self.sessions.lock().map_err(|_| "Lock poisoned")?.contains_key(session_id) {
+                return Err("Session not found".into());
+            }
+        }
+        *self.project_auto_cd.lock().map_err(|_| "Lock poisoned")? = session_id.map(str::to_string);
+        Ok(())
+    }
+
+    /// For a target's new window title: cds the following session to the project, if it
+    /// changed and the shell is at its prompt with nothing typed. Returns the directory.
+    pub fn follow_target_project(&self, bundle_id: &str, title: Option<&str>) -> Result<Option<String>, String> {
+        let Some(session_id) = self.project_auto_cd.lock().map_err(|_| "Lock poisoned")?.clone() else { return Ok(None) };
+        let Some(dir) = title.map(|title| self.target_project_dir(bundle_id, title)).transpose()?.flatten() else {
+            return Ok(None);
+        };
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let Some(session) = sessions.get(&session_id) else { return Ok(None) };
+        let at_prompt = session.pid.is_some() && process::foreground_pid(session) == session.pid;
+        if !at_prompt || session.input_locked || session.title.remote.is_some() || session.input_line.pending().is_some() {
+            return Ok(None);
+        }
+        let cwd = session.pid.and_then(process::process_cwd).or_else(|| session.title.cwd.clone());
+        if cwd.as_deref() == Some(dir.as_str()) {
+            return Ok(None);
+        }
+        // Straight to the shell: not a command the user typed, so not history
+        if let Ok(mut writer) = session.writer.lock() {
+            let _ = write!(writer, " cd -- {}\r", quote(&dir));
+        }
+        drop(sessions);
+        self.events.emit("project-auto-cd", ProjectAutoCdPayload { session_id, dir: dir.clone() });
+        Ok(Some(dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_editor_titles() {
+        let hint = |name: &str| ProjectHint { path: None, name: Some(name.into()) };
+        assert_eq!(parse_project_title("com.microsoft.VSCode", "● main.rs — shelll — Visual Studio Code"), hint("shelll"));
+        assert_eq!(parse_project_title("com.microsoft.VSCode", "shelll (Workspace) — Visual Studio Code"), hint("shelll"));
+        assert_eq!(parse_project_title("com.microsoft.VSCode", "main.rs — shelll [SSH: devbox] — Visual Studio Code"), ProjectHint::default());
+        assert_eq!(parse_project_title("com.microsoft.VSCode", "Visual Studio Code"), ProjectHint::default());
+        assert_eq!(parse_project_title("com.jetbrains.rustrover", "shelll – pty.rs"), hint("shelll"));
+        assert_eq!(
+            parse_project_title("com.jetbrains.intellij", "shelll [~/src/shelll] – .../core/src/pty.rs"),
+            ProjectHint { path: Some("~/src/shelll".into()), name: Some("shelll".into()) },
+        );
+        assert_eq!(parse_project_title("com.apple.dt.Xcode", "shelll — ContentView.swift"), hint("shelll"));
+        assert_eq!(parse_project_title("com.apple.dt.Xcode", "Welcome to Xcode"), ProjectHint::default());
+        assert_eq!(
+            parse_project_title("org.vim.MacVim", "main.rs (/srv/app/src) - VIM"),
+            ProjectHint::default(),
+        );
+        assert_eq!(
+            parse_project_title("com.sublimetext.4", "/srv/app/src/main.rs - Sublime Text"),
+            ProjectHint { path: Some("/srv/app/src/main.rs".into()), name: None },
+        );
+    }
+
+    #[test]
+    fn resolves_names_and_paths_to_directories() {
+        let root = std::env::temp_dir().join(format!("shelll-project-{}", uuid::Uuid::new_v4()));
+        let project = root.join("src").join("shelll");
+        std::fs::create_dir_all(project.join("core/src")).unwrap();
+        std::fs::write(project.join("Cargo.toml"), "").unwrap();
+        std::fs::write(project.join("core/src/pty.rs"), "").unwrap();
+
+        let by_path = ProjectHint { path: Some(project.join("core/src/pty.rs").to_string_lossy().into_owned()), name: None };
+        assert_eq!(resolve_project_dir(&by_path, &[], None), Some(project.clone()));
+
+        let by_name = ProjectHint { path: None, name: Some("shelll".into()) };
+        assert_eq!(resolve_project_dir(&by_name, &[], Some(&root)), Some(project.clone()));
+        let recent = [RecentDir { path: project.join("core").to_string_lossy().into_owned(), last_used: 1 }];
+        assert_eq!(resolve_project_dir(&by_name, &recent, None), Some(project.clone()));
+        let missing = ProjectHint { path: None, name: Some("elsewhere".into()) };
+        assert_eq!(resolve_project_dir(&missing, &recent, Some(&root)), None);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
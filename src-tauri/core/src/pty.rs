@@ -0,0 +1,1172 @@
+use crate::activity::ActivityTracker;
+use crate::automation::{self, AutomationTriggeredPayload, TriggerSet};
+use crate::batch::{self, OutputBatcher};
+use crate::bell::{self, Bells};
+use crate::buffer::OutputLog;
+use crate::charset::CharsetTranslator;
+use crate::clipboard::{self, ClipboardSettings};
+use crate::colors::ColorTheme;
+use crate::cwd;
+use crate::display::CellMetrics;
+use crate::eol::InputTranslation;
+use crate::env::EnvMap;
+use crate::events::EventSink;
+use crate::hibernate::{HibernationPolicy, HibernationSnapshot, ReaderGate};
+use crate::feedback::{self, FeedbackEvent, FeedbackTriggers};
+use crate::flow::FlowControl;
+use crate::find::{FindUpdatePayload, SessionFind};
+use crate::forward::ForwardRegistry;
+use crate::heads_up::{self, HeadsUp, HeadsUpReason};
+use crate::history::{HistoryEntry, HistoryMatch, HistoryStore, InputLineTracker, HISTORY_SEARCH_LIMIT};
+use crate::inputs::InputRing;
+use crate::ipc::{OutputData, OutputProtocol};
+use crate::latency::LatencyProbe;
+use crate::lifecycle::KeepAlivePolicy;
+use crate::long_command::{self, LongCommands};
+use crate::memory::LowMemoryReason;
+use crate::notification;
+use crate::pipe::{self, Pipes};
+use crate::pool::{PoolState, WarmPool};
+use crate::predict::{self, EchoPredictor};
+use crate::preexec::PreExecHooks;
+use crate::profiles::Profile;
+use crate::progress::{self, SessionProgress};
+use crate::recent_dirs::RecentDirsStore;
+use crate::recording::Recorder;
+use crate::redact::{RedactionSettings, Redactor};
+use crate::remote::{HostRule, RemoteContext};
+use crate::remote_edit::{self, RemoteEdits};
+use crate::rules::{self, RuleEvent, RuleSet, RuleTrigger};
+use crate::scratchpad;
+use crate::semantic::{self, CommandMarks};
+use crate::shell;
+use crate::snapshot::Snapshots;
+use crate::responder::TerminalIdentity;
+#[cfg(unix)]
+use crate::stream::StderrPipe;
+use crate::stream::{OutputStream, Runs};
+use crate::terminal::{MouseMode, OutputProcessor, SharedWriter, TerminalState};
+use crate::template::TemplateSettings;
+use crate::tmpdir;
+use crate::title::{self, render_tab_title, TabTitlePayload, TitleInputs, DEFAULT_TITLE_TEMPLATE};
+use crate::unix_now;
+use crate::usage::RunningCommand;
+use portable_pty::{Child, CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem, MasterPty};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// Rows and columns of a new session until the frontend resizes it
+pub(crate) const DEFAULT_SIZE: (u16, u16) = (30, 100);
+
+// How long a hung-up shell gets to exit before it is killed
+const EXIT_GRACE: Duration = Duration::from_secs(2);
+
+/// Set in every shell's environment to its session id.
+pub const SESSION_ID_VAR: &str = "SHELLL_SESSION_ID";
+
+pub struct PtySession {
+    pub(crate) writer: SharedWriter,
+    pub(crate) master: Arc<Mutex<Box<dyn MasterPty + Send>>>,
+    pub(crate) pid: Option<u32>,
+    // Terminated and reaped when the session closes
+    pub(crate) child: Option<Box<dyn Child + Send + Sync>>,
+    pub(crate) group_id: Option<String>,
+    pub(crate) title: TitleInputs,
+    // Host of the last OSC 7 report when it wasn't this machine, else empty
+    pub(crate) cwd_host: String,
+    // Set by programs through OSC 0/1; the title itself is in `title`
+    pub(crate) icon_name: Option<String>,
+    // Last reported through OSC 9;4
+    pub(crate) progress: SessionProgress,
+    // OSC 133 marks and the commands they delimited
+    pub(crate) prompt_marks: CommandMarks,
+    pub(crate) last_title: Option<String>,
+    pub(crate) input_line: InputLineTracker,
+    // Total bytes of output read so far; marks refer to positions in this stream
+    pub(crate) output_offset: Arc<AtomicU64>,
+    pub(crate) marks: Vec<OutputMark>,
+    // Unix time of the last input or output
+    pub(crate) last_activity: Arc<AtomicU64>,
+    pub(crate) gate: Arc<ReaderGate>,
+    pub(crate) hibernation: Option<HibernationSnapshot>,
+    // Modes and metadata parsed from the output stream by the reader thread
+    pub(crate) terminal: Arc<Mutex<TerminalState>>,
+    // Read-only: user input is rejected (query replies from the backend still go through)
+    pub(crate) input_locked: bool,
+    pub(crate) latency: Arc<Mutex<LatencyProbe>>,
+    pub(crate) keep_alive: KeepAlivePolicy,
+    // Suspended by the sleep policy; revived on wake
+    pub(crate) suspended_for_sleep: bool,
+    pub(crate) find: Arc<Mutex<SessionFind>>,
+    pub(crate) initial_env: EnvMap,
+    pub(crate) output_log: Arc<Mutex<OutputLog>>,
+    pub(crate) activity: Arc<ActivityTracker>,
+    // Lets injection commands address the session by name (the scratchpad)
+    pub(crate) name: Option<String>,
+    pub(crate) predictor: Arc<Mutex<EchoPredictor>>,
+    // Guest sessions: their temporary HOME, deleted on close
+    pub(crate) guest_home: Option<PathBuf>,
+    // The session's own TMPDIR, deleted on close
+    pub(crate) tmp_dir: Option<PathBuf>,
+    // Program the session was started with
+    pub(crate) shell: String,
+    // Name of the profile it was created with
+    pub(crate) profile: Option<String>,
+    pub(crate) created_at: u64,
+    // Set once the reader hits EOF: nothing is attached to the terminal any more
+    pub(crate) exited: Arc<AtomicBool>,
+    // Foreground job the remote context (in `title`) was last read from
+    pub(crate) remote_pid: Option<u32>,
+    // Set while the session is a warm shell waiting in (or just taken from) the pool
+    pub(crate) pool_state: Option<PoolState>,
+    // Coalesces output into `pty-output` events
+    pub(crate) batcher: Arc<OutputBatcher>,
+    pub(crate) recorder: Arc<Mutex<Option<Recorder>>>,
+    pub(crate) input_translation: InputTranslation,
+    // Lines submitted through `write`, for recall and resending
+    pub(crate) recent_inputs: InputRing,
+}
+
+struct TerminatingChild {
+    child: Box<dyn Child + Send + Sync>,
+    #[cfg(unix)]
+    groups: Vec<libc::pid_t>,
+}
+
+// Gives the hung-up child time to exit, kills it (and its jobs) if it doesn't, and reaps it
+fn terminate(mut t: TerminatingChild) {
+    #[cfg(not(unix))]
+    let _ = t.child.kill();
+    let deadline = Instant::now() + EXIT_GRACE;
+    while Instant::now() < deadline {
+        match t.child.try_wait() {
+            Ok(Some(_)) => return,
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(_) => break,
+        }
+    }
+    #[cfg(unix)]
+    for pgid in &t.groups {
+        unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        }
+    }
+    let _ = t.child.kill();
+    let _ = t.child.wait();
+}
+
+// Canonical input with echo off: a program is reading a password. Line editors turn echo
+// off too, but read raw keys.
+#[cfg(unix)]
+fn reading_password(master: &Mutex<Box<dyn MasterPty + Send>>) -> bool {
+    let Some(fd) = master.lock().ok().and_then(|m| m.as_raw_fd()) else { return false };
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return false;
+    }
+    termios.c_lflag & libc::ECHO == 0 && termios.c_lflag & libc::ICANON != 0
+}
+
+#[cfg(not(unix))]
+fn reading_password(_master: &Mutex<Box<dyn MasterPty + Send>>) -> bool {
+    false
+}
+
+/// How to start a session. Anything left unset comes from the defaults: the user's login
+/// shell, the app's working directory and a 30x100 terminal.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SessionOptions {
+    // Path or name of the program to run
+    pub shell: Option<String>,
+    // Arguments for `shell`; if unset, the shell's own login-shell flags
+    pub args: Option<Vec<String>>,
+    pub cwd: Option<PathBuf>,
+    // Added to (or overriding) the inherited environment
+    #[serde(default)]
+    pub env: EnvMap,
+    pub rows: Option<u16>,
+    pub cols: Option<u16>,
+    // Give the session its own TMPDIR, deleted when it closes (on unless false)
+    pub private_tmp: Option<bool>,
+    // Tag stderr separately in `pty-output` (Unix; see `stream` for the caveats)
+    pub split_stderr: Option<bool>,
+}
+
+impl SessionOptions {
+    pub(crate) fn size(&self) -> (u16, u16) {
+        (
+            self.rows.filter(|&r| r > 0).unwrap_or(DEFAULT_SIZE.0),
+            self.cols.filter(|&c| c > 0).unwrap_or(DEFAULT_SIZE.1),
+        )
+    }
+}
+
+// The command for a new session, and the process name to show until the title updates
+pub(crate) fn session_command(profile: &Profile, options: &SessionOptions) -> Result<(CommandBuilder, String), String> {
+    let shell = options.shell.as_ref().map(PathBuf::from).unwrap_or_else(shell::login_shell);
+    let mut cmd = CommandBuilder::new(&shell);
+    match &options.args {
+        Some(args) => cmd.args(args),
+        None => cmd.args(shell::startup_args(&shell)),
+    }
+    cmd.env("TERM", &profile.term);
+    for (name, value) in &options.env {
+        cmd.env(name, value);
+    }
+    if let Some(cwd) = &options.cwd {
+        if !cwd.is_dir() {
+            return Err(format!("Not a directory: {}", cwd.display()));
+        }
+        cmd.cwd(cwd);
+    }
+    let process_name = shell.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| shell.display().to_string());
+    Ok((cmd, process_name))
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PtyExitedPayload {
+    pub session_id: String,
+    // Both None when the process outlived the terminal
+    pub exit_code: Option<u32>,
+    // Signal description ("Killed", "Hangup", ...) if it was terminated by one
+    pub signal: Option<String>,
+}
+
+// `refresh_tab_title` for the reader thread
+pub(crate) fn emit_tab_title(events: &Arc<dyn EventSink>, session_id: &str, session: &mut PtySession, template: &str) {
+    let title = render_tab_title(template, &session.title);
+    if session.last_title.as_deref() == Some(title.as_str()) {
+        return;
+    }
+    session.last_title = Some(title.clone());
+    events.emit("tab-title", TabTitlePayload {
+        session_id: session_id.to_string(),
+        title,
+    });
+}
+
+// After the reader hits EOF: waits for the shell to exit and reports how. Sessions closed
+// by the user are gone from the map by now and report nothing.
+fn report_exit(sessions: &Mutex<HashMap<String, PtySession>>, session_id: &str, events: &Arc<dyn EventSink>) {
+    let deadline = Instant::now() + EXIT_GRACE;
+    let status = loop {
+        {
+            let Ok(mut sessions) = sessions.lock() else { return };
+            let Some(session) = sessions.get_mut(session_id) else { return };
+            let Some(child) = session.child.as_mut() else { return };
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    session.child = None;
+                    break Some(status);
+                }
+                Ok(None) if Instant::now() < deadline => {}
+                _ => break None,
+            }
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    // portable-pty only exposes the signal through Display
+    let signal = status.as_ref()
+        .and_then(|s| s.to_string().strip_prefix("Terminated by ").map(str::to_string));
+    events.emit("pty-exited", PtyExitedPayload {
+        session_id: session_id.to_string(),
+        exit_code: status.filter(|_| signal.is_none()).map(|s| s.exit_code()),
+        signal,
+    });
+}
+
+#[derive(Clone, Serialize)]
+pub struct PtyOutputPayload {
+    pub session_id: String,
+    // Encoded for the negotiated protocol (see `ipc`)
+    pub data: OutputData,
+    // Stderr only for sessions started with `split_stderr`
+    pub stream: OutputStream,
+}
+
+#[derive(Clone, Serialize)]
+pub struct InputLockPayload {
+    pub session_id: String,
+    pub locked: bool,
+}
+
+pub(crate) fn set_input_lock(events: &Arc<dyn EventSink>, session_id: &str, session: &mut PtySession, locked: bool) {
+    if session.input_locked != locked {
+        session.input_locked = locked;
+        events.emit("session-input-lock-changed", InputLockPayload {
+            session_id: session_id.to_string(),
+            locked,
+        });
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OutputMark {
+    pub id: String,
+    pub label: String,
+    pub offset: u64,
+    pub created_at: u64,
+}
+
+/// Owns every PTY session and the state derived from them (titles, history).
+pub struct SessionManager {
+    pub(crate) sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    pub(crate) templates: Arc<Mutex<TemplateSettings>>,
+    pub(crate) history: Arc<Mutex<HistoryStore>>,
+    // Applied to everything written to disk or exported
+    pub(crate) redactor: Arc<Mutex<Redactor>>,
+    pub(crate) host_rules: Mutex<Vec<HostRule>>,
+    pub(crate) runs: Runs,
+    pub(crate) output_protocol: OutputProtocol,
+    pub(crate) flow_control: FlowControl,
+    pub(crate) pool: Mutex<WarmPool>,
+    pub(crate) snapshots: Mutex<Snapshots>,
+    pub(crate) hibernation_policy: Arc<Mutex<HibernationPolicy>>,
+    pub(crate) identity: Arc<Mutex<TerminalIdentity>>,
+    pub(crate) color_theme: Arc<Mutex<ColorTheme>>,
+    pub(crate) triggers: Arc<Mutex<TriggerSet>>,
+    pub(crate) rules: Arc<Mutex<RuleSet>>,
+    pub(crate) metrics: Mutex<CellMetrics>,
+    pub(crate) forwards: Mutex<ForwardRegistry>,
+    pub(crate) feedback: Arc<Mutex<FeedbackTriggers>>,
+    pub(crate) heads_up: Arc<Mutex<HeadsUp>>,
+    pub(crate) bells: Arc<Mutex<Bells>>,
+    pub(crate) long_commands: Arc<Mutex<LongCommands>>,
+    // Why low-memory mode is on; None while it's off
+    pub(crate) low_memory: Mutex<Option<LowMemoryReason>>,
+    pub(crate) pipes: Arc<Mutex<Pipes>>,
+    // Commands being measured, by session (see `usage`)
+    pub(crate) running_commands: Mutex<HashMap<String, RunningCommand>>,
+    pub(crate) pre_exec: Mutex<PreExecHooks>,
+    // Serializes starting the scratchpad so only one is ever created
+    pub(crate) scratchpad_spawn: Mutex<()>,
+    pub(crate) remote_edits: RemoteEdits,
+    pub(crate) recent_dirs: Mutex<RecentDirsStore>,
+    // The session following the target app's project (see `project`)
+    pub(crate) project_auto_cd: Mutex<Option<String>>,
+    // What OSC 52 may do with the system clipboard
+    pub(crate) clipboard: Arc<Mutex<ClipboardSettings>>,
+    pub(crate) events: Arc<dyn EventSink>,
+}
+
+impl SessionManager {
+    pub fn new(events: Arc<dyn EventSink>, history: HistoryStore) -> Self {
+        SessionManager {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            templates: Arc::new(Mutex::new(TemplateSettings::default())),
+            history: Arc::new(Mutex::new(history)),
+            redactor: Arc::new(Mutex::new(Redactor::new(&RedactionSettings::default()).unwrap_or_default())),
+            host_rules: Mutex::new(Vec::new()),
+            runs: Runs::default(),
+            output_protocol: OutputProtocol::default(),
+            flow_control: FlowControl::default(),
+            pool: Mutex::new(WarmPool::default()),
+            snapshots: Mutex::new(Snapshots::new()),
+            hibernation_policy: Arc::new(Mutex::new(HibernationPolicy::default())),
+            identity: Arc::new(Mutex::new(TerminalIdentity::default())),
+            color_theme: Arc::new(Mutex::new(ColorTheme::default())),
+            triggers: Arc::new(Mutex::new(TriggerSet::default())),
+            rules: Arc::new(Mutex::new(RuleSet::default())),
+            metrics: Mutex::new(CellMetrics::default()),
+            forwards: Mutex::new(ForwardRegistry::default()),
+            feedback: Arc::new(Mutex::new(FeedbackTriggers::default())),
+            heads_up: Arc::new(Mutex::new(HeadsUp::default())),
+            bells: Arc::new(Mutex::new(Bells::default())),
+            long_commands: Arc::new(Mutex::new(LongCommands::default())),
+            low_memory: Mutex::new(None),
+            pipes: Arc::new(Mutex::new(Pipes::default())),
+            running_commands: Mutex::new(HashMap::new()),
+            pre_exec: Mutex::new(PreExecHooks::default()),
+            scratchpad_spawn: Mutex::new(()),
+            remote_edits: RemoteEdits::default(),
+            recent_dirs: Mutex::new(RecentDirsStore::default()),
+            project_auto_cd: Mutex::new(None),
+            clipboard: Arc::new(Mutex::new(ClipboardSettings::default())),
+            events,
+        }
+    }
+
+    pub fn create_session(&self, profile: &Profile, options: &SessionOptions) -> Result<String, String> {
+        if let Some(session_id) = self.take_pooled(profile, options)? {
+            return Ok(session_id);
+        }
+        self.start_session(profile, options, false)
+    }
+
+    // A pooled session holds its output until it is handed out
+    pub(crate) fn start_session(&self, profile: &Profile, options: &SessionOptions, pooled: bool) -> Result<String, String> {
+        let (mut cmd, process_name) = session_command(profile, options)?;
+        let tmp_dir = tmpdir::prepare(&mut cmd, options)?;
+        #[cfg(unix)]
+        let stderr_pipe = match options.split_stderr {
+            Some(true) => Some(StderrPipe::create(&mut cmd)?),
+            _ => None,
+        };
+        let session_id = match self.spawn_sized(cmd, &process_name, options.size()) {
+            Ok(id) => id,
+            Err(e) => {
+                if let Some(dir) = &tmp_dir {
+                    let _ = std::fs::remove_dir_all(dir);
+                }
+                #[cfg(unix)]
+                if let Some(pipe) = stderr_pipe {
+                    pipe.discard();
+                }
+                return Err(e);
+            }
+        };
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        if let Some(session) = sessions.get_mut(&session_id) {
+            if pooled {
+                session.gate.pause();
+                session.pool_state = Some(PoolState::Idle);
+            }
+            session.keep_alive = profile.keep_alive;
+            session.profile = Some(profile.name.clone());
+            session.tmp_dir = tmp_dir;
+            #[cfg(unix)]
+            if let Some(pipe) = stderr_pipe {
+                pipe.attach(session.batcher.clone());
+            }
+        }
+        Ok(session_id)
+    }
+
+    // At the default size; tests spawn `sh` this way
+    #[cfg(test)]
+    pub(crate) fn spawn_session(&self, cmd: CommandBuilder, process_name: &str) -> Result<String, String> {
+        self.spawn_sized(cmd, process_name, DEFAULT_SIZE)
+    }
+
+    pub(crate) fn spawn_sized(&self, mut cmd: CommandBuilder, process_name: &str, size: (u16, u16)) -> Result<String, String> {
+        let cwd = match cmd.get_cwd() {
+            Some(cwd) => Some(PathBuf::from(cwd)),
+            None => env::current_dir().ok(),
+        };
+        if let Some(cwd) = &cwd {
+            cmd.cwd(cwd);
+        }
+        // Lets processes be attributed to the session even after they leave its process
+        // tree, or after the app restarts
+        let session_id = Uuid::new_v4().to_string();
+        cmd.env(SESSION_ID_VAR, &session_id);
+        let initial_env: EnvMap = cmd.iter_full_env_as_str()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        self.open_session(session_id, process_name, cwd, initial_env, size, |pair| {
+            let child = pair.slave.spawn_command(cmd)
+                .map_err(|e| format!("Failed to spawn shell: {}", e))?;
+            Ok(Some(child))
+        })
+    }
+
+    /// Opens a PTY and starts reading it like any session. `attach` connects the slave
+    /// end (normally by spawning a process) and returns the child, if there is one.
+    pub(crate) fn open_session(
+        &self,
+        session_id: String,
+        process_name: &str,
+        cwd: Option<PathBuf>,
+        initial_env: EnvMap,
+        (rows, cols): (u16, u16),
+        attach: impl FnOnce(&PtyPair) -> Result<Option<Box<dyn Child + Send + Sync>>, String>,
+    ) -> Result<String, String> {
+        let pty_system = NativePtySystem::default();
+
+        let size = self.cell_metrics()?.pty_size(rows, cols);
+        let pair = pty_system.openpty(size).map_err(|e| format!("Failed to create PTY: {}", e))?;
+
+        let mut reader = pair.master.try_clone_reader()
+            .map_err(|e| format!("Failed to clone reader: {}", e))?;
+        let writer = pair.master.take_writer()
+            .map_err(|e| format!("Failed to take writer: {}", e))?;
+
+        let child = attach(&pair)?;
+        let pid = child.as_ref().and_then(|c| c.process_id());
+        let (batcher, flusher) = batch::spawn_flusher(session_id.clone(), self.events.clone(), self.output_protocol.clone(), self.flow_control.clone());
+
+        let session = PtySession {
+            writer: Arc::new(Mutex::new(writer)),
+            master: Arc::new(Mutex::new(pair.master)),
+            pid,
+            child,
+            group_id: None,
+            title: TitleInputs {
+                process: Some(process_name.to_string()),
+                cwd: cwd.map(|c| c.to_string_lossy().into_owned()),
+                osc_title: None,
+                remote: None,
+            },
+            cwd_host: String::new(),
+            icon_name: None,
+            progress: SessionProgress::default(),
+            prompt_marks: CommandMarks::default(),
+            last_title: None,
+            input_line: InputLineTracker::default(),
+            output_offset: Arc::new(AtomicU64::new(0)),
+            marks: Vec::new(),
+            last_activity: Arc::new(AtomicU64::new(unix_now())),
+            gate: Arc::new(ReaderGate::default()),
+            hibernation: None,
+            terminal: Arc::new(Mutex::new(TerminalState::new(size.rows, size.cols))),
+            input_locked: false,
+            latency: Arc::new(Mutex::new(LatencyProbe::default())),
+            keep_alive: KeepAlivePolicy::default(),
+            suspended_for_sleep: false,
+            find: Arc::new(Mutex::new(SessionFind::default())),
+            initial_env,
+            output_log: Arc::new(Mutex::new(self.new_output_log())),
+            activity: Arc::new(ActivityTracker::default()),
+            name: None,
+            predictor: Arc::new(Mutex::new(EchoPredictor::default())),
+            guest_home: None,
+            tmp_dir: None,
+            shell: process_name.to_string(),
+            profile: None,
+            created_at: unix_now(),
+            exited: Arc::new(AtomicBool::new(false)),
+            remote_pid: None,
+            pool_state: None,
+            batcher: batcher.clone(),
+            recorder: Arc::new(Mutex::new(None)),
+            input_translation: InputTranslation::default(),
+            recent_inputs: InputRing::default(),
+        };
+        let output_offset = session.output_offset.clone();
+        let last_activity = session.last_activity.clone();
+        let gate = session.gate.clone();
+        let latency = session.latency.clone();
+        let find = session.find.clone();
+        let output_log = session.output_log.clone();
+        let activity = session.activity.clone();
+        let predictor = session.predictor.clone();
+        let recorder = session.recorder.clone();
+        let exited = session.exited.clone();
+        let mut processor = OutputProcessor::new(
+            session_id.clone(),
+            session.terminal.clone(),
+            session.writer.clone(),
+            self.identity.clone(),
+            self.color_theme.clone(),
+            self.events.clone(),
+        );
+
+        // Store session
+        {
+            let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            sessions.insert(session_id.clone(), session);
+            let templates = self.templates.lock().map_err(|_| "Lock poisoned")?;
+            if let Some(session) = sessions.get_mut(&session_id) {
+                self.refresh_tab_title(&session_id, session, &templates.tab_title);
+            }
+        }
+
+        // Read thread for this session
+        let sid = session_id.clone();
+        let events = self.events.clone();
+        let triggers = self.triggers.clone();
+        let rules = self.rules.clone();
+        let feedback = self.feedback.clone();
+        let heads_up = self.heads_up.clone();
+        let bells = self.bells.clone();
+        let long_commands = self.long_commands.clone();
+        let pipes = self.pipes.clone();
+        let remote_edits = self.remote_edits.clone();
+        let templates = self.templates.clone();
+        let clipboard = self.clipboard.clone();
+        let history = self.history.clone();
+        let sessions = self.sessions.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            let mut charsets = CharsetTranslator::default();
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(n) if n > 0 => {
+                        // Everything past this point only sees UTF-8
+                        let output = charsets.translate(&buf[..n]);
+                        let offset = output_offset.fetch_add(output.len() as u64, Ordering::SeqCst) + output.len() as u64;
+                        if let Ok(mut log) = output_log.lock() {
+                            log.push(&output);
+                        }
+                        if let Some(recorder) = recorder.lock().ok().as_ref().and_then(|r| r.as_ref()) {
+                            recorder.push(&output);
+                        }
+                        last_activity.store(unix_now(), Ordering::SeqCst);
+                        if activity.output() {
+                            events.emit("session-activity-changed", activity.snapshot(&sid));
+                        }
+                        if let Ok(mut latency) = latency.lock() {
+                            latency.output(n);
+                        }
+                        let text = processor.process(&output);
+                        if let Some(update) = predictor.lock().ok().map(|mut p| p.output(&text)) {
+                            predict::emit_update(&events, &sid, update);
+                        }
+                        if processor.take_bell() {
+                            feedback::notify(&feedback, FeedbackEvent::Bell);
+                            heads_up::raise(&heads_up, &sid, HeadsUpReason::Bell, &sessions);
+                            bell::ring(&bells, &sid, &events);
+                        }
+                        for request in processor.take_edit_requests() {
+                            remote_edit::request(&sid, request, &sessions, &remote_edits, &events);
+                        }
+                        if let Some(reported) = processor.take_cwd() {
+                            cwd::apply(&sessions, &templates, &sid, reported, &events);
+                        }
+                        for notification in processor.take_notifications() {
+                            notification::post(&sessions, &sid, notification, &events);
+                        }
+                        let marks = processor.take_prompt_marks();
+                        if !marks.is_empty() {
+                            for command in semantic::apply(&sessions, &history, &sid, marks, offset, &events) {
+                                long_command::check(&long_commands, &sessions, &templates, &sid, &command, &events);
+                            }
+                        }
+                        if let Some(progress) = processor.take_progress() {
+                            progress::apply(&sessions, &sid, progress, &events);
+                        }
+                        for request in processor.take_clipboard_requests() {
+                            clipboard::handle(&clipboard, &sid, request, &sessions, &events);
+                        }
+                        let titles = processor.take_titles();
+                        if !titles.is_empty() {
+                            title::apply_osc_titles(&sessions, &templates, &sid, titles, &events);
+                        }
+                        if let Some((find_id, added, removed)) = find.lock().ok().and_then(|mut f| f.feed(&text)) {
+                            events.emit("find-matches-changed", FindUpdatePayload {
+                                session_id: sid.clone(),
+                                find_id,
+                                added,
+                                removed,
+                            });
+                        }
+                        let fired = triggers.lock().map(|mut t| t.scan(&sid, &text)).unwrap_or_default();
+                        for automation in fired {
+                            events.emit("automation-triggered", AutomationTriggeredPayload {
+                                name: automation.name.clone(),
+                                session_id: sid.clone(),
+                            });
+                            heads_up::raise(&heads_up, &sid, HeadsUpReason::Trigger(automation.name.clone()), &sessions);
+                            automation::spawn_execute(automation, sid.clone(), sessions.clone());
+                        }
+                        if rules.lock().is_ok_and(|r| r.watches(RuleTrigger::OutputMatched)) {
+                            let event = RuleEvent::OutputMatched { session_id: sid.clone(), text: text.clone() };
+                            rules::fire(&rules, &event, &sessions, &events);
+                        }
+                        pipe::forward(&pipes, &sid, &text, &sessions, &events);
+                        // Held back while the session is hibernated
+                        if let Some(data) = gate.pass(&output) {
+                            batcher.push(OutputStream::Stdout, &data);
+                        }
+                    }
+                    Ok(_) => break, // EOF
+                    Err(_) => break, // Error
+                }
+            }
+            exited.store(true, Ordering::SeqCst);
+            // The last output goes out before the exit is reported
+            batcher.close();
+            let _ = flusher.join();
+            report_exit(&sessions, &sid, &events);
+        });
+
+        Ok(session_id)
+    }
+
+    pub fn write(&self, session_id: &str, data: &str) -> Result<(), String> {
+        let data = &self.rewrite_submitted(session_id, data)?;
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session_id = &scratchpad::resolve_id(&sessions, session_id);
+        if let Some(session) = sessions.get_mut(session_id) {
+            if session.input_locked {
+                return Err("Session input is locked".into());
+            }
+            let data = &session.input_translation.translate(data);
+            if session.hibernation.is_some() {
+                self.revive(session_id, session);
+            }
+            self.release_pooled(session_id, session);
+            session.last_activity.store(unix_now(), Ordering::SeqCst);
+            if session.activity.interact() {
+                self.events.emit("session-activity-changed", session.activity.snapshot(session_id));
+            }
+            if let Ok(mut latency) = session.latency.lock() {
+                latency.input(data);
+            }
+            self.predict_input(session_id, session, data);
+            if let Ok(mut writer) = session.writer.lock() {
+                let _ = write!(writer, "{}", data);
+            }
+
+            let mut submitted = session.input_line.feed(data);
+            if !submitted.is_empty() && reading_password(&session.master) {
+                submitted.clear();
+            }
+            session.recent_inputs.record(data, &submitted);
+            // Lines typed into a program the shell runs aren't commands
+            if !submitted.is_empty() && session.guest_home.is_none() && session.prompt_marks.at_prompt() {
+                let mut history = self.history.lock().map_err(|_| "Lock poisoned")?;
+                let redactor = self.redactor.lock().map_err(|_| "Lock poisoned")?;
+                for command in submitted {
+                    let entry = HistoryEntry {
+                        command: redactor.redact(&command).into_owned(),
+                        session_id: session_id.to_string(),
+                        cwd: session.title.cwd.clone(),
+                        host: session.title.remote.as_ref().map(RemoteContext::label),
+                        exit_status: None,
+                        timestamp: unix_now(),
+                        resources: None,
+                    };
+                    // Only the last of several lines submitted at once is the one still running
+                    self.track_command(session_id, entry.command.clone(), entry.timestamp);
+                    session.prompt_marks.submitted(entry.timestamp, &entry.command);
+                    history.record(entry);
+                }
+                if let (Some(cwd), None, true) = (&session.title.cwd, &session.title.remote, session.cwd_host.is_empty()) {
+                    self.record_recent_dir(cwd);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lock_input(&self, session_id: &str, locked: bool) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        set_input_lock(&self.events, session_id, session, locked);
+        Ok(())
+    }
+
+    pub fn resize(&self, session_id: &str, rows: u16, cols: u16) -> Result<(), String> {
+        let size = self.cell_metrics()?.pty_size(rows, cols);
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        if let Some(session) = sessions.get_mut(session_id) {
+            if let Ok(master) = session.master.lock() {
+                let _ = master.resize(size);
+            }
+            if let Ok(mut terminal) = session.terminal.lock() {
+                terminal.screen.resize(rows, cols);
+            }
+            self.release_pooled(session_id, session);
+        }
+        Ok(())
+    }
+
+    pub fn close(&self, session_id: &str) -> Result<(), String> {
+        if let Some(child) = self.close_session(session_id)? {
+            thread::spawn(move || terminate(child));
+        }
+        Ok(())
+    }
+
+    /// Closes every session and waits (briefly) for their processes to exit. For app exit.
+    pub fn close_all(&self) {
+        let ids: Vec<String> = match self.sessions.lock() {
+            Ok(sessions) => sessions.keys().cloned().collect(),
+            Err(_) => return,
+        };
+        let reapers: Vec<_> = ids.iter()
+            .filter_map(|id| self.close_session(id).ok().flatten())
+            .map(|child| thread::spawn(move || terminate(child)))
+            .collect();
+        for reaper in reapers {
+            let _ = reaper.join();
+        }
+    }
+
+    // Removes the session and hangs up its processes; returns the child still to reap
+    fn close_session(&self, session_id: &str) -> Result<Option<TerminatingChild>, String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let mut child = None;
+        if let Some(mut session) = sessions.remove(session_id) {
+            // Don't leave SIGSTOPped processes behind
+            self.revive(session_id, &mut session);
+            // Nobody will ack what's left; a throttled reader must get to EOF
+            session.batcher.close();
+            #[cfg(unix)]
+            crate::hibernate::signal_session(&session, libc::SIGHUP);
+            child = session.child.take().map(|child| TerminatingChild {
+                child,
+                #[cfg(unix)]
+                groups: crate::hibernate::session_groups(&session),
+            });
+            // Stops the writer once what's queued is written
+            if let Ok(mut recorder) = session.recorder.lock() {
+                recorder.take();
+            }
+            if let Some(home) = &session.guest_home {
+                let _ = std::fs::remove_dir_all(home);
+            }
+            if let Some(dir) = &session.tmp_dir {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+        }
+        drop(sessions);
+        if let Ok(mut triggers) = self.triggers.lock() {
+            triggers.forget(session_id);
+        }
+        if let Ok(mut heads_up) = self.heads_up.lock() {
+            heads_up.forget(session_id);
+        }
+        if let Ok(mut bells) = self.bells.lock() {
+            bells.forget(session_id);
+        }
+        self.remove_session_forwards(session_id);
+        self.remove_session_pipes(session_id);
+        if let Ok(mut running) = self.running_commands.lock() {
+            running.remove(session_id);
+        }
+        self.forget_remote_edits(session_id);
+        Ok(child)
+    }
+
+    pub fn terminal_identity(&self) -> Result<TerminalIdentity, String> {
+        let identity = self.identity.lock().map_err(|_| "Lock poisoned")?;
+        Ok(identity.clone())
+    }
+
+    pub fn set_terminal_identity(&self, identity: TerminalIdentity) -> Result<(), String> {
+        let mut current = self.identity.lock().map_err(|_| "Lock poisoned")?;
+        *current = identity;
+        Ok(())
+    }
+
+    pub fn color_theme(&self) -> Result<ColorTheme, String> {
+        let theme = self.color_theme.lock().map_err(|_| "Lock poisoned")?;
+        Ok(theme.clone())
+    }
+
+    /// The frontend's theme, used to answer color queries for unchanged slots.
+    pub fn set_color_theme(&self, theme: ColorTheme) -> Result<(), String> {
+        let mut current = self.color_theme.lock().map_err(|_| "Lock poisoned")?;
+        *current = theme;
+        Ok(())
+    }
+
+    /// The theme as the session's programs have recolored it.
+    pub fn terminal_colors(&self, session_id: &str) -> Result<ColorTheme, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let terminal = session.terminal.lock().map_err(|_| "Lock poisoned")?;
+        let theme = self.color_theme.lock().map_err(|_| "Lock poisoned")?;
+        Ok(terminal.colors.apply(&theme))
+    }
+
+    pub fn mouse_mode(&self, session_id: &str) -> Result<MouseMode, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let terminal = session.terminal.lock().map_err(|_| "Lock poisoned")?;
+        Ok(terminal.mouse)
+    }
+
+    // Emits `tab-title` only when the rendered title actually changed.
+    pub(crate) fn refresh_tab_title(&self, session_id: &str, session: &mut PtySession, template: &str) {
+        emit_tab_title(&self.events, session_id, session, template);
+    }
+
+    pub fn tab_title(&self, session_id: &str) -> Result<String, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let templates = self.templates.lock().map_err(|_| "Lock poisoned")?;
+        Ok(render_tab_title(&templates.tab_title, &session.title))
+    }
+
+    pub fn set_title_template(&self, template: Option<String>) -> Result<(), String> {
+        let mut templates = self.templates()?;
+        templates.tab_title = template.unwrap_or_else(|| DEFAULT_TITLE_TEMPLATE.to_string());
+        self.set_templates(templates)
+    }
+
+    pub fn set_group(&self, session_id: &str, group_id: Option<String>) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        session.group_id = group_id;
+        Ok(())
+    }
+
+    // Resizes every session in the group while holding the sessions lock, so no member can be
+    // resized or closed halfway through. If any resize fails, already-resized members are restored.
+    pub fn resize_group(&self, group_id: &str, rows: u16, cols: u16) -> Result<Vec<String>, String> {
+        let size = self.cell_metrics()?.pty_size(rows, cols);
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let members: Vec<(&String, &PtySession)> = sessions.iter()
+            .filter(|(_, s)| s.group_id.as_deref() == Some(group_id))
+            .collect();
+
+        let mut resized: Vec<(String, PtySize)> = Vec::new();
+        let mut failure = None;
+
+        for (id, session) in &members {
+            let master = session.master.lock().map_err(|_| "Lock poisoned")?;
+            let previous = master.get_size().ok();
+            match master.resize(size) {
+                Ok(()) => resized.push(((*id).clone(), previous.unwrap_or(size))),
+                Err(e) => {
+                    failure = Some(format!("Failed to resize session {}: {}", id, e));
+                    break;
+                }
+            }
+        }
+
+        if let Some(err) = failure {
+            for (id, previous) in &resized {
+                if let Some(master) = sessions.get(id).and_then(|s| s.master.lock().ok()) {
+                    let _ = master.resize(*previous);
+                }
+            }
+            return Err(err);
+        }
+
+        for (id, _) in &resized {
+            if let Some(mut terminal) = sessions.get(id).and_then(|s| s.terminal.lock().ok()) {
+                terminal.screen.resize(rows, cols);
+            }
+        }
+        Ok(resized.into_iter().map(|(id, _)| id).collect())
+    }
+
+    // `session_id` restricts results to one session when provided, otherwise history
+    // from all sessions is searched.
+    pub fn search_history(&self, session_id: Option<&str>, query: &str) -> Result<Vec<HistoryMatch>, String> {
+        let history = self.history.lock().map_err(|_| "Lock poisoned")?;
+        Ok(history.search(query, session_id, HISTORY_SEARCH_LIMIT))
+    }
+
+    pub fn add_mark(&self, session_id: &str, label: String) -> Result<OutputMark, String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        let mark = OutputMark {
+            id: Uuid::new_v4().to_string(),
+            label,
+            offset: session.output_offset.load(Ordering::SeqCst),
+            created_at: unix_now(),
+        };
+        session.marks.push(mark.clone());
+        Ok(mark)
+    }
+
+    // Marks in output order, so the frontend can jump between them
+    pub fn list_marks(&self, session_id: &str) -> Result<Vec<OutputMark>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let mut marks = session.marks.clone();
+        marks.sort_by_key(|m| m.offset);
+        Ok(marks)
+    }
+
+    pub fn remove_mark(&self, session_id: &str, mark_id: &str) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        session.marks.retain(|m| m.id != mark_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use std::time::{Duration, Instant};
+
+    fn manager() -> (SessionManager, Arc<RecordingSink>) {
+        let sink = Arc::new(RecordingSink::default());
+        (SessionManager::new(sink.clone(), HistoryStore::load(None)), sink)
+    }
+
+    fn spawn_sh(manager: &SessionManager) -> String {
+        manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap()
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn output_is_emitted_and_offsets_advance() {
+        let (manager, sink) = manager();
+        let id = spawn_sh(&manager);
+        manager.write(&id, "echo shelll-test\n").unwrap();
+
+        wait_for(|| {
+            let output: Vec<u8> = sink.named("pty-output").iter()
+                .flat_map(|p| serde_json::from_value::<Vec<u8>>(p["data"].clone()).unwrap())
+                .collect();
+            String::from_utf8_lossy(&output).contains("shelll-test\r\n")
+        });
+
+        let mark = manager.add_mark(&id, "after echo".into()).unwrap();
+        assert!(mark.offset > 0);
+        assert_eq!(manager.list_marks(&id).unwrap().len(), 1);
+        manager.remove_mark(&id, &mark.id).unwrap();
+        assert!(manager.list_marks(&id).unwrap().is_empty());
+        manager.close(&id).unwrap();
+    }
+
+    #[test]
+    fn submitted_lines_are_searchable() {
+        let (manager, _sink) = manager();
+        let id = spawn_sh(&manager);
+        manager.write(&id, "true one\r").unwrap();
+        let matches = manager.search_history(Some(&id), "one").unwrap();
+        assert_eq!(matches[0].command, "true one");
+        manager.close(&id).unwrap();
+    }
+
+    #[test]
+    fn locked_sessions_reject_input() {
+        let (manager, sink) = manager();
+        let id = spawn_sh(&manager);
+        manager.lock_input(&id, true).unwrap();
+        assert!(manager.write(&id, "true locked\r").is_err());
+        assert!(manager.search_history(Some(&id), "locked").unwrap().is_empty());
+
+        manager.lock_input(&id, false).unwrap();
+        manager.write(&id, "true unlocked\r").unwrap();
+        assert_eq!(sink.named("session-input-lock-changed").len(), 2);
+        manager.close(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn password_replies_are_not_recorded() {
+        let (manager, _sink) = manager();
+        let id = spawn_sh(&manager);
+        manager.write(&id, "stty -echo; read secret\r").unwrap();
+        let master = manager.sessions.lock().unwrap()[&id].master.clone();
+        wait_for(|| reading_password(&master));
+        manager.write(&id, "hunter2\r").unwrap();
+        assert!(manager.search_history(Some(&id), "hunter2").unwrap().is_empty());
+        assert!(manager.recent_inputs(&id).unwrap().iter().all(|i| i.line != "hunter2"));
+        manager.close(&id).unwrap();
+    }
+
+    #[test]
+    fn resize_group_only_touches_members() {
+        let (manager, _sink) = manager();
+        let a = spawn_sh(&manager);
+        let b = spawn_sh(&manager);
+        let c = spawn_sh(&manager);
+        manager.set_group(&a, Some("g".into())).unwrap();
+        manager.set_group(&b, Some("g".into())).unwrap();
+
+        let mut resized = manager.resize_group("g", 40, 120).unwrap();
+        resized.sort();
+        let mut expected = vec![a.clone(), b.clone()];
+        expected.sort();
+        assert_eq!(resized, expected);
+
+        let sessions = manager.sessions.lock().unwrap();
+        let size = |id: &str| sessions[id].master.lock().unwrap().get_size().unwrap();
+        assert_eq!((size(&a).rows, size(&a).cols), (40, 120));
+        assert_eq!((size(&c).rows, size(&c).cols), (30, 100));
+    }
+
+    #[test]
+    fn hibernated_session_revives_on_input() {
+        let (manager, sink) = manager();
+        let id = spawn_sh(&manager);
+        manager.hibernate_session(&id, true).unwrap();
+        manager.write(&id, "echo revived\n").unwrap();
+
+        let changes = sink.named("session-hibernation-changed");
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1]["hibernated"], false);
+        wait_for(|| {
+            let output: Vec<u8> = sink.named("pty-output").iter()
+                .flat_map(|p| serde_json::from_value::<Vec<u8>>(p["data"].clone()).unwrap())
+                .collect();
+            String::from_utf8_lossy(&output).contains("revived\r\n")
+        });
+        manager.close(&id).unwrap();
+    }
+
+    #[test]
+    fn tab_title_follows_template() {
+        let (manager, sink) = manager();
+        let id = spawn_sh(&manager);
+        manager.set_title_template(Some("{process}!".into())).unwrap();
+        assert_eq!(manager.tab_title(&id).unwrap(), "sh!");
+        let titles = sink.named("tab-title");
+        assert_eq!(titles.last().unwrap()["title"], "sh!");
+    }
+
+    #[test]
+    fn programs_set_the_title_through_osc() {
+        let (manager, sink) = manager();
+        let id = spawn_sh(&manager);
+        manager.write(&id, "printf '\\033]0;%s\\007' deploying\n").unwrap();
+        wait_for(|| !sink.named("session-title-changed").is_empty());
+        let changed = &sink.named("session-title-changed")[0];
+        assert_eq!((changed["title"].clone(), changed["icon_name"].clone()), ("deploying".into(), "deploying".into()));
+        assert_eq!(manager.session_info(&id).unwrap().title.as_deref(), Some("deploying"));
+        manager.close(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn closing_kills_and_reaps_the_shell() {
+        let (manager, _) = manager();
+        let id = spawn_sh(&manager);
+        let pid = manager.sessions.lock().unwrap()[&id].pid.unwrap() as libc::pid_t;
+        manager.close(&id).unwrap();
+        // Fails once the zombie is reaped too
+        wait_for(|| unsafe { libc::kill(pid, 0) } == -1);
+    }
+
+    #[test]
+    fn shell_exit_is_reported() {
+        let (manager, sink) = manager();
+        let id = spawn_sh(&manager);
+        manager.write(&id, "exit 3\r").unwrap();
+        wait_for(|| !sink.named("pty-exited").is_empty());
+        let exited = &sink.named("pty-exited")[0];
+        assert_eq!((exited["session_id"].as_str(), exited["exit_code"].as_u64()), (Some(id.as_str()), Some(3)));
+        assert!(exited["signal"].is_null());
+        manager.close(&id).unwrap();
+    }
+
+    #[test]
+    fn sessions_start_with_the_requested_command() {
+        let (manager, sink) = manager();
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let mut env = EnvMap::new();
+        env.insert("GREETING".into(), "hello".into());
+        let options = SessionOptions {
+            shell: Some("/bin/sh".into()),
+            args: Some(vec!["-c".into(), "echo \"$GREETING from $(pwd) $(stty size)\"; sleep 5".into()]),
+            cwd: Some(dir.clone()),
+            env,
+            rows: Some(40),
+            cols: Some(120),
+            private_tmp: None,
+            split_stderr: None,
+        };
+        let id = manager.create_session(&Profile::default(), &options).unwrap();
+        let expected = format!("hello from {} 40 120", dir.display());
+        wait_for(|| {
+            let output: Vec<u8> = sink.named("pty-output").iter()
+                .flat_map(|p| serde_json::from_value::<Vec<u8>>(p["data"].clone()).unwrap())
+                .collect();
+            String::from_utf8_lossy(&output).contains(&expected)
+        });
+        manager.close(&id).unwrap();
+
+        let missing = SessionOptions { cwd: Some(dir.join("no-such-dir")), ..SessionOptions::default() };
+        assert!(manager.create_session(&Profile::default(), &missing).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! Directories commands were recently run in, newest first, kept across restarts so the
+//! Dock menu (and the frontend) can offer to open a new session in one of them.
+
+use crate::config::{load_json, save_json};
+use crate::pty::SessionManager;
+use crate::unix_now;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub const MAX_RECENT_DIRS: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentDir {
+    pub path: String,
+    // Unix seconds a command was last submitted there
+    pub last_used: u64,
+}
+
+#[derive(Default)]
+pub struct RecentDirsStore {
+    path: Option<PathBuf>,
+    pub dirs: Vec<RecentDir>,
+}
+
+impl RecentDirsStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let dirs = load_json(path.as_deref());
+        RecentDirsStore { path, dirs }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.dirs)
+    }
+
+    /// Moves `dir` to the front. The home directory isn't worth listing and is skipped.
+    /// Returns whether the list changed.
+    pub fn record(&mut self, dir: &str, now: u64) -> bool {
+        let dir = match dir.trim_end_matches('/') {
+            "" => "/",
+            trimmed => trimmed,
+        };
+        let home = std::env::var("HOME").unwrap_or_default();
+        if !home.is_empty() && dir == home.trim_end_matches('/') {
+            return false;
+        }
+        if self.dirs.first().is_some_and(|first| first.path == dir) {
+            self.dirs[0].last_used = now;
+            return false;
+        }
+        self.dirs.retain(|recent| recent.path != dir);
+        self.dirs.insert(0, RecentDir { path: dir.to_string(), last_used: now });
+        self.dirs.truncate(MAX_RECENT_DIRS);
+        true
+    }
+}
+
+impl SessionManager {
+    pub fn set_recent_dirs(&self, store: RecentDirsStore) -> Result<(), String> {
+        *self.recent_dirs.lock().map_err(|_| "Lock poisoned")? = store;
+        Ok(())
+    }
+
+    pub fn recent_dirs(&self) -> Result<Vec<RecentDir>, String> {
+        Ok(self.recent_dirs.lock().map_err(|_| "Lock poisoned")?.dirs.clone())
+    }
+
+    pub fn clear_recent_dirs(&self) -> Result<(), String> {
+        let mut store = self.recent_dirs.lock().map_err(|_| "Lock poisoned")?;
+        store.dirs.clear();
+        store.save()
+    }
+
+    // Only saved when the order changes, not on every command in the same directory
+    pub(crate) fn record_recent_dir(&self, dir: &str) {
+        let Ok(mut store) = self.recent_dirs.lock() else { return };
+        if store.record(dir, unix_now()) {
+            let _ = store.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_newest_directories_first() {
+        let mut store = RecentDirsStore::load(None);
+        assert!(store.record("/src/shelll/", 1));
+        assert!(store.record("/src/other", 2));
+        assert!(!store.record("/src/other", 3));
+        assert_eq!(store.dirs[0], RecentDir { path: "/src/other".into(), last_used: 3 });
+        assert!(store.record("/src/shelll", 4));
+        assert_eq!(store.dirs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>(), ["/src/shelll", "/src/other"]);
+
+        for i in 0..20 {
+            store.record(&format!("/tmp/{}", i), 10 + i);
+        }
+        assert_eq!(store.dirs.len(), MAX_RECENT_DIRS);
+        assert_eq!(store.dirs[0].path, "/tmp/19");
+    }
+
+    #[test]
+    fn skips_the_home_directory() {
+        let Some(home) = std::env::var("HOME").ok().filter(|h| h.len() > 1) else { return };
+        let mut store = RecentDirsStore::load(None);
+        assert!(!store.record(&format!("{}/", home), 1));
+        assert!(store.dirs.is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+//! Remote sessions: when the foreground job is `ssh`, `mosh`, `docker exec` or
+//! `kubectl exec`, the session is "on" that host or container. The context is read from
+//! the job's command line, checked whenever the foreground job changes, and shown in tab
+//! titles (`{remote}`, `{host}`), session info and the command history. Host rules pick a
+//! color for sessions on matching hosts, e.g. red for production.
+
+use crate::colors::Rgb;
+use crate::config::{load_json, save_json};
+use crate::forward::parse_ssh_command;
+use crate::process;
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// Options of `docker exec`/`docker run` and `kubectl exec` that take a value
+const DOCKER_VALUE_FLAGS: &[&str] = &[
+    "-e", "--env", "--env-file", "-u", "--user", "-w", "--workdir", "--name", "-v", "--volume",
+    "-p", "--publish", "--network", "--entrypoint", "--platform", "-h", "--hostname", "--mount",
+];
+const KUBECTL_VALUE_FLAGS: &[&str] = &["-c", "--container", "-n", "--namespace", "--context", "--kubeconfig", "-f", "--filename"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteKind {
+    Ssh,
+    Mosh,
+    Docker,
+    Kubectl,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RemoteContext {
+    pub kind: RemoteKind,
+    // Host for ssh/mosh, container (or image) for docker, pod for kubectl
+    pub host: String,
+    pub user: Option<String>,
+    // Kubernetes context and namespace, when given on the command line
+    pub context: Option<String>,
+    pub namespace: Option<String>,
+}
+
+impl RemoteContext {
+    /// Short form for titles and the history: "deploy@prod", "docker:web", "k8s:staging/api-0".
+    pub fn label(&self) -> String {
+        match self.kind {
+            RemoteKind::Ssh | RemoteKind::Mosh => match &self.user {
+                Some(user) => format!("{}@{}", user, self.host),
+                None => self.host.clone(),
+            },
+            RemoteKind::Docker => format!("docker:{}", self.host),
+            RemoteKind::Kubectl => {
+                let scope = [&self.context, &self.namespace].into_iter().flatten().cloned().collect::<Vec<_>>();
+                if scope.is_empty() {
+                    format!("k8s:{}", self.host)
+                } else {
+                    format!("k8s:{}/{}", scope.join("/"), self.host)
+                }
+            }
+        }
+    }
+}
+
+fn program_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// "user@host" (ssh also accepts ssh://user@host:port)
+fn split_destination(destination: &str) -> (Option<String>, String) {
+    let destination = destination.strip_prefix("ssh://").unwrap_or(destination);
+    let (user, host) = match destination.rsplit_once('@') {
+        Some((user, host)) => (Some(user.to_string()), host),
+        None => (None, destination),
+    };
+    let host = host.split(':').next().unwrap_or(host);
+    (user, host.to_string())
+}
+
+// First positional argument, skipping options (and the values of those in `value_flags`)
+fn first_positional<'a>(args: &'a [String], value_flags: &[&str]) -> Option<&'a str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            return iter.next().map(String::as_str);
+        }
+        if !arg.starts_with('-') {
+            return Some(arg);
+        }
+        if !arg.contains('=') && value_flags.contains(&arg.as_str()) {
+            iter.next();
+        }
+    }
+    None
+}
+
+fn flag_value(args: &[String], names: &[&str]) -> Option<String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            break;
+        }
+        for name in names {
+            if arg == name {
+                return iter.next().cloned();
+            }
+            if let Some(value) = arg.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+                return Some(value.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// The remote context of a command line, if it connects somewhere.
+pub fn parse_remote_command(args: &[String]) -> Option<RemoteContext> {
+    let program = program_name(args.first()?);
+    let remote = |kind, host: String, user| RemoteContext { kind, host, user, context: None, namespace: None };
+    match program {
+        "ssh" => {
+            let (user, host) = split_destination(&parse_ssh_command(args)?.destination);
+            Some(remote(RemoteKind::Ssh, host, user))
+        }
+        "mosh" => {
+            let destination = args[1..].iter().find(|a| !a.starts_with('-'))?;
+            let (user, host) = split_destination(destination);
+            Some(remote(RemoteKind::Mosh, host, user))
+        }
+        "docker" | "podman" => {
+            // Only interactive subcommands put the session inside a container
+            let rest = match args.get(1)?.as_str() {
+                "exec" | "attach" | "run" => &args[2..],
+                "container" if matches!(args.get(2)?.as_str(), "exec" | "attach" | "run") => &args[3..],
+                _ => return None,
+            };
+            let container = first_positional(rest, DOCKER_VALUE_FLAGS)?;
+            let user = flag_value(rest, &["-u", "--user"]);
+            Some(remote(RemoteKind::Docker, container.to_string(), user))
+        }
+        "kubectl" | "oc" => {
+            let at = args.iter().position(|a| a == "exec" || a == "attach")?;
+            let pod = first_positional(&args[at + 1..], KUBECTL_VALUE_FLAGS)?;
+            Some(RemoteContext {
+                context: flag_value(args, &["--context"]),
+                namespace: flag_value(args, &["-n", "--namespace"]),
+                ..remote(RemoteKind::Kubectl, pod.trim_start_matches("pod/").to_string(), None)
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Sessions whose host or label matches `pattern` (`*` matches anything) get `color`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostRule {
+    pub pattern: String,
+    pub color: Rgb,
+}
+
+// `*` matches any run of characters; matching ignores case
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.to_lowercase(), text.to_lowercase());
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let [first, middle @ .., last] = parts.as_slice() else {
+        return pattern == text;
+    };
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The color of the first rule matching `remote`.
+pub fn host_color(rules: &[HostRule], remote: &RemoteContext) -> Option<Rgb> {
+    let label = remote.label();
+    rules.iter()
+        .find(|r| wildcard_match(&r.pattern, &remote.host) || wildcard_match(&r.pattern, &label))
+        .map(|r| r.color)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteSettings {
+    #[serde(default)]
+    pub host_rules: Vec<HostRule>,
+}
+
+// Host rules persisted as JSON in the config dir
+pub struct RemoteStore {
+    path: Option<PathBuf>,
+    pub settings: RemoteSettings,
+}
+
+impl RemoteStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        RemoteStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RemoteChangedPayload {
+    pub session_id: String,
+    // None when the session is back on this machine
+    pub remote: Option<RemoteContext>,
+    pub label: Option<String>,
+    // From the first matching host rule
+    pub color: Option<Rgb>,
+}
+
+impl SessionManager {
+    pub fn set_remote_settings(&self, settings: RemoteSettings) -> Result<(), String> {
+        *self.host_rules.lock().map_err(|_| "Lock poisoned")? = settings.host_rules;
+        Ok(())
+    }
+
+    pub fn session_remote(&self, session_id: &str) -> Result<Option<RemoteContext>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        Ok(session.title.remote.clone())
+    }
+
+    /// Re-reads the remote context of sessions whose foreground job changed. Returns the
+    /// sessions whose context changed.
+    pub fn refresh_remotes(&self) -> Result<Vec<String>, String> {
+        let changed_jobs: Vec<(String, Option<u32>)> = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            sessions.iter()
+                .filter(|(_, s)| s.hibernation.is_none())
+                .filter_map(|(id, s)| {
+                    let pid = process::foreground_pid(s);
+                    (pid != s.remote_pid).then(|| (id.clone(), pid))
+                })
+                .collect()
+        };
+        let mut changed = Vec::new();
+        for (id, pid) in changed_jobs {
+            // Outside the lock: this runs ps
+            let remote = pid.and_then(process::process_args)
+                .and_then(|args| parse_remote_command(&args));
+            let rules = self.host_rules.lock().map_err(|_| "Lock poisoned")?.clone();
+            let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let Some(session) = sessions.get_mut(&id) else { continue };
+            session.remote_pid = pid;
+            if session.title.remote == remote {
+                continue;
+            }
+            session.title.remote = remote.clone();
+            let templates = self.templates.lock().map_err(|_| "Lock poisoned")?;
+            self.refresh_tab_title(&id, session, &templates.tab_title);
+            self.events.emit("session-remote-changed", RemoteChangedPayload {
+                session_id: id.clone(),
+                label: remote.as_ref().map(RemoteContext::label),
+                color: remote.as_ref().and_then(|r| host_color(&rules, r)),
+                remote,
+            });
+            changed.push(id);
+        }
+        Ok(changed)
+    }
+
+    /// Checks for remote sessions every couple of seconds until the manager is dropped.
+    pub fn start_remote_watcher(self: &Arc<Self>) {
+        let manager = Arc::downgrade(self);
+        thread::spawn(move || {
+            while let Some(manager) = manager.upgrade() {
+                let _ = manager.refresh_remotes();
+                drop(manager);
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Option<RemoteContext> {
+        parse_remote_command(&s.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn recognizes_remote_commands() {
+        assert_eq!(parse("/usr/bin/ssh -p 2222 deploy@prod-db-1 uptime").unwrap().label(), "deploy@prod-db-1");
+        assert_eq!(parse("mosh --ssh=ssh bastion").unwrap().host, "bastion");
+        assert_eq!(parse("docker exec -it -u root -e A=1 web bash").unwrap().label(), "docker:web");
+        assert_eq!(parse("docker container exec -it api sh").unwrap().host, "api");
+        let pod = parse("kubectl --context prod exec -it -n payments pod/api-0 -c app -- sh").unwrap();
+        assert_eq!(pod.label(), "k8s:prod/payments/api-0");
+        assert_eq!(parse("docker ps"), None);
+        assert_eq!(parse("kubectl get pods"), None);
+        assert_eq!(parse("vim notes"), None);
+    }
+
+    #[test]
+    fn host_rules_color_matching_sessions() {
+        let red = Rgb::new(200, 0, 0);
+        let rules = vec![HostRule { pattern: "prod-*".into(), color: red }];
+        assert_eq!(host_color(&rules, &parse("ssh PROD-web").unwrap()), Some(red));
+        assert_eq!(host_color(&rules, &parse("ssh staging-web").unwrap()), None);
+        assert!(wildcard_match("*.example.com", "db.example.com"));
+        assert!(!wildcard_match("db*", "web-db"));
+        assert!(wildcard_match("k8s:prod*", "k8s:prod/api-0"));
+    }
+}
@@ -0,0 +1,554 @@
+//! Editing remote files in a local editor. `shelll edit <file>` on the remote host (the
+//! shell function from `remote_edit_function`, installed there by hand) prints the file
+//! inside a private OSC sequence; once the user allows it, the backend saves a local copy,
+//! opens it, and on every save types the new contents back to the still-running function,
+//! which writes them to the remote file. Everything goes through the session's own terminal
+//! stream, so it works over any SSH connection.
+//!
+//! Off until turned on in `RemoteEditSettings`. Requests must carry this install's nonce,
+//! so output that merely contains the sequence (a `cat`ed file, a hostile server) can't
+//! start an edit, and each one waits for `confirm_remote_edit` before anything is written
+//! or opened. Blocked requests are reported with `remote-edit-blocked`.
+//!
+//! Protocol, after the function has put the terminal in non-canonical no-echo mode:
+//! remote → local: `ESC ] 5379 ; edit ; <nonce> ; <base64 path> ; <base64 content> BEL`
+//! local → remote: `w<base64 content>\n` per save, `q\n` when done.
+
+use crate::config::{load_json, save_json};
+use crate::events::EventSink;
+use crate::pty::{set_input_lock, PtySession, SessionManager};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+pub const REMOTE_EDIT_OSC: &[u8] = b"5379;edit;";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// Base64 grows this by a third, which has to stay under the parser's 1 MiB OSC limit
+pub const MAX_FILE_BYTES: usize = 512 * 1024;
+
+// POSIX sh; bash and zsh too. @NONCE@ and @MAX_BYTES@ are filled in by `remote_edit_function`.
+const REMOTE_EDIT_FUNCTION: &str = r#"shelll() {
+  if [ "$1" != edit ] || [ ! -f "$2" ]; then
+    echo "usage: shelll edit <file>" >&2
+    return 2
+  fi
+  if [ "$(($(wc -c < "$2")))" -gt @MAX_BYTES@ ]; then
+    echo "shelll: $2 is too large to edit (over @MAX_BYTES@ bytes)" >&2
+    return 1
+  fi
+  _shelll_path="$(cd "$(dirname "$2")" && pwd)/$(basename "$2")"
+  _shelll_tty="$(stty -g 2>/dev/null)"
+  stty -echo -icanon 2>/dev/null
+  printf '\033]5379;edit;@NONCE@;%s;' "$(printf %s "$_shelll_path" | base64 | tr -d '\n')"
+  base64 < "$2" | tr -d '\n'
+  printf '\a'
+  while IFS= read -r _shelll_line; do
+    case "$_shelll_line" in
+      q) break ;;
+      w*)
+        printf %s "${_shelll_line#w}" | base64 -d > "$_shelll_path.shelll-tmp" \
+          && cat "$_shelll_path.shelll-tmp" > "$_shelll_path" \
+          && echo "shelll: saved $2"
+        rm -f "$_shelll_path.shelll-tmp"
+        ;;
+    esac
+  done
+  [ -n "$_shelll_tty" ] && stty "$_shelll_tty"
+}
+"#;
+
+/// The `shelll edit` function for remote hosts, carrying this install's nonce.
+pub fn remote_edit_function(nonce: &str) -> String {
+    REMOTE_EDIT_FUNCTION.replace("@NONCE@", nonce).replace("@MAX_BYTES@", &MAX_FILE_BYTES.to_string())
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteEditSettings {
+    // Sessions may ask to open files locally at all
+    pub enabled: bool,
+    // Generated once per install and baked into the remote function
+    pub nonce: String,
+}
+
+// Remote edit settings persisted as JSON in the config dir
+pub struct RemoteEditStore {
+    path: Option<PathBuf>,
+    pub settings: RemoteEditSettings,
+}
+
+impl RemoteEditStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut store = RemoteEditStore { settings: load_json(path.as_deref()), path };
+        if store.settings.nonce.is_empty() {
+            store.settings.nonce = Uuid::new_v4().simple().to_string();
+            let _ = store.save();
+        }
+        store
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RemoteEditRequest {
+    pub(crate) nonce: String,
+    pub(crate) path: String,
+    pub(crate) content: Vec<u8>,
+}
+
+/// The body of a remote edit OSC (without `ESC ]` and the terminator), if it is one.
+pub(crate) fn parse_request(osc: &[u8]) -> Option<RemoteEditRequest> {
+    let rest = osc.strip_prefix(REMOTE_EDIT_OSC)?;
+    let mut fields = rest.splitn(3, |&b| b == b';');
+    let nonce = String::from_utf8(fields.next()?.to_vec()).ok()?;
+    let path = String::from_utf8(BASE64.decode(fields.next()?).ok()?).ok()?;
+    let content = BASE64.decode(fields.next()?).ok()?;
+    Some(RemoteEditRequest { nonce, path, content })
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RemoteEdit {
+    pub edit_id: String,
+    pub session_id: String,
+    pub remote_path: String,
+    pub local_path: PathBuf,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RemoteEditPayload {
+    pub edit_id: String,
+    pub session_id: String,
+    pub remote_path: String,
+}
+
+impl RemoteEditPayload {
+    fn new(edit: &RemoteEdit) -> Self {
+        RemoteEditPayload {
+            edit_id: edit.edit_id.clone(),
+            session_id: edit.session_id.clone(),
+            remote_path: edit.remote_path.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct RemoteEditRequestedPayload {
+    pub edit_id: String,
+    pub session_id: String,
+    pub remote_path: String,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RemoteEditBlockedPayload {
+    pub session_id: String,
+    pub remote_path: String,
+    pub reason: String,
+}
+
+struct PendingEdit {
+    session_id: String,
+    request: RemoteEditRequest,
+}
+
+#[derive(Default)]
+pub(crate) struct RemoteEditState {
+    settings: RemoteEditSettings,
+    // Announced by a session, waiting for the user to allow them
+    pending: HashMap<String, PendingEdit>,
+    active: HashMap<String, RemoteEdit>,
+}
+
+pub(crate) type RemoteEdits = Arc<Mutex<RemoteEditState>>;
+
+// Never the generic opener: the file name comes from the remote host, and xdg-open would
+// hand it to whatever its extension is associated with
+fn editor_command() -> Result<Command, String> {
+    #[cfg(target_os = "macos")]
+    {
+        let mut command = Command::new("open");
+        command.arg("-t");
+        Ok(command)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        // $VISUAL/$EDITOR may carry arguments ("code --wait"), so it goes through the shell
+        let editor = ["VISUAL", "EDITOR"].iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|editor| !editor.trim().is_empty());
+        if let Some(editor) = editor {
+            let mut command = Command::new("sh");
+            command.args(["-c", &format!("{} \"$1\"", editor), "sh"]);
+            return Ok(command);
+        }
+        let output = Command::new("xdg-mime")
+            .args(["query", "default", "text/plain"])
+            .output()
+            .map_err(|e| format!("Failed to look up the text editor: {}", e))?;
+        let desktop_entry = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if desktop_entry.is_empty() {
+            return Err("No text editor found; set $VISUAL or $EDITOR".into());
+        }
+        let mut command = Command::new("gtk-launch");
+        command.arg(desktop_entry);
+        Ok(command)
+    }
+}
+
+fn open_in_editor(path: &Path) -> Result<(), String> {
+    editor_command()?
+        .arg(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open editor: {}", e))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn remove_local_copy(edit: &RemoteEdit) {
+    if let Some(dir) = edit.local_path.parent() {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+// Sends the function back to the prompt and gives the session its input back
+fn release_session(sessions: &Mutex<HashMap<String, PtySession>>, session_id: &str, events: &Arc<dyn EventSink>) {
+    let Ok(mut sessions) = sessions.lock() else { return };
+    if let Some(session) = sessions.get_mut(session_id) {
+        if let Ok(mut writer) = session.writer.lock() {
+            let _ = writer.write_all(b"q\n");
+        }
+        set_input_lock(events, session_id, session, false);
+    }
+}
+
+/// From the reader thread: a request with this install's nonce locks the session's input
+/// (typing would corrupt the transfer) and waits for the user, or is sent back to the
+/// prompt if remote editing is off. Anything else is ignored.
+pub(crate) fn request(
+    session_id: &str,
+    request: RemoteEditRequest,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    edits: &RemoteEdits,
+    events: &Arc<dyn EventSink>,
+) {
+    let Ok(settings) = edits.lock().map(|e| e.settings.clone()) else { return };
+    let blocked = |reason: &str| {
+        events.emit("remote-edit-blocked", RemoteEditBlockedPayload {
+            session_id: session_id.to_string(),
+            remote_path: request.path.clone(),
+            reason: reason.to_string(),
+        });
+    };
+    if settings.nonce.is_empty() || request.nonce != settings.nonce {
+        // Not our function, so nothing is waiting for an answer
+        return blocked("The request did not come from this install's shelll function");
+    }
+    if !settings.enabled {
+        release_session(sessions, session_id, events);
+        return blocked("Remote editing is turned off");
+    }
+
+    {
+        let Ok(mut sessions) = sessions.lock() else { return };
+        let Some(session) = sessions.get_mut(session_id) else { return };
+        set_input_lock(events, session_id, session, true);
+    }
+    let edit_id = Uuid::new_v4().to_string();
+    events.emit("remote-edit-requested", RemoteEditRequestedPayload {
+        edit_id: edit_id.clone(),
+        session_id: session_id.to_string(),
+        remote_path: request.path.clone(),
+        bytes: request.content.len(),
+    });
+    if let Ok(mut edits) = edits.lock() {
+        edits.pending.insert(edit_id, PendingEdit { session_id: session_id.to_string(), request });
+    }
+}
+
+// Writes the local copy, opens the editor and watches for saves until the edit is finished
+fn start(
+    edit_id: &str,
+    pending: PendingEdit,
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    edits: &RemoteEdits,
+    events: &Arc<dyn EventSink>,
+) -> Result<(), String> {
+    let PendingEdit { session_id, request } = pending;
+    let name = Path::new(&request.path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "file".into());
+    let dir = std::env::temp_dir().join("shelll-edit").join(edit_id);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let local_path = dir.join(name);
+    if let Err(e) = fs::write(&local_path, &request.content) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(format!("Failed to write {}: {}", local_path.display(), e));
+    }
+
+    let writer = {
+        let sessions = sessions.lock().map_err(|_| "Lock poisoned")?;
+        sessions.get(&session_id).ok_or("Session not found")?.writer.clone()
+    };
+    let edit = RemoteEdit {
+        edit_id: edit_id.to_string(),
+        session_id,
+        remote_path: request.path,
+        local_path: local_path.clone(),
+    };
+    events.emit("remote-edit-opened", RemoteEditPayload::new(&edit));
+    edits.lock().map_err(|_| "Lock poisoned")?.active.insert(edit_id.to_string(), edit);
+    if let Err(e) = open_in_editor(&local_path) {
+        eprintln!("{}", e);
+    }
+
+    let edit_id = edit_id.to_string();
+    let edits = edits.clone();
+    let events = events.clone();
+    thread::spawn(move || {
+        let mut last_saved = modified(&local_path);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let Some(edit) = edits.lock().ok().and_then(|e| e.active.get(&edit_id).cloned()) else {
+                break;
+            };
+            let current = modified(&local_path);
+            if current == last_saved {
+                continue;
+            }
+            last_saved = current;
+            let Ok(content) = fs::read(&local_path) else {
+                continue;
+            };
+            let line = format!("w{}\n", BASE64.encode(content));
+            let written = writer.lock()
+                .map_err(|_| ())
+                .and_then(|mut w| w.write_all(line.as_bytes()).map_err(|_| ()));
+            if written.is_ok() {
+                events.emit("remote-edit-saved", RemoteEditPayload::new(&edit));
+            }
+        }
+    });
+    Ok(())
+}
+
+impl SessionManager {
+    pub fn set_remote_edit_settings(&self, settings: RemoteEditSettings) -> Result<(), String> {
+        self.remote_edits.lock().map_err(|_| "Lock poisoned")?.settings = settings;
+        Ok(())
+    }
+
+    pub fn list_remote_edits(&self) -> Result<Vec<RemoteEdit>, String> {
+        let edits = self.remote_edits.lock().map_err(|_| "Lock poisoned")?;
+        Ok(edits.active.values().cloned().collect())
+    }
+
+    /// The user's answer to `remote-edit-requested`. Declining (or failing to open the file)
+    /// returns the remote function to its prompt and unlocks the session's input.
+    pub fn confirm_remote_edit(&self, edit_id: &str, allow: bool) -> Result<(), String> {
+        let pending = self.remote_edits.lock()
+            .map_err(|_| "Lock poisoned")?
+            .pending
+            .remove(edit_id)
+            .ok_or("Remote edit not found")?;
+        let session_id = pending.session_id.clone();
+        let payload = RemoteEditPayload {
+            edit_id: edit_id.to_string(),
+            session_id: session_id.clone(),
+            remote_path: pending.request.path.clone(),
+        };
+        let result = if allow {
+            start(edit_id, pending, &self.sessions, &self.remote_edits, &self.events)
+        } else {
+            Ok(())
+        };
+        if !allow || result.is_err() {
+            release_session(&self.sessions, &session_id, &self.events);
+            self.events.emit("remote-edit-declined", payload);
+        }
+        result
+    }
+
+    /// Ends the edit: the remote function returns to the prompt and input is unlocked.
+    /// Saves made after this are not transferred.
+    pub fn finish_remote_edit(&self, edit_id: &str) -> Result<(), String> {
+        let edit = self.remote_edits.lock()
+            .map_err(|_| "Lock poisoned")?
+            .active
+            .remove(edit_id)
+            .ok_or("Remote edit not found")?;
+        release_session(&self.sessions, &edit.session_id, &self.events);
+        remove_local_copy(&edit);
+        self.events.emit("remote-edit-finished", RemoteEditPayload::new(&edit));
+        Ok(())
+    }
+
+    // On close: the session can't receive the contents any more
+    pub(crate) fn forget_remote_edits(&self, session_id: &str) {
+        if let Ok(mut edits) = self.remote_edits.lock() {
+            edits.pending.retain(|_, p| p.session_id != session_id);
+            edits.active.retain(|_, e| {
+                let closed = e.session_id == session_id;
+                if closed {
+                    remove_local_copy(e);
+                }
+                !closed
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use portable_pty::CommandBuilder;
+    use std::process::Stdio;
+    use std::time::Instant;
+
+    fn osc(nonce: &str, path: &str, content: &[u8]) -> Vec<u8> {
+        let mut osc = REMOTE_EDIT_OSC.to_vec();
+        osc.extend(nonce.bytes());
+        osc.push(b';');
+        osc.extend(BASE64.encode(path).bytes());
+        osc.push(b';');
+        osc.extend(BASE64.encode(content).bytes());
+        osc
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    // Runs the function on `file` with `input` as what the terminal sends back
+    fn run_function(file: &Path, input: &str) -> std::process::Output {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{}\nshelll edit '{}'", remote_edit_function("n0nce"), file.display()))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    #[test]
+    fn parses_requests() {
+        let request = parse_request(&osc("n0nce", "/etc/hosts", b"127.0.0.1 localhost\n")).unwrap();
+        assert_eq!(request.nonce, "n0nce");
+        assert_eq!(request.path, "/etc/hosts");
+        assert_eq!(request.content, b"127.0.0.1 localhost\n");
+        assert_eq!(parse_request(b"0;title"), None);
+        assert_eq!(parse_request(b"5379;edit;n0nce;not base64!;"), None);
+        assert_eq!(parse_request(b"5379;edit;L2V0Yy9ob3N0cw==;"), None);
+    }
+
+    #[test]
+    fn remote_function_round_trips() {
+        let dir = std::env::temp_dir().join(format!("shelll-remote-edit-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes.txt");
+        fs::write(&file, "before\n").unwrap();
+
+        let output = run_function(&file, &format!("w{}\nq\n", BASE64.encode("after\n")));
+        let start = output.stdout.windows(2).position(|w| w == b"\x1b]").unwrap() + 2;
+        let end = output.stdout.iter().position(|&b| b == 0x07).unwrap();
+        let request = parse_request(&output.stdout[start..end]).unwrap();
+        assert_eq!(request.nonce, "n0nce");
+        assert_eq!(request.path, file.display().to_string());
+        assert_eq!(request.content, b"before\n");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "after\n");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn remote_function_refuses_files_the_osc_cannot_carry() {
+        let dir = std::env::temp_dir().join(format!("shelll-remote-edit-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("big.log");
+        fs::write(&file, vec![b'x'; MAX_FILE_BYTES + 1]).unwrap();
+
+        let output = run_function(&file, "");
+        assert!(!output.status.success());
+        assert!(!output.stdout.windows(2).any(|w| w == b"\x1b]"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn requests_need_the_nonce_the_setting_and_the_user() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        let announce = |nonce: &str| {
+            let body = String::from_utf8(osc(nonce, "/tmp/notes.txt", b"hi\n")).unwrap();
+            manager.write(&id, &format!("printf '\\033]{}\\a'\n", body)).unwrap();
+        };
+        let settings = RemoteEditSettings { enabled: false, nonce: "n0nce".into() };
+        manager.set_remote_edit_settings(settings.clone()).unwrap();
+
+        announce("n0nce");
+        wait_for(|| sink.named("remote-edit-blocked").len() == 1);
+        assert_eq!(sink.named("remote-edit-blocked")[0]["reason"], "Remote editing is turned off");
+
+        manager.set_remote_edit_settings(RemoteEditSettings { enabled: true, ..settings }).unwrap();
+        announce("forged");
+        wait_for(|| sink.named("remote-edit-blocked").len() == 2);
+
+        announce("n0nce");
+        wait_for(|| sink.named("remote-edit-requested").len() == 1);
+        let requested = &sink.named("remote-edit-requested")[0];
+        assert_eq!(requested["remote_path"], "/tmp/notes.txt");
+        assert!(manager.sessions.lock().unwrap()[&id].input_locked);
+        // Nothing is written or opened until the user answers
+        assert!(manager.list_remote_edits().unwrap().is_empty());
+
+        manager.confirm_remote_edit(requested["edit_id"].as_str().unwrap(), false).unwrap();
+        assert_eq!(sink.named("remote-edit-declined").len(), 1);
+        assert!(!manager.sessions.lock().unwrap()[&id].input_locked);
+        assert!(manager.confirm_remote_edit(requested["edit_id"].as_str().unwrap(), true).is_err());
+        manager.close(&id).unwrap();
+    }
+
+    #[test]
+    fn forgetting_a_session_removes_its_local_copies() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let dir = std::env::temp_dir().join("shelll-edit").join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let edit = RemoteEdit {
+            edit_id: "e1".into(),
+            session_id: "s1".into(),
+            remote_path: "/etc/hosts".into(),
+            local_path: dir.join("hosts"),
+        };
+        fs::write(&edit.local_path, "127.0.0.1 localhost\n").unwrap();
+        manager.remote_edits.lock().unwrap().active.insert("e1".into(), edit);
+
+        manager.forget_remote_edits("s1");
+        assert!(manager.list_remote_edits().unwrap().is_empty());
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,128 @@
+//! Backend replies to terminal queries (device attributes, status reports, XTVERSION,
+//! XTGETTCAP) so programs that block on a response don't hang when the frontend
+//! doesn't answer. Disabled by default because xterm.js answers these itself; enable it
+//! for sessions without a live renderer (hidden windows, automation clients).
+
+use crate::vt::Sequence;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerminalIdentity {
+    pub enabled: bool,
+    // Parameters of the DA1 reply: CSI ? <primary_da> c
+    pub primary_da: String,
+    // Parameters of the DA2 reply: CSI > <secondary_da> c
+    pub secondary_da: String,
+    // Reported by XTVERSION and as the XTGETTCAP "TN" capability
+    pub name: String,
+    pub version: String,
+    pub colors: u32,
+}
+
+impl Default for TerminalIdentity {
+    fn default() -> Self {
+        TerminalIdentity {
+            enabled: false,
+            // VT220 with ANSI color
+            primary_da: "62;22".to_string(),
+            secondary_da: "0;10;1".to_string(),
+            name: "xterm-256color".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            colors: 256,
+        }
+    }
+}
+
+fn hex_decode(hex: &str) -> Option<String> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect();
+    bytes.and_then(|b| String::from_utf8(b).ok())
+}
+
+fn hex_encode(s: &str) -> String {
+    s.bytes().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn capability(identity: &TerminalIdentity, name: &str) -> Option<String> {
+    match name {
+        "TN" | "name" => Some(identity.name.clone()),
+        "Co" | "colors" => Some(identity.colors.to_string()),
+        "RGB" if identity.colors > 256 => Some("8".to_string()),
+        _ => None,
+    }
+}
+
+/// The bytes to write back to the PTY for `seq`, if it's a query we answer.
+pub fn respond(seq: &Sequence, identity: &TerminalIdentity) -> Option<Vec<u8>> {
+    if !identity.enabled {
+        return None;
+    }
+    let reply = match seq {
+        Sequence::Csi { private: None, intermediates, final_byte: b'c', .. }
+            if intermediates.is_empty() && seq.params().iter().all(|p| *p == 0) =>
+        {
+            format!("\x1b[?{}c", identity.primary_da)
+        }
+        Sequence::Csi { private: Some(b'>'), intermediates, final_byte: b'c', .. } if intermediates.is_empty() => {
+            format!("\x1b[>{}c", identity.secondary_da)
+        }
+        // DSR 5: operating status
+        Sequence::Csi { private: None, final_byte: b'n', .. } if seq.params() == [5] => "\x1b[0n".to_string(),
+        // XTVERSION
+        Sequence::Csi { private: Some(b'>'), final_byte: b'q', .. } => {
+            format!("\x1bP>|shelll({})\x1b\\", identity.version)
+        }
+        // XTGETTCAP: DCS + q <hex-name>;<hex-name> ST, answered one capability per reply
+        Sequence::Dcs(data) if data.starts_with(b"+q") => {
+            let names = String::from_utf8_lossy(&data[2..]).into_owned();
+            names.split(';')
+                .map(|hex| match hex_decode(hex).and_then(|name| capability(identity, &name)) {
+                    Some(value) => format!("\x1bP1+r{}={}\x1b\\", hex, hex_encode(&value)),
+                    None => format!("\x1bP0+r{}\x1b\\", hex),
+                })
+                .collect()
+        }
+        _ => return None,
+    };
+    Some(reply.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vt::Scanner;
+
+    fn reply(input: &[u8]) -> Option<String> {
+        let identity = TerminalIdentity { enabled: true, ..TerminalIdentity::default() };
+        let seqs = Scanner::default().feed(input);
+        respond(&seqs[0], &identity).map(|b| String::from_utf8(b).unwrap())
+    }
+
+    #[test]
+    fn answers_device_attributes_and_status() {
+        assert_eq!(reply(b"\x1b[c").as_deref(), Some("\x1b[?62;22c"));
+        assert_eq!(reply(b"\x1b[0c").as_deref(), Some("\x1b[?62;22c"));
+        assert_eq!(reply(b"\x1b[>c").as_deref(), Some("\x1b[>0;10;1c"));
+        assert_eq!(reply(b"\x1b[5n").as_deref(), Some("\x1b[0n"));
+        assert_eq!(reply(b"\x1b[6n"), None);
+    }
+
+    #[test]
+    fn answers_xtgettcap_per_capability() {
+        // "TN" and "zz"
+        let answer = reply(b"\x1bP+q544E;7A7A\x1b\\").unwrap();
+        assert_eq!(answer, format!("\x1bP1+r544E={}\x1b\\\x1bP0+r7A7A\x1b\\", hex_encode("xterm-256color")));
+    }
+
+    #[test]
+    fn stays_silent_when_disabled() {
+        let seqs = Scanner::default().feed(b"\x1b[c");
+        assert_eq!(respond(&seqs[0], &TerminalIdentity::default()), None);
+    }
+}
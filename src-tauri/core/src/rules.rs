@@ -0,0 +1,490 @@
+//! Declarative automations read from `rules.toml` in the config dir, for behavior that
+//! would otherwise need plugin code. A rule names an event, conditions on it and the
+//! actions to run when they hold:
+//!
+//! ```toml
+//! [[rule]]
+//! name = "Slow build finished"
+//! on = "command_finished"
+//! when = { command = "cargo build", min_duration_secs = 30 }
+//! then = [
+//!     { action = "notify", title = "Build finished", body = "Exited with {exit_code} after {duration}" },
+//!     { action = "window", window = "show" },
+//! ]
+//! ```
+//!
+//! Events are `focus_changed` (an app came to the front), `command_finished` (as reported
+//! by `command_finished`) and `output_matched` (a session printed text matching the
+//! `pattern` regex). Text in actions can use `{app}`, `{bundle_id}`, `{session_id}`,
+//! `{command}`, `{exit_code}`, `{duration}` and `{match}`. A rule's actions run in order on
+//! their own thread; one that fails stops the rest.
+
+use crate::automation::{self, Step};
+use crate::events::EventSink;
+use crate::feedback::Feedback;
+use crate::focus::RunningApp;
+use crate::notification;
+use crate::process;
+use crate::pty::{PtySession, SessionManager};
+use crate::template::{self, format_duration};
+use crate::unix_now;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleTrigger {
+    FocusChanged,
+    CommandFinished,
+    OutputMatched,
+}
+
+/// All set conditions must hold. One that doesn't apply to the event (`app` for a
+/// finished command) never holds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleConditions {
+    // Name or bundle id of the app that came to the front
+    pub app: Option<String>,
+    pub session_id: Option<String>,
+    // Substring of the finished command line
+    pub command: Option<String>,
+    pub exit_code: Option<i32>,
+    // Only failed (true) or only successful (false) commands
+    pub failed: Option<bool>,
+    pub min_duration_secs: Option<u64>,
+    // Regex matched against printed text (escape sequences removed)
+    pub pattern: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowAction {
+    Show,
+    Hide,
+    Focus,
+    Minimize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RuleAction {
+    Notify {
+        title: String,
+        #[serde(default)]
+        body: String,
+    },
+    // Run with the shell, waiting for it to finish; the event's values are also passed in
+    // SHELLL_* variables
+    RunScript { command: String },
+    FocusApp { app: String },
+    // Applied to Shelll's own window by the frontend
+    Window { window: WindowAction },
+    // Written to `session_id`, or to the session the event came from
+    SendToSession {
+        session_id: Option<String>,
+        text: String,
+    },
+    PlayFeedback { feedback: Feedback },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    pub on: RuleTrigger,
+    #[serde(default)]
+    pub when: RuleConditions,
+    pub then: Vec<RuleAction>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuleEvent {
+    FocusChanged { app: RunningApp },
+    CommandFinished {
+        session_id: String,
+        command: Option<String>,
+        exit_code: i32,
+        duration: Option<Duration>,
+    },
+    OutputMatched { session_id: String, text: String },
+}
+
+impl RuleEvent {
+    fn trigger(&self) -> RuleTrigger {
+        match self {
+            RuleEvent::FocusChanged { .. } => RuleTrigger::FocusChanged,
+            RuleEvent::CommandFinished { .. } => RuleTrigger::CommandFinished,
+            RuleEvent::OutputMatched { .. } => RuleTrigger::OutputMatched,
+        }
+    }
+
+    fn session_id(&self) -> Option<&str> {
+        match self {
+            RuleEvent::FocusChanged { .. } => None,
+            RuleEvent::CommandFinished { session_id, .. } | RuleEvent::OutputMatched { session_id, .. } => Some(session_id),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct RuleFiredPayload {
+    pub name: String,
+    pub on: RuleTrigger,
+    pub session_id: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RuleWindowPayload {
+    pub rule: String,
+    pub action: WindowAction,
+}
+
+#[derive(Default, Deserialize)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+/// Parses and checks a rules file.
+pub fn parse_rules(text: &str) -> Result<Vec<Rule>, String> {
+    let file: RulesFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    for rule in &file.rules {
+        compile(rule)?;
+    }
+    Ok(file.rules)
+}
+
+fn compile(rule: &Rule) -> Result<CompiledRule, String> {
+    if rule.name.trim().is_empty() {
+        return Err("Rule name cannot be empty".into());
+    }
+    let pattern = match &rule.when.pattern {
+        Some(pattern) => Some(Regex::new(pattern).map_err(|e| format!("Rule '{}': {}", rule.name, e))?),
+        None if rule.on == RuleTrigger::OutputMatched => {
+            return Err(format!("Rule '{}' matches output but has no pattern", rule.name));
+        }
+        None => None,
+    };
+    Ok(CompiledRule { rule: rule.clone(), pattern })
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RulesStatus {
+    pub path: Option<String>,
+    pub rules: Vec<Rule>,
+    // Why the file couldn't be used; no rules run until it's fixed
+    pub error: Option<String>,
+}
+
+// Rules read from rules.toml in the config dir. Edited by hand, never written back
+pub struct RulesStore {
+    path: Option<PathBuf>,
+    pub rules: Vec<Rule>,
+    pub error: Option<String>,
+}
+
+impl RulesStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut store = RulesStore { path, rules: Vec::new(), error: None };
+        store.reload();
+        store
+    }
+
+    /// Reads the file again. A missing file means no rules.
+    pub fn reload(&mut self) {
+        let text = match self.path.as_ref().map(fs::read_to_string) {
+            Some(Ok(text)) => text,
+            Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => {
+                self.rules.clear();
+                self.error = Some(format!("Failed to read rules: {}", e));
+                return;
+            }
+            _ => String::new(),
+        };
+        match parse_rules(&text) {
+            Ok(rules) => {
+                self.rules = rules;
+                self.error = None;
+            }
+            Err(e) => {
+                self.rules.clear();
+                self.error = Some(e);
+            }
+        }
+    }
+
+    pub fn status(&self) -> RulesStatus {
+        RulesStatus {
+            path: self.path.as_ref().map(|p| p.to_string_lossy().into_owned()),
+            rules: self.rules.clone(),
+            error: self.error.clone(),
+        }
+    }
+
+    pub fn watches(&self, trigger: RuleTrigger) -> bool {
+        self.rules.iter().any(|r| r.on == trigger)
+    }
+}
+
+struct CompiledRule {
+    rule: Rule,
+    pattern: Option<Regex>,
+}
+
+impl CompiledRule {
+    // The values actions can use, if the rule matches
+    fn matches(&self, event: &RuleEvent) -> Option<HashMap<&'static str, String>> {
+        if self.rule.on != event.trigger() {
+            return None;
+        }
+        let when = &self.rule.when;
+        if when.session_id.as_deref().is_some_and(|id| Some(id) != event.session_id()) {
+            return None;
+        }
+        let mut vars = HashMap::new();
+        if let Some(session_id) = event.session_id() {
+            vars.insert("session_id", session_id.to_string());
+        }
+        match event {
+            RuleEvent::FocusChanged { app } => {
+                if when.command.is_some() || when.exit_code.is_some() || when.failed.is_some() || when.min_duration_secs.is_some() {
+                    return None;
+                }
+                if when.app.as_ref().is_some_and(|a| *a != app.name && !a.eq_ignore_ascii_case(&app.bundle_id)) {
+                    return None;
+                }
+                vars.insert("app", app.name.clone());
+                vars.insert("bundle_id", app.bundle_id.clone());
+            }
+            RuleEvent::CommandFinished { command, exit_code, duration, .. } => {
+                if when.app.is_some() || when.pattern.is_some() {
+                    return None;
+                }
+                if when.command.as_ref().is_some_and(|c| !command.as_ref().is_some_and(|command| command.contains(c.as_str()))) {
+                    return None;
+                }
+                if when.exit_code.is_some_and(|c| c != *exit_code) || when.failed.is_some_and(|f| f != (*exit_code != 0)) {
+                    return None;
+                }
+                if when.min_duration_secs.is_some_and(|min| !duration.is_some_and(|d| d.as_secs() >= min)) {
+                    return None;
+                }
+                if let Some(command) = command {
+                    vars.insert("command", command.clone());
+                }
+                vars.insert("exit_code", exit_code.to_string());
+                if let Some(duration) = duration {
+                    vars.insert("duration", format_duration(*duration));
+                }
+            }
+            RuleEvent::OutputMatched { text, .. } => {
+                if when.app.is_some() || when.command.is_some() || when.exit_code.is_some() || when.failed.is_some() || when.min_duration_secs.is_some() {
+                    return None;
+                }
+                let found = self.pattern.as_ref()?.find(text)?;
+                vars.insert("match", found.as_str().to_string());
+            }
+        }
+        Some(vars)
+    }
+}
+
+/// The loaded rules, matched against events as they happen.
+#[derive(Default)]
+pub(crate) struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    pub(crate) fn watches(&self, trigger: RuleTrigger) -> bool {
+        self.rules.iter().any(|r| r.rule.on == trigger)
+    }
+
+    fn fired(&self, event: &RuleEvent) -> Vec<(Rule, HashMap<&'static str, String>)> {
+        self.rules.iter()
+            .filter_map(|r| r.matches(event).map(|vars| (r.rule.clone(), vars)))
+            .collect()
+    }
+}
+
+fn run_script(command: &str, vars: &HashMap<&'static str, String>) -> Result<(), String> {
+    let mut cmd = process::shell_command(command);
+    for (name, value) in vars {
+        cmd.env(format!("SHELLL_{}", name.to_uppercase()), value);
+    }
+    let status = cmd.status().map_err(|e| format!("Failed to run '{}': {}", command, e))?;
+    if !status.success() {
+        return Err(format!("'{}' exited with {}", command, status));
+    }
+    Ok(())
+}
+
+fn execute(
+    rule: &Rule,
+    vars: &HashMap<&'static str, String>,
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    events: &Arc<dyn EventSink>,
+) -> Result<(), String> {
+    let render = |text: &str| template::render(text, |name| vars.get(name).cloned()).0;
+    let session_id = vars.get("session_id").map(String::as_str);
+    for action in &rule.then {
+        match action {
+            RuleAction::Notify { title, body } => notification::show_notification(&render(title), &render(body))?,
+            RuleAction::RunScript { command } => run_script(command, vars)?,
+            RuleAction::FocusApp { app } => {
+                automation::execute(&[Step::FocusApp { app: render(app) }], session_id, sessions)?
+            }
+            RuleAction::Window { window } => events.emit("rule-window-action", RuleWindowPayload {
+                rule: rule.name.clone(),
+                action: *window,
+            }),
+            RuleAction::SendToSession { session_id: target, text } => {
+                let step = Step::SendToSession { session_id: target.clone(), text: render(text) };
+                automation::execute(&[step], session_id, sessions)?
+            }
+            RuleAction::PlayFeedback { feedback } => {
+                automation::execute(&[Step::PlayFeedback { feedback: feedback.clone() }], session_id, sessions)?
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the rules `event` fires, each on its own thread.
+pub(crate) fn fire(
+    rules: &Mutex<RuleSet>,
+    event: &RuleEvent,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    events: &Arc<dyn EventSink>,
+) {
+    let fired = rules.lock().map(|r| r.fired(event)).unwrap_or_default();
+    for (rule, vars) in fired {
+        events.emit("rule-fired", RuleFiredPayload {
+            name: rule.name.clone(),
+            on: rule.on,
+            session_id: event.session_id().map(String::from),
+        });
+        let (sessions, events) = (sessions.clone(), events.clone());
+        thread::spawn(move || {
+            if let Err(e) = execute(&rule, &vars, &sessions, &events) {
+                eprintln!("Rule '{}' failed: {}", rule.name, e);
+            }
+        });
+    }
+}
+
+impl SessionManager {
+    /// Replaces the rules matched against events.
+    pub fn set_rules(&self, rules: &[Rule]) -> Result<(), String> {
+        let compiled = rules.iter().map(compile).collect::<Result<Vec<_>, _>>()?;
+        self.rules.lock().map_err(|_| "Lock poisoned")?.rules = compiled;
+        Ok(())
+    }
+
+    /// Runs the rules an app coming to the front fires.
+    pub fn focus_changed(&self, app: &RunningApp) {
+        fire(&self.rules, &RuleEvent::FocusChanged { app: app.clone() }, &self.sessions, &self.events);
+    }
+
+    // The last line submitted in the session, and how long ago
+    pub(crate) fn last_command(&self, session_id: &str) -> (Option<String>, Option<Duration>) {
+        let Ok(sessions) = self.sessions.lock() else { return (None, None) };
+        let Some(input) = sessions.get(session_id).and_then(|s| s.recent_inputs.last()) else { return (None, None) };
+        (Some(input.line.clone()), Some(Duration::from_secs(unix_now().saturating_sub(input.sent_at))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+
+    const RULES: &str = r#"
+        [[rule]]
+        name = "Slow build"
+        on = "command_finished"
+        when = { command = "cargo build", failed = true, min_duration_secs = 30 }
+        then = [{ action = "window", window = "show" }]
+
+        [[rule]]
+        name = "Editor"
+        on = "focus_changed"
+        when = { app = "com.microsoft.VSCode" }
+        then = [{ action = "notify", title = "Back to {app}" }]
+
+        [[rule]]
+        name = "Panic"
+        on = "output_matched"
+        when = { pattern = "panicked at '([^']*)'" }
+        then = [{ action = "run_script", command = "true" }]
+    "#;
+
+    fn rule_set(text: &str) -> RuleSet {
+        RuleSet { rules: parse_rules(text).unwrap().iter().map(|r| compile(r).unwrap()).collect() }
+    }
+
+    fn fired_names(rules: &RuleSet, event: &RuleEvent) -> Vec<String> {
+        rules.fired(event).into_iter().map(|(rule, _)| rule.name).collect()
+    }
+
+    #[test]
+    fn matches_events_against_conditions() {
+        let rules = rule_set(RULES);
+        let finished = |command: &str, exit_code, secs| RuleEvent::CommandFinished {
+            session_id: "s1".into(),
+            command: Some(command.into()),
+            exit_code,
+            duration: Some(Duration::from_secs(secs)),
+        };
+        assert_eq!(fired_names(&rules, &finished("cargo build --release", 101, 45)), ["Slow build"]);
+        assert!(fired_names(&rules, &finished("cargo build", 0, 45)).is_empty());
+        assert!(fired_names(&rules, &finished("cargo build", 1, 5)).is_empty());
+
+        let vscode = RunningApp { name: "Code".into(), bundle_id: "com.microsoft.VSCode".into() };
+        let fired = rules.fired(&RuleEvent::FocusChanged { app: vscode });
+        assert_eq!(fired[0].1["app"], "Code");
+
+        let output = RuleEvent::OutputMatched { session_id: "s1".into(), text: "thread 'main' panicked at 'oops'".into() };
+        assert_eq!(rules.fired(&output)[0].1["match"], "panicked at 'oops'");
+        assert!(rules.watches(RuleTrigger::OutputMatched));
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        assert!(parse_rules("").unwrap().is_empty());
+        assert!(parse_rules("[[rule]]\nname = \"x\"\non = \"output_matched\"\nthen = []").unwrap_err().contains("no pattern"));
+        assert!(parse_rules("[[rule]]\nname = \"x\"\non = \"output_matched\"\nwhen = { pattern = \"(\" }\nthen = []").is_err());
+        // A misspelled condition would otherwise match everything
+        assert!(parse_rules("[[rule]]\nname = \"x\"\non = \"focus_changed\"\nwhen = { ap = \"Xcode\" }\nthen = []").is_err());
+    }
+
+    #[test]
+    fn runs_actions_with_event_values() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let rules = parse_rules(r#"
+            [[rule]]
+            name = "Focus"
+            on = "focus_changed"
+            then = [{ action = "window", window = "hide" }]
+        "#).unwrap();
+        manager.set_rules(&rules).unwrap();
+        manager.focus_changed(&RunningApp { name: "Xcode".into(), bundle_id: "com.apple.dt.Xcode".into() });
+        for _ in 0..100 {
+            if !sink.named("rule-window-action").is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(sink.named("rule-fired")[0]["name"], "Focus");
+        assert_eq!(sink.named("rule-window-action")[0]["action"], "hide");
+    }
+}
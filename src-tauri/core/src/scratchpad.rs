@@ -0,0 +1,126 @@
+//! The scratchpad: an always-available session outside the tab list for quick one-off
+//! commands. Injection commands can address it as "scratchpad" instead of by UUID; it is
+//! (re)started on demand and on launch when enabled.
+
+use crate::config::{load_json, save_json};
+use crate::profiles::Profile;
+use crate::pty::{PtySession, SessionManager, SessionOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub const SCRATCHPAD_NAME: &str = "scratchpad";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScratchpadSettings {
+    // Started at launch; otherwise only when first targeted
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Default profile when None
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Default for ScratchpadSettings {
+    fn default() -> Self {
+        ScratchpadSettings { enabled: true, profile: None }
+    }
+}
+
+// Scratchpad settings persisted as JSON in the config dir
+pub struct ScratchpadStore {
+    path: Option<PathBuf>,
+    pub settings: ScratchpadSettings,
+}
+
+impl ScratchpadStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        ScratchpadStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScratchpadPayload {
+    pub session_id: String,
+}
+
+/// Maps a session name to its id; anything else is returned unchanged.
+pub(crate) fn resolve_id(sessions: &HashMap<String, PtySession>, target: &str) -> String {
+    if sessions.contains_key(target) {
+        return target.to_string();
+    }
+    sessions.iter()
+        .find(|(_, s)| s.name.as_deref() == Some(target))
+        .map(|(id, _)| id.clone())
+        .unwrap_or_else(|| target.to_string())
+}
+
+impl SessionManager {
+    /// The scratchpad's session id, starting it with `profile` if it isn't running.
+    pub fn ensure_scratchpad(&self, profile: &Profile) -> Result<String, String> {
+        self.ensure_named(SCRATCHPAD_NAME, || self.create_session(profile, &SessionOptions::default()))
+    }
+
+    fn ensure_named(&self, name: &str, spawn: impl FnOnce() -> Result<String, String>) -> Result<String, String> {
+        let _spawning = self.scratchpad_spawn.lock().map_err(|_| "Lock poisoned")?;
+        {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let id = resolve_id(&sessions, name);
+            if sessions.contains_key(&id) {
+                return Ok(id);
+            }
+        }
+        let id = spawn()?;
+        {
+            let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get_mut(&id).ok_or("Session not found")?;
+            session.name = Some(name.to_string());
+        }
+        self.events.emit("scratchpad-started", ScratchpadPayload { session_id: id.clone() });
+        Ok(id)
+    }
+
+    pub fn scratchpad_session(&self) -> Result<Option<String>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let id = resolve_id(&sessions, SCRATCHPAD_NAME);
+        Ok(sessions.contains_key(&id).then_some(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use portable_pty::CommandBuilder;
+    use std::sync::Arc;
+
+    #[test]
+    fn scratchpad_is_addressable_by_name() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let spawn = || manager.spawn_session(CommandBuilder::new("sh"), "sh");
+        let id = manager.ensure_named(SCRATCHPAD_NAME, spawn).unwrap();
+        assert_eq!(manager.ensure_named(SCRATCHPAD_NAME, spawn).unwrap(), id);
+        assert_eq!(sink.named("scratchpad-started").len(), 1);
+
+        let offset = manager.read_output_since(&id, 0, 4096).unwrap().next_offset;
+        manager.write(SCRATCHPAD_NAME, "echo from-scratch\r").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        let output = manager.read_output_since(&id, offset, 4096).unwrap();
+        assert!(String::from_utf8_lossy(&output.data).contains("from-scratch"));
+
+        manager.close(&id).unwrap();
+        assert_eq!(manager.scratchpad_session().unwrap(), None);
+    }
+}
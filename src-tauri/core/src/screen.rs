@@ -0,0 +1,405 @@
+//! Plain-text model of the visible screen, kept in sync with the PTY output so
+//! automation can ask what's on screen instead of pattern-matching the raw byte stream.
+//! Tracks characters and the cursor only; colors and attributes stay with the frontend.
+
+use crate::pty::SessionManager;
+use crate::vt::Sequence;
+use serde::{Deserialize, Serialize};
+
+const TAB_WIDTH: usize = 8;
+
+/// Zero-based.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct CursorPosition {
+    pub row: u16,
+    pub col: u16,
+}
+
+/// Zero-based origin; parts outside the screen are clipped.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ScreenRect {
+    pub row: u16,
+    pub col: u16,
+    pub rows: u16,
+    pub cols: u16,
+}
+
+pub struct Screen {
+    rows: usize,
+    cols: usize,
+    lines: Vec<Vec<char>>,
+    row: usize,
+    col: usize,
+    // The cursor is past the last column; the next printable character wraps first
+    wrap_pending: bool,
+    saved: (usize, usize),
+    // Scrolling region, inclusive
+    top: usize,
+    bottom: usize,
+    // The primary screen while the alternate screen is shown
+    primary: Option<Vec<Vec<char>>>,
+    // Incomplete UTF-8 sequence split across reads
+    utf8: Vec<u8>,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen::new(24, 80)
+    }
+}
+
+impl Screen {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        let (rows, cols) = (rows.max(1) as usize, cols.max(1) as usize);
+        Screen {
+            rows,
+            cols,
+            lines: vec![vec![' '; cols]; rows],
+            row: 0,
+            col: 0,
+            wrap_pending: false,
+            saved: (0, 0),
+            top: 0,
+            bottom: rows - 1,
+            primary: None,
+            utf8: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.rows as u16, self.cols as u16)
+    }
+
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        let (rows, cols) = (rows.max(1) as usize, cols.max(1) as usize);
+        // Keep the cursor line visible by dropping lines off the top
+        if self.row >= rows {
+            let excess = self.row + 1 - rows;
+            self.lines.drain(..excess);
+            self.row -= excess;
+        }
+        for grid in std::iter::once(&mut self.lines).chain(self.primary.as_mut()) {
+            grid.resize(rows, vec![' '; cols]);
+            for line in grid.iter_mut() {
+                line.resize(cols, ' ');
+            }
+        }
+        self.rows = rows;
+        self.cols = cols;
+        self.col = self.col.min(cols - 1);
+        self.saved = (self.saved.0.min(rows - 1), self.saved.1.min(cols - 1));
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.wrap_pending = false;
+    }
+
+    pub fn cursor(&self) -> CursorPosition {
+        CursorPosition { row: self.row as u16, col: self.col as u16 }
+    }
+
+    /// One string per row of `rect`, with trailing blanks trimmed.
+    pub fn region(&self, rect: ScreenRect) -> Vec<String> {
+        let start = (rect.col as usize).min(self.cols);
+        let end = (start + rect.cols as usize).min(self.cols);
+        self.lines.iter()
+            .skip(rect.row as usize)
+            .take(rect.rows as usize)
+            .map(|line| line[start..end].iter().collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    pub fn apply(&mut self, seq: &Sequence) {
+        match seq {
+            Sequence::Text(bytes) => self.text(bytes),
+            Sequence::Control(byte) => self.control(*byte),
+            Sequence::Esc { intermediates, final_byte } if intermediates.is_empty() => match final_byte {
+                b'7' => self.saved = (self.row, self.col),
+                b'8' => self.restore_cursor(),
+                b'D' => self.linefeed(),
+                b'E' => {
+                    self.col = 0;
+                    self.linefeed();
+                }
+                b'M' => self.reverse_index(),
+                _ => {}
+            },
+            Sequence::Csi { private: None, intermediates, final_byte, .. } if intermediates.is_empty() => {
+                self.csi(*final_byte, &seq.params());
+            }
+            Sequence::Csi { private: Some(b'?'), final_byte: final_byte @ (b'h' | b'l'), .. } => {
+                for mode in seq.params() {
+                    if matches!(mode, 47 | 1047 | 1049) {
+                        self.alternate_screen(*final_byte == b'h', mode == 1049);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn blank(&self) -> Vec<char> {
+        vec![' '; self.cols]
+    }
+
+    fn text(&mut self, bytes: &[u8]) {
+        self.utf8.extend_from_slice(bytes);
+        let buf = std::mem::take(&mut self.utf8);
+        let mut rest = &buf[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    s.chars().for_each(|c| self.print(c));
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    std::str::from_utf8(valid).unwrap_or_default().chars().for_each(|c| self.print(c));
+                    match e.error_len() {
+                        Some(len) => {
+                            self.print(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            self.utf8 = after.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        if self.wrap_pending {
+            self.col = 0;
+            self.linefeed();
+        }
+        self.lines[self.row][self.col] = c;
+        if self.col + 1 == self.cols {
+            self.wrap_pending = true;
+        } else {
+            self.col += 1;
+        }
+    }
+
+    fn control(&mut self, byte: u8) {
+        self.wrap_pending = false;
+        match byte {
+            0x08 => self.col = self.col.saturating_sub(1),
+            0x09 => self.col = ((self.col / TAB_WIDTH + 1) * TAB_WIDTH).min(self.cols - 1),
+            0x0a..=0x0c => self.linefeed(),
+            0x0d => self.col = 0,
+            _ => {}
+        }
+    }
+
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        if self.row == self.bottom {
+            self.scroll_up(1);
+        } else if self.row + 1 < self.rows {
+            self.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        if self.row == self.top {
+            self.scroll_down(1);
+        } else {
+            self.row = self.row.saturating_sub(1);
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            self.lines.remove(self.top);
+            self.lines.insert(self.bottom, self.blank());
+        }
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            self.lines.remove(self.bottom);
+            self.lines.insert(self.top, self.blank());
+        }
+    }
+
+    fn restore_cursor(&mut self) {
+        (self.row, self.col) = self.saved;
+        self.wrap_pending = false;
+    }
+
+    fn alternate_screen(&mut self, enter: bool, save_cursor: bool) {
+        if enter && self.primary.is_none() {
+            if save_cursor {
+                self.saved = (self.row, self.col);
+            }
+            let blank = vec![self.blank(); self.rows];
+            self.primary = Some(std::mem::replace(&mut self.lines, blank));
+        } else if !enter {
+            if let Some(primary) = self.primary.take() {
+                self.lines = primary;
+                if save_cursor {
+                    self.restore_cursor();
+                }
+            }
+        }
+    }
+
+    fn erase(&mut self, row: usize, from: usize, to: usize) {
+        let to = to.min(self.cols);
+        if from < to {
+            self.lines[row][from..to].fill(' ');
+        }
+    }
+
+    fn csi(&mut self, final_byte: u8, params: &[u32]) {
+        let arg = |i: usize| params.get(i).copied().unwrap_or(0) as usize;
+        // Count parameters default to 1
+        let n = arg(0).max(1);
+        let (last_row, last_col) = (self.rows - 1, self.cols - 1);
+        self.wrap_pending = false;
+        match final_byte {
+            b'A' => self.row = self.row.saturating_sub(n),
+            b'B' | b'e' => self.row = (self.row + n).min(last_row),
+            b'C' | b'a' => self.col = (self.col + n).min(last_col),
+            b'D' => self.col = self.col.saturating_sub(n),
+            b'E' => (self.row, self.col) = ((self.row + n).min(last_row), 0),
+            b'F' => (self.row, self.col) = (self.row.saturating_sub(n), 0),
+            b'G' | b'`' => self.col = (n - 1).min(last_col),
+            b'd' => self.row = (n - 1).min(last_row),
+            b'H' | b'f' => {
+                self.row = (arg(0).max(1) - 1).min(last_row);
+                self.col = (arg(1).max(1) - 1).min(last_col);
+            }
+            b'J' => {
+                let rows = match arg(0) {
+                    0 => {
+                        self.erase(self.row, self.col, self.cols);
+                        self.row + 1..self.rows
+                    }
+                    1 => {
+                        self.erase(self.row, 0, self.col + 1);
+                        0..self.row
+                    }
+                    _ => 0..self.rows,
+                };
+                for row in rows {
+                    self.erase(row, 0, self.cols);
+                }
+            }
+            b'K' => match arg(0) {
+                0 => self.erase(self.row, self.col, self.cols),
+                1 => self.erase(self.row, 0, self.col + 1),
+                _ => self.erase(self.row, 0, self.cols),
+            },
+            b'X' => self.erase(self.row, self.col, self.col + n),
+            b'@' => {
+                let line = &mut self.lines[self.row];
+                for _ in 0..n.min(self.cols - self.col) {
+                    line.insert(self.col, ' ');
+                }
+                line.truncate(self.cols);
+            }
+            b'P' => {
+                let line = &mut self.lines[self.row];
+                for _ in 0..n.min(self.cols - self.col) {
+                    line.remove(self.col);
+                    line.push(' ');
+                }
+            }
+            b'L' | b'M' if (self.top..=self.bottom).contains(&self.row) => {
+                let top = self.top;
+                self.top = self.row;
+                if final_byte == b'L' {
+                    self.scroll_down(n);
+                } else {
+                    self.scroll_up(n);
+                }
+                self.top = top;
+                self.col = 0;
+            }
+            b'S' => self.scroll_up(n),
+            b'T' => self.scroll_down(n),
+            b'r' => {
+                let top = arg(0).max(1) - 1;
+                let bottom = if arg(1) == 0 { last_row } else { (arg(1) - 1).min(last_row) };
+                if top < bottom {
+                    (self.top, self.bottom) = (top, bottom);
+                    (self.row, self.col) = (0, 0);
+                }
+            }
+            b's' => self.saved = (self.row, self.col),
+            b'u' => self.restore_cursor(),
+            _ => {}
+        }
+    }
+}
+
+impl SessionManager {
+    pub fn cursor_position(&self, session_id: &str) -> Result<CursorPosition, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let terminal = session.terminal.lock().map_err(|_| "Lock poisoned")?;
+        Ok(terminal.screen.cursor())
+    }
+
+    pub fn read_screen_region(&self, session_id: &str, rect: ScreenRect) -> Result<Vec<String>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        let terminal = session.terminal.lock().map_err(|_| "Lock poisoned")?;
+        Ok(terminal.screen.region(rect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vt::Scanner;
+
+    fn screen(rows: u16, cols: u16, output: &[u8]) -> Screen {
+        let mut screen = Screen::new(rows, cols);
+        for seq in Scanner::with_text().feed(output) {
+            screen.apply(&seq);
+        }
+        screen
+    }
+
+    fn all(screen: &Screen) -> Vec<String> {
+        let (rows, cols) = screen.size();
+        screen.region(ScreenRect { row: 0, col: 0, rows, cols })
+    }
+
+    #[test]
+    fn prints_wraps_and_scrolls() {
+        let s = screen(3, 5, b"helloworld\r\nabc\r\nxyz");
+        assert_eq!(all(&s), vec!["world", "abc", "xyz"]);
+        assert_eq!(s.cursor(), CursorPosition { row: 2, col: 3 });
+    }
+
+    #[test]
+    fn moves_cursor_and_erases() {
+        let s = screen(3, 10, b"aaaaaaaaaa\r\nbbbbbbbbbb\x1b[1;4H\x1b[K\x1b[2;2H\x1b[2X\x1b[3;1Hz\x1b[D\x1b[2@");
+        assert_eq!(all(&s), vec!["aaa", "b  bbbbbbb", "  z"]);
+        assert_eq!(s.region(ScreenRect { row: 1, col: 3, rows: 5, cols: 2 }), vec!["bb", ""]);
+    }
+
+    #[test]
+    fn alternate_screen_restores_primary() {
+        let s = screen(2, 10, b"prompt$ \x1b[?1049h\x1b[Hvim\x1b[?1049l");
+        assert_eq!(all(&s), vec!["prompt$", ""]);
+        assert_eq!(s.cursor(), CursorPosition { row: 0, col: 8 });
+    }
+
+    #[test]
+    fn utf8_split_across_reads() {
+        let mut s = Screen::new(1, 10);
+        let mut scanner = Scanner::with_text();
+        for chunk in [&b"caf\xc3"[..], &b"\xa9!"[..]] {
+            for seq in scanner.feed(chunk) {
+                s.apply(&seq);
+            }
+        }
+        assert_eq!(all(&s), vec!["café!"]);
+    }
+}
@@ -0,0 +1,284 @@
+//! Semantic prompt marks (FinalTerm / OSC 133) from shells with the integration loaded:
+//! `A` where the prompt starts, `B` where the command line starts, `C` where its output
+//! starts and `D;<exit>` when it's done. Every mark is reported with `prompt-mark` and
+//! the stream offset it was seen at (the end of the read that carried it), and each
+//! finished command goes into a per-session log with its exit code and duration and
+//! is reported with `command-finished`. The exit code also goes to the history entry of
+//! the line that was submitted at the prompt.
+
+use crate::cwd;
+use crate::events::EventSink;
+use crate::history::HistoryStore;
+use crate::pty::{PtySession, SessionManager};
+use crate::unix_now;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Finished commands kept per session
+const MAX_LOGGED_COMMANDS: usize = 200;
+const MAX_COMMAND_CHARS: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PromptMark {
+    PromptStart,
+    CommandStart,
+    // The command line, when the shell sends it along (`cmdline=` / `cmdline_url=`)
+    OutputStart(Option<String>),
+    // No exit code if the command line was abandoned (Ctrl-C at the prompt)
+    CommandFinished(Option<i32>),
+}
+
+impl PromptMark {
+    fn name(&self) -> &'static str {
+        match self {
+            PromptMark::PromptStart => "prompt_start",
+            PromptMark::CommandStart => "command_start",
+            PromptMark::OutputStart(_) => "output_start",
+            PromptMark::CommandFinished(_) => "command_finished",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PromptMarkPayload {
+    pub session_id: String,
+    // "prompt_start", "command_start", "output_start" or "command_finished"
+    pub mark: String,
+    pub offset: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CommandRecord {
+    pub command: Option<String>,
+    pub cwd: Option<String>,
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub exit_code: Option<i32>,
+    // Stream offsets of the command's output (see `read_output_since`)
+    pub output_start: u64,
+    pub output_end: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CommandFinishedPayload {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub command: CommandRecord,
+}
+
+// The command between its `C` and `D` marks
+struct RunningMark {
+    command: Option<String>,
+    cwd: Option<String>,
+    started_at: u64,
+    started: Instant,
+    output_start: u64,
+    // Its history entry (timestamp, line), which gets the exit status
+    history_entry: Option<(u64, String)>,
+}
+
+/// Where a session is between marks, and the commands it finished.
+#[derive(Default)]
+pub(crate) struct CommandMarks {
+    // Set at `B`, to tell the command line typed at this prompt from older input
+    prompt_at: Option<u64>,
+    // Set at the first `B`: from then on only lines submitted between `B` and `C` are
+    // commands (fish's integration never sends one)
+    marks_command_lines: bool,
+    // History entry of the last line submitted at this prompt
+    submitted: Option<(u64, String)>,
+    running: Option<RunningMark>,
+    log: VecDeque<CommandRecord>,
+}
+
+impl CommandMarks {
+    /// Whether a line submitted now is a command for the shell rather than input to a
+    /// program it runs. Always true for shells that don't mark their command line.
+    pub(crate) fn at_prompt(&self) -> bool {
+        !self.marks_command_lines || self.prompt_at.is_some()
+    }
+
+    pub(crate) fn submitted(&mut self, timestamp: u64, command: &str) {
+        self.submitted = Some((timestamp, command.to_string()));
+    }
+}
+
+/// The mark in an OSC body (without `ESC ]` and the terminator), if it's OSC 133.
+pub(crate) fn parse_osc133(osc: &[u8]) -> Option<PromptMark> {
+    let rest = std::str::from_utf8(osc.strip_prefix(b"133;")?).ok()?;
+    let mut fields = rest.split(';');
+    let mark = match fields.next()? {
+        "A" => PromptMark::PromptStart,
+        "B" => PromptMark::CommandStart,
+        "C" => {
+            let command = fields.find_map(|field| {
+                if let Some(url) = field.strip_prefix("cmdline_url=") {
+                    cwd::percent_decode(url)
+                } else {
+                    field.strip_prefix("cmdline=").map(str::to_string)
+                }
+            });
+            PromptMark::OutputStart(command.map(|c| c.chars().take(MAX_COMMAND_CHARS).collect()))
+        }
+        "D" => PromptMark::CommandFinished(fields.next().and_then(|code| code.parse().ok())),
+        _ => return None,
+    };
+    Some(mark)
+}
+
+// The log entry for a command finishing at `offset`, if one was running
+fn finish(marks: &mut CommandMarks, exit_code: Option<i32>, offset: u64) -> Option<CommandRecord> {
+    let running = marks.running.take()?;
+    let record = CommandRecord {
+        command: running.command,
+        cwd: running.cwd,
+        started_at: running.started_at,
+        duration_ms: running.started.elapsed().as_millis() as u64,
+        exit_code,
+        output_start: running.output_start,
+        output_end: offset,
+    };
+    if marks.log.len() == MAX_LOGGED_COMMANDS {
+        marks.log.pop_front();
+    }
+    marks.log.push_back(record.clone());
+    Some(record)
+}
+
+// From the reader thread: follows the session through `marks`, seen at stream `offset`.
+// Returns the commands they finished.
+pub(crate) fn apply(
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    history: &Mutex<HistoryStore>,
+    session_id: &str,
+    marks: Vec<PromptMark>,
+    offset: u64,
+    events: &Arc<dyn EventSink>,
+) -> Vec<CommandRecord> {
+    let Ok(mut sessions) = sessions.lock() else { return Vec::new() };
+    let Some(session) = sessions.get_mut(session_id) else { return Vec::new() };
+    let mut done = Vec::new();
+    for mark in marks {
+        events.emit("prompt-mark", PromptMarkPayload {
+            session_id: session_id.to_string(),
+            mark: mark.name().to_string(),
+            offset,
+        });
+        let finished = match mark {
+            PromptMark::PromptStart => None,
+            PromptMark::CommandStart => {
+                session.prompt_marks.prompt_at = Some(unix_now());
+                session.prompt_marks.marks_command_lines = true;
+                session.prompt_marks.submitted = None;
+                None
+            }
+            PromptMark::OutputStart(command) => {
+                // A shell that doesn't send the command line gets the one typed at the prompt
+                let prompt_at = session.prompt_marks.prompt_at.take();
+                let command = command.or_else(|| {
+                    let input = session.recent_inputs.last()?;
+                    prompt_at.is_some_and(|at| input.sent_at >= at).then(|| input.line.clone())
+                });
+                let finished = finish(&mut session.prompt_marks, None, offset);
+                session.prompt_marks.running = Some(RunningMark {
+                    command,
+                    cwd: session.title.cwd.clone(),
+                    started_at: unix_now(),
+                    started: Instant::now(),
+                    output_start: offset,
+                    history_entry: session.prompt_marks.submitted.take(),
+                });
+                finished
+            }
+            PromptMark::CommandFinished(exit_code) => {
+                let entry = session.prompt_marks.running.as_mut().and_then(|r| r.history_entry.take());
+                if let (Some((timestamp, command)), Some(exit_code), Ok(mut history)) = (entry, exit_code, history.lock()) {
+                    history.set_exit_status(session_id, timestamp, &command, exit_code);
+                }
+                finish(&mut session.prompt_marks, exit_code, offset)
+            }
+        };
+        if let Some(command) = finished {
+            events.emit("command-finished", CommandFinishedPayload { session_id: session_id.to_string(), command: command.clone() });
+            done.push(command);
+        }
+    }
+    done
+}
+
+impl SessionManager {
+    /// The commands the session's shell marked as finished, oldest first.
+    pub fn command_log(&self, session_id: &str) -> Result<Vec<CommandRecord>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        Ok(session.prompt_marks.log.iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use portable_pty::CommandBuilder;
+    use std::thread;
+    use std::time::Duration;
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        for _ in 0..150 {
+            if done() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("timed out");
+    }
+
+    #[test]
+    fn parses_osc133() {
+        assert_eq!(parse_osc133(b"133;A"), Some(PromptMark::PromptStart));
+        assert_eq!(parse_osc133(b"133;A;cl=m;aid=12"), Some(PromptMark::PromptStart));
+        assert_eq!(parse_osc133(b"133;B"), Some(PromptMark::CommandStart));
+        assert_eq!(parse_osc133(b"133;C"), Some(PromptMark::OutputStart(None)));
+        assert_eq!(parse_osc133(b"133;C;cmdline_url=make%20test"), Some(PromptMark::OutputStart(Some("make test".into()))));
+        assert_eq!(parse_osc133(b"133;D;2"), Some(PromptMark::CommandFinished(Some(2))));
+        assert_eq!(parse_osc133(b"133;D"), Some(PromptMark::CommandFinished(None)));
+        assert_eq!(parse_osc133(b"133;P;k=i"), None);
+        assert_eq!(parse_osc133(b"13;A"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn logs_marked_commands() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.write(&id, "printf '\\033]133;C;cmdline_url=false\\007'; false; printf '\\033]133;D;%s\\007' $?\n").unwrap();
+        wait_for(|| !sink.named("command-finished").is_empty());
+        let finished = &sink.named("command-finished")[0];
+        assert_eq!((finished["command"].clone(), finished["exit_code"].clone()), ("false".into(), 1.into()));
+        let log = manager.command_log(&id).unwrap();
+        assert_eq!(log.len(), 1);
+        let history = manager.search_history(Some(&id), "printf").unwrap();
+        assert_eq!(history[0].exit_status, Some(1));
+        assert!(log[0].output_end >= log[0].output_start);
+        assert_eq!(sink.named("prompt-mark").len(), 2);
+        manager.close(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lines_typed_into_a_running_command_are_not_history() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        manager.write(&id, "printf '\\033]133;B\\007\\033]133;C\\007'\n").unwrap();
+        wait_for(|| sink.named("prompt-mark").len() == 2);
+        manager.write(&id, "true typed-ahead\r").unwrap();
+        assert!(manager.search_history(Some(&id), "typed-ahead").unwrap().is_empty());
+        assert_eq!(manager.search_history(Some(&id), "printf").unwrap().len(), 1);
+        manager.close(&id).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+//! The user's login shell: `$SHELL`, else the account record (Directory Services on macOS,
+//! the passwd entry elsewhere), else `/bin/sh`. On Windows, PowerShell if installed, else
+//! cmd. Sessions start it as a login shell, with setup only for the shells that need it.
+
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::process::Command;
+
+#[cfg(unix)]
+const FALLBACK_SHELL: &str = "/bin/sh";
+
+/// The shell new sessions run when none is configured.
+#[cfg(unix)]
+pub fn login_shell() -> PathBuf {
+    let candidates = [std::env::var_os("SHELL").map(PathBuf::from), account_shell()];
+    candidates.into_iter().flatten().find(|p| is_executable(p)).unwrap_or_else(|| FALLBACK_SHELL.into())
+}
+
+#[cfg(target_os = "macos")]
+fn account_shell() -> Option<PathBuf> {
+    let user = std::env::var("USER").ok()?;
+    let output = Command::new("dscl").args([".", "-read", &format!("/Users/{}", user), "UserShell"]).output().ok()?;
+    // "UserShell: /bin/zsh"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let shell = stdout.split_once(':')?.1.trim();
+    (output.status.success() && !shell.is_empty()).then(|| shell.into())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn account_shell() -> Option<PathBuf> {
+    let uid = unsafe { libc::getuid() }.to_string();
+    let output = Command::new("getent").args(["passwd", &uid]).output().ok()?;
+    // name:password:uid:gid:gecos:home:shell
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let shell = stdout.trim_end().rsplit(':').next()?;
+    (output.status.success() && !shell.is_empty()).then(|| shell.into())
+}
+
+#[cfg(windows)]
+pub fn login_shell() -> PathBuf {
+    // PowerShell 7 is installed separately from the built-in Windows PowerShell
+    ["pwsh.exe", "powershell.exe"].iter()
+        .find_map(|program| find_in_path(program))
+        .or_else(|| std::env::var_os("COMSPEC").map(PathBuf::from))
+        .unwrap_or_else(|| "cmd.exe".into())
+}
+
+#[cfg(windows)]
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?).map(|dir| dir.join(program)).find(|p| p.is_file())
+}
+
+/// Whether `path` is a file this user can run.
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(windows)]
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Arguments that start `shell` as an interactive login shell.
+pub fn startup_args(shell: &Path) -> Vec<String> {
+    let name = shell.file_stem().map(|n| n.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    match name.trim_start_matches('-') {
+        // Hide zsh's "%" marker for output without a trailing newline; it can only be set
+        // from inside, so the shell re-execs itself
+        "zsh" => vec![
+            "-c".into(),
+            format!("export PROMPT_EOL_MARK=''; exec '{}' -l", shell.display().to_string().replace('\'', r"'\''")),
+        ],
+        "bash" | "fish" => vec!["--login".into()],
+        "sh" | "dash" | "ksh" | "mksh" => vec!["-l".into()],
+        "pwsh" | "powershell" => vec!["-NoLogo".into()],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shell_specific_startup() {
+        assert_eq!(startup_args(Path::new("/opt/homebrew/bin/fish")), vec!["--login"]);
+        assert_eq!(startup_args(Path::new("/bin/zsh"))[1], "export PROMPT_EOL_MARK=''; exec '/bin/zsh' -l");
+        assert!(startup_args(Path::new("/usr/local/bin/nu")).is_empty());
+        assert_eq!(startup_args(Path::new("pwsh.exe")), vec!["-NoLogo"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn login_shell_is_runnable() {
+        assert!(is_executable(&login_shell()));
+        assert!(!is_executable(Path::new("/no/such/shell")));
+    }
+}
@@ -0,0 +1,286 @@
+//! Installs the hooks that make zsh, bash and fish report prompt marks (OSC 133, see
+//! `semantic`) and their working directory (OSC 7, see `cwd`). The hooks live in a
+//! script in the config dir, sourced from a marked block in the shell's startup file;
+//! fish loads its script straight from `conf.d`. Installing again only refreshes the
+//! block, and uninstalling removes exactly what was added.
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BLOCK_START: &str = "# >>> shelll shell integration >>>";
+const BLOCK_END: &str = "# <<< shelll shell integration <<<";
+
+const ZSH_SCRIPT: &str = r#"# shelll shell integration: prompt marks (OSC 133) and working directory (OSC 7)
+[[ -o interactive ]] || return
+[[ -n "$SHELLL_INTEGRATION" ]] && return
+SHELLL_INTEGRATION=1
+
+__shelll_precmd() {
+  local ret=$?
+  [[ -n "$__shelll_running" ]] && printf '\e]133;D;%s\a' "$ret"
+  __shelll_running=
+  local dir=${PWD//\%/%25}
+  printf '\e]7;file://%s%s\a' "$HOST" "${dir// /%20}"
+  printf '\e]133;A\a'
+  [[ $PS1 == *$'\e]133;B'* ]] || PS1+=$'%{\e]133;B\a%}'
+}
+
+__shelll_preexec() {
+  __shelll_running=1
+  printf '\e]133;C\a'
+}
+
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd __shelll_precmd
+add-zsh-hook preexec __shelll_preexec
+"#;
+
+const BASH_SCRIPT: &str = r#"# shelll shell integration: prompt marks (OSC 133) and working directory (OSC 7)
+[[ $- == *i* ]] || return
+[[ -n "$SHELLL_INTEGRATION" ]] && return
+SHELLL_INTEGRATION=1
+
+__shelll_prompt() {
+  [[ -n "$__shelll_running" ]] && printf '\e]133;D;%s\a' "$__shelll_status"
+  __shelll_running=
+  local dir=${PWD//\%/%25}
+  printf '\e]7;file://%s%s\a' "$HOSTNAME" "${dir// /%20}"
+  printf '\e]133;A\a'
+  [[ $PS1 == *'\e]133;B'* ]] || PS1+='\[\e]133;B\a\]'
+  __shelll_at_prompt=1
+}
+
+# Runs before every simple command; only the first one after the prompt starts a command
+__shelll_preexec() {
+  if [[ $BASH_COMMAND == '__shelll_status=$?' ]]; then
+    __shelll_at_prompt=
+    return
+  fi
+  [[ -n "$__shelll_at_prompt" && -z "$COMP_LINE" ]] || return
+  __shelll_at_prompt=
+  __shelll_running=1
+  printf '\e]133;C\a'
+}
+
+trap '__shelll_preexec' DEBUG
+PROMPT_COMMAND="__shelll_status=\$?;${PROMPT_COMMAND:+$PROMPT_COMMAND;}__shelll_prompt"
+"#;
+
+const FISH_SCRIPT: &str = r#"# shelll shell integration: prompt marks (OSC 133) and working directory (OSC 7)
+status is-interactive; or exit
+set -q SHELLL_INTEGRATION; and exit
+set -g SHELLL_INTEGRATION 1
+
+function __shelll_prompt --on-event fish_prompt
+    printf '\e]7;file://%s%s\a' (hostname) (string escape --style=url -- $PWD)
+    printf '\e]133;A\a'
+end
+
+function __shelll_preexec --on-event fish_preexec
+    set -g __shelll_running 1
+    printf '\e]133;C\a'
+end
+
+function __shelll_postexec --on-event fish_postexec
+    set -l ret $status
+    set -q __shelll_running; and printf '\e]133;D;%s\a' $ret
+    set -e __shelll_running
+end
+"#;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrationShell {
+    Zsh,
+    Bash,
+    Fish,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IntegrationStatus {
+    pub shell: IntegrationShell,
+    pub installed: bool,
+    // The hook script
+    pub script: String,
+    // The startup file sourcing it; None for fish, which loads the script itself
+    pub rc_file: Option<String>,
+}
+
+// Where things go for one shell, given the home and config directories
+struct Layout {
+    script: PathBuf,
+    rc_file: Option<PathBuf>,
+}
+
+fn layout(shell: IntegrationShell, home: &Path, config_dir: &Path) -> Layout {
+    let scripts = config_dir.join("shell-integration");
+    match shell {
+        IntegrationShell::Zsh => {
+            let zdotdir = env::var_os("ZDOTDIR").map(PathBuf::from).unwrap_or_else(|| home.to_path_buf());
+            Layout { script: scripts.join("shelll.zsh"), rc_file: Some(zdotdir.join(".zshrc")) }
+        }
+        // Sessions start login shells, which read the first of these that exists
+        IntegrationShell::Bash => {
+            let candidates = [".bash_profile", ".bash_login", ".profile"].map(|name| home.join(name));
+            let rc_file = candidates.iter().find(|p| p.exists()).unwrap_or(&candidates[0]).clone();
+            Layout { script: scripts.join("shelll.bash"), rc_file: Some(rc_file) }
+        }
+        IntegrationShell::Fish => {
+            let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).unwrap_or_else(|| home.join(".config"));
+            Layout { script: config.join("fish").join("conf.d").join("shelll.fish"), rc_file: None }
+        }
+    }
+}
+
+fn script_source(shell: IntegrationShell) -> &'static str {
+    match shell {
+        IntegrationShell::Zsh => ZSH_SCRIPT,
+        IntegrationShell::Bash => BASH_SCRIPT,
+        IntegrationShell::Fish => FISH_SCRIPT,
+    }
+}
+
+// Single-quoted for sh-like shells
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+// `text` without the marked block (and the newline after it), if it has one
+fn remove_block(text: &str) -> Option<String> {
+    let start = text.find(BLOCK_START)?;
+    let end = text[start..].find(BLOCK_END)? + start + BLOCK_END.len();
+    let end = if text[end..].starts_with('\n') { end + 1 } else { end };
+    Some(format!("{}{}", &text[..start], &text[end..]))
+}
+
+// `text` with the block sourcing `script` at its end, replacing any earlier one
+fn with_block(text: &str, script: &Path) -> String {
+    let mut text = remove_block(text).unwrap_or_else(|| text.to_string());
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    let script = quote(script);
+    text.push_str(&format!("{}\n[ -f {} ] && . {}\n{}\n", BLOCK_START, script, script, BLOCK_END));
+    text
+}
+
+fn read_rc(path: &Path) -> Result<String, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+fn write_file(path: &Path, text: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn status_in(shell: IntegrationShell, home: &Path, config_dir: &Path) -> IntegrationStatus {
+    let layout = layout(shell, home, config_dir);
+    let sourced = match &layout.rc_file {
+        Some(rc_file) => fs::read_to_string(rc_file).is_ok_and(|text| text.contains(BLOCK_START)),
+        None => true,
+    };
+    IntegrationStatus {
+        shell,
+        installed: sourced && layout.script.exists(),
+        script: layout.script.to_string_lossy().into_owned(),
+        rc_file: layout.rc_file.map(|p| p.to_string_lossy().into_owned()),
+    }
+}
+
+fn install_in(shell: IntegrationShell, home: &Path, config_dir: &Path) -> Result<IntegrationStatus, String> {
+    let layout = layout(shell, home, config_dir);
+    write_file(&layout.script, script_source(shell))?;
+    if let Some(rc_file) = &layout.rc_file {
+        let text = read_rc(rc_file)?;
+        let updated = with_block(&text, &layout.script);
+        if updated != text {
+            write_file(rc_file, &updated)?;
+        }
+    }
+    Ok(status_in(shell, home, config_dir))
+}
+
+fn uninstall_in(shell: IntegrationShell, home: &Path, config_dir: &Path) -> Result<IntegrationStatus, String> {
+    let layout = layout(shell, home, config_dir);
+    if let Some(rc_file) = &layout.rc_file {
+        if let Some(text) = remove_block(&read_rc(rc_file)?) {
+            write_file(rc_file, &text)?;
+        }
+    }
+    match fs::remove_file(&layout.script) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("Failed to remove {}: {}", layout.script.display(), e));
+        }
+        _ => {}
+    }
+    Ok(status_in(shell, home, config_dir))
+}
+
+fn home_dir() -> Result<PathBuf, String> {
+    env::var("HOME").map(PathBuf::from).map_err(|_| "HOME is not set".into())
+}
+
+/// Whether the integration for `shell` is in place, and where its files are.
+pub fn shell_integration_status(shell: IntegrationShell, config_dir: &Path) -> Result<IntegrationStatus, String> {
+    Ok(status_in(shell, &home_dir()?, config_dir))
+}
+
+/// Writes the hook script for `shell` and sources it from the shell's startup file.
+/// New sessions pick it up; running ones need `exec $SHELL -l` or a new tab.
+pub fn install_shell_integration(shell: IntegrationShell, config_dir: &Path) -> Result<IntegrationStatus, String> {
+    install_in(shell, &home_dir()?, config_dir)
+}
+
+/// Removes the startup file block and the script `install_shell_integration` added.
+pub fn uninstall_shell_integration(shell: IntegrationShell, config_dir: &Path) -> Result<IntegrationStatus, String> {
+    uninstall_in(shell, &home_dir()?, config_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_and_removes_the_block() {
+        let script = Path::new("/Users/me/it's/shelll.zsh");
+        let original = "export EDITOR=vim";
+        let installed = with_block(original, script);
+        assert_eq!(installed.matches(BLOCK_START).count(), 1);
+        assert!(installed.contains(r"[ -f '/Users/me/it'\''s/shelll.zsh' ]"));
+        assert_eq!(with_block(&installed, script), installed);
+        assert_eq!(remove_block(&installed).as_deref(), Some("export EDITOR=vim\n"));
+        assert_eq!(remove_block(original), None);
+    }
+
+    #[test]
+    fn installs_idempotently_and_uninstalls() {
+        let root = env::temp_dir().join(format!("shelll-integration-test-{}", std::process::id()));
+        let (home, config) = (root.join("home"), root.join("config"));
+        fs::create_dir_all(&home).unwrap();
+        let rc_file = home.join(".bash_profile");
+        fs::write(&rc_file, "alias ll='ls -l'\n").unwrap();
+
+        assert!(!status_in(IntegrationShell::Bash, &home, &config).installed);
+        let status = install_in(IntegrationShell::Bash, &home, &config).unwrap();
+        assert!(status.installed);
+        assert_eq!(status.rc_file.as_deref(), Some(rc_file.to_str().unwrap()));
+        let once = fs::read_to_string(&rc_file).unwrap();
+        install_in(IntegrationShell::Bash, &home, &config).unwrap();
+        assert_eq!(fs::read_to_string(&rc_file).unwrap(), once);
+        assert!(fs::read_to_string(&status.script).unwrap().contains("133;C"));
+
+        let status = uninstall_in(IntegrationShell::Bash, &home, &config).unwrap();
+        assert!(!status.installed);
+        assert_eq!(fs::read_to_string(&rc_file).unwrap(), "alias ll='ls -l'\n");
+        assert!(!Path::new(&status.script).exists());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
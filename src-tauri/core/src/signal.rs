@@ -0,0 +1,102 @@
+//! Signals for a session's foreground job, sent directly rather than as control
+//! characters: ^C only works while the program leaves the terminal in canonical mode and
+//! hasn't remapped or ignored it, which is exactly when a hung full-screen program can't
+//! be stopped any other way.
+
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SessionSignal {
+    Sigint,
+    Sigterm,
+    Sigkill,
+    Sighup,
+}
+
+impl SessionSignal {
+    #[cfg(unix)]
+    fn number(self) -> libc::c_int {
+        match self {
+            SessionSignal::Sigint => libc::SIGINT,
+            SessionSignal::Sigterm => libc::SIGTERM,
+            SessionSignal::Sigkill => libc::SIGKILL,
+            SessionSignal::Sighup => libc::SIGHUP,
+        }
+    }
+}
+
+impl SessionManager {
+    /// Sends `signal` to the process group in the terminal's foreground (the shell's own
+    /// group when nothing else is running). Returns the signaled group.
+    #[cfg(unix)]
+    pub fn send_signal(&self, session_id: &str, signal: SessionSignal) -> Result<u32, String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        // A SIGSTOPped job would only see the signal once continued
+        self.revive(session_id, session);
+        let pgid = session.master.lock().ok()
+            .and_then(|m| m.process_group_leader())
+            .or(session.pid.map(|p| p as libc::pid_t))
+            .ok_or("Session has no process")?;
+        if unsafe { libc::kill(-pgid, signal.number()) } != 0 {
+            return Err(format!("Failed to signal process group {}: {}", pgid, std::io::Error::last_os_error()));
+        }
+        Ok(pgid as u32)
+    }
+
+    // Windows has no process groups or signals to send; the closest is killing the shell
+    #[cfg(not(unix))]
+    pub fn send_signal(&self, session_id: &str, signal: SessionSignal) -> Result<u32, String> {
+        if signal != SessionSignal::Sigkill {
+            return Err("Only SIGKILL is supported on this platform".into());
+        }
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(session_id).ok_or("Session not found")?;
+        let pid = session.pid.ok_or("Session has no process")?;
+        let child = session.child.as_mut().ok_or("Session has no process")?;
+        child.kill().map_err(|e| format!("Failed to kill {}: {}", pid, e))?;
+        Ok(pid)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use portable_pty::CommandBuilder;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn interrupts_the_foreground_job_but_not_the_shell() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        // Ignores the terminal's ^C, like a program in raw mode
+        manager.write(&id, "stty -isig; sleep 30\r").unwrap();
+        thread::sleep(Duration::from_millis(300));
+
+        let shell = manager.sessions.lock().unwrap()[&id].pid.unwrap();
+        let pgid = manager.send_signal(&id, SessionSignal::Sigint).unwrap();
+        assert_ne!(pgid, shell);
+        // Typed ahead; only runs once sleep is gone
+        manager.write(&id, "echo still-$((40+2))\r").unwrap();
+        let mut output = String::new();
+        for _ in 0..100 {
+            output = sink.named("pty-output").iter()
+                .flat_map(|p| serde_json::from_value::<Vec<u8>>(p["data"].clone()).unwrap())
+                .map(char::from)
+                .collect();
+            if output.contains("still-42") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(output.contains("still-42"), "{}", output);
+        manager.close(&id).unwrap();
+    }
+}
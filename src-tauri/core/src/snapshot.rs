@@ -0,0 +1,175 @@
+//! Session snapshots: a session's working directory, environment and scrollback at a
+//! point in time, kept in memory. Forking a snapshot starts a new session in that
+//! directory with that environment, its buffer starting with the old scrollback (drawn by
+//! `get_session_buffer`) and a mark where the new session's own output begins. Branches
+//! an investigation without touching the original session.
+
+use crate::env::EnvMap;
+use crate::process;
+use crate::profiles::Profile;
+use crate::pty::{OutputMark, SessionManager, SessionOptions, SESSION_ID_VAR};
+use crate::unix_now;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use uuid::Uuid;
+
+// Oldest snapshots are dropped beyond this; each holds up to 2MB of scrollback
+const MAX_SNAPSHOTS: usize = 20;
+// Belong to the original session, or are set by the shell per command
+const SKIPPED_VARS: &[&str] = &[SESSION_ID_VAR, "_", "TMPDIR", "SHLVL"];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionSnapshot {
+    pub id: String,
+    pub session_id: String,
+    pub created_at: u64,
+    pub shell: String,
+    pub cwd: Option<String>,
+    pub env: EnvMap,
+    // False if the shell was busy and the environment it started with was used instead
+    pub env_captured: bool,
+    pub scrollback_bytes: usize,
+}
+
+pub(crate) struct StoredSnapshot {
+    snapshot: SessionSnapshot,
+    scrollback: Vec<u8>,
+}
+
+pub(crate) type Snapshots = HashMap<String, StoredSnapshot>;
+
+impl SessionManager {
+    /// Records the session's current directory, environment and scrollback. The current
+    /// environment can only be read while the shell is at its prompt; otherwise the one it
+    /// started with is recorded.
+    pub fn snapshot_session(&self, session_id: &str) -> Result<SessionSnapshot, String> {
+        let (pid, shell, title_cwd, initial_env, scrollback) = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            let scrollback = session.output_log.lock().map_err(|_| "Lock poisoned")?.contents();
+            (session.pid, session.shell.clone(), session.title.cwd.clone(), session.initial_env.clone(), scrollback)
+        };
+        let cwd = pid.and_then(process::process_cwd).or(title_cwd);
+        let (env, env_captured) = match self.capture_session_env(session_id) {
+            Ok(env) => (env, true),
+            Err(_) => (initial_env, false),
+        };
+        let snapshot = SessionSnapshot {
+            id: Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            created_at: unix_now(),
+            shell,
+            cwd,
+            env,
+            env_captured,
+            scrollback_bytes: scrollback.len(),
+        };
+
+        let mut snapshots = self.snapshots.lock().map_err(|_| "Lock poisoned")?;
+        while snapshots.len() >= MAX_SNAPSHOTS {
+            let Some(oldest) = snapshots.values().min_by_key(|s| s.snapshot.created_at).map(|s| s.snapshot.id.clone()) else { break };
+            snapshots.remove(&oldest);
+        }
+        snapshots.insert(snapshot.id.clone(), StoredSnapshot { snapshot: snapshot.clone(), scrollback });
+        Ok(snapshot)
+    }
+
+    pub fn list_snapshots(&self) -> Result<Vec<SessionSnapshot>, String> {
+        let snapshots = self.snapshots.lock().map_err(|_| "Lock poisoned")?;
+        let mut list: Vec<SessionSnapshot> = snapshots.values().map(|s| s.snapshot.clone()).collect();
+        list.sort_by_key(|s| s.created_at);
+        Ok(list)
+    }
+
+    pub fn delete_snapshot(&self, snapshot_id: &str) -> Result<(), String> {
+        let mut snapshots = self.snapshots.lock().map_err(|_| "Lock poisoned")?;
+        snapshots.remove(snapshot_id).map(|_| ()).ok_or_else(|| "Snapshot not found".to_string())
+    }
+
+    /// Starts a session restored to the snapshot's directory and environment, with the
+    /// snapshot's scrollback preloaded. Returns the new session's id.
+    pub fn fork_from_snapshot(&self, snapshot_id: &str, profile: &Profile) -> Result<String, String> {
+        let (options, scrollback) = {
+            let snapshots = self.snapshots.lock().map_err(|_| "Lock poisoned")?;
+            let stored = snapshots.get(snapshot_id).ok_or("Snapshot not found")?;
+            let snapshot = &stored.snapshot;
+            let options = SessionOptions {
+                shell: Some(snapshot.shell.clone()),
+                // The directory may be gone since
+                cwd: snapshot.cwd.as_ref().map(PathBuf::from).filter(|d| d.is_dir()),
+                env: snapshot.env.iter()
+                    .filter(|(name, _)| !SKIPPED_VARS.contains(&name.as_str()))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+                ..Default::default()
+            };
+            (options, stored.scrollback.clone())
+        };
+        let session_id = self.create_session(profile, &options)?;
+
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get_mut(&session_id).ok_or("Session not found")?;
+        let mut log = session.output_log.lock().map_err(|_| "Lock poisoned")?;
+        log.prepend(&scrollback);
+        session.output_offset.fetch_add(scrollback.len() as u64, Ordering::SeqCst);
+        drop(log);
+        session.marks.push(OutputMark {
+            id: Uuid::new_v4().to_string(),
+            label: "Forked from snapshot".to_string(),
+            offset: scrollback.len() as u64,
+            created_at: unix_now(),
+        });
+        Ok(session_id)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        for _ in 0..150 {
+            if done() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("timed out");
+    }
+
+    #[test]
+    fn forks_restore_directory_environment_and_scrollback() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let dir = std::env::temp_dir().join(format!("shelll-snapshot-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = SessionOptions { shell: Some("sh".into()), ..Default::default() };
+        let original = manager.create_session(&Profile::default(), &options).unwrap();
+        manager.write(&original, &format!("cd '{}'; export PROBE=branch; echo before-$((20+1))\n", dir.display())).unwrap();
+        let buffer = |id: &str| String::from_utf8_lossy(&manager.sessions.lock().unwrap()[id].output_log.lock().unwrap().contents()).into_owned();
+        wait_for(|| buffer(&original).contains("before-21"));
+
+        let snapshot = manager.snapshot_session(&original).unwrap();
+        assert!(snapshot.env_captured);
+        assert_eq!(snapshot.env.get("PROBE").map(String::as_str), Some("branch"));
+        assert_eq!(snapshot.cwd.as_deref(), Some(dir.to_str().unwrap()));
+
+        let fork = manager.fork_from_snapshot(&snapshot.id, &Profile::default()).unwrap();
+        assert!(buffer(&fork).contains("before-21"));
+        assert_eq!(manager.list_marks(&fork).unwrap()[0].offset, snapshot.scrollback_bytes as u64);
+        manager.write(&fork, "echo \"$PROBE in $(pwd)\"\n").unwrap();
+        wait_for(|| buffer(&fork).contains(&format!("branch in {}", dir.display())));
+
+        manager.delete_snapshot(&snapshot.id).unwrap();
+        assert!(manager.list_snapshots().unwrap().is_empty());
+        manager.close(&original).unwrap();
+        manager.close(&fork).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
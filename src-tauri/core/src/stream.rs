@@ -0,0 +1,237 @@
+//! Output tagged with the stream it came from, so the frontend can color stderr
+//! differently. Commands run without a terminal (`run_command`) have separate pipes
+//! anyway. Terminal sessions normally can't tell the two apart (both are the PTY), but a
+//! session started with `split_stderr` has the shell's stderr (inherited by everything
+//! it runs) redirected to a pipe. Programs that check whether stderr is a terminal will
+//! see that it isn't, and shells that draw their prompt on stderr (bash) get it tagged
+//! as stderr; zsh and fish draw on the terminal directly.
+
+#[cfg(unix)]
+use crate::batch::OutputBatcher;
+use crate::env::EnvMap;
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    // Everything written to the terminal, for sessions that don't split stderr
+    Stdout,
+    Stderr,
+}
+
+/// The read end of a session's stderr, a named pipe the shell opens on startup.
+#[cfg(unix)]
+pub(crate) struct StderrPipe {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl StderrPipe {
+    /// Creates the pipe and wraps `cmd` so it starts with stderr redirected into it.
+    pub(crate) fn create(cmd: &mut portable_pty::CommandBuilder) -> Result<StderrPipe, String> {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::env::temp_dir().join(format!("shelll-stderr-{}", Uuid::new_v4()));
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|_| "Invalid temp path")?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+            return Err(format!("Failed to create stderr pipe: {}", std::io::Error::last_os_error()));
+        }
+        let argv = cmd.get_argv_mut();
+        let program = std::mem::take(argv);
+        argv.extend(["/bin/sh".into(), "-c".into(), "exec 2>\"$0\"; exec \"$@\"".into(), path.clone().into_os_string()]);
+        argv.extend(program);
+        Ok(StderrPipe { path })
+    }
+
+    /// Forwards what the session writes to stderr. Call once the shell has been spawned;
+    /// it blocks opening the pipe until this end is open.
+    pub(crate) fn attach(self, batcher: Arc<OutputBatcher>) {
+        thread::spawn(move || {
+            let file = std::fs::File::open(&self.path);
+            // Both ends are open; nothing else needs the name
+            let _ = std::fs::remove_file(&self.path);
+            let Ok(mut file) = file else { return };
+            let mut buf = [0u8; 4096];
+            while let Ok(n @ 1..) = file.read(&mut buf) {
+                batcher.push(OutputStream::Stderr, &buf[..n]);
+            }
+        });
+    }
+
+    // The shell never started
+    pub(crate) fn discard(self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A command run without a terminal.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RunOptions {
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    #[serde(default)]
+    pub env: EnvMap,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CommandOutputPayload {
+    pub run_id: String,
+    pub stream: OutputStream,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CommandExitedPayload {
+    pub run_id: String,
+    // None if it was killed by a signal
+    pub exit_code: Option<i32>,
+}
+
+// Commands started with `run_command` that haven't exited yet
+pub(crate) type Runs = Arc<Mutex<HashMap<String, Child>>>;
+
+impl SessionManager {
+    /// Starts `options.program`, emitting its stdout and stderr as tagged
+    /// `command-output` events and then `command-exited`. Returns the run id.
+    pub fn run_command(&self, options: &RunOptions) -> Result<String, String> {
+        let mut command = Command::new(&options.program);
+        command.args(&options.args)
+            .envs(&options.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(cwd) = &options.cwd {
+            command.current_dir(cwd);
+        }
+        let mut child = command.spawn().map_err(|e| format!("Failed to run {}: {}", options.program, e))?;
+        let run_id = Uuid::new_v4().to_string();
+
+        let readers: Vec<_> = [
+            child.stdout.take().map(|r| (OutputStream::Stdout, Box::new(r) as Box<dyn Read + Send>)),
+            child.stderr.take().map(|r| (OutputStream::Stderr, Box::new(r) as Box<dyn Read + Send>)),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|(stream, mut reader)| {
+            let events = self.events.clone();
+            let run_id = run_id.clone();
+            thread::spawn(move || {
+                let mut buf = vec![0u8; 64 * 1024];
+                while let Ok(n @ 1..) = reader.read(&mut buf) {
+                    events.emit("command-output", CommandOutputPayload { run_id: run_id.clone(), stream, data: buf[..n].to_vec() });
+                }
+            })
+        })
+        .collect();
+        self.runs.lock().map_err(|_| "Lock poisoned")?.insert(run_id.clone(), child);
+
+        let runs = self.runs.clone();
+        let events = self.events.clone();
+        let id = run_id.clone();
+        thread::spawn(move || {
+            // All output is out before the exit is reported
+            for reader in readers {
+                let _ = reader.join();
+            }
+            let status = loop {
+                {
+                    let Ok(mut runs) = runs.lock() else { return };
+                    let Some(child) = runs.get_mut(&id) else { return };
+                    match child.try_wait() {
+                        Ok(Some(status)) => {
+                            runs.remove(&id);
+                            break Some(status);
+                        }
+                        Ok(None) => {}
+                        Err(_) => {
+                            runs.remove(&id);
+                            break None;
+                        }
+                    }
+                }
+                thread::sleep(Duration::from_millis(20));
+            };
+            events.emit("command-exited", CommandExitedPayload { run_id: id, exit_code: status.and_then(|s| s.code()) });
+        });
+        Ok(run_id)
+    }
+
+    pub fn cancel_command(&self, run_id: &str) -> Result<(), String> {
+        let mut runs = self.runs.lock().map_err(|_| "Lock poisoned")?;
+        let child = runs.get_mut(run_id).ok_or("Command not running")?;
+        child.kill().map_err(|e| format!("Failed to kill: {}", e))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use crate::profiles::Profile;
+    use crate::pty::SessionOptions;
+
+    fn output(sink: &RecordingSink, event: &str, stream: &str) -> String {
+        let bytes: Vec<u8> = sink.named(event).iter()
+            .filter(|p| p["stream"] == stream)
+            .flat_map(|p| serde_json::from_value::<Vec<u8>>(p["data"].clone()).unwrap())
+            .collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        for _ in 0..150 {
+            if done() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("timed out");
+    }
+
+    #[test]
+    fn run_command_tags_each_stream() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let options = RunOptions {
+            program: "sh".into(),
+            args: vec!["-c".into(), "echo built; echo 'warning: slow' >&2; exit 3".into()],
+            ..Default::default()
+        };
+        let run_id = manager.run_command(&options).unwrap();
+        wait_for(|| !sink.named("command-exited").is_empty());
+        assert_eq!(output(&sink, "command-output", "stdout"), "built\n");
+        assert_eq!(output(&sink, "command-output", "stderr"), "warning: slow\n");
+        let exited = &sink.named("command-exited")[0];
+        assert_eq!((exited["run_id"].as_str(), exited["exit_code"].as_i64()), (Some(run_id.as_str()), Some(3)));
+        assert!(manager.cancel_command(&run_id).is_err());
+    }
+
+    #[test]
+    fn split_stderr_sessions_tag_stderr() {
+        let sink = Arc::new(RecordingSink::default());
+        let manager = SessionManager::new(sink.clone(), HistoryStore::load(None));
+        let options = SessionOptions {
+            shell: Some("sh".into()),
+            args: Some(vec!["-c".into(), "sleep 0.2; echo to-out; echo to-err >&2; sleep 5".into()]),
+            split_stderr: Some(true),
+            private_tmp: Some(false),
+            ..Default::default()
+        };
+        let id = manager.create_session(&Profile::default(), &options).unwrap();
+        wait_for(|| output(&sink, "pty-output", "stderr").contains("to-err"));
+        assert!(output(&sink, "pty-output", "stdout").contains("to-out"));
+        assert!(!output(&sink, "pty-output", "stdout").contains("to-err"));
+        manager.close(&id).unwrap();
+    }
+}
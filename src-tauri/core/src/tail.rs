@@ -0,0 +1,189 @@
+//! Tail sessions: a file streamed into a PTY with no process behind it, so it goes
+//! through the normal output pipeline (find, triggers, marks, scrollback) without a shell
+//! running `tail -f`. Following polls the file's size, and reopens it when it is rotated
+//! (replaced or truncated). Unix only: the slave end is opened by path.
+#![cfg_attr(not(unix), allow(unused_imports, dead_code))]
+
+use crate::env::EnvMap;
+use crate::pty::{set_input_lock, PtySession, SessionManager, DEFAULT_SIZE};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// Following starts this far from the end, like `tail -f` starting at the last lines
+const FOLLOW_BACKLOG: u64 = 64 * 1024;
+
+#[cfg(unix)]
+fn file_id(file: &File) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    file.metadata().ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(unix)]
+fn path_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+// The slave end of the PTY, opened by name, with echo and line editing off so keystrokes
+// and query replies from the frontend don't show up in the stream
+#[cfg(unix)]
+fn open_slave(master: &dyn portable_pty::MasterPty) -> Result<File, String> {
+    use std::ffi::CStr;
+    use std::os::unix::io::AsRawFd;
+
+    let fd = master.as_raw_fd().ok_or("PTY has no file descriptor")?;
+    let name = unsafe {
+        let name = libc::ptsname(fd);
+        if name.is_null() {
+            return Err("Failed to get PTY name".into());
+        }
+        CStr::from_ptr(name).to_string_lossy().into_owned()
+    };
+    let slave = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&name)
+        .map_err(|e| format!("Failed to open {}: {}", name, e))?;
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(slave.as_raw_fd(), &mut termios) == 0 {
+            termios.c_lflag &= !(libc::ECHO | libc::ICANON);
+            libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios);
+        }
+    }
+    Ok(slave)
+}
+
+// Copies what's new in the file to the PTY until the session is closed (or once, without
+// `follow`). The PTY turns LF into CRLF.
+#[cfg(unix)]
+fn stream(path: &Path, mut out: File, follow: bool, session_id: &str, sessions: &Mutex<HashMap<String, PtySession>>) {
+    let mut file: Option<File> = None;
+    let mut position = 0u64;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        if !sessions.lock().map(|s| s.contains_key(session_id)).unwrap_or(false) {
+            return;
+        }
+        // (Re)open on first run, after rotation, or once a missing file appears
+        let rotated = match &file {
+            Some(f) => path_id(path).is_some_and(|id| file_id(f) != Some(id)),
+            None => true,
+        };
+        if rotated {
+            if let Ok(f) = File::open(path) {
+                let reopened = file.is_some();
+                let len = f.metadata().map(|m| m.len()).unwrap_or(0);
+                position = if follow && !reopened { len.saturating_sub(FOLLOW_BACKLOG) } else { 0 };
+                if reopened && out.write_all(b"\n--- file rotated ---\n").is_err() {
+                    return;
+                }
+                file = Some(f);
+            }
+        }
+        if let Some(f) = file.as_mut() {
+            let len = f.metadata().map(|m| m.len()).unwrap_or(0);
+            // Truncated in place (copytruncate)
+            if len < position {
+                position = 0;
+            }
+            if len > position && f.seek(SeekFrom::Start(position)).is_ok() {
+                while let Ok(n) = f.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                    if out.write_all(&buf[..n]).is_err() {
+                        return;
+                    }
+                    position += n as u64;
+                }
+            }
+        }
+        if !follow {
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+impl SessionManager {
+    /// Starts a session showing `path`; with `follow`, new lines are streamed as the file
+    /// grows. The session's input is locked since nothing reads it.
+    #[cfg(unix)]
+    pub fn tail_file(&self, path: &Path, follow: bool) -> Result<String, String> {
+        if !path.is_file() {
+            return Err(format!("Not a file: {}", path.display()));
+        }
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut slave = None;
+        let cwd = path.parent().map(PathBuf::from);
+        let session_id = self.open_session(Uuid::new_v4().to_string(), &name, cwd, EnvMap::new(), DEFAULT_SIZE, |pair| {
+            slave = Some(open_slave(pair.master.as_ref())?);
+            Ok(None)
+        })?;
+        let slave = slave.ok_or("Failed to open PTY")?;
+        {
+            let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get_mut(&session_id).ok_or("Session not found")?;
+            set_input_lock(&self.events, &session_id, session, true);
+        }
+
+        let path = path.to_path_buf();
+        let sessions = self.sessions.clone();
+        let id = session_id.clone();
+        thread::spawn(move || {
+            stream(&path, slave, follow, &id, &sessions);
+        });
+        Ok(session_id)
+    }
+
+    #[cfg(not(unix))]
+    pub fn tail_file(&self, _path: &Path, _follow: bool) -> Result<String, String> {
+        Err("Tailing files is not supported on this platform".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use std::fs;
+    use std::sync::Arc;
+
+    fn output(manager: &SessionManager, id: &str) -> String {
+        let chunk = manager.read_output_since(id, 0, 64 * 1024).unwrap();
+        String::from_utf8_lossy(&chunk.data).into_owned()
+    }
+
+    #[test]
+    fn follows_growth_and_rotation() {
+        let dir = std::env::temp_dir().join(format!("shelll-tail-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        fs::write(&log, "first\n").unwrap();
+
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.tail_file(&log, true).unwrap();
+        assert!(manager.write(&id, "typed").is_err());
+        thread::sleep(Duration::from_millis(400));
+        fs::OpenOptions::new().append(true).open(&log).unwrap().write_all(b"second\n").unwrap();
+        thread::sleep(Duration::from_millis(600));
+        assert_eq!(output(&manager, &id), "first\r\nsecond\r\n");
+
+        fs::rename(&log, dir.join("app.log.1")).unwrap();
+        fs::write(&log, "fresh\n").unwrap();
+        thread::sleep(Duration::from_millis(600));
+        assert!(output(&manager, &id).ends_with("--- file rotated ---\r\nfresh\r\n"));
+
+        manager.close(&id).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
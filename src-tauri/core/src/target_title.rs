@@ -0,0 +1,227 @@
+//! The title of a target app's focused window, read through Accessibility: which file
+//! Xcode has open, which page a browser is on. Watched apps are polled and each change is
+//! reported with `target-window-title-changed`, so the frontend can show the context or
+//! cd to the matching project.
+
+use crate::events::EventSink;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Also the `target-window-title-changed` payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TargetWindowTitle {
+    pub bundle_id: String,
+    // None while the app isn't running or has no focused window
+    pub title: Option<String>,
+}
+
+/// The title of the focused window of the app with `bundle_id`. Requires the
+/// Accessibility permission.
+pub fn focused_window_title(bundle_id: &str) -> Result<Option<String>, String> {
+    if !crate::keyboard::accessibility_trusted() {
+        return Err("Reading window titles needs the Accessibility permission".into());
+    }
+    #[cfg(target_os = "macos")]
+    return Ok(crate::focus::running_app_pid(bundle_id).and_then(mac::focused_window_title));
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = bundle_id;
+        Err("Reading window titles is only supported on macOS".into())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod mac {
+    use std::ffi::{c_char, c_void, CStr};
+    use std::ptr;
+
+    type CFStringRef = *const c_void;
+
+    const UTF8: u32 = 0x0800_0100;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: *const c_void);
+        fn CFGetTypeID(cf: *const c_void) -> usize;
+        fn CFStringGetTypeID() -> usize;
+        fn CFStringCreateWithCString(allocator: *const c_void, s: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFStringGetLength(s: CFStringRef) -> isize;
+        fn CFStringGetMaximumSizeForEncoding(length: isize, encoding: u32) -> isize;
+        fn CFStringGetCString(s: CFStringRef, buffer: *mut c_char, size: isize, encoding: u32) -> u8;
+    }
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXUIElementCreateApplication(pid: i32) -> *const c_void;
+        fn AXUIElementCopyAttributeValue(element: *const c_void, attribute: CFStringRef, value: *mut *const c_void) -> i32;
+    }
+
+    // An attribute's value, owned by the caller
+    unsafe fn copy_attribute(element: *const c_void, name: &CStr) -> Option<*const c_void> {
+        let attr = CFStringCreateWithCString(ptr::null(), name.as_ptr(), UTF8);
+        let mut value = ptr::null();
+        let status = AXUIElementCopyAttributeValue(element, attr, &mut value);
+        CFRelease(attr);
+        (status == 0 && !value.is_null()).then_some(value)
+    }
+
+    unsafe fn cf_string(s: CFStringRef) -> Option<String> {
+        if CFGetTypeID(s) != CFStringGetTypeID() {
+            return None;
+        }
+        let size = CFStringGetMaximumSizeForEncoding(CFStringGetLength(s), UTF8) + 1;
+        let mut buffer = vec![0 as c_char; size as usize];
+        (CFStringGetCString(s, buffer.as_mut_ptr(), size, UTF8) != 0)
+            .then(|| CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+    }
+
+    pub fn focused_window_title(pid: i32) -> Option<String> {
+        unsafe {
+            let app = AXUIElementCreateApplication(pid);
+            if app.is_null() {
+                return None;
+            }
+            let window = copy_attribute(app, c"AXFocusedWindow");
+            CFRelease(app);
+            let window = window?;
+            let title = copy_attribute(window, c"AXTitle");
+            CFRelease(window);
+            let title = title?;
+            let text = cf_string(title);
+            CFRelease(title);
+            text
+        }
+    }
+}
+
+/// Told about every title change of a watched app, e.g. to follow its project.
+pub type TitleListener = Arc<dyn Fn(&TargetWindowTitle) + Send + Sync>;
+
+/// Polls the watched apps' focused window titles while there are any.
+#[derive(Clone)]
+pub struct TargetTitleMonitor {
+    // Watched bundle ids and the title last reported for each
+    watched: Arc<Mutex<HashMap<String, Option<String>>>>,
+    polling: Arc<Mutex<bool>>,
+    listener: Arc<Mutex<Option<TitleListener>>>,
+    events: Arc<dyn EventSink>,
+}
+
+impl TargetTitleMonitor {
+    pub fn new(events: Arc<dyn EventSink>) -> Self {
+        TargetTitleMonitor { watched: Arc::default(), polling: Arc::default(), listener: Arc::default(), events }
+    }
+
+    pub fn set_listener(&self, listener: Option<TitleListener>) -> Result<(), String> {
+        *self.listener.lock().map_err(|_| "Lock poisoned")? = listener;
+        Ok(())
+    }
+
+    /// Starts reporting the title of `bundle_id`'s focused window; returns the current one.
+    pub fn watch(&self, bundle_id: &str) -> Result<Option<String>, String> {
+        let title = focused_window_title(bundle_id)?;
+        self.watched.lock().map_err(|_| "Lock poisoned")?.insert(bundle_id.to_string(), title.clone());
+        let mut polling = self.polling.lock().map_err(|_| "Lock poisoned")?;
+        if !*polling {
+            *polling = true;
+            let monitor = self.clone();
+            thread::spawn(move || monitor.poll());
+        }
+        Ok(title)
+    }
+
+    pub fn unwatch(&self, bundle_id: &str) -> Result<(), String> {
+        self.watched.lock().map_err(|_| "Lock poisoned")?
+            .remove(bundle_id)
+            .map(|_| ())
+            .ok_or_else(|| format!("{} is not being watched", bundle_id))
+    }
+
+    pub fn watched(&self) -> Vec<TargetWindowTitle> {
+        let Ok(watched) = self.watched.lock() else { return Vec::new() };
+        let mut titles: Vec<TargetWindowTitle> = watched.iter()
+            .map(|(bundle_id, title)| TargetWindowTitle { bundle_id: bundle_id.clone(), title: title.clone() })
+            .collect();
+        titles.sort_by(|a, b| a.bundle_id.cmp(&b.bundle_id));
+        titles
+    }
+
+    /// Records `bundle_id`'s current title, and reports it if it changed. Ignored for
+    /// apps that aren't watched.
+    pub fn update(&self, bundle_id: &str, title: Option<String>) -> Result<bool, String> {
+        let mut watched = self.watched.lock().map_err(|_| "Lock poisoned")?;
+        let Some(last) = watched.get_mut(bundle_id) else { return Ok(false) };
+        if *last == title {
+            return Ok(false);
+        }
+        *last = title.clone();
+        drop(watched);
+        let changed = TargetWindowTitle { bundle_id: bundle_id.to_string(), title };
+        let listener = self.listener.lock().map_err(|_| "Lock poisoned")?.clone();
+        if let Some(listener) = listener {
+            listener(&changed);
+        }
+        self.events.emit("target-window-title-changed", changed);
+        Ok(true)
+    }
+
+    // Runs until nothing is watched
+    fn poll(&self) {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let bundle_ids: Vec<String> = {
+                let Ok(watched) = self.watched.lock() else { return };
+                let Ok(mut polling) = self.polling.lock() else { return };
+                if watched.is_empty() {
+                    *polling = false;
+                    return;
+                }
+                watched.keys().cloned().collect()
+            };
+            for bundle_id in bundle_ids {
+                // Losing the permission reads as no title
+                let title = focused_window_title(&bundle_id).unwrap_or(None);
+                let _ = self.update(&bundle_id, title);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+
+    #[test]
+    fn reports_title_changes_of_watched_apps() {
+        let sink = Arc::new(RecordingSink::default());
+        let monitor = TargetTitleMonitor::new(sink.clone());
+        monitor.watched.lock().unwrap().insert("com.apple.dt.Xcode".into(), None);
+
+        let title = |t: &str| Some(t.to_string());
+        assert!(monitor.update("com.apple.dt.Xcode", title("shelll — main.rs")).unwrap());
+        assert!(!monitor.update("com.apple.dt.Xcode", title("shelll — main.rs")).unwrap());
+        assert!(monitor.update("com.apple.dt.Xcode", title("shelll — lib.rs")).unwrap());
+        assert!(!monitor.update("com.apple.Safari", title("Docs")).unwrap());
+
+        let heard = Arc::new(Mutex::new(Vec::new()));
+        let listener_heard = heard.clone();
+        monitor.set_listener(Some(Arc::new(move |t: &TargetWindowTitle| listener_heard.lock().unwrap().push(t.title.clone())))).unwrap();
+        assert!(monitor.update("com.apple.dt.Xcode", title("shelll — pty.rs")).unwrap());
+
+        let events = sink.named("target-window-title-changed");
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2]["title"], "shelll — pty.rs");
+        assert_eq!(*heard.lock().unwrap(), vec![title("shelll — pty.rs")]);
+        assert_eq!(monitor.watched()[0].title, title("shelll — pty.rs"));
+
+        monitor.unwatch("com.apple.dt.Xcode").unwrap();
+        assert!(monitor.unwatch("com.apple.dt.Xcode").is_err());
+        assert!(!monitor.update("com.apple.dt.Xcode", None).unwrap());
+    }
+}
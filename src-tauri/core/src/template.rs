@@ -0,0 +1,270 @@
+//! The template language shared by tab titles, notifications and webhooks, so every
+//! surface formats a session the same way. `{name}` expands a variable and `{name|text}`
+//! falls back to `text` when the variable has no value. Variables: process, cwd,
+//! cwd_short, title, branch, command, exit_code, duration, hostname (this machine), remote
+//! (e.g. "deploy@prod" in an ssh session) and host (the remote host, else this machine).
+
+use crate::config::{load_json, save_json};
+use crate::pty::SessionManager;
+use crate::remote::RemoteContext;
+use crate::title::{shorten_cwd, TitleInputs, DEFAULT_TITLE_TEMPLATE};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Expands `template` with `lookup`. The flag is false if a variable without a fallback
+/// had no value (it expands to nothing).
+pub fn render(template: &str, lookup: impl Fn(&str) -> Option<String>) -> (String, bool) {
+    let mut out = String::new();
+    let mut rest = template;
+    let mut complete = true;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let (name, fallback) = match rest[start + 1..start + len].split_once('|') {
+            Some((name, fallback)) => (name, Some(fallback)),
+            None => (&rest[start + 1..start + len], None),
+        };
+        match lookup(name.trim()).filter(|v| !v.is_empty()) {
+            Some(v) => out.push_str(&v),
+            None => match fallback {
+                Some(fallback) => out.push_str(fallback),
+                None => complete = false,
+            },
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    (out, complete)
+}
+
+/// What a session's templates can refer to. The branch and hostname are looked up only
+/// when a template uses them.
+#[derive(Clone, Debug, Default)]
+pub struct TemplateContext {
+    pub process: Option<String>,
+    pub cwd: Option<String>,
+    // Set by the program through escape sequences
+    pub title: Option<String>,
+    // The command line that finished, when it's known
+    pub command: Option<String>,
+    pub exit_code: Option<i32>,
+    pub duration: Option<Duration>,
+    pub remote: Option<RemoteContext>,
+}
+
+impl From<&TitleInputs> for TemplateContext {
+    fn from(inputs: &TitleInputs) -> Self {
+        TemplateContext {
+            process: inputs.process.clone(),
+            cwd: inputs.cwd.clone(),
+            title: inputs.osc_title.clone(),
+            remote: inputs.remote.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl TemplateContext {
+    pub fn value(&self, name: &str) -> Option<String> {
+        match name {
+            "process" => self.process.clone(),
+            "cwd" => self.cwd.clone(),
+            "cwd_short" => self.cwd.as_deref().map(shorten_cwd),
+            "title" => self.title.clone(),
+            "branch" => self.cwd.as_deref().and_then(|cwd| git_branch(Path::new(cwd))),
+            "command" => self.command.clone(),
+            "exit_code" => self.exit_code.map(|c| c.to_string()),
+            "duration" => self.duration.map(format_duration),
+            "hostname" => hostname(),
+            "remote" => self.remote.as_ref().map(RemoteContext::label),
+            "host" => self.remote.as_ref().map(|r| r.host.clone()).or_else(hostname),
+            _ => None,
+        }
+    }
+
+    pub fn render(&self, template: &str) -> (String, bool) {
+        render(template, |name| self.value(name))
+    }
+}
+
+/// "850ms", "12s", "3m 4s", "1h 2m".
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0 => format!("{}ms", duration.as_millis()),
+        1..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// The branch checked out in the repository containing `dir`, or the short commit hash
+/// if HEAD is detached. Reads `.git` directly rather than running git on every refresh.
+pub fn git_branch(dir: &Path) -> Option<String> {
+    let dot_git = dir.ancestors().map(|d| d.join(".git")).find(|p| p.exists())?;
+    // Worktrees and submodules have a file pointing at the real git dir
+    let git_dir = if dot_git.is_file() {
+        let link = fs::read_to_string(&dot_git).ok()?;
+        let target = PathBuf::from(link.strip_prefix("gitdir:")?.trim());
+        if target.is_absolute() { target } else { dot_git.parent()?.join(target) }
+    } else {
+        dot_git
+    };
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    match head.trim().strip_prefix("ref: ") {
+        Some(reference) => Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string()),
+        None => head.get(..7).map(String::from),
+    }
+}
+
+/// This machine's name, without the domain.
+#[cfg(unix)]
+pub fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let name = String::from_utf8_lossy(&buf[..len]);
+    name.split('.').next().filter(|n| !n.is_empty()).map(String::from)
+}
+
+#[cfg(not(unix))]
+pub fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok().filter(|n| !n.is_empty())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateKind {
+    TabTitle,
+    NotificationTitle,
+    NotificationBody,
+    Webhook,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateSettings {
+    pub tab_title: String,
+    pub notification_title: String,
+    pub notification_body: String,
+    // Text posted to webhooks
+    pub webhook: String,
+}
+
+impl Default for TemplateSettings {
+    fn default() -> Self {
+        TemplateSettings {
+            tab_title: DEFAULT_TITLE_TEMPLATE.to_string(),
+            notification_title: "{process} finished".to_string(),
+            notification_body: "Exited with {exit_code|?} after {duration|a moment} in {cwd_short|?}".to_string(),
+            webhook: "{hostname}: {process} exited with {exit_code|?} after {duration|?} in {cwd}".to_string(),
+        }
+    }
+}
+
+impl TemplateSettings {
+    pub fn get(&self, kind: TemplateKind) -> &str {
+        match kind {
+            TemplateKind::TabTitle => &self.tab_title,
+            TemplateKind::NotificationTitle => &self.notification_title,
+            TemplateKind::NotificationBody => &self.notification_body,
+            TemplateKind::Webhook => &self.webhook,
+        }
+    }
+}
+
+// Templates persisted as JSON in the config dir
+pub struct TemplateStore {
+    path: Option<PathBuf>,
+    pub settings: TemplateSettings,
+}
+
+impl TemplateStore {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let settings = load_json(path.as_deref());
+        TemplateStore { path, settings }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("No config directory available")?;
+        save_json(path, &self.settings)
+    }
+}
+
+impl SessionManager {
+    pub fn templates(&self) -> Result<TemplateSettings, String> {
+        Ok(self.templates.lock().map_err(|_| "Lock poisoned")?.clone())
+    }
+
+    pub fn set_templates(&self, settings: TemplateSettings) -> Result<(), String> {
+        let mut sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let mut templates = self.templates.lock().map_err(|_| "Lock poisoned")?;
+        *templates = settings;
+        for (id, session) in sessions.iter_mut() {
+            self.refresh_tab_title(id, session, &templates.tab_title);
+        }
+        Ok(())
+    }
+
+    /// Formats `kind` for a session, e.g. the notification for a command that finished
+    /// with `exit_code` after `duration`. Variables without a value expand to nothing.
+    pub fn render_template(
+        &self,
+        session_id: &str,
+        kind: TemplateKind,
+        exit_code: Option<i32>,
+        duration: Option<Duration>,
+    ) -> Result<String, String> {
+        let context = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            let session = sessions.get(session_id).ok_or("Session not found")?;
+            TemplateContext { exit_code, duration, ..TemplateContext::from(&session.title) }
+        };
+        let templates = self.templates.lock().map_err(|_| "Lock poisoned")?;
+        Ok(context.render(templates.get(kind)).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_variables_and_fallbacks() {
+        let context = TemplateContext {
+            process: Some("cargo".into()),
+            cwd: Some("/srv/app".into()),
+            exit_code: Some(101),
+            duration: Some(Duration::from_secs(185)),
+            ..Default::default()
+        };
+        assert_eq!(
+            context.render("{process} exited {exit_code} after {duration} in {cwd_short}"),
+            ("cargo exited 101 after 3m 5s in app".to_string(), true)
+        );
+        assert_eq!(context.render("{title|untitled} {branch}"), ("untitled ".to_string(), false));
+        assert_eq!(render("{nope", |_| None), ("{nope".to_string(), true));
+        assert_eq!(format_duration(Duration::from_millis(850)), "850ms");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h 2m");
+    }
+
+    #[test]
+    fn reads_the_checked_out_branch() {
+        let repo = std::env::temp_dir().join(format!("shelll-template-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(repo.join("src/deep")).unwrap();
+        fs::write(repo.join(".git/HEAD"), "ref: refs/heads/feature/titles\n").unwrap();
+        assert_eq!(git_branch(&repo.join("src/deep")).as_deref(), Some("feature/titles"));
+        fs::write(repo.join(".git/HEAD"), "3f2a9c1d0e8b7a6f5e4d3c2b1a0f9e8d7c6b5a49\n").unwrap();
+        assert_eq!(git_branch(&repo).as_deref(), Some("3f2a9c1"));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+}
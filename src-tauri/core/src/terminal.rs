@@ -0,0 +1,404 @@
+//! Per-session terminal state derived from the output stream, updated by the reader
+//! thread as control sequences arrive.
+
+use crate::clipboard::{self, ClipboardRequest};
+use crate::colors::{self, ColorOverrides, ColorTheme, TerminalColorsPayload};
+use crate::cwd::{self, ReportedCwd};
+use crate::events::EventSink;
+use crate::links::{HyperlinkTracker, HyperlinksPayload};
+use crate::notification::{self, TerminalNotification};
+use crate::progress::{self, SessionProgress};
+use crate::remote_edit::{self, RemoteEditRequest};
+use crate::responder::{self, TerminalIdentity};
+use crate::screen::Screen;
+use crate::semantic::{self, PromptMark};
+use crate::title::{self, OscTitle};
+use crate::vt::{Scanner, Sequence};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub(crate) type SharedWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseTracking {
+    #[default]
+    None,
+    // DECSET 9: press only
+    X10,
+    // DECSET 1000 (and 1001): press and release
+    Normal,
+    // DECSET 1002: motion while a button is held
+    ButtonEvent,
+    // DECSET 1003: all motion
+    AnyEvent,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseEncoding {
+    #[default]
+    Default,
+    Utf8,
+    Sgr,
+    Urxvt,
+    SgrPixels,
+}
+
+/// When `tracking` is not `None`, the frontend should forward mouse events to the PTY
+/// using `encoding` instead of doing local selection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MouseMode {
+    pub tracking: MouseTracking,
+    pub encoding: MouseEncoding,
+}
+
+#[derive(Clone, Serialize)]
+pub struct MouseModePayload {
+    pub session_id: String,
+    pub mode: MouseMode,
+}
+
+#[derive(Default)]
+pub struct TerminalState {
+    pub mouse: MouseMode,
+    // Set by programs through OSC 4/10/11/12
+    pub colors: ColorOverrides,
+    pub screen: Screen,
+    // DECSET 2004: pasted text is wrapped in ESC[200~ ... ESC[201~
+    pub bracketed_paste: bool,
+}
+
+impl TerminalState {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        TerminalState { screen: Screen::new(rows, cols), ..TerminalState::default() }
+    }
+
+    fn set_private_mode(&mut self, mode: u32, enabled: bool) {
+        if mode == 2004 {
+            self.bracketed_paste = enabled;
+            return;
+        }
+        let tracking = match mode {
+            9 => Some(MouseTracking::X10),
+            1000 | 1001 => Some(MouseTracking::Normal),
+            1002 => Some(MouseTracking::ButtonEvent),
+            1003 => Some(MouseTracking::AnyEvent),
+            _ => None,
+        };
+        if let Some(tracking) = tracking {
+            if enabled {
+                self.mouse.tracking = tracking;
+            } else if self.mouse.tracking == tracking {
+                self.mouse.tracking = MouseTracking::None;
+            }
+            return;
+        }
+
+        let encoding = match mode {
+            1005 => Some(MouseEncoding::Utf8),
+            1006 => Some(MouseEncoding::Sgr),
+            1015 => Some(MouseEncoding::Urxvt),
+            1016 => Some(MouseEncoding::SgrPixels),
+            _ => None,
+        };
+        if let Some(encoding) = encoding {
+            if enabled {
+                self.mouse.encoding = encoding;
+            } else if self.mouse.encoding == encoding {
+                self.mouse.encoding = MouseEncoding::Default;
+            }
+        }
+    }
+}
+
+/// Runs in a session's reader thread: scans each chunk of output and applies the
+/// sequences to the shared `TerminalState`, emitting events for changes.
+pub(crate) struct OutputProcessor {
+    session_id: String,
+    scanner: Scanner,
+    state: Arc<Mutex<TerminalState>>,
+    // For answering terminal queries
+    writer: SharedWriter,
+    identity: Arc<Mutex<TerminalIdentity>>,
+    theme: Arc<Mutex<ColorTheme>>,
+    events: Arc<dyn EventSink>,
+    // A BEL was printed since the last `take_bell`
+    bell: bool,
+    edit_requests: Vec<RemoteEditRequest>,
+    // The last OSC 7 directory since the last `take_cwd`
+    cwd: Option<ReportedCwd>,
+    // OSC 0/1/2 titles since the last `take_titles`
+    titles: Vec<OscTitle>,
+    clipboard_requests: Vec<ClipboardRequest>,
+    links: HyperlinkTracker,
+    notifications: Vec<TerminalNotification>,
+    last_notification: Option<Instant>,
+    // The last OSC 9;4 progress since the last `take_progress`
+    progress: Option<SessionProgress>,
+    prompt_marks: Vec<PromptMark>,
+}
+
+impl OutputProcessor {
+    pub(crate) fn new(
+        session_id: String,
+        state: Arc<Mutex<TerminalState>>,
+        writer: SharedWriter,
+        identity: Arc<Mutex<TerminalIdentity>>,
+        theme: Arc<Mutex<ColorTheme>>,
+        events: Arc<dyn EventSink>,
+    ) -> Self {
+        OutputProcessor {
+            session_id,
+            scanner: Scanner::with_text(),
+            state,
+            writer,
+            identity,
+            theme,
+            events,
+            bell: false,
+            edit_requests: Vec::new(),
+            cwd: None,
+            titles: Vec::new(),
+            clipboard_requests: Vec::new(),
+            links: HyperlinkTracker::default(),
+            notifications: Vec::new(),
+            last_notification: None,
+            progress: None,
+            prompt_marks: Vec::new(),
+        }
+    }
+
+    pub(crate) fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
+    pub(crate) fn take_edit_requests(&mut self) -> Vec<RemoteEditRequest> {
+        std::mem::take(&mut self.edit_requests)
+    }
+
+    pub(crate) fn take_cwd(&mut self) -> Option<ReportedCwd> {
+        self.cwd.take()
+    }
+
+    pub(crate) fn take_titles(&mut self) -> Vec<OscTitle> {
+        std::mem::take(&mut self.titles)
+    }
+
+    pub(crate) fn take_clipboard_requests(&mut self) -> Vec<ClipboardRequest> {
+        std::mem::take(&mut self.clipboard_requests)
+    }
+
+    pub(crate) fn take_notifications(&mut self) -> Vec<TerminalNotification> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    pub(crate) fn take_progress(&mut self) -> Option<SessionProgress> {
+        self.progress.take()
+    }
+
+    pub(crate) fn take_prompt_marks(&mut self) -> Vec<PromptMark> {
+        std::mem::take(&mut self.prompt_marks)
+    }
+
+    fn reply(&self, sequences: &[Sequence], color_replies: String) {
+        let Ok(identity) = self.identity.lock() else {
+            return;
+        };
+        let mut replies: Vec<u8> = sequences.iter()
+            .filter_map(|seq| responder::respond(seq, &identity))
+            .flatten()
+            .collect();
+        // Color queries follow the same switch as the other replies
+        if identity.enabled {
+            replies.extend(color_replies.into_bytes());
+        }
+        if !replies.is_empty() {
+            if let Ok(mut writer) = self.writer.lock() {
+                let _ = writer.write_all(&replies);
+            }
+        }
+    }
+
+    /// Returns the printed text in `data` (with CR and LF), escape sequences removed.
+    pub(crate) fn process(&mut self, data: &[u8]) -> String {
+        let sequences = self.scanner.feed(data);
+        let mut text = String::new();
+        if sequences.is_empty() {
+            return text;
+        }
+        let Ok(mut state) = self.state.lock() else {
+            return text;
+        };
+        let mouse_before = state.mouse;
+        let colors_before = state.colors.clone();
+        let mut color_replies = String::new();
+
+        for seq in &sequences {
+            state.screen.apply(seq);
+            match seq {
+                Sequence::Text(bytes) => {
+                    let printed = String::from_utf8_lossy(bytes);
+                    self.links.text(&printed);
+                    text.push_str(&printed);
+                }
+                Sequence::Control(byte @ (b'\n' | b'\r')) => text.push(*byte as char),
+                Sequence::Bell => self.bell = true,
+                Sequence::Osc(data) => {
+                    self.edit_requests.extend(remote_edit::parse_request(data));
+                    if let Some(reported) = cwd::parse_osc7(data) {
+                        self.cwd = Some(reported);
+                    }
+                    self.titles.extend(title::parse_osc_title(data));
+                    self.clipboard_requests.extend(clipboard::parse_osc52(data));
+                    self.links.osc(data);
+                    if let Some(notification) = notification::parse_notification(data) {
+                        let now = Instant::now();
+                        if self.last_notification.is_none_or(|last| now - last >= notification::MIN_INTERVAL) {
+                            self.last_notification = Some(now);
+                            self.notifications.push(notification);
+                        }
+                    }
+                    self.prompt_marks.extend(semantic::parse_osc133(data));
+                    if let Some(progress) = progress::parse_osc9_4(data) {
+                        self.progress = Some(progress);
+                    }
+                    let queries = state.colors.apply_osc(data);
+                    if let (false, Ok(theme)) = (queries.is_empty(), self.theme.lock()) {
+                        for slot in queries {
+                            color_replies.push_str(&colors::query_reply(slot, state.colors.resolve(&theme, slot)));
+                        }
+                    }
+                }
+                Sequence::Csi { private: Some(b'?'), final_byte: final_byte @ (b'h' | b'l'), .. } => {
+                    for mode in seq.params() {
+                        state.set_private_mode(mode, *final_byte == b'h');
+                    }
+                }
+                // RIS: full reset
+                Sequence::Esc { intermediates, final_byte: b'c' } if intermediates.is_empty() => {
+                    let (rows, cols) = state.screen.size();
+                    *state = TerminalState::new(rows, cols);
+                    self.links.reset();
+                }
+                _ => {}
+            }
+        }
+
+        if state.mouse != mouse_before {
+            self.events.emit("mouse-mode-changed", MouseModePayload {
+                session_id: self.session_id.clone(),
+                mode: state.mouse,
+            });
+        }
+        let links = self.links.take();
+        if !links.is_empty() {
+            self.events.emit("hyperlinks-detected", HyperlinksPayload { session_id: self.session_id.clone(), links });
+        }
+        if state.colors != colors_before {
+            self.events.emit("terminal-colors-changed", TerminalColorsPayload {
+                session_id: self.session_id.clone(),
+                colors: state.colors.clone(),
+            });
+        }
+        drop(state);
+        self.reply(&sequences, color_replies);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+
+    fn processor() -> (OutputProcessor, Arc<Mutex<TerminalState>>, Arc<RecordingSink>) {
+        let state = Arc::new(Mutex::new(TerminalState::default()));
+        let sink = Arc::new(RecordingSink::default());
+        let writer: SharedWriter = Arc::new(Mutex::new(Box::new(Vec::new())));
+        let identity = Arc::new(Mutex::new(TerminalIdentity::default()));
+        let theme = Arc::new(Mutex::new(ColorTheme::default()));
+        (OutputProcessor::new("s1".into(), state.clone(), writer, identity, theme, sink.clone()), state, sink)
+    }
+
+    #[test]
+    fn tracks_mouse_modes() {
+        let (mut processor, state, sink) = processor();
+        processor.process(b"\x1b[?1002;1006h");
+        assert_eq!(state.lock().unwrap().mouse, MouseMode {
+            tracking: MouseTracking::ButtonEvent,
+            encoding: MouseEncoding::Sgr,
+        });
+
+        processor.process(b"\x1b[?1000l");
+        assert_eq!(state.lock().unwrap().mouse.tracking, MouseTracking::ButtonEvent);
+        processor.process(b"\x1b[?1002l\x1b[?1006l");
+        assert_eq!(state.lock().unwrap().mouse, MouseMode::default());
+
+        let events = sink.named("mouse-mode-changed");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["mode"]["tracking"], "button_event");
+        assert_eq!(events[0]["mode"]["encoding"], "sgr");
+    }
+
+    #[test]
+    fn full_reset_clears_mouse_mode() {
+        let (mut processor, state, _sink) = processor();
+        processor.process(b"\x1b[?1003h");
+        processor.process(b"\x1bc");
+        assert_eq!(state.lock().unwrap().mouse, MouseMode::default());
+    }
+
+    #[test]
+    fn tracks_bracketed_paste() {
+        let (mut processor, state, _sink) = processor();
+        processor.process(b"\x1b[?2004h");
+        assert!(state.lock().unwrap().bracketed_paste);
+        processor.process(b"\x1b[?1049;2004l");
+        assert!(!state.lock().unwrap().bracketed_paste);
+    }
+
+    #[test]
+    fn reports_hyperlinks_once_closed() {
+        let (mut processor, _state, sink) = processor();
+        let text = processor.process(b"see \x1b]8;;https://example.com\x1b\\exam");
+        assert!(sink.named("hyperlinks-detected").is_empty());
+        processor.process(b"ple\x1b]8;;\x07 done");
+        assert_eq!(text, "see exam");
+        let links = &sink.named("hyperlinks-detected")[0]["links"];
+        assert_eq!((links[0]["uri"].clone(), links[0]["text"].clone()), ("https://example.com".into(), "example".into()));
+    }
+
+    #[test]
+    fn rate_limits_notifications() {
+        let (mut processor, _state, _sink) = processor();
+        processor.process(b"\x1b]9;Build finished\x07\x1b]777;notify;Tests;2 failed\x07\x1b]9;4;1;50\x07");
+        let notifications = processor.take_notifications();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].body, "Build finished");
+        assert!(processor.take_notifications().is_empty());
+    }
+
+    #[test]
+    fn reports_bells_but_not_osc_terminators() {
+        let (mut processor, _state, _sink) = processor();
+        processor.process(b"\x1b]0;title\x07");
+        assert!(!processor.take_bell());
+        processor.process(b"done\x07");
+        assert!(processor.take_bell());
+        assert!(!processor.take_bell());
+    }
+
+    #[test]
+    fn reports_color_changes() {
+        let (mut processor, state, sink) = processor();
+        processor.process(b"\x1b]11;#fdf6e3\x1b\\\x1b]11;?\x07");
+        processor.process(b"\x1b]111\x07");
+        assert_eq!(state.lock().unwrap().colors, ColorOverrides::default());
+        let events = sink.named("terminal-colors-changed");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["colors"]["background"], "#fdf6e3");
+    }
+}
@@ -0,0 +1,137 @@
+//! The optional `shelll` terminfo entry and checks for TERM/terminfo mismatches.
+
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+pub const SHELLL_TERM: &str = "shelll";
+
+// xterm-256color plus truecolor and styled-underline capabilities
+const SHELLL_TERMINFO_SOURCE: &str = "shelll|shelll terminal,
+\tTc, RGB,
+\tSmulx=\\E[4:%p1%dm,
+\tSetulc=\\E[58:2::%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%d%;m,
+\tuse=xterm-256color,
+";
+
+#[derive(Clone, Serialize)]
+pub struct TerminfoDiagnosis {
+    pub term: String,
+    pub found: bool,
+    // Compiled entry the local ncurses resolved TERM to
+    pub path: Option<String>,
+    pub colors: Option<u32>,
+    pub warnings: Vec<String>,
+}
+
+fn user_terminfo_dir() -> Result<PathBuf, String> {
+    let home = env::var("HOME").map_err(|_| "HOME is not set")?;
+    Ok(PathBuf::from(home).join(".terminfo"))
+}
+
+/// Compiles the shelll entry into ~/.terminfo with `tic`. Returns the install directory.
+pub fn install_shelll_terminfo() -> Result<String, String> {
+    let dir = user_terminfo_dir()?;
+    let source = env::temp_dir().join(format!("shelll-terminfo-{}.src", std::process::id()));
+    fs::write(&source, SHELLL_TERMINFO_SOURCE).map_err(|e| format!("Failed to write terminfo source: {}", e))?;
+
+    let output = Command::new("tic")
+        .arg("-x")
+        .arg("-o")
+        .arg(&dir)
+        .arg(&source)
+        .output();
+    let _ = fs::remove_file(&source);
+    let output = output.map_err(|e| format!("Failed to run tic: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("tic failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(dir.to_string_lossy().into_owned())
+}
+
+// infocmp prints a header like "#\tReconstructed via infocmp from file: /usr/share/terminfo/78/xterm"
+fn parse_infocmp_path(output: &str) -> Option<String> {
+    output.lines()
+        .find_map(|l| l.split_once("from file: "))
+        .map(|(_, path)| path.trim().to_string())
+}
+
+fn parse_colors(output: &str) -> Option<u32> {
+    output.split([',', '\n'])
+        .map(str::trim)
+        .find_map(|cap| cap.strip_prefix("colors#"))
+        .and_then(|v| {
+            let v = v.trim();
+            v.strip_prefix("0x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| v.parse().ok())
+        })
+}
+
+/// Checks whether the local terminfo database knows `term`, and flags setups that tend to
+/// break (e.g. a custom TERM that remote hosts won't have).
+pub fn diagnose_terminfo(term: &str) -> TerminfoDiagnosis {
+    let mut diagnosis = TerminfoDiagnosis {
+        term: term.to_string(),
+        found: false,
+        path: None,
+        colors: None,
+        warnings: Vec::new(),
+    };
+
+    match Command::new("infocmp").arg("-x").arg(term).output() {
+        Ok(output) if output.status.success() => {
+            let text = String::from_utf8_lossy(&output.stdout);
+            diagnosis.found = true;
+            diagnosis.path = parse_infocmp_path(&text);
+            diagnosis.colors = parse_colors(&text);
+        }
+        Ok(_) => diagnosis.warnings.push(format!(
+            "No terminfo entry for '{}'; full-screen programs will misbehave",
+            term
+        )),
+        Err(e) => diagnosis.warnings.push(format!("Could not run infocmp: {}", e)),
+    }
+
+    if term == SHELLL_TERM {
+        if !diagnosis.found {
+            diagnosis.warnings.push("Install the shelll entry or switch the profile to xterm-256color".into());
+        }
+        diagnosis.warnings.push(
+            "Remote hosts need the entry too: infocmp -x shelll | ssh <host> -- tic -x -".into(),
+        );
+    }
+    if diagnosis.colors.is_some_and(|c| c < 256) {
+        diagnosis.warnings.push(format!("'{}' only advertises {} colors", term, diagnosis.colors.unwrap_or(0)));
+    }
+    diagnosis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_infocmp_output() {
+        let output = "#\tReconstructed via infocmp from file: /usr/share/terminfo/x/xterm-256color\n\
+                      xterm-256color|xterm with 256 colors,\n\tam, bce,\n\tcolors#0x100, cols#80,\n";
+        assert_eq!(parse_infocmp_path(output).as_deref(), Some("/usr/share/terminfo/x/xterm-256color"));
+        assert_eq!(parse_colors(output), Some(256));
+        assert_eq!(parse_colors("\tcolors#8, it#8,"), Some(8));
+    }
+
+    #[test]
+    fn shelll_entry_extends_xterm() {
+        assert!(SHELLL_TERMINFO_SOURCE.starts_with("shelll|"));
+        assert!(SHELLL_TERMINFO_SOURCE.contains("use=xterm-256color"));
+    }
+
+    #[test]
+    fn unknown_term_is_reported() {
+        let diagnosis = diagnose_terminfo("definitely-not-a-terminal");
+        assert!(!diagnosis.found);
+        assert!(!diagnosis.warnings.is_empty());
+    }
+}
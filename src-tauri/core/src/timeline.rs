@@ -0,0 +1,148 @@
+//! Focus and command history merged into one timeline ("what app was focused / what ran
+//! when") and exported as JSON or CSV, for time review and incident postmortems.
+
+use crate::focus::{self, FocusEntry};
+use crate::history::HistoryEntry;
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+
+/// Unix times in seconds; open-ended on a missing side.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct TimeRange {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
+
+impl TimeRange {
+    fn contains(&self, timestamp: u64) -> bool {
+        self.start.is_none_or(|start| timestamp >= start) && self.end.is_none_or(|end| timestamp < end)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineKind {
+    Focus,
+    Command,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TimelineEntry {
+    pub timestamp: u64,
+    pub kind: TimelineKind,
+    // The focused app or the command line
+    pub detail: String,
+    pub session_id: Option<String>,
+    pub cwd: Option<String>,
+    pub host: Option<String>,
+    pub exit_status: Option<i32>,
+}
+
+fn build_timeline<'a>(focus: &[FocusEntry], commands: impl IntoIterator<Item = &'a HistoryEntry>, range: TimeRange) -> Vec<TimelineEntry> {
+    let focus = focus.iter().map(|f| TimelineEntry {
+        timestamp: f.timestamp,
+        kind: TimelineKind::Focus,
+        detail: f.app.clone(),
+        session_id: None,
+        cwd: None,
+        host: None,
+        exit_status: None,
+    });
+    let commands = commands.into_iter().map(|c| TimelineEntry {
+        timestamp: c.timestamp,
+        kind: TimelineKind::Command,
+        detail: c.command.clone(),
+        session_id: Some(c.session_id.clone()),
+        cwd: c.cwd.clone(),
+        host: c.host.clone(),
+        exit_status: c.exit_status,
+    });
+    let mut entries: Vec<TimelineEntry> = focus.chain(commands).filter(|e| range.contains(e.timestamp)).collect();
+    // Stable: a focus change and a command in the same second keep focus first
+    entries.sort_by_key(|e| e.timestamp);
+    entries
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn render(entries: &[TimelineEntry], format: TimelineFormat) -> Result<String, String> {
+    match format {
+        TimelineFormat::Json => serde_json::to_string_pretty(entries).map_err(|e| format!("Failed to serialize timeline: {}", e)),
+        TimelineFormat::Csv => {
+            let mut out = String::from("timestamp,kind,detail,session_id,cwd,exit_status,host\n");
+            for e in entries {
+                let kind = match e.kind {
+                    TimelineKind::Focus => "focus",
+                    TimelineKind::Command => "command",
+                };
+                out.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    e.timestamp,
+                    kind,
+                    csv_field(&e.detail),
+                    csv_field(e.session_id.as_deref().unwrap_or("")),
+                    csv_field(e.cwd.as_deref().unwrap_or("")),
+                    e.exit_status.map(|s| s.to_string()).unwrap_or_default(),
+                    csv_field(e.host.as_deref().unwrap_or("")),
+                ));
+            }
+            Ok(out)
+        }
+    }
+}
+
+impl SessionManager {
+    /// Focus changes (recorded while the focus monitor runs) and commands in `range`,
+    /// oldest first.
+    pub fn export_timeline(&self, range: TimeRange, format: TimelineFormat) -> Result<String, String> {
+        let history = self.history.lock().map_err(|_| "Lock poisoned")?;
+        let export = render(&build_timeline(&focus::focus_history(), history.entries(), range), format)?;
+        let redactor = self.redactor.lock().map_err(|_| "Lock poisoned")?;
+        Ok(redactor.redact(&export).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_and_filters_by_range() {
+        let focus = vec![
+            FocusEntry { app: "Safari".into(), timestamp: 100 },
+            FocusEntry { app: "Xcode".into(), timestamp: 300 },
+        ];
+        let commands = vec![HistoryEntry {
+            command: "git commit -m \"fix, finally\"".into(),
+            session_id: "s1".into(),
+            cwd: Some("/repo".into()),
+            host: Some("deploy@prod".into()),
+            exit_status: Some(0),
+            timestamp: 200,
+            resources: None,
+        }];
+        let entries = build_timeline(&focus, &commands, TimeRange { start: Some(150), end: None });
+        assert_eq!(entries.iter().map(|e| e.timestamp).collect::<Vec<_>>(), vec![200, 300]);
+
+        let csv = render(&entries, TimelineFormat::Csv).unwrap();
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("200,command,\"git commit -m \"\"fix, finally\"\"\",s1,/repo,0,deploy@prod")
+        );
+        assert_eq!(csv.lines().nth(2), Some("300,focus,Xcode,,,,"));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::events::EventSink;
+use crate::pty::{emit_tab_title, PtySession};
+use crate::remote::RemoteContext;
+use crate::template::{TemplateContext, TemplateSettings};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_TITLE_TEMPLATE: &str = "{process} — {cwd_short}";
+// Longer program-set titles are cut
+const MAX_OSC_TITLE_CHARS: usize = 256;
+
+// The signals a tab title can be built from. Each is updated independently as the
+// shell's process, working directory, or escape-sequence title changes.
+#[derive(Clone, Default)]
+pub struct TitleInputs {
+    pub process: Option<String>,
+    pub cwd: Option<String>,
+    pub osc_title: Option<String>,
+    // Where the foreground job is connected to (ssh, docker, kubectl)
+    pub remote: Option<RemoteContext>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TabTitlePayload {
+    pub session_id: String,
+    pub title: String,
+}
+
+/// The `session-title-changed` payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionTitlePayload {
+    pub session_id: String,
+    // None once the program clears it
+    pub title: Option<String>,
+    // OSC 1's icon name; most programs set it along with the title (OSC 0)
+    pub icon_name: Option<String>,
+}
+
+/// A title set through OSC 0 (title and icon name), 1 (icon name) or 2 (title).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct OscTitle {
+    pub(crate) sets_title: bool,
+    pub(crate) sets_icon_name: bool,
+    // Empty to clear
+    pub(crate) text: String,
+}
+
+/// The title in an OSC body (without `ESC ]` and the terminator), if it sets one.
+pub(crate) fn parse_osc_title(osc: &[u8]) -> Option<OscTitle> {
+    let (sets_title, sets_icon_name) = match osc.get(..2)? {
+        b"0;" => (true, true),
+        b"1;" => (false, true),
+        b"2;" => (true, false),
+        _ => return None,
+    };
+    let text: String = String::from_utf8_lossy(&osc[2..]).chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_OSC_TITLE_CHARS)
+        .collect();
+    Some(OscTitle { sets_title, sets_icon_name, text: text.trim().to_string() })
+}
+
+// From the reader thread: records the titles and reports them if they changed
+pub(crate) fn apply_osc_titles(
+    sessions: &Mutex<HashMap<String, PtySession>>,
+    templates: &Mutex<TemplateSettings>,
+    session_id: &str,
+    titles: Vec<OscTitle>,
+    events: &Arc<dyn EventSink>,
+) {
+    let Ok(mut sessions) = sessions.lock() else { return };
+    let Some(session) = sessions.get_mut(session_id) else { return };
+    let before = (session.title.osc_title.clone(), session.icon_name.clone());
+    for title in titles {
+        let text = Some(title.text).filter(|t| !t.is_empty());
+        if title.sets_title {
+            session.title.osc_title = text.clone();
+        }
+        if title.sets_icon_name {
+            session.icon_name = text;
+        }
+    }
+    if (&session.title.osc_title, &session.icon_name) == (&before.0, &before.1) {
+        return;
+    }
+    if let Ok(templates) = templates.lock() {
+        emit_tab_title(events, session_id, session, &templates.tab_title);
+    }
+    events.emit("session-title-changed", SessionTitlePayload {
+        session_id: session_id.to_string(),
+        title: session.title.osc_title.clone(),
+        icon_name: session.icon_name.clone(),
+    });
+}
+
+pub fn shorten_cwd(cwd: &str) -> String {
+    let home = env::var("HOME").unwrap_or_default();
+    if !home.is_empty() && cwd.trim_end_matches('/') == home.trim_end_matches('/') {
+        return "~".to_string();
+    }
+    match Path::new(cwd).file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => cwd.to_string(),
+    }
+}
+
+// Renders a template such as "{process} — {cwd_short}" (see `template` for the variables).
+// If the template references a variable that isn't known yet, the program-supplied (OSC)
+// title is used instead, then the bare process name.
+pub fn render_tab_title(template: &str, inputs: &TitleInputs) -> String {
+    let (out, complete) = TemplateContext::from(inputs).render(template);
+    if complete && !out.trim().is_empty() {
+        return out;
+    }
+    inputs.osc_title.clone()
+        .filter(|t| !t.is_empty())
+        .or_else(|| inputs.process.clone())
+        .unwrap_or_else(|| "shelll".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(process: Option<&str>, cwd: Option<&str>, osc_title: Option<&str>) -> TitleInputs {
+        TitleInputs {
+            process: process.map(String::from),
+            cwd: cwd.map(String::from),
+            osc_title: osc_title.map(String::from),
+            remote: None,
+        }
+    }
+
+    #[test]
+    fn renders_all_variables() {
+        let title = render_tab_title(DEFAULT_TITLE_TEMPLATE, &inputs(Some("vim"), Some("/srv/app"), None));
+        assert_eq!(title, "vim — app");
+    }
+
+    #[test]
+    fn falls_back_to_osc_title_then_process() {
+        let template = "{process} — {cwd_short}";
+        assert_eq!(render_tab_title(template, &inputs(Some("zsh"), None, Some("build"))), "build");
+        assert_eq!(render_tab_title(template, &inputs(Some("zsh"), None, None)), "zsh");
+        assert_eq!(render_tab_title(template, &inputs(None, None, None)), "shelll");
+    }
+
+    #[test]
+    fn parses_osc_titles() {
+        let title = |sets_title, sets_icon_name, text: &str| Some(OscTitle { sets_title, sets_icon_name, text: text.into() });
+        assert_eq!(parse_osc_title(b"0;vim main.rs"), title(true, true, "vim main.rs"));
+        assert_eq!(parse_osc_title(b"1;vim"), title(false, true, "vim"));
+        assert_eq!(parse_osc_title(b"2; cargo\tbuild\x1b "), title(true, false, "cargobuild"));
+        assert_eq!(parse_osc_title(b"2;"), title(true, false, ""));
+        assert_eq!(parse_osc_title(b"7;file:///tmp"), None);
+        assert_eq!(parse_osc_title(&[b"2;".as_slice(), &[b'x'; 300]].concat()).unwrap().text.len(), MAX_OSC_TITLE_CHARS);
+    }
+
+    #[test]
+    fn keeps_unterminated_braces_literally() {
+        assert_eq!(render_tab_title("{process} {oops", &inputs(Some("zsh"), None, None)), "zsh {oops");
+    }
+}
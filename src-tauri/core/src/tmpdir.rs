@@ -0,0 +1,94 @@
+//! Per-session temporary directories. Each session gets its own TMPDIR, created at spawn
+//! and deleted when the session closes, so scratch files don't pile up in the global temp
+//! directory. Directories left behind by a crash are removed on the next start.
+
+use crate::guest::remove_stale_dirs;
+use crate::pty::{SessionManager, SessionOptions};
+use portable_pty::CommandBuilder;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn tmp_root() -> PathBuf {
+    std::env::temp_dir().join("shelll-tmp")
+}
+
+// Named after the owning app process, like guest homes
+fn create_tmp_dir() -> Result<PathBuf, String> {
+    let dir = tmp_root().join(format!("{}-{}", std::process::id(), uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&dir, fs::Permissions::from_mode(0o700));
+    }
+    Ok(dir)
+}
+
+fn set_tmp_env(cmd: &mut CommandBuilder, dir: &Path) {
+    cmd.env("TMPDIR", dir);
+    // Windows programs read these instead
+    #[cfg(windows)]
+    for name in ["TMP", "TEMP"] {
+        cmd.env(name, dir);
+    }
+}
+
+/// Creates the session's TMPDIR and points `cmd` at it, unless `options` opt out.
+pub(crate) fn prepare(cmd: &mut CommandBuilder, options: &SessionOptions) -> Result<Option<PathBuf>, String> {
+    if options.private_tmp == Some(false) {
+        return Ok(None);
+    }
+    let dir = create_tmp_dir()?;
+    set_tmp_env(cmd, &dir);
+    Ok(Some(dir))
+}
+
+/// Removes session temp directories whose app process is gone.
+pub fn remove_stale_tmp_dirs() {
+    remove_stale_dirs(&tmp_root());
+}
+
+impl SessionManager {
+    pub fn session_tmp_dir(&self, session_id: &str) -> Result<Option<PathBuf>, String> {
+        let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+        let session = sessions.get(session_id).ok_or("Session not found")?;
+        Ok(session.tmp_dir.clone())
+    }
+
+    /// Shows the session's TMPDIR in the file manager and returns its path.
+    pub fn open_session_tmp(&self, session_id: &str) -> Result<PathBuf, String> {
+        let dir = self.session_tmp_dir(session_id)?.ok_or("Session has no private temp directory")?;
+        crate::files::open_directory(&dir)?;
+        Ok(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+    use crate::history::HistoryStore;
+    use crate::profiles::Profile;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn sessions_get_a_tmpdir_that_is_removed_on_close() {
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let options = SessionOptions { shell: Some("sh".into()), args: Some(Vec::new()), ..Default::default() };
+        let id = manager.create_session(&Profile::default(), &options).unwrap();
+        let dir = manager.session_tmp_dir(&id).unwrap().unwrap();
+
+        manager.write(&id, "echo scratch > \"$TMPDIR/notes\"\r").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert!(dir.join("notes").exists());
+        manager.close(&id).unwrap();
+        assert!(!dir.exists());
+
+        let opted_out = SessionOptions { private_tmp: Some(false), ..options };
+        let id = manager.create_session(&Profile::default(), &opted_out).unwrap();
+        assert_eq!(manager.session_tmp_dir(&id).unwrap(), None);
+        manager.close(&id).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+//! The menu bar (tray) menu: open sessions, "New Session", "Toggle Window" and the app
+//! shelll is paired with, so it's reachable while the window is hidden. Built here as
+//! plain entries; the app turns them into a native menu and item ids back into actions.
+
+use serde::Serialize;
+
+// Longer titles are cut, the menu would be as wide as the longest
+const MAX_TITLE_CHARS: usize = 40;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrayAction {
+    NewSession,
+    ToggleWindow,
+    // Shows the window with the session in front
+    FocusSession(String),
+    // Brings the paired app (a bundle id or name) to the front
+    ActivatePaired(String),
+    Quit,
+}
+
+impl TrayAction {
+    pub fn id(&self) -> String {
+        match self {
+            TrayAction::NewSession => "new-session".into(),
+            TrayAction::ToggleWindow => "toggle-window".into(),
+            TrayAction::FocusSession(id) => format!("session:{}", id),
+            TrayAction::ActivatePaired(app) => format!("paired:{}", app),
+            TrayAction::Quit => "quit".into(),
+        }
+    }
+
+    pub fn parse(id: &str) -> Option<TrayAction> {
+        match id {
+            "new-session" => Some(TrayAction::NewSession),
+            "toggle-window" => Some(TrayAction::ToggleWindow),
+            "quit" => Some(TrayAction::Quit),
+            _ => {
+                if let Some(session_id) = id.strip_prefix("session:") {
+                    Some(TrayAction::FocusSession(session_id.to_string()))
+                } else {
+                    id.strip_prefix("paired:").map(|app| TrayAction::ActivatePaired(app.to_string()))
+                }
+            }
+        }
+    }
+}
+
+/// The `tray-session-selected` payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TraySessionSelectedPayload {
+    pub session_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrayEntry {
+    // Disabled items have no action
+    Item { title: String, action: Option<TrayAction> },
+    Separator,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraySession {
+    pub session_id: String,
+    pub title: String,
+    pub alive: bool,
+}
+
+fn item(title: impl Into<String>, action: Option<TrayAction>) -> TrayEntry {
+    TrayEntry::Item { title: title.into(), action }
+}
+
+fn shorten(title: &str) -> String {
+    if title.chars().count() <= MAX_TITLE_CHARS {
+        return title.to_string();
+    }
+    let cut: String = title.chars().take(MAX_TITLE_CHARS - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// The menu for the open sessions (oldest first) and the paired app, if any.
+pub fn tray_entries(sessions: &[TraySession], paired: Option<&str>) -> Vec<TrayEntry> {
+    let mut entries = Vec::new();
+    if sessions.is_empty() {
+        entries.push(item("No Sessions", None));
+    }
+    for session in sessions {
+        let title = if session.alive { shorten(&session.title) } else { format!("{} (exited)", shorten(&session.title)) };
+        entries.push(item(title, Some(TrayAction::FocusSession(session.session_id.clone()))));
+    }
+    entries.push(TrayEntry::Separator);
+    entries.push(item("New Session", Some(TrayAction::NewSession)));
+    entries.push(item("Toggle Window", Some(TrayAction::ToggleWindow)));
+    entries.push(TrayEntry::Separator);
+    entries.push(match paired {
+        Some(app) => item(format!("Paired with {}", app), Some(TrayAction::ActivatePaired(app.to_string()))),
+        None => item("Not Paired", None),
+    });
+    entries.push(TrayEntry::Separator);
+    entries.push(item("Quit shelll", Some(TrayAction::Quit)));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_sessions_and_the_paired_app() {
+        let sessions = vec![
+            TraySession { session_id: "a".into(), title: "zsh — ~/src".into(), alive: true },
+            TraySession { session_id: "b".into(), title: "x".repeat(50), alive: false },
+        ];
+        let entries = tray_entries(&sessions, Some("com.apple.dt.Xcode"));
+        assert_eq!(entries[0], item("zsh — ~/src", Some(TrayAction::FocusSession("a".into()))));
+        let TrayEntry::Item { title, .. } = &entries[1] else { panic!("expected an item") };
+        assert_eq!(title, &format!("{}… (exited)", "x".repeat(39)));
+        assert!(entries.contains(&item("Paired with com.apple.dt.Xcode", Some(TrayAction::ActivatePaired("com.apple.dt.Xcode".into())))));
+
+        let empty = tray_entries(&[], None);
+        assert_eq!(empty[0], item("No Sessions", None));
+        assert!(empty.contains(&item("Not Paired", None)));
+    }
+
+    #[test]
+    fn item_ids_round_trip() {
+        let actions = [
+            TrayAction::NewSession,
+            TrayAction::ToggleWindow,
+            TrayAction::FocusSession("5f0c-…".into()),
+            TrayAction::ActivatePaired("Visual Studio Code".into()),
+            TrayAction::Quit,
+        ];
+        for action in actions {
+            assert_eq!(TrayAction::parse(&action.id()), Some(action));
+        }
+        assert_eq!(TrayAction::parse("bogus"), None);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::config::{load_json, save_json};
+use crate::unix_now;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/keybrdist/shelll/releases/latest";
+const UPDATE_CHECK_INTERVAL_SECS: u64 = 6 * 60 * 60;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReleaseInfo {
+    pub version: String,
+    pub name: String,
+    pub notes: String,
+    pub url: String,
+    pub published_at: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct UpdateAvailablePayload {
+    pub current_version: String,
+    pub release: ReleaseInfo,
+}
+
+#[derive(Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    name: Option<String>,
+    body: Option<String>,
+    html_url: String,
+    published_at: Option<String>,
+}
+
+// Last response from the releases feed, kept so repeated checks are cheap and
+// conditional requests can use the ETag (GitHub doesn't count 304s against the rate limit).
+#[derive(Default, Serialize, Deserialize)]
+struct UpdateCache {
+    etag: Option<String>,
+    checked_at: u64,
+    release: Option<ReleaseInfo>,
+}
+
+fn fetch_latest_release(cache: &mut UpdateCache) -> Result<(), String> {
+    let mut request = ureq::get(RELEASES_URL)
+        .set("User-Agent", "shelll")
+        .set("Accept", "application/vnd.github+json")
+        .timeout(Duration::from_secs(10));
+    if let (Some(etag), Some(_)) = (&cache.etag, &cache.release) {
+        request = request.set("If-None-Match", etag);
+    }
+
+    let response = request.call().map_err(|e| format!("Failed to query releases: {}", e))?;
+    cache.checked_at = unix_now();
+    if response.status() == 304 {
+        return Ok(());
+    }
+
+    cache.etag = response.header("ETag").map(|s| s.to_string());
+    let release: GithubRelease = response.into_json()
+        .map_err(|e| format!("Failed to parse release: {}", e))?;
+    cache.release = Some(ReleaseInfo {
+        version: release.tag_name.trim_start_matches('v').to_string(),
+        name: release.name.unwrap_or_else(|| release.tag_name.clone()),
+        notes: release.body.unwrap_or_default(),
+        url: release.html_url,
+        published_at: release.published_at,
+    });
+    Ok(())
+}
+
+fn newer_release(release: Option<ReleaseInfo>, current: &semver::Version) -> Result<Option<ReleaseInfo>, String> {
+    let Some(release) = release else {
+        return Ok(None);
+    };
+    let latest = semver::Version::parse(&release.version)
+        .map_err(|e| format!("Invalid release version {}: {}", release.version, e))?;
+    Ok((latest > *current).then_some(release))
+}
+
+/// Returns the latest release if it is newer than `current`. The feed is only queried
+/// when the cached answer is older than the check interval, unless `force` is set.
+pub fn check_latest_release(
+    cache_path: Option<&Path>,
+    current: &semver::Version,
+    force: bool,
+) -> Result<Option<ReleaseInfo>, String> {
+    let mut cache: UpdateCache = load_json(cache_path);
+
+    let stale = unix_now().saturating_sub(cache.checked_at) >= UPDATE_CHECK_INTERVAL_SECS;
+    if force || stale || cache.release.is_none() {
+        fetch_latest_release(&mut cache)?;
+        if let Some(path) = cache_path {
+            let _ = save_json(path, &cache);
+        }
+    }
+
+    newer_release(cache.release, current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(version: &str) -> Option<ReleaseInfo> {
+        Some(ReleaseInfo {
+            version: version.to_string(),
+            name: version.to_string(),
+            notes: String::new(),
+            url: String::new(),
+            published_at: None,
+        })
+    }
+
+    #[test]
+    fn only_newer_versions_are_reported() {
+        let current = semver::Version::parse("0.2.0").unwrap();
+        assert!(newer_release(release("0.3.0"), &current).unwrap().is_some());
+        assert!(newer_release(release("0.2.0"), &current).unwrap().is_none());
+        assert!(newer_release(release("0.1.9"), &current).unwrap().is_none());
+        assert!(newer_release(None, &current).unwrap().is_none());
+        assert!(newer_release(release("nightly"), &current).is_err());
+    }
+}
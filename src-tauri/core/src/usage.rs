@@ -0,0 +1,208 @@
+//! Approximate resource usage per command. A submitted command is tracked until its job
+//! gives the terminal back to the shell (or `command_finished` reports it); meanwhile the
+//! job's process group is sampled with `ps`, and its CPU time and peak memory end up in
+//! the command's history entry. Processes that exit between samples take their CPU time
+//! with them, and commands shorter than a sample interval aren't measured at all.
+
+use crate::process;
+use crate::pty::SessionManager;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandResources {
+    // CPU time (user + system) of the job's processes
+    pub cpu_ms: u64,
+    // Highest combined resident memory seen
+    pub peak_rss_kb: u64,
+    pub wall_ms: u64,
+}
+
+// A submitted command whose job hasn't finished yet
+pub(crate) struct RunningCommand {
+    command: String,
+    // The history entry's timestamp
+    timestamp: u64,
+    started: Instant,
+    // The job's process group, once it has been seen in the foreground
+    pgid: Option<u32>,
+    resources: CommandResources,
+}
+
+// CPU time as ps prints it: "[[dd-]hh:]mm:ss[.cc]" (Linux prints whole seconds)
+fn parse_cpu_time(time: &str) -> Option<u64> {
+    let (days, rest) = match time.split_once('-') {
+        Some((days, rest)) => (days.parse::<u64>().ok()?, rest),
+        None => (0, time),
+    };
+    let mut secs = 0.0;
+    for part in rest.split(':') {
+        secs = secs * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(days * 86_400_000 + (secs * 1000.0).round() as u64)
+}
+
+// `ps -A -o pgid=,rss=,time=` summed per process group: (CPU ms, RSS KB)
+fn parse_group_usage(ps: &str) -> HashMap<u32, (u64, u64)> {
+    let mut groups: HashMap<u32, (u64, u64)> = HashMap::new();
+    for line in ps.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [pgid, rss, time] = fields[..] else { continue };
+        let (Ok(pgid), Ok(rss), Some(cpu)) = (pgid.parse(), rss.parse::<u64>(), parse_cpu_time(time)) else {
+            continue;
+        };
+        let group = groups.entry(pgid).or_default();
+        group.0 += cpu;
+        group.1 += rss;
+    }
+    groups
+}
+
+fn group_usage() -> HashMap<u32, (u64, u64)> {
+    Command::new("ps")
+        .args(["-A", "-o", "pgid=,rss=,time="])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| parse_group_usage(&String::from_utf8_lossy(&o.stdout)))
+        .unwrap_or_default()
+}
+
+impl SessionManager {
+    // Starts measuring the command just recorded in the history
+    pub(crate) fn track_command(&self, session_id: &str, command: String, timestamp: u64) {
+        if let Ok(mut running) = self.running_commands.lock() {
+            running.insert(session_id.to_string(), RunningCommand {
+                command,
+                timestamp,
+                started: Instant::now(),
+                pgid: None,
+                resources: CommandResources::default(),
+            });
+        }
+    }
+
+    /// Samples the jobs of running commands until the manager is dropped. Pauses in
+    /// low-memory mode.
+    pub fn start_usage_sampler(self: &Arc<Self>) {
+        let manager = Arc::downgrade(self);
+        thread::spawn(move || {
+            while let Some(manager) = manager.upgrade() {
+                let _ = manager.sample_usage();
+                drop(manager);
+                thread::sleep(SAMPLE_INTERVAL);
+            }
+        });
+    }
+
+    fn sample_usage(&self) -> Result<(), String> {
+        if self.running_commands.lock().map_err(|_| "Lock poisoned")?.is_empty() || self.low_memory_status()?.enabled {
+            return Ok(());
+        }
+        // Each session's foreground process group, and whether that's the shell itself
+        let foreground: HashMap<String, (Option<u32>, bool)> = {
+            let sessions = self.sessions.lock().map_err(|_| "Lock poisoned")?;
+            sessions.iter()
+                .map(|(id, s)| {
+                    let pgid = process::foreground_pid(s);
+                    (id.clone(), (pgid, pgid.is_none() || pgid == s.pid))
+                })
+                .collect()
+        };
+        let groups = group_usage();
+        let mut finished = Vec::new();
+        {
+            let mut running = self.running_commands.lock().map_err(|_| "Lock poisoned")?;
+            running.retain(|id, _| foreground.contains_key(id));
+            for (id, command) in running.iter_mut() {
+                let (pgid, at_prompt) = foreground[id];
+                if at_prompt {
+                    // Back at the prompt; a command never seen running (a builtin, or
+                    // quicker than a sample) has nothing to report
+                    if command.pgid.is_some() {
+                        finished.push(id.clone());
+                    }
+                    continue;
+                }
+                let Some(pgid) = pgid else { continue };
+                command.pgid = Some(pgid);
+                if let Some(&(cpu_ms, rss_kb)) = groups.get(&pgid) {
+                    command.resources.cpu_ms = command.resources.cpu_ms.max(cpu_ms);
+                    command.resources.peak_rss_kb = command.resources.peak_rss_kb.max(rss_kb);
+                }
+            }
+        }
+        for id in finished {
+            self.finish_command(&id)?;
+        }
+        Ok(())
+    }
+
+    // Stops measuring the command running in `session_id` and stores what was measured
+    pub(crate) fn finish_command(&self, session_id: &str) -> Result<Option<CommandResources>, String> {
+        let Some(command) = self.running_commands.lock().map_err(|_| "Lock poisoned")?.remove(session_id) else {
+            return Ok(None);
+        };
+        if command.pgid.is_none() {
+            return Ok(None);
+        }
+        let resources = CommandResources { wall_ms: command.started.elapsed().as_millis() as u64, ..command.resources };
+        let mut history = self.history.lock().map_err(|_| "Lock poisoned")?;
+        history.set_resources(session_id, command.timestamp, &command.command, resources.clone());
+        Ok(Some(resources))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ps_cpu_times() {
+        assert_eq!(parse_cpu_time("0:01.25"), Some(1250));
+        assert_eq!(parse_cpu_time("00:02:03"), Some(123_000));
+        assert_eq!(parse_cpu_time("1-00:00:01"), Some(86_401_000));
+        assert_eq!(parse_cpu_time("soon"), None);
+    }
+
+    #[test]
+    fn sums_usage_per_process_group() {
+        let ps = "  100  2048 0:01.00\n  100  1024 0:00.50\n  200   512 00:00:02\ngarbage\n";
+        let groups = parse_group_usage(ps);
+        assert_eq!(groups[&100], (1500, 3072));
+        assert_eq!(groups[&200], (2000, 512));
+        assert_eq!(groups.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn measures_commands_run_in_a_session() {
+        use crate::events::testing::RecordingSink;
+        use crate::history::HistoryStore;
+        use portable_pty::CommandBuilder;
+
+        let manager = SessionManager::new(Arc::new(RecordingSink::default()), HistoryStore::load(None));
+        let id = manager.spawn_session(CommandBuilder::new("sh"), "sh").unwrap();
+        // A job of its own, so it shows up as the foreground process group
+        manager.write(&id, "set -m; sleep 1\n").unwrap();
+        for _ in 0..50 {
+            manager.sample_usage().unwrap();
+            let history = manager.history.lock().unwrap();
+            if let Some(resources) = history.entries().iter().find_map(|e| e.resources.clone()) {
+                assert!(resources.peak_rss_kb > 0);
+                assert!(resources.wall_ms >= 500);
+                manager.close(&id).unwrap();
+                return;
+            }
+            drop(history);
+            thread::sleep(Duration::from_millis(100));
+        }
+        panic!("the command was never measured");
+    }
+}
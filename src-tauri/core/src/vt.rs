@@ -0,0 +1,232 @@
+//! Minimal streaming scanner for the control sequences in PTY output. Printable text is
+//! skipped (the frontend renders it); only sequences the backend reacts to are surfaced.
+//! Sequences split across reads are reassembled.
+
+// OSC/DCS payloads beyond this are discarded rather than buffered without bound
+const MAX_STRING_LEN: usize = 1024 * 1024;
+const MAX_PARAMS_LEN: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Sequence {
+    Csi {
+        // Leading '?', '>', '<' or '=' marker, if any
+        private: Option<u8>,
+        params: String,
+        intermediates: Vec<u8>,
+        final_byte: u8,
+    },
+    Esc {
+        intermediates: Vec<u8>,
+        final_byte: u8,
+    },
+    Osc(Vec<u8>),
+    Dcs(Vec<u8>),
+    Bell,
+    // Only reported by scanners created with `Scanner::with_text`
+    Text(Vec<u8>),
+    // BS, HT, LF, VT, FF, CR
+    Control(u8),
+}
+
+impl Sequence {
+    /// Numeric CSI parameters; missing values are reported as 0.
+    pub fn params(&self) -> Vec<u32> {
+        match self {
+            Sequence::Csi { params, .. } if !params.is_empty() => params
+                .split(';')
+                .map(|p| p.split(':').next().unwrap_or("").parse().unwrap_or(0))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    Osc,
+    Dcs,
+    // Saw ESC inside an OSC/DCS string; expecting '\' to terminate it
+    StringEsc,
+}
+
+#[derive(Default)]
+pub struct Scanner {
+    state: State,
+    // Which string type StringEsc returns to / terminates
+    string_is_dcs: bool,
+    private: Option<u8>,
+    params: String,
+    intermediates: Vec<u8>,
+    string: Vec<u8>,
+    overflow: bool,
+    // Also surface printable text and layout controls (for the screen model)
+    text: bool,
+}
+
+impl Scanner {
+    pub fn with_text() -> Self {
+        Scanner { text: true, ..Scanner::default() }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Sequence> {
+        let mut out = Vec::new();
+        for &byte in data {
+            self.advance(byte, &mut out);
+        }
+        out
+    }
+
+    fn enter_escape(&mut self) {
+        self.state = State::Escape;
+        self.intermediates.clear();
+    }
+
+    fn push_string(&mut self, byte: u8) {
+        if self.string.len() >= MAX_STRING_LEN {
+            self.overflow = true;
+        } else {
+            self.string.push(byte);
+        }
+    }
+
+    fn finish_string(&mut self, out: &mut Vec<Sequence>) {
+        let data = std::mem::take(&mut self.string);
+        if !std::mem::take(&mut self.overflow) {
+            out.push(if self.string_is_dcs { Sequence::Dcs(data) } else { Sequence::Osc(data) });
+        }
+        self.state = State::Ground;
+    }
+
+    fn advance(&mut self, byte: u8, out: &mut Vec<Sequence>) {
+        match self.state {
+            State::Ground => match byte {
+                0x1b => self.enter_escape(),
+                0x07 => out.push(Sequence::Bell),
+                0x08..=0x0d if self.text => out.push(Sequence::Control(byte)),
+                0x20..=0x7e | 0x80.. if self.text => match out.last_mut() {
+                    Some(Sequence::Text(text)) => text.push(byte),
+                    _ => out.push(Sequence::Text(vec![byte])),
+                },
+                _ => {}
+            },
+            State::Escape => match byte {
+                b'[' => {
+                    self.state = State::Csi;
+                    self.private = None;
+                    self.params.clear();
+                    self.intermediates.clear();
+                }
+                b']' | b'P' => {
+                    self.state = if byte == b'P' { State::Dcs } else { State::Osc };
+                    self.string_is_dcs = byte == b'P';
+                    self.string.clear();
+                    self.overflow = false;
+                }
+                0x1b => self.enter_escape(),
+                0x20..=0x2f => self.intermediates.push(byte),
+                0x30..=0x7e => {
+                    out.push(Sequence::Esc {
+                        intermediates: std::mem::take(&mut self.intermediates),
+                        final_byte: byte,
+                    });
+                    self.state = State::Ground;
+                }
+                // C0 controls inside an escape are executed, not part of it
+                0x07 => out.push(Sequence::Bell),
+                _ => self.state = State::Ground,
+            },
+            State::Csi => match byte {
+                b'?' | b'>' | b'<' | b'=' if self.params.is_empty() && self.private.is_none() => {
+                    self.private = Some(byte);
+                }
+                0x30..=0x3f if self.params.len() < MAX_PARAMS_LEN => self.params.push(byte as char),
+                0x30..=0x3f => {}
+                0x20..=0x2f => self.intermediates.push(byte),
+                0x40..=0x7e => {
+                    out.push(Sequence::Csi {
+                        private: self.private.take(),
+                        params: std::mem::take(&mut self.params),
+                        intermediates: std::mem::take(&mut self.intermediates),
+                        final_byte: byte,
+                    });
+                    self.state = State::Ground;
+                }
+                0x1b => self.enter_escape(),
+                0x07 => out.push(Sequence::Bell),
+                _ => {}
+            },
+            State::Osc | State::Dcs => match byte {
+                0x07 if self.state == State::Osc => self.finish_string(out),
+                0x1b => self.state = State::StringEsc,
+                0x18 | 0x1a => self.state = State::Ground, // CAN / SUB abort
+                _ => self.push_string(byte),
+            },
+            State::StringEsc => {
+                if byte == b'\\' {
+                    self.finish_string(out);
+                } else {
+                    // Unterminated string: drop it and treat this as a new escape
+                    self.string.clear();
+                    self.enter_escape();
+                    self.advance(byte, out);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_private_csi_across_reads() {
+        let mut scanner = Scanner::default();
+        assert!(scanner.feed(b"hello \x1b[?10").is_empty());
+        let seqs = scanner.feed(b"00;1006h world");
+        assert_eq!(seqs, vec![Sequence::Csi {
+            private: Some(b'?'),
+            params: "1000;1006".into(),
+            intermediates: vec![],
+            final_byte: b'h',
+        }]);
+        assert_eq!(seqs[0].params(), vec![1000, 1006]);
+    }
+
+    #[test]
+    fn parses_osc_with_either_terminator() {
+        let mut scanner = Scanner::default();
+        let seqs = scanner.feed(b"\x1b]0;title\x07\x1b]7;file:///tmp\x1b\\");
+        assert_eq!(seqs, vec![
+            Sequence::Osc(b"0;title".to_vec()),
+            Sequence::Osc(b"7;file:///tmp".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn reports_bell_and_dcs() {
+        let mut scanner = Scanner::default();
+        let seqs = scanner.feed(b"ding\x07\x1bP+q544e\x1b\\");
+        assert_eq!(seqs, vec![Sequence::Bell, Sequence::Dcs(b"+q544e".to_vec())]);
+    }
+
+    #[test]
+    fn text_is_only_reported_when_requested() {
+        assert!(Scanner::default().feed(b"ab\r\n").is_empty());
+        let seqs = Scanner::with_text().feed(b"ab\x1b[Kc\r\n");
+        assert_eq!(seqs[0], Sequence::Text(b"ab".to_vec()));
+        assert_eq!(seqs[2], Sequence::Text(b"c".to_vec()));
+        assert_eq!(&seqs[3..], &[Sequence::Control(b'\r'), Sequence::Control(b'\n')]);
+    }
+
+    #[test]
+    fn escape_interrupts_unterminated_osc() {
+        let mut scanner = Scanner::default();
+        let seqs = scanner.feed(b"\x1b]0;broken\x1bc");
+        assert_eq!(seqs, vec![Sequence::Esc { intermediates: vec![], final_byte: b'c' }]);
+    }
+}
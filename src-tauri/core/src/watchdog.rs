@@ -0,0 +1,251 @@
+//! Watchdog for IPC command handlers: each call is timed, and one that runs past the
+//! threshold is logged and reported with `command-slow` — once while it's still stuck
+//! and again when it finally returns — so a frozen UI can be traced to the command that
+//! blocked it. Recent slow calls and per-command worst cases are kept for the frontend.
+
+use crate::events::EventSink;
+use crate::unix_now;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_THRESHOLD: Duration = Duration::from_millis(250);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_RECENT: usize = 50;
+
+/// Also the `command-slow` payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SlowCommand {
+    pub command: String,
+    // How long it had run when reported
+    pub duration_ms: u64,
+    // False while it's still running
+    pub finished: bool,
+    // Unix seconds it started
+    pub started_at: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SlowCommandStats {
+    pub command: String,
+    pub slow_calls: u64,
+    pub max_ms: u64,
+    pub total_slow_ms: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WatchdogReport {
+    pub threshold_ms: u64,
+    // Newest last
+    pub recent: Vec<SlowCommand>,
+    // Slowest first
+    pub commands: Vec<SlowCommandStats>,
+    // Calls running past the threshold right now
+    pub stuck: Vec<SlowCommand>,
+}
+
+struct InFlight {
+    command: String,
+    started: Instant,
+    started_at: u64,
+    reported: bool,
+}
+
+#[derive(Default)]
+struct Records {
+    in_flight: HashMap<u64, InFlight>,
+    recent: VecDeque<SlowCommand>,
+    stats: HashMap<String, SlowCommandStats>,
+}
+
+pub struct CommandWatchdog {
+    events: Arc<dyn EventSink>,
+    threshold: Mutex<Duration>,
+    next_id: AtomicU64,
+    records: Mutex<Records>,
+}
+
+/// Finishes timing its call when dropped.
+pub struct WatchedCall<'a> {
+    watchdog: &'a CommandWatchdog,
+    id: u64,
+}
+
+impl Drop for WatchedCall<'_> {
+    fn drop(&mut self) {
+        self.watchdog.finish(self.id, Instant::now());
+    }
+}
+
+impl CommandWatchdog {
+    pub fn new(events: Arc<dyn EventSink>) -> Self {
+        CommandWatchdog {
+            events,
+            threshold: Mutex::new(DEFAULT_THRESHOLD),
+            next_id: AtomicU64::new(0),
+            records: Mutex::new(Records::default()),
+        }
+    }
+
+    pub fn set_threshold(&self, threshold: Duration) -> Result<(), String> {
+        if threshold.is_zero() {
+            return Err("Threshold must be positive".into());
+        }
+        *self.threshold.lock().map_err(|_| "Lock poisoned")? = threshold;
+        Ok(())
+    }
+
+    /// Times a call to `command` until the returned guard is dropped.
+    pub fn watch(&self, command: &str) -> WatchedCall<'_> {
+        WatchedCall { watchdog: self, id: self.begin(command, Instant::now()) }
+    }
+
+    fn begin(&self, command: &str, now: Instant) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        if let Ok(mut records) = self.records.lock() {
+            let call = InFlight { command: command.to_string(), started: now, started_at: unix_now(), reported: false };
+            records.in_flight.insert(id, call);
+        }
+        id
+    }
+
+    fn finish(&self, id: u64, now: Instant) {
+        let Ok(threshold) = self.threshold.lock().map(|t| *t) else { return };
+        let Ok(mut records) = self.records.lock() else { return };
+        let Some(call) = records.in_flight.remove(&id) else { return };
+        let elapsed = now.saturating_duration_since(call.started);
+        if elapsed < threshold {
+            return;
+        }
+        let slow = slow_command(&call, elapsed, true);
+        let stats = records.stats.entry(call.command.clone()).or_insert_with(|| SlowCommandStats {
+            command: call.command.clone(),
+            ..Default::default()
+        });
+        stats.slow_calls += 1;
+        stats.max_ms = stats.max_ms.max(slow.duration_ms);
+        stats.total_slow_ms += slow.duration_ms;
+        if records.recent.len() == MAX_RECENT {
+            records.recent.pop_front();
+        }
+        records.recent.push_back(slow.clone());
+        drop(records);
+        eprintln!("Command {} took {}ms", slow.command, slow.duration_ms);
+        self.events.emit("command-slow", slow);
+    }
+
+    /// Reports calls that have been running past the threshold, once each.
+    fn check_stuck(&self, now: Instant) {
+        let Ok(threshold) = self.threshold.lock().map(|t| *t) else { return };
+        let Ok(mut records) = self.records.lock() else { return };
+        let mut stuck = Vec::new();
+        for call in records.in_flight.values_mut() {
+            let elapsed = now.saturating_duration_since(call.started);
+            if !call.reported && elapsed >= threshold {
+                call.reported = true;
+                stuck.push(slow_command(call, elapsed, false));
+            }
+        }
+        drop(records);
+        for slow in stuck {
+            eprintln!("Command {} has been running for {}ms", slow.command, slow.duration_ms);
+            self.events.emit("command-slow", slow);
+        }
+    }
+
+    pub fn report(&self) -> Result<WatchdogReport, String> {
+        let threshold = *self.threshold.lock().map_err(|_| "Lock poisoned")?;
+        let records = self.records.lock().map_err(|_| "Lock poisoned")?;
+        let now = Instant::now();
+        let mut commands: Vec<SlowCommandStats> = records.stats.values().cloned().collect();
+        commands.sort_by(|a, b| b.max_ms.cmp(&a.max_ms).then_with(|| a.command.cmp(&b.command)));
+        let stuck = records.in_flight.values()
+            .map(|call| (call, now.saturating_duration_since(call.started)))
+            .filter(|(_, elapsed)| *elapsed >= threshold)
+            .map(|(call, elapsed)| slow_command(call, elapsed, false))
+            .collect();
+        Ok(WatchdogReport {
+            threshold_ms: threshold.as_millis() as u64,
+            recent: records.recent.iter().cloned().collect(),
+            commands,
+            stuck,
+        })
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        let mut records = self.records.lock().map_err(|_| "Lock poisoned")?;
+        records.recent.clear();
+        records.stats.clear();
+        Ok(())
+    }
+
+    /// Checks for stuck calls in the background for as long as the watchdog lives.
+    pub fn start(self: &Arc<Self>) {
+        let watchdog = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+            let Some(watchdog) = watchdog.upgrade() else { return };
+            watchdog.check_stuck(Instant::now());
+        });
+    }
+}
+
+fn slow_command(call: &InFlight, elapsed: Duration, finished: bool) -> SlowCommand {
+    SlowCommand {
+        command: call.command.clone(),
+        duration_ms: elapsed.as_millis() as u64,
+        finished,
+        started_at: call.started_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::testing::RecordingSink;
+
+    #[test]
+    fn reports_slow_and_stuck_calls() {
+        let sink = Arc::new(RecordingSink::default());
+        let watchdog = CommandWatchdog::new(sink.clone());
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+
+        let fast = watchdog.begin("resize_pty", start);
+        watchdog.finish(fast, ms(10));
+        let blocked = watchdog.begin("write_to_pty", start);
+        watchdog.check_stuck(ms(100));
+        assert!(sink.named("command-slow").is_empty());
+
+        watchdog.check_stuck(ms(300));
+        watchdog.check_stuck(ms(400));
+        watchdog.finish(blocked, ms(900));
+        let events = sink.named("command-slow");
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0]["finished"].clone(), events[0]["duration_ms"].clone()), (false.into(), 300.into()));
+        assert_eq!((events[1]["finished"].clone(), events[1]["duration_ms"].clone()), (true.into(), 900.into()));
+
+        let report = watchdog.report().unwrap();
+        assert_eq!(report.recent.len(), 1);
+        assert!(report.stuck.is_empty());
+        let expected = SlowCommandStats { command: "write_to_pty".into(), slow_calls: 1, max_ms: 900, total_slow_ms: 900 };
+        assert_eq!(report.commands, vec![expected]);
+    }
+
+    #[test]
+    fn threshold_is_adjustable() {
+        let watchdog = CommandWatchdog::new(Arc::new(RecordingSink::default()));
+        assert!(watchdog.set_threshold(Duration::ZERO).is_err());
+        watchdog.set_threshold(Duration::from_millis(20)).unwrap();
+        let start = Instant::now();
+        let id = watchdog.begin("list_sessions", start);
+        watchdog.finish(id, start + Duration::from_millis(50));
+        assert_eq!(watchdog.report().unwrap().threshold_ms, 20);
+        assert_eq!(watchdog.report().unwrap().recent.len(), 1);
+        watchdog.clear().unwrap();
+        assert!(watchdog.report().unwrap().commands.is_empty());
+    }
+}
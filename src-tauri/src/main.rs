@@ -1,317 +1,2152 @@
-#![allow(unexpected_cfgs)]
+use shelll_core::accessibility::{self, AccessibilityStatus, AccessibilityWatcher};
+use shelll_core::activity::SessionActivity;
+use shelll_core::archive::{ArchiveMetadata, OpenedArchive};
+use shelll_core::automation::{Automation, AutomationStore};
+use shelll_core::bell::{self, BellHooks, BellSettings, BellStore};
+use shelll_core::buffer::{OutputChunk, SessionBuffer, MAX_READ_BYTES};
+use shelll_core::chrome::{self, DragRegion, TitlebarOptions, WindowChrome};
+use shelll_core::clipboard::{ClipboardSettings, ClipboardStore};
+use shelll_core::cmdline::{self, ParsedCommandLine, ShellDialect};
+use shelll_core::colors::ColorTheme;
+use shelll_core::config::{OnboardingEvent, OnboardingState, OnboardingStore};
+use shelll_core::diagnostics::{self, DiagnosticsReport};
+use shelll_core::display::CellMetrics;
+use shelll_core::dock::{self, DockEdge, DockOptions, WindowDock};
+use shelll_core::dock_menu::{self, DockMenuNewSessionPayload};
+use shelll_core::dropdown::{DropdownOptions, WindowDropdown};
+use shelll_core::env::{EnvDiff, EnvMap, ProcessEnv};
+use shelll_core::eol::InputTranslation;
+use shelll_core::feedback::{self, Feedback, FeedbackSettings, FeedbackStore};
+use shelll_core::files::{self, QuarantineInfo, SafeOpenOptions};
+use shelll_core::find::FindResult;
+use shelll_core::focus::{self, DelayStrategy, FocusMonitor, FocusSubscription, HandoffReport, RunningApp};
+use shelll_core::follow::{self, FollowOptions, FollowSide, WindowFollower};
+use shelll_core::hibernate::HibernationPolicy;
+use shelll_core::forward::{ForwardKind, PortForward};
+use shelll_core::guest;
+use shelll_core::heads_up::{FocusSessionPayload, HeadsUpHooks, HeadsUpSettings, HeadsUpStore};
+use shelll_core::history::{HistoryMatch, HistoryStore};
+use shelll_core::hotkey::{self, Chord, HotkeySettings, HotkeyStatus, HotkeyStore};
+use shelll_core::html::RenderedOutput;
+use shelll_core::info::{SessionInfo, SessionSummary};
+use shelll_core::inputs::RecentInput;
+use shelll_core::keyboard::{self, InjectionMethod};
+use shelll_core::latency::LatencyStats;
+use shelll_core::lifecycle::{self, LifecycleEvent};
+use shelll_core::links::{self, LinkSettings, LinkStore};
+use shelll_core::long_command::{LongCommandSettings, LongCommandStore};
+use shelll_core::memory::LowMemoryStatus;
+use shelll_core::notification;
+use shelll_core::permissions::{Grant, Operation, PermissionRegistry, ScopedSessions};
+use shelll_core::pipe::PipeInfo;
+use shelll_core::pool::{PoolSettings, PoolStore};
+use shelll_core::predict::PredictionMode;
+use shelll_core::preexec::{PreExecSettings, PreExecStore};
+use shelll_core::process::TaggedProcess;
+use shelll_core::profiles::{Profile, ProfileStore};
+use shelll_core::progress;
+use shelll_core::pty::{OutputMark, SessionOptions};
+use shelll_core::recent_dirs::{RecentDir, RecentDirsStore};
+use shelll_core::recording::{RecordingOptions, RecordingStats};
+use shelll_core::redact::{RedactionPreview, RedactionSettings, RedactionStore};
+use shelll_core::remote::{RemoteSettings, RemoteStore};
+use shelll_core::remote_edit::{remote_edit_function, RemoteEdit, RemoteEditSettings, RemoteEditStore};
+use shelll_core::responder::TerminalIdentity;
+use shelll_core::rules::{RuleTrigger, RulesStatus, RulesStore};
+use shelll_core::scratchpad::{ScratchpadSettings, ScratchpadStore, SCRATCHPAD_NAME};
+use shelll_core::screen::{CursorPosition, ScreenRect};
+use shelll_core::semantic::CommandRecord;
+use shelll_core::template::{TemplateKind, TemplateSettings, TemplateStore};
+use shelll_core::shell_integration::{self, IntegrationShell, IntegrationStatus};
+use shelll_core::signal::SessionSignal;
+use shelll_core::snapshot::SessionSnapshot;
+use shelll_core::stream::RunOptions;
+use shelll_core::target_title::{self, TargetTitleMonitor, TargetWindowTitle};
+use shelll_core::terminal::MouseMode;
+use shelll_core::terminfo::{self, TerminfoDiagnosis};
+use shelll_core::tmpdir;
+use shelll_core::tray::{self, TrayAction, TrayEntry, TraySession, TraySessionSelectedPayload};
+use shelll_core::timeline::{TimeRange, TimelineFormat};
+use shelll_core::update::{self, ReleaseInfo, UpdateAvailablePayload};
+use shelll_core::watchdog::{CommandWatchdog, WatchdogReport};
+use shelll_core::events::EventRoutes;
+use shelll_core::{EventSink, SessionManager};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{
+    CustomMenuItem, GlobalShortcutManager, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu, SystemTrayMenuItem,
+};
+#[cfg(target_os = "macos")]
+use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
+
+// Forwards core events to the webviews that own the session or subscribed to the event,
+// or to every webview if none did
+struct TauriEvents {
+    app: tauri::AppHandle,
+    routes: Arc<Mutex<EventRoutes>>,
+}
+
+impl EventSink for TauriEvents {
+    fn emit_value(&self, event: &str, payload: serde_json::Value) {
+        let targets = self.routes.lock().ok().and_then(|r| r.targets(event, &payload));
+        match targets {
+            Some(labels) => {
+                for label in labels {
+                    if let Some(window) = self.app.get_window(&label) {
+                        let _ = window.emit(event, payload.clone());
+                    }
+                }
+            }
+            None => {
+                let _ = self.app.emit_all(event, payload);
+            }
+        }
+    }
+}
+
+struct AppState {
+    events: Arc<dyn EventSink>,
+    routes: Arc<Mutex<EventRoutes>>,
+    sessions: Arc<SessionManager>,
+    onboarding: Mutex<OnboardingStore>,
+    profiles: Mutex<ProfileStore>,
+    automations: Mutex<AutomationStore>,
+    chrome: Mutex<WindowChrome>,
+    feedback: Mutex<FeedbackStore>,
+    clipboard: Mutex<ClipboardStore>,
+    remote_edit: Mutex<RemoteEditStore>,
+    links: Mutex<LinkStore>,
+    heads_up: Mutex<HeadsUpStore>,
+    bell: Mutex<BellStore>,
+    long_command: Mutex<LongCommandStore>,
+    hotkey: Mutex<HotkeyStore>,
+    pre_exec: Mutex<PreExecStore>,
+    redaction: Mutex<RedactionStore>,
+    templates: Mutex<TemplateStore>,
+    remote: Mutex<RemoteStore>,
+    scratchpad: Mutex<ScratchpadStore>,
+    pool: Mutex<PoolStore>,
+    rules: Mutex<RulesStore>,
+    permissions: PermissionRegistry,
+    focus: FocusMonitor,
+    accessibility: Arc<AccessibilityWatcher>,
+    target_titles: TargetTitleMonitor,
+    // Times every command handler (see `watched`)
+    watchdog: Arc<CommandWatchdog>,
+    // Edge-docked windows by label
+    docks: Mutex<HashMap<String, WindowDock>>,
+    // The main window's dropdown mode
+    dropdown: Mutex<WindowDropdown>,
+    // Windows following another app's window, by label; only dropped on the main thread
+    followers: Mutex<HashMap<String, WindowFollower>>,
+}
+
+#[tauri::command]
+fn check_accessibility_permission(state: tauri::State<AppState>) -> Result<AccessibilityStatus, String> {
+    let status = accessibility::accessibility_status();
+    state.accessibility.update(status)?;
+    Ok(status)
+}
+
+// Shows the system prompt (and optionally System Settings); the grant arrives later as
+// accessibility-permission-changed
+#[tauri::command]
+fn request_accessibility_permission(open_settings: Option<bool>) -> Result<AccessibilityStatus, String> {
+    accessibility::request_accessibility(open_settings.unwrap_or(false))
+}
+
+// Off the main thread: Accessibility calls wait on the other app
+#[tauri::command(async)]
+fn get_target_window_title(bundle_id: String) -> Result<Option<String>, String> {
+    target_title::focused_window_title(&bundle_id)
+}
+
+// Reports target-window-title-changed whenever the app's focused window title changes;
+// returns the current title
+#[tauri::command(async)]
+fn watch_target_window_title(bundle_id: String, state: tauri::State<AppState>) -> Result<Option<String>, String> {
+    state.target_titles.watch(&bundle_id)
+}
+
+#[tauri::command]
+fn unwatch_target_window_title(bundle_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.target_titles.unwatch(&bundle_id)
+}
+
+// The project open in the target editor's focused window; the first focus target if no
+// bundle id is given
+#[tauri::command(async)]
+fn get_target_project_dir(bundle_id: Option<String>, state: tauri::State<AppState>) -> Result<Option<String>, String> {
+    let bundle_id = bundle_id
+        .or_else(|| state.focus.subscriptions().into_iter().find_map(|s| s.targets.into_iter().next()))
+        .ok_or("No target app")?;
+    match target_title::focused_window_title(&bundle_id)? {
+        Some(title) => state.sessions.target_project_dir(&bundle_id, &title),
+        None => Ok(None),
+    }
+}
+
+// Keeps the session in the project of whichever watched target changes window (see
+// watch_target_window_title); None stops it
+#[tauri::command]
+fn set_project_auto_cd(session_id: Option<String>, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_project_auto_cd(session_id.as_deref())
+}
+
+#[tauri::command]
+fn get_running_apps() -> Vec<RunningApp> {
+    focus::get_running_applications()
+}
+
+#[tauri::command]
+fn get_frontmost_app() -> Option<String> {
+    focus::get_frontmost_app_name()
+}
+
+// Types into another app with the current keyboard layout; off the main thread since it
+// waits for the app to activate
+#[tauri::command(async)]
+fn send_text_to_app(app_name: String, text: String, method: Option<InjectionMethod>) -> Result<(), String> {
+    focus::send_text_to_app(&app_name, &text, method.unwrap_or_default())
+}
+
+// Like send_text_to_app, but types only once the focus monitor has seen the target in
+// front, instead of after a fixed sleep
+#[tauri::command(async)]
+fn focus_target_and_send(
+    bundle_id: String,
+    text: String,
+    delay_strategy: Option<DelayStrategy>,
+    method: Option<InjectionMethod>,
+    state: tauri::State<AppState>,
+) -> Result<HandoffReport, String> {
+    state.focus.focus_target_and_send(&bundle_id, &text, delay_strategy.unwrap_or_default(), method.unwrap_or_default())
+}
+
+// Brings a running app to the front by bundle id, e.g. to switch back to the target app
+#[tauri::command]
+fn activate_app(bundle_id: String) -> Result<RunningApp, String> {
+    focus::activate_running_app(&bundle_id)
+}
+
+#[tauri::command]
+fn get_keyboard_layout() -> Option<String> {
+    keyboard::keyboard_layout()
+}
+
+const FOCUS_MONITOR_EVENTS: [&str; 3] = ["app-focus-changed", "app-launched", "app-terminated"];
+
+// Each caller gets its own subscription (and its own app-focus-changed, app-launched and
+// app-terminated events, tagged with the returned id). Targets are bundle ids, any number of them; app names still match too
+#[tauri::command]
+fn start_focus_monitor(window: tauri::Window, targets: Vec<String>, state: tauri::State<AppState>) -> Result<String, String> {
+    if let Ok(mut routes) = state.routes.lock() {
+        for event in FOCUS_MONITOR_EVENTS {
+            routes.subscribe(event, window.label());
+        }
+    }
+    state.focus.subscribe(window.label(), targets)
+}
+
+#[tauri::command]
+fn set_focus_monitor_targets(subscription_id: String, targets: Vec<String>, state: tauri::State<AppState>) -> Result<(), String> {
+    state.focus.set_targets(&subscription_id, targets)
+}
+
+#[tauri::command]
+fn add_focus_monitor_target(subscription_id: String, target: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.focus.add_target(&subscription_id, target)
+}
+
+#[tauri::command]
+fn remove_focus_monitor_target(subscription_id: String, target: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.focus.remove_target(&subscription_id, &target)
+}
+
+#[tauri::command]
+fn stop_focus_monitor(window: tauri::Window, subscription_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.focus.unsubscribe(&subscription_id)?;
+    if !state.focus.subscriptions().iter().any(|s| s.owner == window.label()) {
+        if let Ok(mut routes) = state.routes.lock() {
+            for event in FOCUS_MONITOR_EVENTS {
+                routes.unsubscribe(event, window.label());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_focus_monitors(state: tauri::State<AppState>) -> Vec<FocusSubscription> {
+    state.focus.subscriptions()
+}
+
+#[tauri::command]
+fn create_pty_session(
+    window: tauri::Window,
+    profile: Option<String>,
+    guest: Option<bool>,
+    options: Option<SessionOptions>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let profile = state.profiles.lock().map_err(|_| "Lock poisoned")?.resolve(profile.as_deref())?;
+    // Shell, arguments, working directory, extra env and initial size; unset fields use the defaults
+    let options = options.unwrap_or_default();
+    // Guest sessions get a temporary HOME and no history, all deleted on close
+    let session_id = if guest.unwrap_or(false) {
+        state.sessions.create_guest_session(&profile, &options)?
+    } else {
+        state.sessions.create_session(&profile, &options)?
+    };
+    claim_session(&state, &session_id, &window)?;
+    state.sessions.fill_pool();
+    Ok(session_id)
+}
+
+// Warm shells use the default profile, so they're replaced when it changes
+fn configure_pool(state: &AppState) -> Result<(), String> {
+    let profile = state.profiles.lock().map_err(|_| "Lock poisoned")?.resolve(None)?;
+    let settings = state.pool.lock().map_err(|_| "Lock poisoned")?.settings.clone();
+    state.sessions.configure_pool(&settings, &profile)
+}
+
+#[tauri::command]
+fn get_pool_settings(state: tauri::State<AppState>) -> Result<PoolSettings, String> {
+    Ok(state.pool.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
+
+// How many idle shells to keep ready for new tabs (0 turns the pool off)
+#[tauri::command]
+fn set_pool_settings(settings: PoolSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    {
+        let mut store = state.pool.lock().map_err(|_| "Lock poisoned")?;
+        store.settings = settings;
+        store.save()?;
+    }
+    configure_pool(&state)
+}
+
+fn claim_session(state: &AppState, session_id: &str, window: &tauri::Window) -> Result<(), String> {
+    state.routes.lock().map_err(|_| "Lock poisoned")?.claim_session(session_id, window.label());
+    Ok(())
+}
+
+// Another window starts showing the session (e.g. a tab dragged out); its events are
+// delivered there too
+#[tauri::command]
+fn attach_session(window: tauri::Window, session_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    claim_session(&state, &session_id, &window)
+}
+
+#[tauri::command]
+fn detach_session(window: tauri::Window, session_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.routes.lock().map_err(|_| "Lock poisoned")?.release_session(&session_id, window.label());
+    Ok(())
+}
+
+// For showing a guest badge on the tab
+#[tauri::command]
+fn is_guest_session(session_id: String, state: tauri::State<AppState>) -> Result<bool, String> {
+    state.sessions.is_guest_session(&session_id)
+}
+
+#[tauri::command]
+fn write_to_pty(session_id: String, data: String, paste: Option<bool>, state: tauri::State<AppState>) -> Result<(), String> {
+    if paste.unwrap_or(false) {
+        state.sessions.paste(&session_id, &data)
+    } else {
+        state.sessions.write(&session_id, &data)
+    }
+}
+
+// Opens $EDITOR in a new session and pastes what's saved into this one when it exits
+// (compose-finished); returns the editor's session id
+#[tauri::command]
+fn compose_in_editor(
+    session_id: String,
+    initial: Option<String>,
+    editor: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    state.sessions.compose_in_editor(&session_id, initial.as_deref(), editor.as_deref())
+}
+
+// Directories commands were recently run in, newest first (also the Dock menu's)
+#[tauri::command]
+fn get_recent_dirs(state: tauri::State<AppState>) -> Result<Vec<RecentDir>, String> {
+    state.sessions.recent_dirs()
+}
+
+#[tauri::command]
+fn clear_recent_dirs(state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.clear_recent_dirs()
+}
+
+// Line-ending translation for a session's input (serial devices, remote systems)
+#[tauri::command]
+fn set_input_translation(session_id: String, translation: InputTranslation, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_input_translation(&session_id, translation)
+}
+
+#[tauri::command]
+fn get_redaction_settings(state: tauri::State<AppState>) -> Result<RedactionSettings, String> {
+    Ok(state.redaction.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
+
+// Secret patterns scrubbed from recordings, the history file and exports
+#[tauri::command]
+fn set_redaction_settings(settings: RedactionSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_redaction_settings(&settings)?;
+    let mut store = state.redaction.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings;
+    store.save()
+}
+
+// What would be redacted from `text`, with unsaved `settings` if given
+#[tauri::command]
+fn preview_redaction(text: String, settings: Option<RedactionSettings>, state: tauri::State<AppState>) -> Result<RedactionPreview, String> {
+    state.sessions.preview_redaction(&text, settings.as_ref())
+}
+
+#[tauri::command]
+fn get_input_translation(session_id: String, state: tauri::State<AppState>) -> Result<InputTranslation, String> {
+    state.sessions.input_translation(&session_id)
+}
+
+// Makes a session read-only (e.g. a production SSH session being watched) or writable again
+#[tauri::command]
+fn lock_session_input(session_id: String, locked: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.lock_input(&session_id, locked)
+}
+
+#[tauri::command]
+fn set_latency_probe(session_id: String, enabled: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_latency_probe(&session_id, enabled)
+}
+
+#[tauri::command]
+fn get_latency_stats(session_id: String, state: tauri::State<AppState>) -> Result<Option<LatencyStats>, String> {
+    state.sessions.latency_stats(&session_id)
+}
+
+// `cell_width`/`cell_height` are the font's cell size in CSS pixels, used for the PTY's pixel size
+#[tauri::command]
+fn resize_pty(
+    session_id: String,
+    rows: u16,
+    cols: u16,
+    cell_width: Option<f64>,
+    cell_height: Option<f64>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    if let (Some(width), Some(height)) = (cell_width, cell_height) {
+        state.sessions.set_cell_size(width, height)?;
+    }
+    state.sessions.resize(&session_id, rows, cols)
+}
+
+#[tauri::command]
+fn get_cell_metrics(state: tauri::State<AppState>) -> Result<CellMetrics, String> {
+    state.sessions.cell_metrics()
+}
+
+#[tauri::command]
+fn close_pty_session(session_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.close(&session_id)?;
+    state.routes.lock().map_err(|_| "Lock poisoned")?.forget_session(&session_id);
+    Ok(())
+}
+
+// Whether the frontend should forward mouse events to the session, and how to encode them
+#[tauri::command]
+fn get_mouse_mode(session_id: String, state: tauri::State<AppState>) -> Result<MouseMode, String> {
+    state.sessions.mouse_mode(&session_id)
+}
+
+#[tauri::command]
+fn get_terminal_identity(state: tauri::State<AppState>) -> Result<TerminalIdentity, String> {
+    state.sessions.terminal_identity()
+}
+
+#[tauri::command]
+fn set_terminal_identity(identity: TerminalIdentity, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_terminal_identity(identity)
+}
+
+// The frontend reports its theme so color queries from programs get the real colors
+#[tauri::command]
+fn set_color_theme(theme: ColorTheme, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_color_theme(theme)
+}
+
+// The theme with the session's OSC 4/10/11/12 changes applied
+#[tauri::command]
+fn get_terminal_colors(session_id: String, state: tauri::State<AppState>) -> Result<ColorTheme, String> {
+    state.sessions.terminal_colors(&session_id)
+}
+
+#[tauri::command]
+fn list_profiles(state: tauri::State<AppState>) -> Result<Vec<Profile>, String> {
+    Ok(state.profiles.lock().map_err(|_| "Lock poisoned")?.list())
+}
+
+#[tauri::command]
+fn save_profile(profile: Profile, state: tauri::State<AppState>) -> Result<(), String> {
+    state.profiles.lock().map_err(|_| "Lock poisoned")?.save_profile(profile)?;
+    configure_pool(&state)
+}
+
+#[tauri::command]
+fn delete_profile(name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.profiles.lock().map_err(|_| "Lock poisoned")?.delete_profile(&name)?;
+    configure_pool(&state)
+}
+
+// Checks the TERM of `profile` (default profile if omitted) against the local terminfo database
+#[tauri::command(async)]
+fn diagnose_terminfo(profile: Option<String>, state: tauri::State<AppState>) -> Result<TerminfoDiagnosis, String> {
+    let profile = state.profiles.lock().map_err(|_| "Lock poisoned")?.resolve(profile.as_deref())?;
+    Ok(terminfo::diagnose_terminfo(&profile.term))
+}
+
+#[tauri::command]
+fn get_remote_settings(state: tauri::State<AppState>) -> Result<RemoteSettings, String> {
+    Ok(state.remote.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
+
+// Host rules coloring remote sessions (e.g. red for prod-*)
+#[tauri::command]
+fn set_remote_settings(settings: RemoteSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_remote_settings(settings.clone())?;
+    let mut store = state.remote.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings;
+    store.save()
+}
+
+// SIGINT/SIGTERM/SIGKILL/SIGHUP to the foreground job, for programs that ignore ^C
+#[tauri::command]
+fn send_signal(session_id: String, signal: SessionSignal, state: tauri::State<AppState>) -> Result<u32, String> {
+    state.sessions.send_signal(&session_id, signal)
+}
+
+// Runs a command without a terminal; output arrives as command-output events tagged
+// stdout or stderr
+#[tauri::command]
+fn run_command(options: RunOptions, state: tauri::State<AppState>) -> Result<String, String> {
+    state.sessions.run_command(&options)
+}
+
+#[tauri::command]
+fn cancel_command(run_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.cancel_command(&run_id)
+}
+
+// Picks the pty-output encoding: the frontend lists the protocol versions it can decode
+#[tauri::command]
+fn negotiate_output_protocol(supported: Vec<u32>, state: tauri::State<AppState>) -> Result<u32, String> {
+    state.sessions.negotiate_output_protocol(&supported)
+}
+
+// With flow control on, a session stops reading its PTY while too much output is unacked
+#[tauri::command]
+fn set_flow_control(enabled: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_flow_control(enabled)
+}
+
+// The frontend has written this many bytes of the session's output to its terminal
+#[tauri::command]
+fn ack_output(session_id: String, bytes: usize, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.ack_output(&session_id, bytes)
+}
+
+// Lines submitted in this tab, newest first, for recall independent of the shell's history
+#[tauri::command]
+fn get_recent_inputs(session_id: String, state: tauri::State<AppState>) -> Result<Vec<RecentInput>, String> {
+    state.sessions.recent_inputs(&session_id)
+}
+
+#[tauri::command]
+fn resend_input(session_id: String, input_id: u64, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.resend_input(&session_id, input_id)
+}
+
+// Erases the last send while it's still unsubmitted at the prompt
+#[tauri::command]
+fn undo_last_send(session_id: String, state: tauri::State<AppState>) -> Result<bool, String> {
+    state.sessions.undo_last_send(&session_id)
+}
+
+// Every open session, so the frontend can rebuild its tabs after a reload
+#[tauri::command]
+fn list_sessions(state: tauri::State<AppState>) -> Result<Vec<SessionSummary>, String> {
+    state.sessions.list_sessions()
+}
+
+// Shell pid, working directory, foreground process and program-set title, for tab titles
+// and confirming before closing a busy session
+#[tauri::command]
+fn get_session_info(session_id: String, state: tauri::State<AppState>) -> Result<SessionInfo, String> {
+    state.sessions.session_info(&session_id)
+}
+
+// The directory the session's shell last reported (OSC 7), for opening a new tab there;
+// None while it's on another host
+#[tauri::command]
+fn get_session_cwd(session_id: String, state: tauri::State<AppState>) -> Result<Option<String>, String> {
+    state.sessions.session_cwd(&session_id)
+}
+
+// Commands the session's shell marked with OSC 133, with exit codes and durations
+#[tauri::command]
+fn get_command_log(session_id: String, state: tauri::State<AppState>) -> Result<Vec<CommandRecord>, String> {
+    state.sessions.command_log(&session_id)
+}
+
+// Shows the session's private TMPDIR in the file manager; returns its path
+#[tauri::command]
+fn open_session_tmp(session_id: String, state: tauri::State<AppState>) -> Result<String, String> {
+    Ok(state.sessions.open_session_tmp(&session_id)?.display().to_string())
+}
+
+// Health check for the troubleshooting page: PTY, shell, terminfo, permissions and config
+#[tauri::command(async)]
+fn run_diagnostics(app: tauri::AppHandle, profile: Option<String>, state: tauri::State<AppState>) -> Result<DiagnosticsReport, String> {
+    let profile = state.profiles.lock().map_err(|_| "Lock poisoned")?.resolve(profile.as_deref())?;
+    let config_dir = app.path_resolver().app_config_dir();
+    Ok(diagnostics::run_diagnostics(&profile, config_dir.as_deref()))
+}
+
+#[tauri::command(async)]
+fn install_terminfo() -> Result<String, String> {
+    terminfo::install_shelll_terminfo()
+}
+
+#[tauri::command]
+fn get_shell_integration_status(app: tauri::AppHandle, shell: IntegrationShell) -> Result<IntegrationStatus, String> {
+    shell_integration::shell_integration_status(shell, &integration_dir(&app)?)
+}
+
+// Hooks for OSC 133 prompt marks and OSC 7, sourced from the shell's startup file;
+// installing twice leaves one copy
+#[tauri::command]
+fn install_shell_integration(app: tauri::AppHandle, shell: IntegrationShell) -> Result<IntegrationStatus, String> {
+    shell_integration::install_shell_integration(shell, &integration_dir(&app)?)
+}
+
+#[tauri::command]
+fn uninstall_shell_integration(app: tauri::AppHandle, shell: IntegrationShell) -> Result<IntegrationStatus, String> {
+    shell_integration::uninstall_shell_integration(shell, &integration_dir(&app)?)
+}
+
+// The hook scripts live in the config dir
+fn integration_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    app.path_resolver().app_config_dir().ok_or_else(|| "No config directory available".to_string())
+}
+
+#[tauri::command]
+fn get_cursor_position(session_id: String, state: tauri::State<AppState>) -> Result<CursorPosition, String> {
+    state.sessions.cursor_position(&session_id)
+}
+
+#[tauri::command]
+fn read_screen_region(session_id: String, rect: ScreenRect, state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+    state.sessions.read_screen_region(&session_id, rect)
+}
+
+#[tauri::command]
+fn list_automations(state: tauri::State<AppState>) -> Result<Vec<Automation>, String> {
+    Ok(state.automations.lock().map_err(|_| "Lock poisoned")?.list())
+}
+
+#[tauri::command]
+fn save_automation(automation: Automation, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut automations = state.automations.lock().map_err(|_| "Lock poisoned")?;
+    automations.save_automation(automation)?;
+    state.sessions.set_automations(automations.list())
+}
+
+#[tauri::command]
+fn delete_automation(name: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut automations = state.automations.lock().map_err(|_| "Lock poisoned")?;
+    automations.delete_automation(&name)?;
+    state.sessions.set_automations(automations.list())
+}
+
+// Returns the steps that were (or, with dry_run, would be) executed
+#[tauri::command(async)]
+fn run_automation(
+    name: String,
+    session_id: Option<String>,
+    dry_run: Option<bool>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<String>, String> {
+    let automation = state.automations.lock().map_err(|_| "Lock poisoned")?.get(&name)?;
+    state.sessions.run_automation(&automation, session_id.as_deref(), dry_run.unwrap_or(false))
+}
+
+// Called on every keystroke of the find bar; match changes then stream as events
+#[tauri::command]
+fn find_incremental(session_id: String, query: String, state: tauri::State<AppState>) -> Result<FindResult, String> {
+    state.sessions.find_incremental(&session_id, &query)
+}
+
+#[tauri::command]
+fn stop_find(session_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.stop_find(&session_id)
+}
+
+// Forwards through the SSH session's control master, or a background tunnel without one.
+// `kind` is "local" (-L, the default) or "remote" (-R).
+#[tauri::command(async)]
+fn add_port_forward(
+    session_id: String,
+    local: String,
+    remote: String,
+    kind: Option<ForwardKind>,
+    state: tauri::State<AppState>,
+) -> Result<PortForward, String> {
+    state.sessions.add_port_forward(&session_id, &local, &remote, kind.unwrap_or_default())
+}
+
+#[tauri::command]
+fn list_port_forwards(state: tauri::State<AppState>) -> Result<Vec<PortForward>, String> {
+    state.sessions.list_port_forwards()
+}
+
+#[tauri::command(async)]
+fn remove_port_forward(forward_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.remove_port_forward(&forward_id)
+}
+
+// Runs `env` at the session's prompt with output hidden; fails while a command is running
+#[tauri::command(async)]
+fn capture_session_env(session_id: String, state: tauri::State<AppState>) -> Result<EnvMap, String> {
+    state.sessions.capture_session_env(&session_id)
+}
+
+// Current environment against the one the shell started with
+#[tauri::command(async)]
+fn diff_session_env(session_id: String, state: tauri::State<AppState>) -> Result<EnvDiff, String> {
+    state.sessions.diff_session_env(&session_id)
+}
+
+// Environment of a process in one of the sessions (e.g. a child's PATH); others are refused
+#[tauri::command(async)]
+fn get_process_env(pid: u32, state: tauri::State<AppState>) -> Result<ProcessEnv, String> {
+    state.sessions.process_env(pid)
+}
+
+// Processes tagged with the session's id, including daemonized ones
+#[tauri::command(async)]
+fn get_session_processes(session_id: String, state: tauri::State<AppState>) -> Result<Vec<TaggedProcess>, String> {
+    state.sessions.session_processes(&session_id)
+}
+
+// Processes left behind by closed sessions or an earlier run of the app
+#[tauri::command(async)]
+fn get_orphaned_processes(state: tauri::State<AppState>) -> Result<Vec<TaggedProcess>, String> {
+    state.sessions.orphaned_processes()
+}
+
+// Pull-based alternative to `pty-output` events; continue from the returned `next_offset`
+#[tauri::command]
+fn read_output_since(
+    session_id: String,
+    offset: u64,
+    max_bytes: Option<usize>,
+    state: tauri::State<AppState>,
+) -> Result<OutputChunk, String> {
+    state.sessions.read_output_since(&session_id, offset, max_bytes.unwrap_or(MAX_READ_BYTES))
+}
+
+// Everything retained for a session, to redraw it after a reload or in a new window
+#[tauri::command]
+fn get_session_buffer(session_id: String, state: tauri::State<AppState>) -> Result<SessionBuffer, String> {
+    state.sessions.session_buffer(&session_id)
+}
+
+// Records cwd, environment and scrollback; off the main thread since reading the
+// environment waits for the shell
+#[tauri::command(async)]
+fn snapshot_session(session_id: String, state: tauri::State<AppState>) -> Result<SessionSnapshot, String> {
+    state.sessions.snapshot_session(&session_id)
+}
+
+#[tauri::command]
+fn list_snapshots(state: tauri::State<AppState>) -> Result<Vec<SessionSnapshot>, String> {
+    state.sessions.list_snapshots()
+}
+
+#[tauri::command]
+fn delete_snapshot(snapshot_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.delete_snapshot(&snapshot_id)
+}
+
+// A new session in the snapshot's directory and environment; its buffer starts with the
+// snapshot's scrollback
+#[tauri::command]
+fn fork_from_snapshot(
+    window: tauri::Window,
+    snapshot_id: String,
+    profile: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let profile = state.profiles.lock().map_err(|_| "Lock poisoned")?.resolve(profile.as_deref())?;
+    let session_id = state.sessions.fork_from_snapshot(&snapshot_id, &profile)?;
+    claim_session(&state, &session_id, &window)?;
+    Ok(session_id)
+}
+
+// Called when the user switches to a tab
+#[tauri::command]
+fn focus_session(session_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.focus_session(&session_id)
+}
+
+// MRU order for Ctrl-Tab switching
+#[tauri::command]
+fn get_sessions_by_activity(state: tauri::State<AppState>) -> Result<Vec<SessionActivity>, String> {
+    state.sessions.sessions_by_activity()
+}
+
+// Rects (CSS px) of the custom titlebar that drag the window; `no_drag` excludes tabs
+// and buttons inside them
+#[tauri::command]
+fn set_drag_regions(
+    drag: Vec<DragRegion>,
+    no_drag: Option<Vec<DragRegion>>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let mut chrome = state.chrome.lock().map_err(|_| "Lock poisoned")?;
+    chrome.set_drag_regions(drag, no_drag.unwrap_or_default());
+    Ok(())
+}
+
+// Called on mousedown anywhere in the titlebar; starts a native drag if the point is in
+// a drag region. Returns whether it did.
+#[tauri::command]
+fn start_window_drag(window: tauri::Window, x: f64, y: f64, state: tauri::State<AppState>) -> Result<bool, String> {
+    let chrome = state.chrome.lock().map_err(|_| "Lock poisoned")?;
+    if !chrome.is_drag_point(x, y) {
+        return Ok(false);
+    }
+    window.start_dragging().map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+#[tauri::command]
+fn get_titlebar(state: tauri::State<AppState>) -> Result<TitlebarOptions, String> {
+    Ok(state.chrome.lock().map_err(|_| "Lock poisoned")?.titlebar())
+}
+
+#[tauri::command]
+fn set_titlebar(window: tauri::Window, options: TitlebarOptions, state: tauri::State<AppState>) -> Result<(), String> {
+    // AppKit must be touched on the main thread
+    let (tx, rx) = std::sync::mpsc::channel();
+    #[cfg(target_os = "macos")]
+    let target = window.clone();
+    window
+        .run_on_main_thread(move || {
+            #[cfg(target_os = "macos")]
+            let result = target.ns_window()
+                .map_err(|e| e.to_string())
+                .and_then(|ns_window| chrome::apply_titlebar(ns_window, options));
+            #[cfg(not(target_os = "macos"))]
+            let result = chrome::apply_titlebar(std::ptr::null_mut(), options);
+            let _ = tx.send(result);
+        })
+        .map_err(|e| e.to_string())?;
+    rx.recv().map_err(|_| "Window closed".to_string())??;
+    state.chrome.lock().map_err(|_| "Lock poisoned")?.set_titlebar(options);
+    Ok(())
+}
+
+// Runs `f` with the window's NSWindow on the main thread, where AppKit must be used
+fn with_ns_window<T: Send + 'static>(
+    window: &tauri::Window,
+    f: impl FnOnce(*mut c_void) -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    #[cfg(target_os = "macos")]
+    let target = window.clone();
+    window
+        .run_on_main_thread(move || {
+            #[cfg(target_os = "macos")]
+            let ns_window = target.ns_window().map_err(|e| e.to_string());
+            #[cfg(not(target_os = "macos"))]
+            let ns_window: Result<*mut c_void, String> = Ok(std::ptr::null_mut());
+            let _ = tx.send(ns_window.and_then(f));
+        })
+        .map_err(|e| e.to_string())?;
+    rx.recv().map_err(|_| "Window closed".to_string())?
+}
+
+// Glues the window to a screen edge. With autohide it slides off-screen when it loses
+// focus, and back in on the hotkey or when the pointer touches that edge.
+#[tauri::command]
+fn dock_window(
+    app: tauri::AppHandle,
+    window: tauri::Window,
+    edge: DockEdge,
+    size: f64,
+    autohide: bool,
+    hotkey: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    if window.label() == "main" && state.dropdown.lock().map_err(|_| "Lock poisoned")?.options().is_some() {
+        return Err("The window is in dropdown mode".into());
+    }
+    if state.followers.lock().map_err(|_| "Lock poisoned")?.contains_key(window.label()) {
+        return Err("Stop following the app's window before docking".into());
+    }
+    if let Some(hotkey) = &hotkey {
+        let global = state.hotkey.lock().map_err(|_| "Lock poisoned")?.registered.clone();
+        let in_use: Vec<(String, String)> = global.map(|g| ("the show/hide hotkey".to_string(), g)).into_iter().collect();
+        if let Some(conflict) = hotkey::find_conflict(&Chord::parse(hotkey)?, &in_use) {
+            return Err(conflict);
+        }
+    }
+    let frames = with_ns_window(&window, dock::window_frames)?;
+    let options = DockOptions { edge, size, autohide, hotkey: hotkey.clone() };
+    let (frame, previous_hotkey) = {
+        let mut docks = state.docks.lock().map_err(|_| "Lock poisoned")?;
+        let dock = docks.entry(window.label().to_string()).or_default();
+        let previous = dock.options().and_then(|o| o.hotkey.clone());
+        (dock.dock(options, frames)?, previous)
+    };
+    if previous_hotkey != hotkey {
+        let mut shortcuts = app.global_shortcut_manager();
+        if let Some(previous) = previous_hotkey {
+            let _ = shortcuts.unregister(&previous);
+        }
+        if let Some(hotkey) = hotkey {
+            let target = window.clone();
+            shortcuts
+                .register(&hotkey, move || {
+                    let _ = toggle_dock(&target, &target.state::<AppState>());
+                })
+                .map_err(|e| format!("Failed to register {}: {}", hotkey, e))?;
+        }
+    }
+    with_ns_window(&window, move |ns_window| dock::set_frame(ns_window, frame, true))?;
+    emit_dock_changed(&window, &state)
+}
+
+#[tauri::command]
+fn undock_window(app: tauri::AppHandle, window: tauri::Window, state: tauri::State<AppState>) -> Result<(), String> {
+    let (frame, hotkey) = {
+        let mut docks = state.docks.lock().map_err(|_| "Lock poisoned")?;
+        let dock = docks.get_mut(window.label()).ok_or("Window is not docked")?;
+        let hotkey = dock.options().and_then(|o| o.hotkey.clone());
+        (dock.undock(), hotkey)
+    };
+    if let Some(hotkey) = hotkey {
+        let _ = app.global_shortcut_manager().unregister(&hotkey);
+    }
+    if let Some(frame) = frame {
+        with_ns_window(&window, move |ns_window| dock::set_frame(ns_window, frame, false))?;
+    }
+    emit_dock_changed(&window, &state)
+}
+
+// Keeps the window beside the focused window of the app with `bundle_id`, moving and
+// resizing with it. Needs the Accessibility permission.
+#[tauri::command]
+fn follow_target_window(
+    window: tauri::Window,
+    bundle_id: String,
+    side: Option<FollowSide>,
+    width: f64,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    if window.label() == "main" && state.dropdown.lock().map_err(|_| "Lock poisoned")?.options().is_some() {
+        return Err("The window is in dropdown mode".into());
+    }
+    if state.docks.lock().map_err(|_| "Lock poisoned")?.get(window.label()).is_some_and(|d| d.options().is_some()) {
+        return Err("Undock the window before following another app".into());
+    }
+    if !width.is_finite() || width <= 0.0 {
+        return Err("Width must be positive".into());
+    }
+    let pid = focus::running_app_pid(&bundle_id).ok_or_else(|| format!("{} is not running", bundle_id))?;
+    let options = FollowOptions { side: side.unwrap_or_default(), width };
+    let target = window.clone();
+    // Observers are set up, and replaced ones dropped, on the main thread
+    with_ns_window(&window, move |_| {
+        let label = target.label().to_string();
+        let app = target.app_handle();
+        let follower = WindowFollower::start(pid, move |frame| {
+            if let Some(frame) = frame {
+                let _ = place_beside(&target, frame, &options);
+            }
+        })?;
+        app.state::<AppState>().followers.lock().map_err(|_| "Lock poisoned")?.insert(label, follower);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+fn stop_following_window(window: tauri::Window) -> Result<(), String> {
+    let app = window.app_handle();
+    let label = window.label().to_string();
+    with_ns_window(&window, move |_| {
+        let follower = app.state::<AppState>().followers.lock().map_err(|_| "Lock poisoned")?.remove(&label);
+        follower.map(drop).ok_or_else(|| "Window is not following an app".to_string())
+    })
+}
+
+// Moves the window next to `target` (the followed app's window) on its screen. Runs on
+// the main thread, from the follower's callback.
+fn place_beside(window: &tauri::Window, target: dock::Frame, options: &FollowOptions) -> Result<(), String> {
+    let (center_x, center_y) = (target.x + target.width / 2.0, target.y + target.height / 2.0);
+    let (_, visible) = dock::screen_at(center_x, center_y).ok_or("No screen available")?;
+    let frame = follow::follow_frame(target, visible, options);
+    #[cfg(target_os = "macos")]
+    let ns_window = window.ns_window().map_err(|e| e.to_string())?;
+    #[cfg(not(target_os = "macos"))]
+    let ns_window: *mut c_void = {
+        let _ = window;
+        std::ptr::null_mut()
+    };
+    dock::set_frame(ns_window, frame, false)
+}
+
+// Shows and focuses the main window, or hides it when it's already in front
+fn toggle_main_window(app: &tauri::AppHandle) {
+    let Some(window) = app.get_window("main") else { return };
+    let state = app.state::<AppState>();
+    if state.dropdown.lock().is_ok_and(|d| d.options().is_some()) {
+        let _ = toggle_dropdown(&window, &state);
+        return;
+    }
+    let in_front = window.is_visible().unwrap_or(false)
+        && window.is_focused().unwrap_or(false)
+        && !window.is_minimized().unwrap_or(false);
+    if in_front {
+        let _ = window.hide();
+    } else {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+// Brings the main window up (dropped down, in dropdown mode) without toggling it away
+fn show_main_window(app: &tauri::AppHandle) {
+    let Some(window) = app.get_window("main") else { return };
+    let state = app.state::<AppState>();
+    if state.dropdown.lock().is_ok_and(|d| d.options().is_some()) {
+        let _ = set_dropdown_shown(&window, &state, true);
+        return;
+    }
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+fn tray_menu(entries: &[TrayEntry]) -> SystemTrayMenu {
+    entries.iter().fold(SystemTrayMenu::new(), |menu, entry| match entry {
+        TrayEntry::Item { title, action: Some(action) } => menu.add_item(CustomMenuItem::new(action.id(), title)),
+        TrayEntry::Item { title, action: None } => {
+            menu.add_item(CustomMenuItem::new(format!("label:{}", title), title).disabled())
+        }
+        TrayEntry::Separator => menu.add_native_item(SystemTrayMenuItem::Separator),
+    })
+}
+
+// The tray menu as things stand: open sessions by tab title, and the first focus-monitor
+// target as the paired app
+fn current_tray_entries(state: &AppState) -> Vec<TrayEntry> {
+    let mut sessions: Vec<TraySession> = state.sessions.list_sessions().unwrap_or_default().into_iter()
+        .map(|s| TraySession {
+            title: state.sessions.tab_title(&s.session_id).unwrap_or(s.shell),
+            session_id: s.session_id,
+            alive: s.alive,
+        })
+        .collect();
+    sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+    let paired = state.focus.subscriptions().into_iter().find_map(|s| s.targets.into_iter().next());
+    tray::tray_entries(&sessions, paired.as_deref())
+}
+
+// Rebuilds the tray menu whenever sessions or the paired app change
+fn watch_tray_menu(app: tauri::AppHandle) {
+    thread::spawn(move || {
+        let mut shown = None;
+        loop {
+            let entries = current_tray_entries(&app.state::<AppState>());
+            if shown.as_ref() != Some(&entries) {
+                if app.tray_handle().set_menu(tray_menu(&entries)).is_err() {
+                    return;
+                }
+                shown = Some(entries);
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+}
+
+// Mirrors the sessions' OSC 9;4 progress and unseen bells onto the Dock icon (macOS only)
+fn watch_dock_tile(app: tauri::AppHandle) {
+    if !cfg!(target_os = "macos") {
+        return;
+    }
+    thread::spawn(move || {
+        let mut shown = (progress::SessionProgress::default(), None);
+        loop {
+            let sessions = &app.state::<AppState>().sessions;
+            let progress = sessions.dock_progress().unwrap_or_default();
+            let current = (progress, bell::dock_badge(sessions.unseen_bells().unwrap_or(0), progress));
+            if current != shown {
+                let (progress, badge) = current.clone();
+                let drawn = app.run_on_main_thread(move || {
+                    if let Err(e) = progress::show_dock_progress(progress) {
+                        eprintln!("Failed to show progress on the Dock icon: {}", e);
+                    }
+                    if let Err(e) = bell::set_dock_badge(badge.as_deref()) {
+                        eprintln!("Failed to set the Dock badge: {}", e);
+                    }
+                });
+                if drawn.is_err() {
+                    return;
+                }
+                shown = current;
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    });
+}
+
+// Recent directories come from the sessions; picking one shows the window and leaves
+// starting the session to the frontend (dock-menu-new-session)
+fn install_app_dock_menu(app: tauri::AppHandle) {
+    let sessions = app.state::<AppState>().sessions.clone();
+    let installed = dock_menu::install_dock_menu(
+        move || dock_menu::dock_menu_items(&sessions.recent_dirs().unwrap_or_default()),
+        move |cwd| {
+            show_main_window(&app);
+            app.state::<AppState>().events.emit("dock-menu-new-session", DockMenuNewSessionPayload { cwd });
+        },
+    );
+    if let Err(e) = installed {
+        if cfg!(target_os = "macos") {
+            eprintln!("Failed to set up the Dock menu: {}", e);
+        }
+    }
+}
+
+// Clicking a program's notification brings its session to the front
+fn install_notification_handler(app: tauri::AppHandle) {
+    let installed = notification::install_notification_handler(move |session_id| {
+        show_main_window(&app);
+        app.state::<AppState>().events.emit("focus-session", FocusSessionPayload { session_id });
+    });
+    if let Err(e) = installed {
+        if cfg!(target_os = "macos") {
+            eprintln!("Failed to set up notifications: {}", e);
+        }
+    }
+}
+
+fn handle_tray_action(app: &tauri::AppHandle, action: TrayAction) {
+    let state = app.state::<AppState>();
+    match action {
+        TrayAction::NewSession => {
+            show_main_window(app);
+            state.events.emit("tray-new-session", ());
+        }
+        TrayAction::ToggleWindow => toggle_main_window(app),
+        TrayAction::FocusSession(session_id) => {
+            show_main_window(app);
+            state.events.emit("tray-session-selected", TraySessionSelectedPayload { session_id });
+        }
+        TrayAction::ActivatePaired(target) => {
+            if focus::activate_running_app(&target).is_err() {
+                let _ = focus::activate_app(&target);
+            }
+        }
+        TrayAction::Quit => app.exit(0),
+    }
+}
+
+fn toggle_dropdown(window: &tauri::Window, state: &AppState) -> Result<(), String> {
+    let shown = state.dropdown.lock().map_err(|_| "Lock poisoned")?.is_shown();
+    set_dropdown_shown(window, state, !shown)
+}
+
+// Slides the dropdown down on the display the pointer is on, or back up and out of sight
+fn set_dropdown_shown(window: &tauri::Window, state: &AppState, shown: bool) -> Result<(), String> {
+    if shown {
+        let frames = with_ns_window(window, dock::pointer_screen_frames)?;
+        let slide = state.dropdown.lock().map_err(|_| "Lock poisoned")?.show(frames);
+        let Some((start, end)) = slide else { return Ok(()) };
+        window.unminimize().map_err(|e| e.to_string())?;
+        with_ns_window(window, move |ns_window| dock::set_frame(ns_window, start, false))?;
+        window.show().map_err(|e| e.to_string())?;
+        with_ns_window(window, move |ns_window| dock::set_frame(ns_window, end, true))?;
+        window.set_focus().map_err(|e| e.to_string())?;
+    } else {
+        let frame = state.dropdown.lock().map_err(|_| "Lock poisoned")?.hide();
+        let Some(frame) = frame else { return Ok(()) };
+        with_ns_window(window, move |ns_window| dock::set_frame(ns_window, frame, true))?;
+        window.hide().map_err(|e| e.to_string())?;
+    }
+    emit_dropdown_changed(state)
+}
+
+fn emit_dropdown_changed(state: &AppState) -> Result<(), String> {
+    let payload = state.dropdown.lock().map_err(|_| "Lock poisoned")?.payload();
+    state.events.emit("window-dropdown-changed", payload);
+    Ok(())
+}
+
+// Puts the main window in dropdown mode (floating above other windows, hidden until the
+// hotkey drops it down) or back to a normal window where it was before
+fn apply_dropdown(app: &tauri::AppHandle, state: &AppState, options: Option<DropdownOptions>) -> Result<(), String> {
+    let window = app.get_window("main").ok_or("Main window not found")?;
+    if options.is_some() && state.docks.lock().map_err(|_| "Lock poisoned")?.get("main").is_some_and(|d| d.options().is_some()) {
+        return Err("Undock the window before turning on dropdown mode".into());
+    }
+    let was_enabled = state.dropdown.lock().map_err(|_| "Lock poisoned")?.options().is_some();
+    match options {
+        Some(options) => {
+            let frames = with_ns_window(&window, dock::window_frames)?;
+            state.dropdown.lock().map_err(|_| "Lock poisoned")?.enable(options, frames.window)?;
+            if !was_enabled {
+                with_ns_window(&window, |ns_window| dock::set_floating(ns_window, true))?;
+                window.hide().map_err(|e| e.to_string())?;
+            }
+        }
+        None if was_enabled => {
+            let frame = state.dropdown.lock().map_err(|_| "Lock poisoned")?.disable();
+            with_ns_window(&window, move |ns_window| {
+                dock::set_floating(ns_window, false)?;
+                match frame {
+                    Some(frame) => dock::set_frame(ns_window, frame, false),
+                    None => Ok(()),
+                }
+            })?;
+            window.show().map_err(|e| e.to_string())?;
+        }
+        None => return Ok(()),
+    }
+    emit_dropdown_changed(state)
+}
+
+// Registers the global hotkey for `settings` in place of the current one. Refuses chords
+// that clash with the system's or a docked window's; if registering fails, the previous
+// hotkey stays.
+fn apply_hotkey(app: &tauri::AppHandle, state: &AppState, settings: &HotkeySettings) -> Result<(), String> {
+    let chord = settings.enabled.then(|| Chord::parse(&settings.chord)).transpose()?;
+    if let Some(chord) = &chord {
+        let docked: Vec<(String, String)> = state.docks.lock().map_err(|_| "Lock poisoned")?
+            .values()
+            .filter_map(|dock| dock.options().and_then(|o| o.hotkey.clone()))
+            .map(|hotkey| ("a docked window".to_string(), hotkey))
+            .collect();
+        if let Some(conflict) = hotkey::find_conflict(chord, &docked) {
+            return Err(conflict);
+        }
+    }
+    let mut store = state.hotkey.lock().map_err(|_| "Lock poisoned")?;
+    let wanted = chord.map(|_| settings.chord.clone());
+    if store.registered == wanted {
+        return Ok(());
+    }
+    let register = |accelerator: &str| {
+        let handle = app.clone();
+        app.global_shortcut_manager().register(accelerator, move || toggle_main_window(&handle))
+    };
+    if let Some(previous) = &store.registered {
+        let _ = app.global_shortcut_manager().unregister(previous);
+    }
+    if let Some(accelerator) = &wanted {
+        if let Err(e) = register(accelerator) {
+            if let Some(previous) = &store.registered {
+                if register(previous).is_err() {
+                    store.registered = None;
+                }
+            }
+            return Err(format!("Failed to register {}: {}", accelerator, e));
+        }
+    }
+    store.registered = wanted;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_hotkey(state: tauri::State<AppState>) -> Result<HotkeyStatus, String> {
+    Ok(state.hotkey.lock().map_err(|_| "Lock poisoned")?.status())
+}
+
+// Rebinds the show/hide hotkey and switches dropdown mode; an error leaves the current
+// hotkey in place
+#[tauri::command]
+fn set_hotkey(app: tauri::AppHandle, settings: HotkeySettings, state: tauri::State<AppState>) -> Result<HotkeyStatus, String> {
+    apply_hotkey(&app, &state, &settings)?;
+    apply_dropdown(&app, &state, settings.dropdown.clone())?;
+    let mut store = state.hotkey.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings;
+    store.save()?;
+    let status = store.status();
+    state.events.emit("hotkey-changed", status.clone());
+    Ok(status)
+}
+
+#[tauri::command]
+fn get_pre_exec_settings(state: tauri::State<AppState>) -> Result<PreExecSettings, String> {
+    Ok(state.pre_exec.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
+
+// Hooks that rewrite command lines typed at the prompt before the shell runs them
+#[tauri::command]
+fn set_pre_exec_settings(settings: PreExecSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_pre_exec_settings(&settings)?;
+    let mut store = state.pre_exec.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings;
+    store.save()
+}
+
+// What `chord` would clash with, if anything, for warning while the user picks one
+#[tauri::command]
+fn check_hotkey(chord: String, state: tauri::State<AppState>) -> Result<Option<String>, String> {
+    let chord = Chord::parse(&chord)?;
+    let mut in_use: Vec<(String, String)> = state.docks.lock().map_err(|_| "Lock poisoned")?
+        .values()
+        .filter_map(|dock| dock.options().and_then(|o| o.hotkey.clone()))
+        .map(|hotkey| ("a docked window".to_string(), hotkey))
+        .collect();
+    if let Some(registered) = &state.hotkey.lock().map_err(|_| "Lock poisoned")?.registered {
+        in_use.push(("the show/hide hotkey".to_string(), registered.clone()));
+    }
+    Ok(hotkey::find_conflict(&chord, &in_use))
+}
+
+// Slides a docked window in or out, as the hotkey does
+#[tauri::command]
+fn toggle_docked_window(window: tauri::Window, state: tauri::State<AppState>) -> Result<(), String> {
+    toggle_dock(&window, &state)
+}
+
+fn toggle_dock(window: &tauri::Window, state: &AppState) -> Result<(), String> {
+    let shown = state.docks.lock().map_err(|_| "Lock poisoned")?
+        .get(window.label())
+        .is_some_and(WindowDock::is_shown);
+    set_dock_shown(window, state, !shown)
+}
+
+fn set_dock_shown(window: &tauri::Window, state: &AppState, shown: bool) -> Result<(), String> {
+    let frame = state.docks.lock().map_err(|_| "Lock poisoned")?
+        .get_mut(window.label())
+        .and_then(|dock| dock.set_shown(shown));
+    let Some(frame) = frame else { return Ok(()) };
+    with_ns_window(window, move |ns_window| dock::set_frame(ns_window, frame, true))?;
+    if shown {
+        window.set_focus().map_err(|e| e.to_string())?;
+    }
+    emit_dock_changed(window, state)
+}
+
+fn emit_dock_changed(window: &tauri::Window, state: &AppState) -> Result<(), String> {
+    let docks = state.docks.lock().map_err(|_| "Lock poisoned")?;
+    if let Some(dock) = docks.get(window.label()) {
+        state.events.emit("window-dock-changed", dock.payload());
+    }
+    Ok(())
+}
+
+// Reveals hidden docked windows when the pointer touches their edge
+fn watch_dock_edges(app: tauri::AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(50));
+        let Some((x, y)) = dock::pointer_location() else { return };
+        let state = app.state::<AppState>();
+        let revealed: Vec<String> = match state.docks.lock() {
+            Ok(docks) => docks.iter().filter(|(_, d)| d.reveals(x, y)).map(|(label, _)| label.clone()).collect(),
+            Err(_) => return,
+        };
+        for label in revealed {
+            if let Some(window) = app.get_window(&label) {
+                let _ = set_dock_shown(&window, &state, true);
+            }
+        }
+    });
+}
 
-use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem, MasterPty};
-use std::collections::HashMap;
-use std::env;
-use std::io::{Read, Write};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
-use tauri::Manager;
-use uuid::Uuid;
-use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
-use serde::{Deserialize, Serialize};
+#[tauri::command]
+fn play_feedback(kind: Feedback) -> Result<(), String> {
+    feedback::play_feedback(&kind)
+}
 
-#[cfg(target_os = "macos")]
-use objc::{msg_send, sel, sel_impl, class};
+#[tauri::command]
+fn get_clipboard_settings(state: tauri::State<AppState>) -> Result<ClipboardSettings, String> {
+    Ok(state.clipboard.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
 
-struct PtySession {
-    writer: Arc<Mutex<Box<dyn Write + Send>>>,
-    master: Arc<Mutex<Box<dyn MasterPty + Send>>>,
+// Whether programs may set (and read) the clipboard through OSC 52, and how much
+#[tauri::command]
+fn set_clipboard_settings(settings: ClipboardSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut store = state.clipboard.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings.clone();
+    store.save()?;
+    state.sessions.set_clipboard_settings(settings)
 }
 
-struct AppState {
-    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+// Opens a link from the terminal (OSC 8 or detected) if its scheme is allowed
+#[tauri::command]
+fn open_url(url: String, state: tauri::State<AppState>) -> Result<(), String> {
+    let settings = state.links.lock().map_err(|_| "Lock poisoned")?.settings.clone();
+    links::open_url(&url, &settings)
 }
 
-#[derive(Clone, Serialize)]
-struct PtyOutputPayload {
-    session_id: String,
-    data: Vec<u8>,
+#[tauri::command]
+fn get_link_settings(state: tauri::State<AppState>) -> Result<LinkSettings, String> {
+    Ok(state.links.lock().map_err(|_| "Lock poisoned")?.settings.clone())
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-struct RunningApp {
-    name: String,
-    bundle_id: String,
+// Schemes open_url allows besides http, https, file and mailto
+#[tauri::command]
+fn set_link_settings(settings: LinkSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut store = state.links.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings;
+    store.save()
 }
 
-#[derive(Clone, Serialize)]
-struct FocusChangedPayload {
-    focused_app: String,
-    is_target_focused: bool,
-    is_self_focused: bool,
+#[tauri::command]
+fn get_feedback_settings(state: tauri::State<AppState>) -> Result<FeedbackSettings, String> {
+    Ok(state.feedback.lock().map_err(|_| "Lock poisoned")?.settings.clone())
 }
 
-// Global flag to control focus monitoring
-static FOCUS_MONITOR_ACTIVE: AtomicBool = AtomicBool::new(false);
-static FOCUS_MONITOR_TARGET: Mutex<Option<String>> = Mutex::new(None);
+// Which sound or haptic plays on bell and on command success/failure
+#[tauri::command]
+fn set_feedback_settings(settings: FeedbackSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut store = state.feedback.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings.clone();
+    store.save()?;
+    state.sessions.set_feedback_settings(settings)
+}
 
-#[cfg(target_os = "macos")]
-fn get_frontmost_app_name() -> Option<String> {
-    unsafe {
-        let workspace: *mut objc::runtime::Object = msg_send![class!(NSWorkspace), sharedWorkspace];
-        let frontmost_app: *mut objc::runtime::Object = msg_send![workspace, frontmostApplication];
-        if frontmost_app.is_null() {
-            return None;
-        }
-        let name: *mut objc::runtime::Object = msg_send![frontmost_app, localizedName];
-        if name.is_null() {
-            return None;
-        }
-        let utf8: *const i8 = msg_send![name, UTF8String];
-        if utf8.is_null() {
-            return None;
-        }
-        Some(std::ffi::CStr::from_ptr(utf8).to_string_lossy().into_owned())
-    }
+// Hands the loaded rules to the engine; focus changes are only watched while a rule needs them
+fn apply_rules(state: &AppState) -> Result<RulesStatus, String> {
+    let rules = state.rules.lock().map_err(|_| "Lock poisoned")?;
+    state.sessions.set_rules(&rules.rules)?;
+    let listener: Option<focus::FocusListener> = if rules.watches(RuleTrigger::FocusChanged) {
+        let sessions = state.sessions.clone();
+        Some(Arc::new(move |app: &RunningApp| sessions.focus_changed(app)))
+    } else {
+        None
+    };
+    state.focus.set_listener(listener)?;
+    Ok(rules.status())
 }
 
-#[cfg(not(target_os = "macos"))]
-fn get_frontmost_app_name() -> Option<String> {
-    None
+#[tauri::command]
+fn get_rules(state: tauri::State<AppState>) -> Result<RulesStatus, String> {
+    Ok(state.rules.lock().map_err(|_| "Lock poisoned")?.status())
 }
 
-#[cfg(target_os = "macos")]
-fn get_running_applications() -> Vec<RunningApp> {
-    unsafe {
-        let workspace: *mut objc::runtime::Object = msg_send![class!(NSWorkspace), sharedWorkspace];
-        let apps: *mut objc::runtime::Object = msg_send![workspace, runningApplications];
-        let count: usize = msg_send![apps, count];
+// Reads rules.toml again after it was edited. A file with errors leaves no rules running;
+// the error is in the returned status
+#[tauri::command]
+fn reload_rules(state: tauri::State<AppState>) -> Result<RulesStatus, String> {
+    state.rules.lock().map_err(|_| "Lock poisoned")?.reload();
+    apply_rules(&state)
+}
 
-        let mut result = Vec::new();
+#[tauri::command]
+fn get_heads_up_settings(state: tauri::State<AppState>) -> Result<HeadsUpSettings, String> {
+    Ok(state.heads_up.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
 
-        for i in 0..count {
-            let app: *mut objc::runtime::Object = msg_send![apps, objectAtIndex: i];
+// Whether bells and automation triggers raise a notification or Dock bounce while a
+// target app is in front
+#[tauri::command]
+fn set_heads_up_settings(settings: HeadsUpSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut store = state.heads_up.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings.clone();
+    store.save()?;
+    state.sessions.set_heads_up_settings(settings)
+}
 
-            // Check if it's a regular app (not background)
-            let activation_policy: i64 = msg_send![app, activationPolicy];
-            if activation_policy != 0 {
-                continue; // Skip non-regular apps
-            }
+#[tauri::command]
+fn get_bell_settings(state: tauri::State<AppState>) -> Result<BellSettings, String> {
+    Ok(state.bell.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
 
-            let name: *mut objc::runtime::Object = msg_send![app, localizedName];
-            let bundle_id: *mut objc::runtime::Object = msg_send![app, bundleIdentifier];
+// What a bell does: a session-bell event, a Dock bounce, a count on the Dock badge
+#[tauri::command]
+fn set_bell_settings(settings: BellSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut store = state.bell.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings.clone();
+    store.save()?;
+    state.sessions.set_bell_settings(settings)
+}
 
-            if name.is_null() {
-                continue;
-            }
+#[tauri::command]
+fn get_long_command_settings(state: tauri::State<AppState>) -> Result<LongCommandSettings, String> {
+    Ok(state.long_command.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
 
-            let name_utf8: *const i8 = msg_send![name, UTF8String];
-            let name_str = if !name_utf8.is_null() {
-                std::ffi::CStr::from_ptr(name_utf8).to_string_lossy().into_owned()
-            } else {
-                continue;
-            };
-
-            let bundle_str = if !bundle_id.is_null() {
-                let bundle_utf8: *const i8 = msg_send![bundle_id, UTF8String];
-                if !bundle_utf8.is_null() {
-                    std::ffi::CStr::from_ptr(bundle_utf8).to_string_lossy().into_owned()
-                } else {
-                    String::new()
-                }
-            } else {
-                String::new()
-            };
+// Notify when a marked command ran at least this long while the window wasn't focused
+#[tauri::command]
+fn set_long_command_settings(settings: LongCommandSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut store = state.long_command.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings.clone();
+    store.save()?;
+    state.sessions.set_long_command_settings(settings)
+}
 
-            result.push(RunningApp {
-                name: name_str,
-                bundle_id: bundle_str,
-            });
-        }
+#[tauri::command]
+fn get_low_memory_status(state: tauri::State<AppState>) -> Result<LowMemoryStatus, String> {
+    state.sessions.low_memory_status()
+}
 
-        // Sort by name
-        result.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
-        result
-    }
+// Also turns off a low-memory mode that memory pressure turned on
+#[tauri::command]
+fn set_low_memory(enabled: bool, state: tauri::State<AppState>) -> Result<LowMemoryStatus, String> {
+    state.sessions.set_low_memory(enabled)
 }
 
-#[cfg(not(target_os = "macos"))]
-fn get_running_applications() -> Vec<RunningApp> {
-    Vec::new()
+// Types output lines of one session (matching `filter`, if given) into another
+#[tauri::command]
+fn pipe_sessions(source_id: String, dest_id: String, filter: Option<String>, state: tauri::State<AppState>) -> Result<String, String> {
+    state.sessions.pipe_sessions(&source_id, &dest_id, filter.as_deref())
 }
 
 #[tauri::command]
-fn get_running_apps() -> Vec<RunningApp> {
-    get_running_applications()
+fn unpipe_sessions(pipe_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.unpipe_sessions(&pipe_id)
 }
 
 #[tauri::command]
-fn get_frontmost_app() -> Option<String> {
-    get_frontmost_app_name()
+fn list_pipes(state: tauri::State<AppState>) -> Result<Vec<PipeInfo>, String> {
+    state.sessions.list_pipes()
+}
+
+fn ensure_scratchpad(state: &AppState) -> Result<String, String> {
+    let settings = state.scratchpad.lock().map_err(|_| "Lock poisoned")?.settings.clone();
+    let profile = state.profiles.lock().map_err(|_| "Lock poisoned")?.resolve(settings.profile.as_deref())?;
+    state.sessions.ensure_scratchpad(&profile)
 }
 
+// The scratchpad's session id, started if needed, for attaching a terminal to it
 #[tauri::command]
-fn start_focus_monitor(app_handle: tauri::AppHandle, target_app: String) {
-    // Set the target and activate monitoring
-    if let Ok(mut target) = FOCUS_MONITOR_TARGET.lock() {
-        *target = Some(target_app.clone());
-    }
+fn get_scratchpad_session(window: tauri::Window, state: tauri::State<AppState>) -> Result<String, String> {
+    let session_id = ensure_scratchpad(&state)?;
+    claim_session(&state, &session_id, &window)?;
+    Ok(session_id)
+}
 
-    // If already running, just update target
-    if FOCUS_MONITOR_ACTIVE.load(Ordering::SeqCst) {
-        return;
-    }
+#[tauri::command]
+fn send_to_scratchpad(data: String, state: tauri::State<AppState>) -> Result<(), String> {
+    ensure_scratchpad(&state)?;
+    state.sessions.write(SCRATCHPAD_NAME, &data)
+}
 
-    FOCUS_MONITOR_ACTIVE.store(true, Ordering::SeqCst);
+#[tauri::command]
+fn get_scratchpad_settings(state: tauri::State<AppState>) -> Result<ScratchpadSettings, String> {
+    Ok(state.scratchpad.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
 
-    thread::spawn(move || {
-        let mut last_app: Option<String> = None;
+#[tauri::command]
+fn set_scratchpad_settings(settings: ScratchpadSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut store = state.scratchpad.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings;
+    store.save()
+}
 
-        while FOCUS_MONITOR_ACTIVE.load(Ordering::SeqCst) {
-            if let Some(current_app) = get_frontmost_app_name() {
-                // Only emit if changed
-                if last_app.as_ref() != Some(&current_app) {
-                    last_app = Some(current_app.clone());
+// Tokens, redirects and the word at `cursor` (a byte offset), for completion and highlighting
+#[tauri::command]
+fn parse_command_line(line: String, cursor: Option<usize>, shell: Option<ShellDialect>) -> ParsedCommandLine {
+    cmdline::parse_command_line(&line, cursor, shell.unwrap_or_default())
+}
 
-                    let target = FOCUS_MONITOR_TARGET.lock()
-                        .ok()
-                        .and_then(|t| t.clone());
+// Focus changes and commands as JSON or CSV text, for saving to a file
+#[tauri::command]
+fn export_timeline(
+    range: Option<TimeRange>,
+    format: Option<TimelineFormat>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    state.sessions.export_timeline(range.unwrap_or_default(), format.unwrap_or_default())
+}
 
-                    if let Some(target_name) = target {
-                        let is_self = current_app == "Shelll" || current_app == "shelll";
-                        let is_target = current_app == target_name;
+// The session's scrollback as an HTML page, colors and links included
+#[tauri::command]
+fn export_output_html(session_id: String, state: tauri::State<AppState>) -> Result<String, String> {
+    state.sessions.export_output_html(&session_id)
+}
 
-                        let payload = FocusChangedPayload {
-                            focused_app: current_app,
-                            is_target_focused: is_target,
-                            is_self_focused: is_self,
-                        };
+// Keeps the session's scrollback, commands and marks in a file (usually just before it's
+// closed); `path` comes from a save dialog
+#[tauri::command(async)]
+fn archive_session(session_id: String, path: String, state: tauri::State<AppState>) -> Result<ArchiveMetadata, String> {
+    state.sessions.archive_session(&session_id, std::path::Path::new(&path))
+}
 
-                        let _ = app_handle.emit_all("app-focus-changed", payload);
-                    }
-                }
-            }
+// A read-only view of an archived session
+#[tauri::command(async)]
+fn open_archive(path: String, state: tauri::State<AppState>) -> Result<OpenedArchive, String> {
+    state.sessions.open_archive(&files::resolve_user_path(&path)?)
+}
 
-            thread::sleep(Duration::from_millis(200));
-        }
-    });
+// Output between two offsets as HTML and plain text, for copying as rich text
+#[tauri::command]
+fn output_html(session_id: String, start: u64, end: u64, state: tauri::State<AppState>) -> Result<RenderedOutput, String> {
+    state.sessions.output_html(&session_id, start, end)
 }
 
 #[tauri::command]
-fn stop_focus_monitor() {
-    FOCUS_MONITOR_ACTIVE.store(false, Ordering::SeqCst);
-    if let Ok(mut target) = FOCUS_MONITOR_TARGET.lock() {
-        *target = None;
-    }
+fn get_remote_edit_settings(state: tauri::State<AppState>) -> Result<RemoteEditSettings, String> {
+    Ok(state.remote_edit.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
+
+// Whether `shelll edit` on remote hosts may open files here at all; the nonce stays as it is
+#[tauri::command]
+fn set_remote_edit_enabled(enabled: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    let mut store = state.remote_edit.lock().map_err(|_| "Lock poisoned")?;
+    store.settings.enabled = enabled;
+    store.save()?;
+    state.sessions.set_remote_edit_settings(store.settings.clone())
+}
+
+#[tauri::command]
+fn list_remote_edits(state: tauri::State<AppState>) -> Result<Vec<RemoteEdit>, String> {
+    state.sessions.list_remote_edits()
 }
 
+// The user's answer to a `remote-edit-requested` event
 #[tauri::command]
-fn create_pty_session(app_handle: tauri::AppHandle, state: tauri::State<AppState>) -> Result<String, String> {
-    let session_id = Uuid::new_v4().to_string();
+fn confirm_remote_edit(edit_id: String, allow: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.confirm_remote_edit(&edit_id, allow)
+}
 
-    let pty_system = NativePtySystem::default();
-    let mut cmd = CommandBuilder::new("zsh");
-    cmd.env("TERM", "xterm-256color");
-    cmd.args(["-c", "export PROMPT_EOL_MARK=''; exec zsh"]);
+// Returns the remote shell to its prompt and unlocks the session's input
+#[tauri::command]
+fn finish_remote_edit(edit_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.finish_remote_edit(&edit_id)
+}
 
-    if let Ok(cwd) = env::current_dir() {
-        cmd.cwd(cwd);
-    }
+// The `shelll edit` shell function, with this install's nonce, for installing on remote hosts
+#[tauri::command]
+fn get_remote_edit_function(state: tauri::State<AppState>) -> Result<String, String> {
+    Ok(remote_edit_function(&state.remote_edit.lock().map_err(|_| "Lock poisoned")?.settings.nonce))
+}
 
-    let pair = pty_system.openpty(PtySize {
-        rows: 30,
-        cols: 100,
-        pixel_width: 0,
-        pixel_height: 0,
-    }).map_err(|e| format!("Failed to create PTY: {}", e))?;
+// Predictive local echo: off, only while ssh is in the foreground, or always
+#[tauri::command]
+fn set_echo_prediction(session_id: String, mode: PredictionMode, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_echo_prediction(&session_id, mode)
+}
 
-    let mut reader = pair.master.try_clone_reader()
-        .map_err(|e| format!("Failed to clone reader: {}", e))?;
-    let writer = pair.master.take_writer()
-        .map_err(|e| format!("Failed to take writer: {}", e))?;
+// A session streaming a file instead of running a shell; returns its session id
+#[tauri::command]
+fn tail_file(window: tauri::Window, path: String, follow: bool, state: tauri::State<AppState>) -> Result<String, String> {
+    let session_id = state.sessions.tail_file(&files::resolve_user_path(&path)?, follow)?;
+    claim_session(&state, &session_id, &window)?;
+    Ok(session_id)
+}
 
-    // Spawn shell
-    let child = pair.slave.spawn_command(cmd)
-        .map_err(|e| format!("Failed to spawn shell: {}", e))?;
-    // Keep child alive
-    Box::leak(Box::new(child));
+// Writes happen on a separate thread; see `RecordingOptions` for the backpressure policy
+#[tauri::command]
+fn start_recording(session_id: String, options: RecordingOptions, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.start_recording(&session_id, options)
+}
 
-    let session = PtySession {
-        writer: Arc::new(Mutex::new(writer)),
-        master: Arc::new(Mutex::new(pair.master)),
-    };
+#[tauri::command]
+fn stop_recording(session_id: String, state: tauri::State<AppState>) -> Result<RecordingStats, String> {
+    state.sessions.stop_recording(&session_id)
+}
 
-    // Store session
-    {
-        let mut sessions = state.sessions.lock().map_err(|_| "Lock poisoned")?;
-        sessions.insert(session_id.clone(), session);
-    }
+// None when the session isn't being recorded
+#[tauri::command]
+fn get_recording_stats(session_id: String, state: tauri::State<AppState>) -> Result<Option<RecordingStats>, String> {
+    state.sessions.recording_stats(&session_id)
+}
 
-    // Read thread for this session
-    let sid = session_id.clone();
-    thread::spawn(move || {
-        let mut buf = [0u8; 4096];
-        loop {
-            match reader.read(&mut buf) {
-                Ok(n) if n > 0 => {
-                    let payload = PtyOutputPayload {
-                        session_id: sid.clone(),
-                        data: buf[..n].to_vec(),
-                    };
-                    let _ = app_handle.emit_all("pty-output", payload);
-                }
-                Ok(_) => break, // EOF
-                Err(_) => break, // Error
-            }
-        }
-    });
+#[tauri::command]
+fn get_tab_title(session_id: String, state: tauri::State<AppState>) -> Result<String, String> {
+    state.sessions.tab_title(&session_id)
+}
+
+#[tauri::command]
+fn set_tab_title_template(template: Option<String>, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_title_template(template)?;
+    let mut store = state.templates.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = state.sessions.templates()?;
+    store.save()
+}
+
+#[tauri::command]
+fn get_templates(state: tauri::State<AppState>) -> Result<TemplateSettings, String> {
+    Ok(state.templates.lock().map_err(|_| "Lock poisoned")?.settings.clone())
+}
+
+// Templates for tab titles, notifications and webhooks
+#[tauri::command]
+fn set_templates(settings: TemplateSettings, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_templates(settings.clone())?;
+    let mut store = state.templates.lock().map_err(|_| "Lock poisoned")?;
+    store.settings = settings;
+    store.save()
+}
+
+// Formats a notification or webhook for a session the same way as every other surface
+#[tauri::command]
+fn render_template(
+    session_id: String,
+    kind: TemplateKind,
+    exit_code: Option<i32>,
+    duration_ms: Option<u64>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    state.sessions.render_template(&session_id, kind, exit_code, duration_ms.map(Duration::from_millis))
+}
+
+#[tauri::command]
+fn set_session_group(session_id: String, group_id: Option<String>, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_group(&session_id, group_id)
+}
+
+#[tauri::command]
+fn resize_group(group_id: String, rows: u16, cols: u16, state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+    state.sessions.resize_group(&group_id, rows, cols)
+}
+
+// Called on every keystroke of the search UI
+#[tauri::command]
+fn history_search_incremental(
+    session_id: Option<String>,
+    query: String,
+    state: tauri::State<AppState>,
+) -> Result<Vec<HistoryMatch>, String> {
+    state.sessions.search_history(session_id.as_deref(), &query)
+}
+
+#[tauri::command]
+fn add_mark(session_id: String, label: String, state: tauri::State<AppState>) -> Result<OutputMark, String> {
+    state.sessions.add_mark(&session_id, label)
+}
+
+#[tauri::command]
+fn list_marks(session_id: String, state: tauri::State<AppState>) -> Result<Vec<OutputMark>, String> {
+    state.sessions.list_marks(&session_id)
+}
+
+#[tauri::command]
+fn remove_mark(session_id: String, mark_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.remove_mark(&session_id, &mark_id)
+}
+
+#[tauri::command]
+fn get_hibernation_policy(state: tauri::State<AppState>) -> Result<HibernationPolicy, String> {
+    state.sessions.hibernation_policy()
+}
+
+#[tauri::command]
+fn set_hibernation_policy(policy: HibernationPolicy, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.set_hibernation_policy(policy)
+}
+
+#[tauri::command]
+fn hibernate_session(session_id: String, suspend_process: bool, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.hibernate_session(&session_id, suspend_process)
+}
 
+#[tauri::command]
+fn revive_session(session_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    state.sessions.revive_session(&session_id)
+}
+
+// `sessions: None` grants access to every session
+#[tauri::command]
+fn create_automation_token(
+    label: String,
+    sessions: Option<Vec<String>>,
+    operations: Vec<Operation>,
+    state: tauri::State<AppState>,
+) -> Result<Grant, String> {
+    state.permissions.issue(label, sessions, operations)
+}
+
+#[tauri::command]
+fn revoke_automation_token(token: String, state: tauri::State<AppState>) -> Result<bool, String> {
+    state.permissions.revoke(&token)
+}
+
+#[tauri::command]
+fn list_automation_tokens(state: tauri::State<AppState>) -> Result<Vec<Grant>, String> {
+    state.permissions.list()
+}
+
+// Automation clients reach sessions through these, never the unchecked commands above
+fn scoped<'a>(state: &'a AppState, token: &str) -> ScopedSessions<'a> {
+    ScopedSessions::new(&state.sessions, &state.permissions, token)
+}
+
+#[tauri::command]
+fn automation_create_session(
+    token: String,
+    profile: Option<String>,
+    options: Option<SessionOptions>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let profile = state.profiles.lock().map_err(|_| "Lock poisoned")?.resolve(profile.as_deref())?;
+    let session_id = scoped(&state, &token).create_session(&profile, &options.unwrap_or_default())?;
+    state.sessions.fill_pool();
     Ok(session_id)
 }
 
 #[tauri::command]
-fn write_to_pty(session_id: String, data: String, state: tauri::State<AppState>) -> Result<(), String> {
-    let sessions = state.sessions.lock().map_err(|_| "Lock poisoned")?;
-    if let Some(session) = sessions.get(&session_id) {
-        if let Ok(mut writer) = session.writer.lock() {
-            let _ = write!(writer, "{}", data);
-        }
-    }
+fn automation_close_session(token: String, session_id: String, state: tauri::State<AppState>) -> Result<(), String> {
+    scoped(&state, &token).close(&session_id)?;
+    state.routes.lock().map_err(|_| "Lock poisoned")?.forget_session(&session_id);
     Ok(())
 }
 
 #[tauri::command]
-fn resize_pty(session_id: String, rows: u16, cols: u16, state: tauri::State<AppState>) -> Result<(), String> {
-    let sessions = state.sessions.lock().map_err(|_| "Lock poisoned")?;
-    if let Some(session) = sessions.get(&session_id) {
-        if let Ok(master) = session.master.lock() {
-            let _ = master.resize(PtySize {
-                rows,
-                cols,
-                pixel_width: 0,
-                pixel_height: 0,
-            });
-        }
+fn automation_write(token: String, session_id: String, data: String, state: tauri::State<AppState>) -> Result<(), String> {
+    scoped(&state, &token).write(&session_id, &data)
+}
+
+#[tauri::command]
+fn automation_resize(
+    token: String,
+    session_id: String,
+    rows: u16,
+    cols: u16,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    scoped(&state, &token).resize(&session_id, rows, cols)
+}
+
+#[tauri::command]
+fn automation_get_tab_title(token: String, session_id: String, state: tauri::State<AppState>) -> Result<String, String> {
+    scoped(&state, &token).tab_title(&session_id)
+}
+
+#[tauri::command]
+fn automation_list_marks(
+    token: String,
+    session_id: String,
+    state: tauri::State<AppState>,
+) -> Result<Vec<OutputMark>, String> {
+    scoped(&state, &token).list_marks(&session_id)
+}
+
+#[tauri::command]
+fn automation_read_output_since(
+    token: String,
+    session_id: String,
+    offset: u64,
+    max_bytes: Option<usize>,
+    state: tauri::State<AppState>,
+) -> Result<OutputChunk, String> {
+    scoped(&state, &token).read_output_since(&session_id, offset, max_bytes.unwrap_or(MAX_READ_BYTES))
+}
+
+#[tauri::command]
+fn get_onboarding_state(state: tauri::State<AppState>) -> Result<OnboardingState, String> {
+    let store = state.onboarding.lock().map_err(|_| "Lock poisoned")?;
+    Ok(store.state.clone())
+}
+
+#[tauri::command]
+fn advance_onboarding(event: OnboardingEvent, state: tauri::State<AppState>) -> Result<OnboardingState, String> {
+    let mut store = state.onboarding.lock().map_err(|_| "Lock poisoned")?;
+    store.state.apply(event)?;
+    store.save()?;
+    state.events.emit("onboarding-changed", store.state.clone());
+    Ok(store.state.clone())
+}
+
+#[tauri::command]
+fn reset_onboarding(state: tauri::State<AppState>) -> Result<OnboardingState, String> {
+    let mut store = state.onboarding.lock().map_err(|_| "Lock poisoned")?;
+    store.state = OnboardingState::default();
+    store.save()?;
+    state.events.emit("onboarding-changed", store.state.clone());
+    Ok(store.state.clone())
+}
+
+#[tauri::command(async)]
+fn check_latest_release(app_handle: tauri::AppHandle, force: Option<bool>) -> Result<Option<ReleaseInfo>, String> {
+    let cache_path = app_handle.path_resolver().app_cache_dir()
+        .map(|d| d.join("update-check.json"));
+    let current = &app_handle.package_info().version;
+    let release = update::check_latest_release(cache_path.as_deref(), current, force.unwrap_or(false))?;
+
+    if let Some(release) = &release {
+        let _ = app_handle.emit_all("update-available", UpdateAvailablePayload {
+            current_version: current.to_string(),
+            release: release.clone(),
+        });
     }
-    Ok(())
+    Ok(release)
 }
 
 #[tauri::command]
-fn close_pty_session(session_id: String, state: tauri::State<AppState>) -> Result<(), String> {
-    let mut sessions = state.sessions.lock().map_err(|_| "Lock poisoned")?;
-    sessions.remove(&session_id);
-    Ok(())
+fn get_quarantine_info(path: String) -> Result<Option<QuarantineInfo>, String> {
+    files::read_quarantine(&files::resolve_user_path(&path)?)
+}
+
+#[tauri::command]
+fn remove_quarantine(path: String) -> Result<(), String> {
+    files::clear_quarantine(&files::resolve_user_path(&path)?)
+}
+
+// Opens a file with the system handler, but only after the user confirms a native dialog
+// describing where the file came from and what will be changed. Returns false if declined.
+#[tauri::command(async)]
+fn safe_open(window: tauri::Window, path: String, options: Option<SafeOpenOptions>) -> Result<bool, String> {
+    let options = options.unwrap_or_default();
+    let path = files::resolve_user_path(&path)?;
+    let quarantine = files::read_quarantine(&path)?;
+
+    let message = files::describe_safe_open(&path, quarantine.as_ref(), &options);
+    if !tauri::api::dialog::blocking::ask(Some(&window), "Open File", message) {
+        return Ok(false);
+    }
+
+    files::open_confirmed(&path, quarantine.as_ref(), &options)?;
+    Ok(true)
+}
+
+#[tauri::command]
+fn get_command_watchdog_report(state: tauri::State<AppState>) -> Result<WatchdogReport, String> {
+    state.watchdog.report()
+}
+
+#[tauri::command]
+fn set_command_watchdog_threshold(threshold_ms: u64, state: tauri::State<AppState>) -> Result<(), String> {
+    state.watchdog.set_threshold(Duration::from_millis(threshold_ms))
+}
+
+#[tauri::command]
+fn clear_command_watchdog(state: tauri::State<AppState>) -> Result<(), String> {
+    state.watchdog.clear()
+}
+
+// Runs every command under the watchdog. Synchronous commands (which hold up the main
+// thread) are timed to the end; async ones only until they've been spawned.
+fn watched(
+    handler: impl Fn(tauri::Invoke) + Send + Sync + 'static,
+) -> impl Fn(tauri::Invoke) + Send + Sync + 'static {
+    move |invoke| {
+        let window = invoke.message.window();
+        let state = window.try_state::<AppState>();
+        let _call = state.as_ref().map(|s| s.watchdog.watch(invoke.message.command()));
+        handler(invoke)
+    }
 }
 
 fn main() {
     tauri::Builder::default()
+        .system_tray(SystemTray::new().with_menu(tray_menu(&tray::tray_entries(&[], None))))
+        .on_system_tray_event(|app, event| {
+            if let SystemTrayEvent::MenuItemClick { id, .. } = event {
+                if let Some(action) = TrayAction::parse(&id) {
+                    handle_tray_action(app, action);
+                }
+            }
+        })
         .setup(|app| {
             let window = app.get_window("main").unwrap();
 
             #[cfg(target_os = "macos")]
             apply_vibrancy(&window, NSVisualEffectMaterial::HudWindow, None, None)
               .expect("Unsupported platform! 'apply_vibrancy' is only supported on macOS");
+            // The hotkey brings the window up over full-screen apps too
+            #[cfg(target_os = "macos")]
+            hotkey::show_over_fullscreen(window.ns_window()?)?;
+
+            let config_dir = app.path_resolver().app_config_dir();
+            let data_dir = app.path_resolver().app_data_dir();
+            let routes = Arc::new(Mutex::new(EventRoutes::default()));
+            let events: Arc<dyn EventSink> = Arc::new(TauriEvents { app: app.handle(), routes: routes.clone() });
+            let sessions = Arc::new(SessionManager::new(
+                events.clone(),
+                HistoryStore::load(data_dir.map(|d| d.join("history.jsonl"))),
+            ));
+            sessions.start_hibernation_sweeper();
+            sessions.start_memory_pressure_watcher();
+            sessions.start_usage_sampler();
+            sessions.start_remote_watcher();
+            guest::remove_stale_homes();
+            tmpdir::remove_stale_tmp_dirs();
+            keyboard::watch_keyboard_layout(events.clone());
+            let accessibility = Arc::new(AccessibilityWatcher::new(events.clone()));
+            accessibility.start();
+            let watchdog = Arc::new(CommandWatchdog::new(events.clone()));
+            watchdog.start();
+            sessions.set_scale_factor(window.scale_factor()?)?;
+            let power_sessions = sessions.clone();
+            lifecycle::observe_power_events(move |event| {
+                let _ = power_sessions.handle_lifecycle(event);
+            });
+            let automations = AutomationStore::load(config_dir.as_ref().map(|d| d.join("automations.json")));
+            sessions.set_automations(automations.list())?;
+            let feedback = FeedbackStore::load(config_dir.as_ref().map(|d| d.join("feedback.json")));
+            sessions.set_feedback_settings(feedback.settings.clone())?;
+            let clipboard = ClipboardStore::load(config_dir.as_ref().map(|d| d.join("clipboard.json")));
+            sessions.set_clipboard_settings(clipboard.settings.clone())?;
+            let remote_edit = RemoteEditStore::load(config_dir.as_ref().map(|d| d.join("remote_edit.json")));
+            sessions.set_remote_edit_settings(remote_edit.settings.clone())?;
+            let links = LinkStore::load(config_dir.as_ref().map(|d| d.join("links.json")));
+            let heads_up = HeadsUpStore::load(config_dir.as_ref().map(|d| d.join("heads_up.json")));
+            sessions.set_heads_up_settings(heads_up.settings.clone())?;
+            let bell = BellStore::load(config_dir.as_ref().map(|d| d.join("bell.json")));
+            sessions.set_bell_settings(bell.settings.clone())?;
+            let long_command = LongCommandStore::load(config_dir.as_ref().map(|d| d.join("long_command.json")));
+            sessions.set_long_command_settings(long_command.settings.clone())?;
+            sessions.set_recent_dirs(RecentDirsStore::load(config_dir.as_ref().map(|d| d.join("recent_dirs.json"))))?;
+            let hotkey = HotkeyStore::load(config_dir.as_ref().map(|d| d.join("hotkey.json")));
+            let pre_exec = PreExecStore::load(config_dir.as_ref().map(|d| d.join("pre_exec.json")));
+            if let Err(e) = sessions.set_pre_exec_settings(&pre_exec.settings) {
+                eprintln!("Failed to apply pre-exec hooks: {}", e);
+            }
+            let redaction = RedactionStore::load(config_dir.as_ref().map(|d| d.join("redaction.json")));
+            if let Err(e) = sessions.set_redaction_settings(&redaction.settings) {
+                eprintln!("Failed to apply redaction rules: {}", e);
+            }
+            let templates = TemplateStore::load(config_dir.as_ref().map(|d| d.join("templates.json")));
+            sessions.set_templates(templates.settings.clone())?;
+            let remote = RemoteStore::load(config_dir.as_ref().map(|d| d.join("remote.json")));
+            sessions.set_remote_settings(remote.settings.clone())?;
+            let scratchpad = ScratchpadStore::load(config_dir.as_ref().map(|d| d.join("scratchpad.json")));
+            let pool = PoolStore::load(config_dir.as_ref().map(|d| d.join("pool.json")));
+            let rules = RulesStore::load(config_dir.as_ref().map(|d| d.join("rules.toml")));
+            if let Some(e) = &rules.error {
+                eprintln!("Failed to load rules: {}", e);
+            }
 
             app.manage(AppState {
-                sessions: Arc::new(Mutex::new(HashMap::new())),
+                sessions,
+                onboarding: Mutex::new(OnboardingStore::load(
+                    config_dir.as_ref().map(|d| d.join("onboarding.json")),
+                )),
+                profiles: Mutex::new(ProfileStore::load(
+                    config_dir.map(|d| d.join("profiles.json")),
+                )),
+                automations: Mutex::new(automations),
+                chrome: Mutex::new(WindowChrome::default()),
+                feedback: Mutex::new(feedback),
+                clipboard: Mutex::new(clipboard),
+                remote_edit: Mutex::new(remote_edit),
+                links: Mutex::new(links),
+                heads_up: Mutex::new(heads_up),
+                bell: Mutex::new(bell),
+                long_command: Mutex::new(long_command),
+                hotkey: Mutex::new(hotkey),
+                pre_exec: Mutex::new(pre_exec),
+                redaction: Mutex::new(redaction),
+                templates: Mutex::new(templates),
+                remote: Mutex::new(remote),
+                scratchpad: Mutex::new(scratchpad),
+                pool: Mutex::new(pool),
+                rules: Mutex::new(rules),
+                permissions: PermissionRegistry::default(),
+                focus: FocusMonitor::new(events.clone()),
+                target_titles: TargetTitleMonitor::new(events.clone()),
+                accessibility,
+                watchdog,
+                docks: Mutex::new(HashMap::new()),
+                dropdown: Mutex::new(WindowDropdown::default()),
+                followers: Mutex::new(HashMap::new()),
+                events,
+                routes,
             });
 
+            watch_dock_edges(app.handle());
+            watch_tray_menu(app.handle());
+            watch_dock_tile(app.handle());
+            install_app_dock_menu(app.handle());
+            install_notification_handler(app.handle());
+            let state = app.state::<AppState>();
+            if state.scratchpad.lock().map_err(|_| "Lock poisoned")?.settings.enabled {
+                ensure_scratchpad(&state)?;
+            }
+            configure_pool(&state)?;
+            apply_rules(&state)?;
+            let hotkey_settings = state.hotkey.lock().map_err(|_| "Lock poisoned")?.settings.clone();
+            if let Err(e) = apply_hotkey(&app.handle(), &state, &hotkey_settings) {
+                eprintln!("Failed to set up the hotkey: {}", e);
+            }
+            if let Err(e) = apply_dropdown(&app.handle(), &state, hotkey_settings.dropdown.clone()) {
+                eprintln!("Failed to set up dropdown mode: {}", e);
+            }
+            let project_sessions = state.sessions.clone();
+            state.target_titles.set_listener(Some(Arc::new(move |changed: &TargetWindowTitle| {
+                let _ = project_sessions.follow_target_project(&changed.bundle_id, changed.title.as_deref());
+            })))?;
+            let monitor = state.focus.clone();
+            let attention = window.clone();
+            let focused = window.clone();
+            let bouncing = window.clone();
+            let commands_focused = window.clone();
+            state.sessions.set_long_command_focus_check(Arc::new(move || commands_focused.is_focused().unwrap_or(false)))?;
+            state.sessions.set_bell_hooks(BellHooks {
+                app_active: Arc::new(move || focused.is_focused().unwrap_or(false)),
+                bounce: Arc::new(move || {
+                    let _ = bouncing.request_user_attention(Some(tauri::UserAttentionType::Informational));
+                }),
+            })?;
+            state.sessions.set_heads_up_hooks(HeadsUpHooks {
+                in_target: Arc::new(move || monitor.target_focused()),
+                flash: Arc::new(move || {
+                    let _ = attention.request_user_attention(Some(tauri::UserAttentionType::Informational));
+                }),
+            })?;
+
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![
+        .on_window_event(|event| {
+            let state = event.window().state::<AppState>();
+            match event.event() {
+                tauri::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                    let _ = state.sessions.set_scale_factor(*scale_factor);
+                }
+                // Sessions whose profile keeps them alive need the process; keep the window
+                // reachable from the Dock instead of quitting
+                tauri::WindowEvent::CloseRequested { api, .. } => {
+                    let remaining = state.sessions.handle_lifecycle(LifecycleEvent::WindowClosed).unwrap_or(0);
+                    if remaining > 0 {
+                        api.prevent_close();
+                        let _ = event.window().minimize();
+                    }
+                }
+                // Back from a heads-up: show the session it was about
+                tauri::WindowEvent::Focused(true) => {
+                    let _ = state.sessions.take_heads_up_jump();
+                    let _ = state.sessions.clear_unseen_bells();
+                }
+                tauri::WindowEvent::Focused(false) => {
+                    let hide = state.docks.lock()
+                        .map(|docks| docks.get(event.window().label()).is_some_and(WindowDock::hides_on_blur))
+                        .unwrap_or(false);
+                    if hide {
+                        let _ = set_dock_shown(event.window(), &state, false);
+                    }
+                    if event.window().label() == "main" {
+                        let _ = set_dropdown_shown(event.window(), &state, false);
+                    }
+                }
+                tauri::WindowEvent::Destroyed => {
+                    state.focus.unsubscribe_owner(event.window().label());
+                    if let Ok(mut docks) = state.docks.lock() {
+                        docks.remove(event.window().label());
+                    }
+                    if let Ok(mut routes) = state.routes.lock() {
+                        routes.forget_window(event.window().label());
+                    }
+                }
+                _ => {}
+            }
+        })
+        .invoke_handler(watched(tauri::generate_handler![
             create_pty_session,
             write_to_pty,
             resize_pty,
@@ -319,8 +2154,193 @@ fn main() {
             get_running_apps,
             get_frontmost_app,
             start_focus_monitor,
-            stop_focus_monitor
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+            stop_focus_monitor,
+            set_focus_monitor_targets,
+            list_focus_monitors,
+            get_onboarding_state,
+            advance_onboarding,
+            reset_onboarding,
+            check_latest_release,
+            get_quarantine_info,
+            remove_quarantine,
+            safe_open,
+            set_session_group,
+            resize_group,
+            get_tab_title,
+            set_tab_title_template,
+            history_search_incremental,
+            add_mark,
+            list_marks,
+            remove_mark,
+            create_automation_token,
+            revoke_automation_token,
+            list_automation_tokens,
+            automation_create_session,
+            automation_close_session,
+            automation_write,
+            automation_resize,
+            automation_get_tab_title,
+            automation_list_marks,
+            automation_read_output_since,
+            get_hibernation_policy,
+            set_hibernation_policy,
+            hibernate_session,
+            revive_session,
+            get_mouse_mode,
+            get_terminal_identity,
+            set_terminal_identity,
+            list_profiles,
+            save_profile,
+            delete_profile,
+            diagnose_terminfo,
+            install_terminfo,
+            lock_session_input,
+            set_latency_probe,
+            get_latency_stats,
+            get_cursor_position,
+            read_screen_region,
+            list_automations,
+            save_automation,
+            delete_automation,
+            run_automation,
+            get_cell_metrics,
+            find_incremental,
+            stop_find,
+            add_port_forward,
+            list_port_forwards,
+            remove_port_forward,
+            capture_session_env,
+            diff_session_env,
+            read_output_since,
+            focus_session,
+            get_sessions_by_activity,
+            set_drag_regions,
+            start_window_drag,
+            get_titlebar,
+            set_titlebar,
+            play_feedback,
+            get_feedback_settings,
+            set_feedback_settings,
+            get_scratchpad_session,
+            send_to_scratchpad,
+            get_scratchpad_settings,
+            set_scratchpad_settings,
+            export_timeline,
+            get_remote_edit_settings,
+            set_remote_edit_enabled,
+            list_remote_edits,
+            confirm_remote_edit,
+            finish_remote_edit,
+            get_remote_edit_function,
+            set_echo_prediction,
+            tail_file,
+            attach_session,
+            detach_session,
+            parse_command_line,
+            set_color_theme,
+            get_terminal_colors,
+            get_process_env,
+            is_guest_session,
+            send_text_to_app,
+            get_keyboard_layout,
+            start_recording,
+            stop_recording,
+            get_recording_stats,
+            get_session_processes,
+            get_orphaned_processes,
+            set_input_translation,
+            get_input_translation,
+            get_redaction_settings,
+            set_redaction_settings,
+            preview_redaction,
+            dock_window,
+            undock_window,
+            toggle_docked_window,
+            run_diagnostics,
+            open_session_tmp,
+            list_sessions,
+            get_templates,
+            set_templates,
+            render_template,
+            get_session_info,
+            get_remote_settings,
+            set_remote_settings,
+            send_signal,
+            run_command,
+            cancel_command,
+            negotiate_output_protocol,
+            get_pool_settings,
+            set_pool_settings,
+            get_session_buffer,
+            snapshot_session,
+            list_snapshots,
+            delete_snapshot,
+            fork_from_snapshot,
+            set_flow_control,
+            ack_output,
+            get_recent_inputs,
+            resend_input,
+            undo_last_send,
+            focus_target_and_send,
+            get_rules,
+            reload_rules,
+            add_focus_monitor_target,
+            remove_focus_monitor_target,
+            get_heads_up_settings,
+            set_heads_up_settings,
+            get_low_memory_status,
+            set_low_memory,
+            pipe_sessions,
+            unpipe_sessions,
+            list_pipes,
+            activate_app,
+            get_hotkey,
+            set_hotkey,
+            check_hotkey,
+            get_pre_exec_settings,
+            set_pre_exec_settings,
+            export_output_html,
+            output_html,
+            follow_target_window,
+            stop_following_window,
+            check_accessibility_permission,
+            request_accessibility_permission,
+            get_command_watchdog_report,
+            set_command_watchdog_threshold,
+            clear_command_watchdog,
+            get_target_window_title,
+            watch_target_window_title,
+            unwatch_target_window_title,
+            archive_session,
+            open_archive,
+            compose_in_editor,
+            get_recent_dirs,
+            clear_recent_dirs,
+            get_target_project_dir,
+            set_project_auto_cd,
+            get_session_cwd,
+            get_clipboard_settings,
+            set_clipboard_settings,
+            open_url,
+            get_link_settings,
+            set_link_settings,
+            get_bell_settings,
+            set_bell_settings,
+            get_command_log,
+            get_shell_integration_status,
+            install_shell_integration,
+            uninstall_shell_integration,
+            get_long_command_settings,
+            set_long_command_settings
+        ]))
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            // Don't leave shells (and their jobs) running after the app quits
+            if let tauri::RunEvent::Exit = event {
+                if let Some(state) = app.try_state::<AppState>() {
+                    state.sessions.close_all();
+                }
+            }
+        });
 }
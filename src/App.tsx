@@ -20,6 +20,8 @@ import {
   Layout,
   Link,
   Unlink,
+  MemoryStick,
+  CornerUpLeft,
 } from "lucide-react";
 import "xterm/css/xterm.css";
 import clsx from "clsx";
@@ -38,9 +40,28 @@ interface Block {
   lines: string[];
 }
 
+interface LowMemoryStatus {
+  enabled: boolean;
+  reason: "manual" | "pressure" | null;
+  retained_bytes: number;
+}
+
 interface PtyOutputPayload {
   session_id: string;
-  data: number[];
+  // number[] until protocol 2 (base64) is negotiated
+  data: number[] | string;
+  stream: "stdout" | "stderr";
+}
+
+// pty-output encodings this frontend can decode
+const OUTPUT_PROTOCOLS = [1, 2];
+
+function decodeOutput(data: number[] | string): Uint8Array {
+  if (typeof data !== "string") return new Uint8Array(data);
+  const binary = atob(data);
+  const bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) bytes[i] = binary.charCodeAt(i);
+  return bytes;
 }
 
 const DEFAULT_FONT =
@@ -99,6 +120,7 @@ export default function App() {
   const [selectedBlockIds, setSelectedBlockIds] = useState<Set<string>>(new Set());
   const [isRedactMode, setIsRedactMode] = useState(true);
   const [isPinned, setIsPinned] = useState(true);
+  const [lowMemory, setLowMemory] = useState<LowMemoryStatus | null>(null);
   const [showCmdPalette, setShowCmdPalette] = useState(false);
   const [showFontSettings, setShowFontSettings] = useState(false);
   const [showResizeSettings, setShowResizeSettings] = useState(false);
@@ -119,25 +141,88 @@ export default function App() {
     tabManager.createTab();
   }, []);
 
+  // Ask for base64 output; older backends without the command keep sending arrays.
+  // Output is acked once written, so a flood of output can't outrun the terminal
+  useEffect(() => {
+    invoke("negotiate_output_protocol", { supported: OUTPUT_PROTOCOLS }).catch(() => {});
+    invoke("set_flow_control", { enabled: true }).catch(() => {});
+  }, []);
+
+  // Window actions from rules.toml
+  useEffect(() => {
+    const unlisten = listen<{ rule: string; action: string }>("rule-window-action", (event) => {
+      switch (event.payload.action) {
+        case "show":
+          appWindow.show();
+          break;
+        case "hide":
+          appWindow.hide();
+          break;
+        case "focus":
+          appWindow.show().then(() => appWindow.setFocus());
+          break;
+        case "minimize":
+          appWindow.minimize();
+          break;
+      }
+    });
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  // Low-memory mode keeps less scrollback; show it so missing output isn't a surprise
+  useEffect(() => {
+    invoke<LowMemoryStatus>("get_low_memory_status").then(setLowMemory).catch(() => {});
+    const unlisten = listen<LowMemoryStatus>("low-memory-changed", (event) => {
+      setLowMemory(event.payload);
+    });
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  // Back from a heads-up: show the session that asked for attention
+  useEffect(() => {
+    const unlisten = listen<{ session_id: string }>("focus-session", (event) => {
+      const tab = tabManager.tabs.find((t) => t.sessionId === event.payload.session_id);
+      if (tab) {
+        tabManager.switchTab(tab.id);
+      }
+    });
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [tabManager.tabs]);
+
   // Listen for PTY output and route to correct terminal
   useEffect(() => {
     const unlisten = listen<PtyOutputPayload>("pty-output", (event) => {
       const { session_id, data } = event.payload;
-      const byteData = new Uint8Array(data);
+      const byteData = decodeOutput(data);
+
+      const ack = () =>
+        invoke("ack_output", { sessionId: session_id, bytes: byteData.length }).catch(() => {});
 
       // Find the tab with this session ID
       const tab = tabManager.tabs.find((t) => t.sessionId === session_id);
-      if (!tab) return;
+      if (!tab) {
+        ack();
+        return;
+      }
 
-      // Find the terminal pane element and write data
+      // Find the terminal pane element and write data; it acks once xterm has parsed it
       const container = terminalContainerRef.current;
-      if (container) {
-        const pane = container.querySelector(
-          `[data-session-id="${session_id}"]`
-        ) as any;
-        if (pane && pane.__writeData) {
-          pane.__writeData(byteData);
-        }
+      const pane = container?.querySelector(
+        `[data-session-id="${session_id}"]`
+      ) as any;
+      if (pane && pane.__writeData) {
+        pane.__writeData(byteData, ack);
+      } else {
+        ack();
       }
 
       // Request block scan if this is the active tab
@@ -372,10 +457,21 @@ export default function App() {
           name: "Markdown",
           extensions: ["md"],
         },
+        {
+          name: "HTML",
+          extensions: ["html"],
+        },
       ],
     });
 
     if (filePath) {
+      const sessionId = tabManager.activeTab?.sessionId;
+      // HTML keeps colors and links; the backend renders it from the raw output
+      if (filePath.endsWith(".html") && sessionId) {
+        const html = await invoke<string>("export_output_html", { sessionId });
+        await writeTextFile(filePath, html);
+        return;
+      }
       const content = isRedactMode ? redactText(text) : text;
       await writeTextFile(filePath, content);
     }
@@ -487,6 +583,20 @@ export default function App() {
             {isPinned ? <Pin size={14} /> : <PinOff size={14} />}
           </button>
 
+          {lowMemory?.enabled && (
+            <button
+              onClick={() => invoke("set_low_memory", { enabled: false }).catch(() => {})}
+              className="text-amber-400 opacity-70 hover:opacity-100 transition-opacity"
+              title={`Low-memory mode${
+                lowMemory.reason === "pressure" ? " (memory pressure)" : ""
+              }: scrollback limited to ${Math.round(
+                lowMemory.retained_bytes / 1024
+              )} KB. Click to turn off.`}
+            >
+              <MemoryStick size={14} />
+            </button>
+          )}
+
           <button
             onClick={() => {
               setShowAppPicker(!showAppPicker);
@@ -512,6 +622,16 @@ export default function App() {
               <Unlink size={14} />
             )}
           </button>
+
+          {windowAttachment.attachedApp?.bundle_id && (
+            <button
+              onClick={windowAttachment.switchToAttachedApp}
+              className="text-white/40 hover:text-white transition-colors"
+              title={`Switch back to ${windowAttachment.attachedApp.name}`}
+            >
+              <CornerUpLeft size={14} />
+            </button>
+          )}
         </div>
 
         <div className="flex items-center gap-2">
@@ -65,10 +65,14 @@ export function TerminalPane({
     const resizeObserver = new ResizeObserver(() => {
       if (fitAddonRef.current) {
         fitAddonRef.current.fit();
+        // The rendered screen spans exactly rows x cols cells
+        const screen = term.element?.querySelector<HTMLElement>(".xterm-screen");
         invoke("resize_pty", {
           sessionId: tab.sessionId,
           rows: term.rows,
           cols: term.cols,
+          cellWidth: screen ? screen.clientWidth / term.cols : null,
+          cellHeight: screen ? screen.clientHeight / term.rows : null,
         }).catch(() => {});
         onRequestScanBlocks();
       }
@@ -107,13 +111,16 @@ export function TerminalPane({
       terminalRef.current.focus();
       // Refit when becoming active to ensure correct sizing
       fitAddonRef.current?.fit();
+      invoke("focus_session", { sessionId: tab.sessionId }).catch(() => {});
     }
-  }, [isActive]);
+  }, [isActive, tab.sessionId]);
 
   // Method to write data to terminal (called from parent via ref or event)
-  const writeData = useCallback((data: Uint8Array) => {
+  const writeData = useCallback((data: Uint8Array, onWritten?: () => void) => {
     if (terminalRef.current) {
-      terminalRef.current.write(data);
+      terminalRef.current.write(data, onWritten);
+    } else {
+      onWritten?.();
     }
   }, []);
 
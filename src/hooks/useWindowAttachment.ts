@@ -1,4 +1,4 @@
-import { useState, useEffect, useCallback } from "react";
+import { useState, useEffect, useCallback, useRef } from "react";
 import { listen } from "@tauri-apps/api/event";
 import { invoke } from "@tauri-apps/api/tauri";
 import { appWindow } from "@tauri-apps/api/window";
@@ -9,15 +9,34 @@ export interface RunningApp {
 }
 
 interface FocusChangedPayload {
+  subscription_id: string;
   focused_app: string;
+  focused_bundle_id: string;
+  focused_target: string | null;
   is_target_focused: boolean;
   is_self_focused: boolean;
 }
 
+interface AppLifecyclePayload {
+  subscription_id: string;
+  app: RunningApp;
+  target: string;
+}
+
 export function useWindowAttachment() {
   const [attachedApp, setAttachedApp] = useState<RunningApp | null>(null);
   const [runningApps, setRunningApps] = useState<RunningApp[]>([]);
   const [isLoading, setIsLoading] = useState(false);
+  // Our focus monitor subscription; other features may run their own
+  const subscriptionId = useRef<string | null>(null);
+
+  const stopMonitor = useCallback(async () => {
+    const id = subscriptionId.current;
+    subscriptionId.current = null;
+    if (id) {
+      await invoke("stop_focus_monitor", { subscriptionId: id });
+    }
+  }, []);
 
   // Fetch running applications
   const fetchRunningApps = useCallback(async () => {
@@ -32,11 +51,17 @@ export function useWindowAttachment() {
     }
   }, []);
 
-  // Attach to an application
+  // Attach to an application, matched by bundle id so renames and other languages don't
+  // break it
   const attachToApp = useCallback(async (app: RunningApp) => {
     setAttachedApp(app);
+    const targets = [app.bundle_id || app.name];
     try {
-      await invoke("start_focus_monitor", { targetApp: app.name });
+      if (subscriptionId.current) {
+        await invoke("set_focus_monitor_targets", { subscriptionId: subscriptionId.current, targets });
+      } else {
+        subscriptionId.current = await invoke<string>("start_focus_monitor", { targets });
+      }
     } catch (error) {
       console.error("Failed to start focus monitor:", error);
       setAttachedApp(null);
@@ -47,20 +72,31 @@ export function useWindowAttachment() {
   const detach = useCallback(async () => {
     setAttachedApp(null);
     try {
-      await invoke("stop_focus_monitor");
+      await stopMonitor();
       // Ensure window is visible when detaching
       await appWindow.show();
     } catch (error) {
       console.error("Failed to stop focus monitor:", error);
     }
-  }, []);
+  }, [stopMonitor]);
+
+  // Bring the attached app back to the front, e.g. after running a command here
+  const switchToAttachedApp = useCallback(async () => {
+    if (!attachedApp?.bundle_id) return;
+    try {
+      await invoke<RunningApp>("activate_app", { bundleId: attachedApp.bundle_id });
+    } catch (error) {
+      console.error("Failed to activate app:", error);
+    }
+  }, [attachedApp]);
 
   // Listen for focus change events
   useEffect(() => {
     if (!attachedApp) return;
 
     const unlisten = listen<FocusChangedPayload>("app-focus-changed", async (event) => {
-      const { is_target_focused, is_self_focused } = event.payload;
+      const { subscription_id, is_target_focused, is_self_focused } = event.payload;
+      if (subscription_id !== subscriptionId.current) return;
 
       // Show window if target app or self is focused
       if (is_target_focused || is_self_focused) {
@@ -70,19 +106,29 @@ export function useWindowAttachment() {
       }
     });
 
+    // Come up with the target app and go away when it quits
+    const unlistenLaunched = listen<AppLifecyclePayload>("app-launched", async (event) => {
+      if (event.payload.subscription_id !== subscriptionId.current) return;
+      await appWindow.show();
+    });
+    const unlistenTerminated = listen<AppLifecyclePayload>("app-terminated", async (event) => {
+      if (event.payload.subscription_id !== subscriptionId.current) return;
+      await appWindow.hide();
+    });
+
     return () => {
       unlisten.then((f) => f());
+      unlistenLaunched.then((f) => f());
+      unlistenTerminated.then((f) => f());
     };
   }, [attachedApp]);
 
   // Cleanup on unmount
   useEffect(() => {
     return () => {
-      if (attachedApp) {
-        invoke("stop_focus_monitor").catch(console.error);
-      }
+      stopMonitor().catch(console.error);
     };
-  }, [attachedApp]);
+  }, [stopMonitor]);
 
   return {
     attachedApp,
@@ -92,5 +138,6 @@ export function useWindowAttachment() {
     fetchRunningApps,
     attachToApp,
     detach,
+    switchToAttachedApp,
   };
 }